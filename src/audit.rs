@@ -0,0 +1,131 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::{app_support_dir, Config, MetadataStore};
+use crate::metadata::path_hash;
+use crate::state::CellPosition;
+
+const HEADER: [&str; 4] = ["time", "cell", "old", "new"];
+
+/// One cell change: when, where, and the text before and after
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    /// Local time, e.g. `2024-01-31 14:05:00`
+    pub time: String,
+    pub cell: CellPosition,
+    pub old: String,
+    pub new: String,
+}
+
+impl AuditEntry {
+    fn record(&self) -> [String; 4] {
+        [self.time.clone(), self.cell.to_reference(), self.old.clone(), self.new.clone()]
+    }
+}
+
+/// Cell changes recorded for a file, appended to a CSV log as they're made
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+    /// The cells as of the last recorded change, to find what changed since
+    cells: Vec<Vec<String>>,
+    /// Entries already in the log file
+    written: usize,
+}
+
+impl AuditLog {
+    /// Start recording changes to `cells`, after the entries already logged for `csv_path`
+    pub fn new(cells: &[Vec<String>], csv_path: Option<&Path>) -> Self {
+        let entries = csv_path.map(|path| read_log(&log_path(path))).unwrap_or_default();
+        Self { written: entries.len(), entries, cells: cells.to_vec() }
+    }
+
+    /// Record every cell that differs from last time. Returns whether any did.
+    pub fn record(&mut self, cells: &[Vec<String>]) -> bool {
+        let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let before = self.entries.len();
+        for (row, (old_row, new_row)) in self.cells.iter_mut().zip(cells).enumerate() {
+            if old_row == new_row {
+                continue;
+            }
+            for (col, (old, new)) in old_row.iter_mut().zip(new_row).enumerate() {
+                if old != new {
+                    self.entries.push(AuditEntry {
+                        time: time.clone(),
+                        cell: CellPosition::new(row, col),
+                        old: std::mem::replace(old, new.clone()),
+                        new: new.clone(),
+                    });
+                }
+            }
+        }
+        self.entries.len() > before
+    }
+
+    /// Append entries not yet in `csv_path`'s log to it, creating it with a header row
+    pub fn flush(&mut self, csv_path: &Path) -> io::Result<()> {
+        if self.written == self.entries.len() {
+            return Ok(());
+        }
+        let path = log_path(csv_path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let is_new = !path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut writer = csv::Writer::from_writer(file);
+        if is_new {
+            writer.write_record(HEADER)?;
+        }
+        for entry in &self.entries[self.written..] {
+            writer.write_record(entry.record())?;
+        }
+        writer.flush()?;
+        self.written = self.entries.len();
+        Ok(())
+    }
+
+    /// Write every entry to a CSV file of its own
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(HEADER)?;
+        for entry in &self.entries {
+            writer.write_record(entry.record())?;
+        }
+        writer.flush()
+    }
+}
+
+/// Where a file's changes are logged: `<name>.audit.csv` beside it, or in the app support
+/// directory when the config keeps metadata centrally
+pub fn log_path(csv_path: &Path) -> PathBuf {
+    if Config::get().metadata_store == MetadataStore::Central
+        && let Some(dir) = app_support_dir()
+    {
+        let csv_path = std::fs::canonicalize(csv_path).unwrap_or_else(|_| csv_path.to_path_buf());
+        return dir.join("audit").join(format!("{:016x}.csv", path_hash(&csv_path)));
+    }
+    let mut path = csv_path.to_path_buf();
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("spreadsheet.csv");
+    path.set_file_name(format!("{}.audit.csv", file_name));
+    path
+}
+
+/// Entries of a log file; a missing or unreadable one has none
+fn read_log(path: &Path) -> Vec<AuditEntry> {
+    let Ok(mut reader) = csv::Reader::from_path(path) else {
+        return Vec::new();
+    };
+    reader
+        .records()
+        .flatten()
+        .filter_map(|record| {
+            Some(AuditEntry {
+                time: record.get(0)?.to_string(),
+                cell: CellPosition::from_reference(record.get(1)?)?,
+                old: record.get(2)?.to_string(),
+                new: record.get(3)?.to_string(),
+            })
+        })
+        .collect()
+}
@@ -9,10 +9,47 @@ use gpui::*;
 use unicode_segmentation::*;
 
 use crate::Theme;
+use crate::formula;
+use crate::menu::{Redo, Undo};
 
 const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(600);
 const CURSOR_FADE_DURATION: Duration = Duration::from_millis(400);
 const CURSOR_ANIMATION_STEP: Duration = Duration::from_millis(16); // ~60fps
+/// How often a drag selection held past the input's edge scrolls it further
+const DRAG_SCROLL_INTERVAL: Duration = Duration::from_millis(30);
+/// Most edits remembered for undo while editing one cell
+const UNDO_LIMIT: usize = 100;
+
+/// Height of one line of text in the input; multi-line content grows by this much per line
+pub const LINE_HEIGHT: f32 = 20.0;
+
+/// How the text cursor draws attention while editing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CursorBlink {
+    /// Fade in and out, redrawing at ~60fps during each fade
+    #[default]
+    Fade,
+    /// Switch on and off
+    Blink,
+    /// Always shown
+    Steady,
+}
+
+impl CursorBlink {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim() {
+            "fade" => Some(Self::Fade),
+            "blink" => Some(Self::Blink),
+            "steady" | "off" => Some(Self::Steady),
+            _ => None,
+        }
+    }
+
+    /// The default: a plain blink when reduced motion is asked for, else a fade
+    pub fn preferred() -> Self {
+        if crate::config::reduced_motion() { Self::Blink } else { Self::Fade }
+    }
+}
 
 /// Ease-in-out cubic function for smooth animation
 fn ease_in_out_cubic(t: f32) -> f32 {
@@ -23,6 +60,61 @@ fn ease_in_out_cubic(t: f32) -> f32 {
     }
 }
 
+/// Length in bytes of the longest common prefix of two strings
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((idx, _), _)| idx)
+}
+
+/// Index of the line containing a byte offset (an offset at a line's end belongs to that line)
+fn line_for_offset(ranges: &[Range<usize>], offset: usize) -> usize {
+    ranges
+        .iter()
+        .position(|range| offset <= range.end)
+        .unwrap_or(ranges.len() - 1)
+}
+
+/// Text runs for one line: formula highlight colors, plus an underline on the part that
+/// overlaps the IME marked range
+fn runs_for_line(
+    line: &Range<usize>,
+    colors: &[(Range<usize>, Hsla)],
+    marked_range: Option<&Range<usize>>,
+    run: &TextRun,
+) -> Vec<TextRun> {
+    let clip = |offset: usize| offset.clamp(line.start, line.end);
+    let mut breaks = vec![line.start, line.end];
+    for range in colors.iter().map(|(range, _)| range).chain(marked_range) {
+        breaks.extend([clip(range.start), clip(range.end)]);
+    }
+    breaks.sort_unstable();
+    breaks.dedup();
+
+    breaks
+        .windows(2)
+        .map(|pair| {
+            let (start, end) = (pair[0], pair[1]);
+            let color = colors
+                .iter()
+                .find(|(range, _)| range.start <= start && end <= range.end)
+                .map_or(run.color, |(_, color)| *color);
+            let marked = marked_range.is_some_and(|marked| marked.start <= start && end <= marked.end);
+            TextRun {
+                len: end - start,
+                color,
+                underline: marked.then_some(UnderlineStyle {
+                    color: Some(color),
+                    thickness: px(1.0),
+                    wavy: false,
+                }),
+                ..run.clone()
+            }
+        })
+        .collect()
+}
+
 actions!(
     cell_input,
     [
@@ -45,23 +137,51 @@ actions!(
         SelectWordRight,
         DeleteToStart,
         DeleteWordBackward,
+        InsertNewline,
+        CompletionNext,
+        CompletionPrevious,
+        AcceptCompletion,
     ]
 );
 
+/// Content and selection to return to with undo or redo
+#[derive(Clone)]
+struct EditSnapshot {
+    content: SharedString,
+    selected_range: Range<usize>,
+}
+
 pub struct CellInput {
     pub focus_handle: FocusHandle,
     pub content: SharedString,
     pub selected_range: Range<usize>,
     pub selection_reversed: bool,
     pub marked_range: Option<Range<usize>>,
-    pub last_layout: Option<ShapedLine>,
+    pub last_layout: Vec<ShapedLine>,
     pub last_bounds: Option<Bounds<Pixels>>,
     pub is_selecting: bool,
+    /// Mouse position of a drag selection, while it's scrolling the content
+    drag_position: Option<Point<Pixels>>,
     pub cursor_opacity: f32,
     pub cursor_fading_in: bool,
     pub blink_epoch: usize,
     pub fade_start: Option<Instant>,
+    pub cursor_blink: CursorBlink,
     pub scroll_offset: Pixels,
+    /// Entity re-rendered whenever the content or cursor changes (the grid, which draws
+    /// the editor's popups)
+    pub observer: Option<EntityId>,
+    /// Highlighted entry of the function completion list
+    pub completion_index: usize,
+    /// Reference last inserted by pointing at cells; pointing again replaces it
+    pub reference_range: Option<Range<usize>>,
+    /// Text already in the cell's column, offered to finish what's being typed
+    pub autocomplete_values: Vec<String>,
+    // Edits made since editing began, undone with cmd-z
+    undo_stack: Vec<EditSnapshot>,
+    redo_stack: Vec<EditSnapshot>,
+    /// Where the run of characters being typed ends, so the run undoes in one step
+    typing_at: Option<usize>,
 }
 
 impl CellInput {
@@ -72,14 +192,23 @@ impl CellInput {
             selected_range: 0..0,
             selection_reversed: false,
             marked_range: None,
-            last_layout: None,
+            last_layout: Vec::new(),
             last_bounds: None,
             is_selecting: false,
+            drag_position: None,
             cursor_opacity: 1.0,
             cursor_fading_in: true,
             blink_epoch: 0,
             fade_start: None,
+            cursor_blink: CursorBlink::preferred(),
             scroll_offset: px(0.),
+            observer: None,
+            completion_index: 0,
+            reference_range: None,
+            autocomplete_values: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            typing_at: None,
         }
     }
 
@@ -91,8 +220,62 @@ impl CellInput {
         self.selection_reversed = false;
         self.marked_range = None;
         self.scroll_offset = px(0.);
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.typing_at = None;
         self.reset_cursor_blink(cx);
-        cx.notify();
+        self.changed(cx);
+    }
+
+    fn snapshot(&self) -> EditSnapshot {
+        EditSnapshot { content: self.content.clone(), selected_range: self.selected_range.clone() }
+    }
+
+    /// Remember the content before replacing `range` with `new_text`, unless the edit
+    /// continues a run of typed characters
+    fn record_edit(&mut self, range: &Range<usize>, new_text: &str) {
+        let typing = range.is_empty() && new_text != "\n" && new_text.graphemes(true).count() == 1;
+        if !(typing && self.typing_at == Some(range.start)) {
+            self.push_undo();
+        }
+        self.redo_stack.clear();
+        self.typing_at = typing.then_some(range.start + new_text.len());
+    }
+
+    fn push_undo(&mut self) {
+        self.undo_stack.push(self.snapshot());
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    /// Undo the last edit to the content; with none left, the grid handles the undo
+    fn undo(&mut self, _: &Undo, _: &mut Window, cx: &mut Context<Self>) {
+        let Some(snapshot) = self.undo_stack.pop() else {
+            cx.propagate();
+            return;
+        };
+        self.redo_stack.push(self.snapshot());
+        self.restore(snapshot, cx);
+    }
+
+    fn redo(&mut self, _: &Redo, _: &mut Window, cx: &mut Context<Self>) {
+        let Some(snapshot) = self.redo_stack.pop() else {
+            cx.propagate();
+            return;
+        };
+        self.undo_stack.push(self.snapshot());
+        self.restore(snapshot, cx);
+    }
+
+    fn restore(&mut self, snapshot: EditSnapshot, cx: &mut Context<Self>) {
+        self.content = snapshot.content;
+        self.selected_range = snapshot.selected_range;
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.typing_at = None;
+        self.reset_cursor_blink(cx);
+        self.changed(cx);
     }
 
     /// Get the content of the cell input (used when exiting edit mode)
@@ -100,8 +283,167 @@ impl CellInput {
         self.content.to_string()
     }
 
+    /// Number of lines the content spans (used to size the cell while editing)
+    pub fn line_count(&self) -> usize {
+        self.content.matches('\n').count() + 1
+    }
+
+    /// Byte range of each line of the content, excluding the newlines
+    fn line_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for (idx, _) in self.content.match_indices('\n') {
+            ranges.push(start..idx);
+            start = idx + 1;
+        }
+        ranges.push(start..self.content.len());
+        ranges
+    }
+
+    /// Line index for a vertical position within the input bounds
+    fn line_for_y(&self, y: Pixels) -> usize {
+        let bounds_top = self.last_bounds.map_or(px(0.), |bounds| bounds.top());
+        let line = (f32::from(y - bounds_top) / LINE_HEIGHT).max(0.0) as usize;
+        line.min(self.last_layout.len().saturating_sub(1))
+    }
+
+    /// Re-render this input and its observer after a content or cursor change
+    fn changed(&mut self, cx: &mut Context<Self>) {
+        self.completion_index = 0;
+        self.reference_range = None;
+        cx.notify();
+        self.notify_observer(cx);
+    }
+
+    fn notify_observer(&self, cx: &mut Context<Self>) {
+        if let Some(observer) = self.observer {
+            let app: &mut App = cx;
+            app.notify(observer);
+        }
+    }
+
+    /// Byte range of the function name being typed before the cursor in a formula
+    fn completion_prefix(&self) -> Option<Range<usize>> {
+        let cursor = self.cursor_offset();
+        let before = &self.content[..cursor];
+        // Nothing to complete outside formulas or inside a string literal
+        if !before.starts_with('=') || before.matches('"').count() % 2 == 1 {
+            return None;
+        }
+        let start = before
+            .trim_end_matches(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            .len();
+        let prefix = &before[start..];
+        let in_reference = before[..start].ends_with(['$', ':']);
+        let mid_word = self.content[cursor..].starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '(');
+        let is_name = prefix.starts_with(|c: char| c.is_ascii_alphabetic());
+        (is_name && !in_reference && !mid_word).then_some(start..cursor)
+    }
+
+    /// Function names matching the name being typed in a formula
+    pub fn completions(&self) -> Vec<&'static str> {
+        let Some(range) = self.completion_prefix() else {
+            return Vec::new();
+        };
+        let prefix = self.content[range].to_uppercase();
+        formula::FUNCTIONS
+            .iter()
+            .copied()
+            .filter(|name| name.starts_with(&prefix))
+            .collect()
+    }
+
+    /// The rest of the column value the text typed so far begins (ignoring case), shown after
+    /// the cursor; when several values match, only as much as they all share
+    pub fn autocompletion(&self) -> Option<&str> {
+        let typed: &str = &self.content;
+        let at_end = self.selected_range == (typed.len()..typed.len());
+        if typed.trim().is_empty() || typed.starts_with('=') || !at_end || self.marked_range.is_some() {
+            return None;
+        }
+        let typed_lower = typed.to_lowercase();
+        let typed_chars = typed.chars().count();
+        let mut rests = self
+            .autocomplete_values
+            .iter()
+            .filter(|value| value.to_lowercase().starts_with(&typed_lower))
+            .filter_map(|value| value.char_indices().nth(typed_chars).map(|(idx, _)| &value[idx..]));
+        let first = rests.next()?;
+        let shared = rests.fold(first.len(), |len, rest| common_prefix_len(&first[..len], rest));
+        (shared > 0).then(|| &first[..shared])
+    }
+
+    /// Replace the name being typed with a function name and open its argument list
+    pub fn complete(&mut self, name: &str, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(range) = self.completion_prefix() {
+            self.selected_range = range;
+            self.marked_range = None;
+            self.replace_text_in_range(None, &format!("{}(", name), window, cx);
+        }
+    }
+
+    /// Whether arrow keys and clicks on other cells should insert references: right after
+    /// inserting one, or when the cursor follows an operator or `(` in a formula
+    pub fn is_pointing(&self) -> bool {
+        if self.reference_range.is_some() {
+            return true;
+        }
+        let cursor = self.cursor_offset();
+        let before = self.content[..cursor].trim_end();
+        let after = self.content[cursor..].trim_start();
+        self.selected_range.is_empty()
+            && self.content.starts_with('=')
+            && before.matches('"').count().is_multiple_of(2)
+            && before.ends_with(['=', '(', ',', '+', '-', '*', '/', '^', '&', '<', '>', ':'])
+            && (after.is_empty() || after.starts_with([')', ',', '+', '-', '*', '/', '^', '&', '<', '>', '=']))
+    }
+
+    /// Insert a cell reference at the cursor, replacing the one last inserted by pointing
+    pub fn insert_reference(&mut self, reference: &str, window: &mut Window, cx: &mut Context<Self>) {
+        let range = self.reference_range.take().unwrap_or(self.selected_range.clone());
+        self.selected_range = range.clone();
+        self.marked_range = None;
+        self.replace_text_in_range(None, reference, window, cx);
+        self.reference_range = Some(range.start..range.start + reference.len());
+    }
+
+    fn completion_next(&mut self, _: &CompletionNext, _: &mut Window, cx: &mut Context<Self>) {
+        self.step_completion(1, cx);
+    }
+
+    fn completion_previous(&mut self, _: &CompletionPrevious, _: &mut Window, cx: &mut Context<Self>) {
+        self.step_completion(-1, cx);
+    }
+
+    /// Move the highlight through the completion list, wrapping at either end
+    fn step_completion(&mut self, delta: isize, cx: &mut Context<Self>) {
+        let count = self.completions().len();
+        if count == 0 {
+            cx.propagate();
+            return;
+        }
+        self.completion_index = (self.completion_index as isize + delta).rem_euclid(count as isize) as usize;
+        cx.notify();
+        self.notify_observer(cx);
+    }
+
+    fn accept_completion(&mut self, _: &AcceptCompletion, window: &mut Window, cx: &mut Context<Self>) {
+        let completions = self.completions();
+        let Some(name) = completions.get(self.completion_index).or(completions.first()) else {
+            match self.autocompletion().map(str::to_owned) {
+                Some(rest) => self.replace_text_in_range(None, &rest, window, cx),
+                None => cx.propagate(),
+            }
+            return;
+        };
+        self.complete(name, window, cx);
+    }
+
     fn left(&mut self, _: &Left, _: &mut Window, cx: &mut Context<Self>) {
-        if self.selected_range.is_empty() {
+        // While pointing, arrows move the grid's reference pointer instead
+        if self.is_pointing() {
+            cx.propagate();
+        } else if self.selected_range.is_empty() {
             self.move_to(self.previous_boundary(self.cursor_offset()), cx);
         } else {
             self.move_to(self.selected_range.start, cx)
@@ -109,7 +451,9 @@ impl CellInput {
     }
 
     fn right(&mut self, _: &Right, _: &mut Window, cx: &mut Context<Self>) {
-        if self.selected_range.is_empty() {
+        if self.is_pointing() {
+            cx.propagate();
+        } else if self.selected_range.is_empty() {
             self.move_to(self.next_boundary(self.selected_range.end), cx);
         } else {
             self.move_to(self.selected_range.end, cx)
@@ -117,10 +461,18 @@ impl CellInput {
     }
 
     fn select_left(&mut self, _: &SelectLeft, _: &mut Window, cx: &mut Context<Self>) {
+        if self.is_pointing() {
+            cx.propagate();
+            return;
+        }
         self.select_to(self.previous_boundary(self.cursor_offset()), cx);
     }
 
     fn select_right(&mut self, _: &SelectRight, _: &mut Window, cx: &mut Context<Self>) {
+        if self.is_pointing() {
+            cx.propagate();
+            return;
+        }
         self.select_to(self.next_boundary(self.cursor_offset()), cx);
     }
 
@@ -181,31 +533,87 @@ impl CellInput {
         self.replace_text_in_range(None, "", window, cx)
     }
 
+    fn insert_newline(&mut self, _: &InsertNewline, window: &mut Window, cx: &mut Context<Self>) {
+        self.replace_text_in_range(None, "\n", window, cx)
+    }
+
     fn on_mouse_down(
         &mut self,
         event: &MouseDownEvent,
         _window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        self.is_selecting = true;
-
-        if event.modifiers.shift {
-            self.select_to(self.index_for_mouse_position(event.position), cx);
-        } else {
-            self.move_to(self.index_for_mouse_position(event.position), cx)
+        let offset = self.index_for_mouse_position(event.position);
+        // Dragging extends a plain click; a double or triple click keeps what it selected
+        self.is_selecting = event.click_count == 1;
+
+        match event.click_count {
+            1 if event.modifiers.shift => self.select_to(offset, cx),
+            1 => self.move_to(offset, cx),
+            2 => self.select_range(self.word_range_at(offset), cx),
+            _ => {
+                let ranges = self.line_ranges();
+                self.select_range(ranges[line_for_offset(&ranges, offset)].clone(), cx);
+            }
         }
     }
 
     fn on_mouse_up(&mut self, _: &MouseUpEvent, _window: &mut Window, _: &mut Context<Self>) {
         self.is_selecting = false;
+        self.drag_position = None;
     }
 
-    fn on_mouse_move(&mut self, event: &MouseMoveEvent, _: &mut Window, cx: &mut Context<Self>) {
-        if self.is_selecting {
-            self.select_to(self.index_for_mouse_position(event.position), cx);
+    /// Extend a drag selection to the mouse, which may have left the input
+    fn drag_to(&mut self, position: Point<Pixels>, cx: &mut Context<Self>) {
+        if !self.is_selecting {
+            return;
+        }
+        self.select_to(self.index_for_mouse_position(position), cx);
+        let scrolling = self.drag_position.is_some();
+        self.drag_position = Some(position);
+        if !scrolling {
+            self.scroll_while_dragging(cx);
         }
     }
 
+    /// Keep extending the selection while the mouse is held past the left or right edge,
+    /// faster the further past it is
+    fn scroll_while_dragging(&mut self, cx: &mut Context<Self>) {
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                cx.background_executor().timer(DRAG_SCROLL_INTERVAL).await;
+                let should_continue = this
+                    .update(cx, |this, cx| {
+                        let (Some(position), Some(bounds)) = (this.drag_position, this.last_bounds) else {
+                            return false;
+                        };
+                        let past = if position.x < bounds.left() {
+                            bounds.left() - position.x
+                        } else if position.x > bounds.right() {
+                            position.x - bounds.right()
+                        } else {
+                            return true;
+                        };
+                        let mut offset = this.cursor_offset();
+                        for _ in 0..1 + (f32::from(past) / 16.0) as usize {
+                            offset = if position.x < bounds.left() {
+                                this.previous_boundary(offset)
+                            } else {
+                                this.next_boundary(offset)
+                            };
+                        }
+                        this.select_to(offset, cx);
+                        true
+                    })
+                    .unwrap_or(false);
+                if !should_continue {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
     fn show_character_palette(
         &mut self,
         _: &ShowCharacterPalette,
@@ -239,12 +647,49 @@ impl CellInput {
         }
     }
 
-    fn reset_cursor_blink(&mut self, cx: &mut Context<Self>) {
+    /// Show the cursor and stop animating it, e.g. once editing ends
+    pub fn stop_cursor_blink(&mut self) {
         self.cursor_opacity = 1.0;
-        self.cursor_fading_in = true;
         self.fade_start = None;
         self.blink_epoch += 1;
+    }
+
+    fn reset_cursor_blink(&mut self, cx: &mut Context<Self>) {
+        self.cursor_fading_in = true;
+        self.stop_cursor_blink();
         let epoch = self.blink_epoch;
+        match self.cursor_blink {
+            CursorBlink::Fade => self.fade_cursor(epoch, cx),
+            CursorBlink::Blink => self.blink_cursor(epoch, cx),
+            CursorBlink::Steady => {}
+        }
+    }
+
+    /// Toggle the cursor on and off until the blink epoch changes
+    fn blink_cursor(&mut self, epoch: usize, cx: &mut Context<Self>) {
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                cx.background_executor().timer(CURSOR_BLINK_INTERVAL).await;
+                let should_continue = this
+                    .update(cx, |this, cx| {
+                        if this.blink_epoch != epoch {
+                            return false;
+                        }
+                        this.cursor_opacity = if this.cursor_opacity > 0.0 { 0.0 } else { 1.0 };
+                        cx.notify();
+                        true
+                    })
+                    .unwrap_or(false);
+                if !should_continue {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Fade the cursor out and in until the blink epoch changes
+    fn fade_cursor(&mut self, epoch: usize, cx: &mut Context<Self>) {
         cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
             // Initial delay before first blink
             cx.background_executor().timer(CURSOR_BLINK_INTERVAL).await;
@@ -321,7 +766,7 @@ impl CellInput {
     fn move_to(&mut self, offset: usize, cx: &mut Context<Self>) {
         self.selected_range = offset..offset;
         self.reset_cursor_blink(cx);
-        cx.notify()
+        self.changed(cx);
     }
 
     fn cursor_offset(&self) -> usize {
@@ -337,8 +782,7 @@ impl CellInput {
             return 0;
         }
 
-        let (Some(bounds), Some(line)) = (self.last_bounds.as_ref(), self.last_layout.as_ref())
-        else {
+        let Some(bounds) = self.last_bounds.as_ref() else {
             return 0;
         };
         if position.y < bounds.top() {
@@ -347,8 +791,21 @@ impl CellInput {
         if position.y > bounds.bottom() {
             return self.content.len();
         }
+        let ranges = self.line_ranges();
+        let index = self.line_for_y(position.y);
+        let (Some(range), Some(line)) = (ranges.get(index), self.last_layout.get(index)) else {
+            return self.content.len();
+        };
         // Account for scroll offset when calculating position
-        line.closest_index_for_x(position.x - bounds.left() + self.scroll_offset)
+        let x = line.closest_index_for_x(position.x - bounds.left() + self.scroll_offset);
+        range.start + x.min(range.len())
+    }
+
+    fn select_range(&mut self, range: Range<usize>, cx: &mut Context<Self>) {
+        self.selected_range = range;
+        self.selection_reversed = false;
+        self.reset_cursor_blink(cx);
+        self.changed(cx);
     }
 
     fn select_to(&mut self, offset: usize, cx: &mut Context<Self>) {
@@ -361,7 +818,7 @@ impl CellInput {
             self.selection_reversed = !self.selection_reversed;
             self.selected_range = self.selected_range.end..self.selected_range.start;
         }
-        cx.notify()
+        self.changed(cx);
     }
 
     fn offset_from_utf16(&self, offset: usize) -> usize {
@@ -458,6 +915,26 @@ impl CellInput {
 
         self.content.len()
     }
+
+    /// The word (or run of spaces or punctuation) at an offset, preferring the one before
+    /// it at the end of a word
+    fn word_range_at(&self, offset: usize) -> Range<usize> {
+        let words = self.content.split_word_bound_indices().map(|(idx, word)| idx..idx + word.len());
+        let is_word = |range: &Range<usize>| {
+            self.content[range.clone()].chars().next().is_some_and(|c| c.is_alphanumeric() || c == '_')
+        };
+        let mut before = None;
+        for range in words {
+            if range.contains(&offset) {
+                return match before {
+                    Some(before) if range.start == offset && !is_word(&range) && is_word(&before) => before,
+                    _ => range,
+                };
+            }
+            before = Some(range);
+        }
+        before.unwrap_or(offset..offset)
+    }
 }
 
 impl EntityInputHandler for CellInput {
@@ -512,13 +989,17 @@ impl EntityInputHandler for CellInput {
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
 
+        // A composition was recorded when it began
+        if self.marked_range.is_none() {
+            self.record_edit(&range, new_text);
+        }
         self.content =
             (self.content[0..range.start].to_owned() + new_text + &self.content[range.end..])
                 .into();
         self.selected_range = range.start + new_text.len()..range.start + new_text.len();
         self.marked_range.take();
         self.reset_cursor_blink(cx);
-        cx.notify();
+        self.changed(cx);
     }
 
     fn replace_and_mark_text_in_range(
@@ -535,6 +1016,11 @@ impl EntityInputHandler for CellInput {
             .or(self.marked_range.clone())
             .unwrap_or(self.selected_range.clone());
 
+        if self.marked_range.is_none() {
+            self.push_undo();
+            self.redo_stack.clear();
+            self.typing_at = None;
+        }
         self.content =
             (self.content[0..range.start].to_owned() + new_text + &self.content[range.end..])
                 .into();
@@ -542,10 +1028,10 @@ impl EntityInputHandler for CellInput {
         self.selected_range = new_selected_range_utf16
             .as_ref()
             .map(|range_utf16| self.range_from_utf16(range_utf16))
-            .map(|new_range| new_range.start + range.start..new_range.end + range.end)
+            .map(|new_range| new_range.start + range.start..new_range.end + range.start)
             .unwrap_or_else(|| range.start + new_text.len()..range.start + new_text.len());
 
-        cx.notify();
+        self.changed(cx);
     }
 
     fn bounds_for_range(
@@ -555,16 +1041,22 @@ impl EntityInputHandler for CellInput {
         _window: &mut Window,
         _cx: &mut Context<Self>,
     ) -> Option<Bounds<Pixels>> {
-        let last_layout = self.last_layout.as_ref()?;
         let range = self.range_from_utf16(&range_utf16);
+        let ranges = self.line_ranges();
+        let index = line_for_offset(&ranges, range.start);
+        let line_range = &ranges[index];
+        let line = self.last_layout.get(index)?;
+        let top = bounds.top() + px(LINE_HEIGHT * index as f32);
+        // Measured in the scrolled text, so the IME candidate window follows what's on screen
+        let left = bounds.left() - self.scroll_offset;
         Some(Bounds::from_corners(
             point(
-                bounds.left() + last_layout.x_for_index(range.start),
-                bounds.top(),
+                left + line.x_for_index(range.start - line_range.start),
+                top,
             ),
             point(
-                bounds.left() + last_layout.x_for_index(range.end),
-                bounds.bottom(),
+                left + line.x_for_index(range.end.min(line_range.end) - line_range.start),
+                top + px(LINE_HEIGHT),
             ),
         ))
     }
@@ -576,11 +1068,12 @@ impl EntityInputHandler for CellInput {
         _cx: &mut Context<Self>,
     ) -> Option<usize> {
         let line_point = self.last_bounds?.localize(&point)?;
-        let last_layout = self.last_layout.as_ref()?;
+        let index = self.line_for_y(point.y);
+        let line_range = self.line_ranges().get(index)?.clone();
+        let line = self.last_layout.get(index)?;
 
-        assert_eq!(last_layout.text, self.content);
-        let utf8_index = last_layout.index_for_x(point.x - line_point.x)?;
-        Some(self.offset_to_utf16(utf8_index))
+        let utf8_index = line.index_for_x(line_point.x + self.scroll_offset)?;
+        Some(self.offset_to_utf16(line_range.start + utf8_index.min(line_range.len())))
     }
 }
 
@@ -590,10 +1083,12 @@ pub struct CellInputElement {
 }
 
 pub struct CellInputPrepaintState {
-    line: Option<ShapedLine>,
+    lines: Vec<ShapedLine>,
     cursor: Option<(Bounds<Pixels>, Rgba)>,
     cursor_opacity: f32,
-    selection: Option<PaintQuad>,
+    selection: Vec<PaintQuad>,
+    /// Autocompletion drawn faintly after the cursor, and where
+    suggestion: Option<(ShapedLine, Point<Pixels>)>,
     scroll_offset: Pixels,
     vertical_offset: Pixels,
 }
@@ -623,7 +1118,8 @@ impl Element for CellInputElement {
     ) -> (LayoutId, Self::RequestLayoutState) {
         let mut style = Style::default();
         style.size.width = relative(1.).into();
-        style.size.height = window.line_height().into();
+        let line_count = self.input.read(cx).line_count();
+        style.size.height = px(LINE_HEIGHT * line_count as f32).into();
         (window.request_layout(style, [], cx), ())
     }
 
@@ -640,52 +1136,24 @@ impl Element for CellInputElement {
         let content = input.content.clone();
         let selected_range = input.selected_range.clone();
         let cursor = input.cursor_offset();
+        let line_ranges = input.line_ranges();
         let style = window.text_style();
         let theme = cx.global::<Theme>();
         let mut scroll_offset = input.scroll_offset;
-
-        let (display_text, text_color) = if content.is_empty() {
-            ("".into(), style.color)
-        } else {
-            (content.clone(), style.color)
-        };
+        let colors: Vec<(Range<usize>, Hsla)> = formula::highlight(&content)
+            .spans
+            .into_iter()
+            .map(|(range, kind)| (range, theme.formula_color(kind).into()))
+            .collect();
 
         let run = TextRun {
-            len: display_text.len(),
+            len: 0,
             font: style.font(),
-            color: text_color,
+            color: style.color,
             background_color: None,
             underline: None,
             strikethrough: None,
         };
-        let runs = if let Some(marked_range) = input.marked_range.as_ref() {
-            vec![
-                TextRun {
-                    len: marked_range.start,
-                    ..run.clone()
-                },
-                TextRun {
-                    len: marked_range.end - marked_range.start,
-                    underline: Some(UnderlineStyle {
-                        color: Some(run.color),
-                        thickness: px(1.0),
-                        wavy: false,
-                    }),
-                    ..run.clone()
-                },
-                TextRun {
-                    len: display_text.len() - marked_range.end,
-                    ..run.clone()
-                },
-            ]
-            .into_iter()
-            .filter(|run| run.len > 0)
-            .collect()
-        } else if display_text.is_empty() {
-            vec![]
-        } else {
-            vec![run]
-        };
 
         let font_size = style.font_size.to_pixels(window.rem_size());
 
@@ -695,24 +1163,35 @@ impl Element for CellInputElement {
         let x_height = window.text_system().x_height(font_id, font_size);
         let vertical_offset = (cap_height - x_height) / 2.0;
 
-        let line = if display_text.is_empty() {
-            window.text_system().shape_line(" ".into(), font_size, &[TextRun {
-                len: 1,
-                font: style.font(),
-                color: Hsla::transparent_black().into(),
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            }], None)
-        } else {
-            window.text_system().shape_line(display_text, font_size, &runs, None)
-        };
+        // Shape each line separately; shaped lines cannot contain newlines
+        let lines: Vec<ShapedLine> = line_ranges
+            .iter()
+            .map(|range| {
+                if range.is_empty() {
+                    window.text_system().shape_line(" ".into(), font_size, &[TextRun {
+                        len: 1,
+                        color: Hsla::transparent_black().into(),
+                        ..run.clone()
+                    }], None)
+                } else {
+                    let text: SharedString = content[range.clone()].to_string().into();
+                    let runs = runs_for_line(range, &colors, input.marked_range.as_ref(), &run);
+                    window.text_system().shape_line(text, font_size, &runs, None)
+                }
+            })
+            .collect();
 
-        let cursor_pos = if content.is_empty() {
-            px(0.)
-        } else {
-            line.x_for_index(cursor)
+        let x_for_offset = |line: usize, offset: usize| {
+            if line_ranges[line].is_empty() {
+                px(0.)
+            } else {
+                lines[line].x_for_index(offset - line_ranges[line].start)
+            }
         };
+
+        let cursor_line = line_for_offset(&line_ranges, cursor);
+        let cursor_pos = x_for_offset(cursor_line, cursor);
+        let cursor_top = bounds.top() + px(LINE_HEIGHT * cursor_line as f32);
         let cursor_opacity = input.cursor_opacity;
 
         // Calculate visible width (bounds width minus some padding for the cursor)
@@ -731,39 +1210,50 @@ impl Element for CellInputElement {
 
         let (selection, cursor) = if selected_range.is_empty() {
             (
-                None,
+                Vec::new(),
                 Some((
                     Bounds::new(
-                        point(bounds.left() + cursor_pos - scroll_offset, bounds.top()),
-                        size(px(2.), bounds.bottom() - bounds.top()),
+                        point(bounds.left() + cursor_pos - scroll_offset, cursor_top),
+                        size(px(2.), px(LINE_HEIGHT)),
                     ),
                     theme.accent,
                 )),
             )
         } else {
-            (
-                Some(fill(
-                    Bounds::from_corners(
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.start) - scroll_offset,
-                            bounds.top(),
+            // One highlight per line the selection touches
+            let selection = line_ranges
+                .iter()
+                .enumerate()
+                .filter(|(_, range)| selected_range.start <= range.end && selected_range.end >= range.start)
+                .map(|(line, range)| {
+                    let top = bounds.top() + px(LINE_HEIGHT * line as f32);
+                    let start = selected_range.start.clamp(range.start, range.end);
+                    let end = selected_range.end.clamp(range.start, range.end);
+                    fill(
+                        Bounds::from_corners(
+                            point(bounds.left() + x_for_offset(line, start) - scroll_offset, top),
+                            point(bounds.left() + x_for_offset(line, end) - scroll_offset, top + px(LINE_HEIGHT)),
                         ),
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.end) - scroll_offset,
-                            bounds.bottom(),
-                        ),
-                    ),
-                    rgba(0x3311ff30),
-                )),
-                None,
-            )
+                        rgba(0x3311ff30),
+                    )
+                })
+                .collect();
+            (selection, None)
         };
 
+        let suggestion = input.autocompletion().map(|rest| {
+            let text: SharedString = rest.to_string().into();
+            let runs = [TextRun { len: text.len(), color: theme.subtext0.into(), ..run.clone() }];
+            let line = window.text_system().shape_line(text, font_size, &runs, None);
+            (line, point(bounds.left() + cursor_pos - scroll_offset, cursor_top + vertical_offset))
+        });
+
         CellInputPrepaintState {
-            line: Some(line),
+            lines,
             cursor,
             cursor_opacity,
             selection,
+            suggestion,
             scroll_offset,
             vertical_offset,
         }
@@ -785,17 +1275,26 @@ impl Element for CellInputElement {
             ElementInputHandler::new(bounds, self.input.clone()),
             cx,
         );
-        if let Some(selection) = prepaint.selection.take() {
+        for selection in prepaint.selection.drain(..) {
             window.paint_quad(selection)
         }
-        let line = prepaint.line.take().unwrap();
+        let lines = std::mem::take(&mut prepaint.lines);
         let scroll_offset = prepaint.scroll_offset;
         let vertical_offset = prepaint.vertical_offset;
 
         // Paint text with scroll offset applied, using calculated x-height centering offset
-        let text_origin = point(bounds.origin.x - scroll_offset, bounds.origin.y + vertical_offset);
-        line.paint(text_origin, window.line_height(), gpui::TextAlign::Left, None, window, cx)
-            .unwrap();
+        for (index, line) in lines.iter().enumerate() {
+            let text_origin = point(
+                bounds.origin.x - scroll_offset,
+                bounds.origin.y + px(LINE_HEIGHT * index as f32) + vertical_offset,
+            );
+            line.paint(text_origin, px(LINE_HEIGHT), gpui::TextAlign::Left, None, window, cx)
+                .unwrap();
+        }
+        if let Some((line, origin)) = prepaint.suggestion.take() {
+            line.paint(origin, px(LINE_HEIGHT), gpui::TextAlign::Left, None, window, cx)
+                .unwrap();
+        }
 
         if focus_handle.is_focused(window) {
             if let Some((cursor_bounds, cursor_color)) = prepaint.cursor.take() {
@@ -813,8 +1312,18 @@ impl Element for CellInputElement {
             }
         }
 
+        // Follow a drag selection outside the input, so it can scroll to text out of view
+        if self.input.read(cx).is_selecting {
+            let input = self.input.clone();
+            window.on_mouse_event(move |event: &MouseMoveEvent, phase, _window, cx| {
+                if phase == DispatchPhase::Bubble {
+                    input.update(cx, |input, cx| input.drag_to(event.position, cx));
+                }
+            });
+        }
+
         self.input.update(cx, |input, _cx| {
-            input.last_layout = Some(line);
+            input.last_layout = lines;
             input.last_bounds = Some(bounds);
             input.scroll_offset = scroll_offset;
         });
@@ -837,6 +1346,10 @@ impl Render for CellInput {
             .on_action(cx.listener(Self::delete))
             .on_action(cx.listener(Self::delete_to_start))
             .on_action(cx.listener(Self::delete_word_backward))
+            .on_action(cx.listener(Self::insert_newline))
+            .on_action(cx.listener(Self::completion_next))
+            .on_action(cx.listener(Self::completion_previous))
+            .on_action(cx.listener(Self::accept_completion))
             .on_action(cx.listener(Self::left))
             .on_action(cx.listener(Self::right))
             .on_action(cx.listener(Self::select_left))
@@ -852,18 +1365,18 @@ impl Render for CellInput {
             .on_action(cx.listener(Self::paste))
             .on_action(cx.listener(Self::cut))
             .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
-            .on_mouse_move(cx.listener(Self::on_mouse_move))
             .bg(theme.surface0)
             .size_full()
             .overflow_hidden()
-            .line_height(px(20.))
+            .line_height(px(LINE_HEIGHT))
             .text_size(px(14.))
             .child(
                 div()
-                    .h(px(20.))
                     .w_full()
                     .overflow_hidden()
                     .px(px(4.))
@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use gpui::{Bounds, PathBuilder, Pixels, Point, Rgba, Window, fill, point, px, size};
+
+use crate::state::{CellPosition, CellRange};
+use crate::value;
+
+/// How a chart draws its series
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChartKind {
+    #[default]
+    Line,
+    Bar,
+    Scatter,
+}
+
+impl ChartKind {
+    pub const ALL: [ChartKind; 3] = [ChartKind::Line, ChartKind::Bar, ChartKind::Scatter];
+
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "line" => Some(Self::Line),
+            "bar" | "column" => Some(Self::Bar),
+            "scatter" | "xy" => Some(Self::Scatter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Line => "Line",
+            Self::Bar => "Bar",
+            Self::Scatter => "Scatter",
+        }
+    }
+}
+
+/// A chart floating over the grid, drawn from whatever its range holds when it renders
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chart {
+    pub range: CellRange,
+    pub kind: ChartKind,
+    /// Top-left corner, in window coordinates
+    pub origin: Point<Pixels>,
+}
+
+/// One column of a chart's range
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// One per data row; `None` where the cell isn't a number
+    pub values: Vec<Option<f64>>,
+}
+
+/// What a chart plots, read from its range: an optional label row, an optional first column
+/// of categories (x values for a scatter chart), and a series per remaining column
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChartData {
+    pub categories: Vec<String>,
+    pub series: Vec<Series>,
+}
+
+/// A shape in plot coordinates, with (0, 0) at the top-left of the plot
+enum Mark {
+    /// A horizontal guide at this height
+    Gridline(f32),
+    /// Connected points of a series; a gap in the data starts a new line
+    Line(usize, Vec<(f32, f32)>),
+    Bar(usize, f32, f32, f32, f32),
+    Dot(usize, f32, f32),
+}
+
+const DOT_RADIUS: f32 = 3.0;
+
+impl ChartData {
+    /// Read a range through `text`, which gives a cell's value as shown (formula results
+    /// included). `None` when there's nothing numeric to plot.
+    pub fn from_range(range: CellRange, kind: ChartKind, text: impl Fn(usize, usize) -> String) -> Option<Self> {
+        let number = |row: usize, col: usize| value::parse_number(text(row, col).trim());
+        // A first row with no numbers in it labels the columns
+        let has_header = range.rows().count() > 1
+            && range.cols().all(|col| number(range.start.row, col).is_none())
+            && range.cols().any(|col| !text(range.start.row, col).trim().is_empty());
+        let first_data_row = range.start.row + usize::from(has_header);
+        let rows: Vec<usize> = (first_data_row..=range.end.row).collect();
+        // A first column of text names the points; a scatter chart always reads it as x
+        let has_categories = range.cols().count() > 1
+            && (kind == ChartKind::Scatter
+                || rows.iter().any(|&row| {
+                    let cell = text(row, range.start.col);
+                    !cell.trim().is_empty() && number(row, range.start.col).is_none()
+                }));
+        let first_series_col = range.start.col + usize::from(has_categories);
+
+        let categories = rows
+            .iter()
+            .enumerate()
+            .map(|(i, &row)| match has_categories {
+                true => text(row, range.start.col).trim().to_string(),
+                false => (i + 1).to_string(),
+            })
+            .collect();
+        let series: Vec<Series> = (first_series_col..=range.end.col)
+            .map(|col| Series {
+                name: match text(range.start.row, col).trim() {
+                    name if has_header && !name.is_empty() => name.to_string(),
+                    _ => CellPosition::col_to_letter(col),
+                },
+                values: rows.iter().map(|&row| number(row, col)).collect(),
+            })
+            .collect();
+        series
+            .iter()
+            .any(|series| series.values.iter().any(Option::is_some))
+            .then_some(Self { categories, series })
+    }
+
+    /// The lowest and highest value on the y axis; bars always include zero
+    pub fn y_bounds(&self, kind: ChartKind) -> (f64, f64) {
+        let values = self.series.iter().flat_map(|series| series.values.iter().flatten().copied());
+        let (mut min, mut max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), n| (min.min(n), max.max(n)));
+        if kind == ChartKind::Bar {
+            min = min.min(0.0);
+            max = max.max(0.0);
+        }
+        if min == max { (min - 1.0, max + 1.0) } else { (min, max) }
+    }
+
+    /// x values for a scatter chart, from the category column where it holds numbers
+    fn x_values(&self) -> Vec<Option<f64>> {
+        self.categories.iter().map(|category| value::parse_number(category)).collect()
+    }
+
+    fn marks(&self, kind: ChartKind, width: f32, height: f32) -> Vec<Mark> {
+        let (min, max) = self.y_bounds(kind);
+        let y = |n: f64| height * (1.0 - ((n - min) / (max - min)) as f32);
+        let count = self.categories.len();
+        let mut marks: Vec<Mark> = (0..=4).map(|i| Mark::Gridline(height * i as f32 / 4.0)).collect();
+
+        match kind {
+            ChartKind::Line => {
+                let x = |i: usize| if count > 1 { width * i as f32 / (count - 1) as f32 } else { width / 2.0 };
+                for (index, series) in self.series.iter().enumerate() {
+                    let mut line = Vec::new();
+                    for (i, value) in series.values.iter().enumerate() {
+                        match value {
+                            Some(n) => line.push((x(i), y(*n))),
+                            None if !line.is_empty() => marks.push(Mark::Line(index, std::mem::take(&mut line))),
+                            None => {}
+                        }
+                    }
+                    if !line.is_empty() {
+                        marks.push(Mark::Line(index, line));
+                    }
+                }
+            }
+            ChartKind::Bar => {
+                let slot = width / count.max(1) as f32;
+                let bar_width = slot * 0.8 / self.series.len() as f32;
+                let zero = y(0.0);
+                for (index, series) in self.series.iter().enumerate() {
+                    for (i, value) in series.values.iter().enumerate() {
+                        let Some(n) = value else { continue };
+                        let left = slot * i as f32 + slot * 0.1 + bar_width * index as f32;
+                        let (top, bottom) = if *n < 0.0 { (zero, y(*n)) } else { (y(*n), zero) };
+                        marks.push(Mark::Bar(index, left, top, bar_width, (bottom - top).max(1.0)));
+                    }
+                }
+            }
+            ChartKind::Scatter => {
+                let xs = self.x_values();
+                let (x_min, x_max) = xs.iter().flatten().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &n| (min.min(n), max.max(n)));
+                let x = |n: f64| match x_max - x_min {
+                    range if range > 0.0 => width * ((n - x_min) / range) as f32,
+                    _ => width / 2.0,
+                };
+                for (index, series) in self.series.iter().enumerate() {
+                    for (value, x_value) in series.values.iter().zip(&xs) {
+                        if let (Some(n), Some(x_value)) = (value, x_value) {
+                            marks.push(Mark::Dot(index, x(*x_value), y(*n)));
+                        }
+                    }
+                }
+            }
+        }
+        marks
+    }
+
+    /// Draw the plot to fill `bounds`, series colored in turn from `palette`
+    pub fn paint(&self, kind: ChartKind, bounds: Bounds<Pixels>, palette: &[Rgba], grid_color: Rgba, window: &mut Window) {
+        let (left, top) = (f32::from(bounds.origin.x), f32::from(bounds.origin.y));
+        let at = |x: f32, y: f32| point(px(left + x), px(top + y));
+        let color = |index: usize| palette[index % palette.len()];
+        for mark in self.marks(kind, f32::from(bounds.size.width), f32::from(bounds.size.height)) {
+            match mark {
+                Mark::Gridline(y) => {
+                    window.paint_quad(fill(Bounds::new(at(0.0, y), size(bounds.size.width, px(1.0))), grid_color));
+                }
+                Mark::Line(index, points) => {
+                    let mut path = PathBuilder::stroke(px(2.0));
+                    for (i, &(x, y)) in points.iter().enumerate() {
+                        if i == 0 { path.move_to(at(x, y)) } else { path.line_to(at(x, y)) }
+                    }
+                    // A lone point has no line to draw; mark it instead
+                    if let [(x, y)] = points[..] {
+                        window.paint_quad(fill(Bounds::new(at(x - DOT_RADIUS, y - DOT_RADIUS), size(px(DOT_RADIUS * 2.0), px(DOT_RADIUS * 2.0))), color(index)));
+                    } else if let Ok(path) = path.build() {
+                        window.paint_path(path, color(index));
+                    }
+                }
+                Mark::Bar(index, x, y, width, height) => {
+                    window.paint_quad(fill(Bounds::new(at(x, y), size(px(width), px(height))), color(index)));
+                }
+                Mark::Dot(index, x, y) => {
+                    let dot = Bounds::new(at(x - DOT_RADIUS, y - DOT_RADIUS), size(px(DOT_RADIUS * 2.0), px(DOT_RADIUS * 2.0)));
+                    window.paint_quad(fill(dot, color(index)).corner_radii(px(DOT_RADIUS)));
+                }
+            }
+        }
+    }
+
+    /// Write the plot as a PNG image of the given size, on `background`
+    pub fn save_png(
+        &self,
+        kind: ChartKind,
+        path: &Path,
+        (width, height): (u32, u32),
+        palette: &[Rgba],
+        grid_color: Rgba,
+        background: Rgba,
+    ) -> Result<(), String> {
+        use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+        const MARGIN: f32 = 24.0;
+        let mut pixmap = Pixmap::new(width, height).ok_or("Image size can't be zero")?;
+        let color = |rgba: Rgba| tiny_skia::Color::from_rgba(rgba.r, rgba.g, rgba.b, rgba.a).unwrap_or(tiny_skia::Color::BLACK);
+        let paint = |rgba: Rgba| {
+            let mut paint = Paint::default();
+            paint.set_color(color(rgba));
+            paint.anti_alias = true;
+            paint
+        };
+        let series_paint = |index: usize| paint(palette[index % palette.len()]);
+        pixmap.fill(color(background));
+
+        let transform = Transform::from_translate(MARGIN, MARGIN);
+        let plot = (width as f32 - MARGIN * 2.0, height as f32 - MARGIN * 2.0);
+        for mark in self.marks(kind, plot.0, plot.1) {
+            match mark {
+                Mark::Gridline(y) => {
+                    if let Some(rect) = Rect::from_xywh(0.0, y, plot.0, 1.0) {
+                        pixmap.fill_rect(rect, &paint(grid_color), transform, None);
+                    }
+                }
+                Mark::Line(index, points) => {
+                    if let [(x, y)] = points[..] {
+                        if let Some(dot) = PathBuilder::from_circle(x, y, DOT_RADIUS) {
+                            pixmap.fill_path(&dot, &series_paint(index), FillRule::Winding, transform, None);
+                        }
+                        continue;
+                    }
+                    let mut path = PathBuilder::new();
+                    for (i, &(x, y)) in points.iter().enumerate() {
+                        if i == 0 { path.move_to(x, y) } else { path.line_to(x, y) }
+                    }
+                    if let Some(path) = path.finish() {
+                        let stroke = Stroke { width: 2.0, ..Stroke::default() };
+                        pixmap.stroke_path(&path, &series_paint(index), &stroke, transform, None);
+                    }
+                }
+                Mark::Bar(index, x, y, width, height) => {
+                    if let Some(rect) = Rect::from_xywh(x, y, width, height) {
+                        pixmap.fill_rect(rect, &series_paint(index), transform, None);
+                    }
+                }
+                Mark::Dot(index, x, y) => {
+                    if let Some(dot) = PathBuilder::from_circle(x, y, DOT_RADIUS) {
+                        pixmap.fill_path(&dot, &series_paint(index), FillRule::Winding, transform, None);
+                    }
+                }
+            }
+        }
+        pixmap.save_png(path).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use gpui::Global;
+
+use crate::command_palette::expand_home;
+use crate::file_io::{self, Format};
+use crate::formula;
+use crate::metadata::SpreadsheetMetadata;
+use crate::pipe;
+use crate::plugin::Registry;
+use crate::state::{CellPosition, GRID_COLS, GRID_ROWS};
+use crate::value::ColumnType;
+
+const CONVERT_USAGE: &str = "usage: zsheets convert <input> <output>";
+const EVAL_USAGE: &str = "usage: zsheets eval <sheet> [--cell <ref>]... [--recalc] [-o <output>]";
+const PIPE_OUT_USAGE: &str = "usage: zsheets --pipe-out <command>";
+
+/// Where the sheet goes as CSV when the app quits: `zsheets --stdout` prints it, and
+/// `zsheets --pipe-out <command>` pipes it into a shell command
+#[derive(Clone)]
+pub enum ExitOutput {
+    Stdout,
+    Pipe(String),
+}
+
+impl Global for ExitOutput {}
+
+impl ExitOutput {
+    /// The output the app was started with, if any
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut output = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--stdout" => output = Some(ExitOutput::Stdout),
+                "--pipe-out" => output = Some(ExitOutput::Pipe(args.next().ok_or(PIPE_OUT_USAGE)?.clone())),
+                _ => {}
+            }
+        }
+        Ok(output)
+    }
+
+    pub fn emit(&self, rows: &[Vec<String>]) {
+        let result = match self {
+            ExitOutput::Stdout => {
+                pipe::to_csv(rows).and_then(|csv| std::io::stdout().write_all(&csv).map_err(|e| e.to_string()))
+            }
+            ExitOutput::Pipe(command) => pipe::write(command, rows).map(|output| print!("{}", output)),
+        };
+        if let Err(e) = result {
+            eprintln!("zsheets: {}", e);
+        }
+    }
+}
+
+/// Run a subcommand given on the command line without opening a window, returning its exit
+/// code. `None` means there was no subcommand and the app should start as usual.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "convert" => convert(rest),
+        "eval" => eval(rest),
+        _ => return None,
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("zsheets: {}", e);
+            1
+        }
+    })
+}
+
+/// `zsheets convert in.csv out.xlsx`: formats come from the file extensions, and inputs a
+/// plugin imports are read through it
+fn convert(args: &[String]) -> Result<(), String> {
+    let [input, output] = args else {
+        return Err(CONVERT_USAGE.to_string());
+    };
+    let (input, output) = (expand_home(input), expand_home(output));
+    let output_format = format_of(&output)?;
+    let rows = read_input(&input)?;
+    file_io::write_rows(&output, &rows, output_format, &column_types(&input))
+        .map_err(|e| format!("{}: {}", output.display(), e))
+}
+
+/// `zsheets eval sheet.csv --cell D10` prints each named cell's value, one per line.
+/// `--recalc` writes the sheet with its formulas replaced by their values, to `-o` or as CSV
+/// on stdout.
+fn eval(args: &[String]) -> Result<(), String> {
+    let mut input = None;
+    let mut references = Vec::new();
+    let mut recalc = false;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cell" | "-c" => references.push(args.next().ok_or(EVAL_USAGE)?),
+            "--recalc" => recalc = true,
+            "--output" | "-o" => output = Some(expand_home(args.next().ok_or(EVAL_USAGE)?)),
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(expand_home(arg)),
+            _ => return Err(format!("unexpected argument {}\n{}", arg, EVAL_USAGE)),
+        }
+    }
+    let Some(input) = input else {
+        return Err(EVAL_USAGE.to_string());
+    };
+    if references.is_empty() && !recalc && output.is_none() {
+        return Err(EVAL_USAGE.to_string());
+    }
+    let positions = references
+        .iter()
+        .map(|reference| CellPosition::from_reference(reference).ok_or_else(|| format!("not a cell: {}", reference)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let rows = read_input(&input)?;
+    // Formulas see the sheet as the grid would hold it
+    let cells = file_io::fit_to_grid(rows.clone());
+    let computed = formula::evaluate_all(&cells);
+    let value_at = |row: usize, col: usize| match &computed[row][col] {
+        Some(value) => value.to_string(),
+        None => cells[row][col].clone(),
+    };
+
+    for position in positions {
+        println!("{}", value_at(position.row, position.col));
+    }
+    if recalc || output.is_some() {
+        // Past the grid's edges there are no formulas, so values are written as read
+        let values: Vec<Vec<String>> = rows
+            .iter()
+            .enumerate()
+            .map(|(row, values)| {
+                let in_grid = if row < GRID_ROWS { values.len().min(GRID_COLS) } else { 0 };
+                (0..in_grid).map(|col| value_at(row, col)).chain(values[in_grid..].iter().cloned()).collect()
+            })
+            .collect();
+        match output {
+            Some(output) => file_io::write_rows(&output, &values, format_of(&output)?, &column_types(&input))
+                .map_err(|e| format!("{}: {}", output.display(), e))?,
+            None => {
+                let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(std::io::stdout());
+                for row in &values {
+                    writer.write_record(row).map_err(|e| e.to_string())?;
+                }
+                writer.flush().map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A file's rows, read through a plugin importer if one claims it
+fn read_input(path: &Path) -> Result<Vec<Vec<String>>, String> {
+    match Registry::get().import(path) {
+        Some(rows) => rows,
+        None => file_io::read_rows(path, format_of(path)?),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The column types set on a CSV or TSV file with `:type`, kept in its metadata
+fn column_types(path: &Path) -> BTreeMap<usize, ColumnType> {
+    match Format::from_path(path) {
+        Some(Format::Csv | Format::Tsv) => SpreadsheetMetadata::load(path)
+            .ok()
+            .and_then(|metadata| metadata.column_types)
+            .unwrap_or_default(),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn format_of(path: &Path) -> Result<Format, String> {
+    Format::from_path(path).ok_or_else(|| format!("{}: unknown format (use .csv, .tsv, .json, .xlsx, or .parquet)", path.display()))
+}
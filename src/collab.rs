@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::{CellPosition, GRID_COLS, GRID_ROWS};
+
+/// Port `:share` listens on unless given one
+pub const DEFAULT_PORT: u16 = 7455;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a connection to the host has to give the share code before it's dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// When a cell was last set, and by which instance. Every instance orders stamps the same
+/// way, so applying the latest one per cell leaves both sheets equal whatever order edits
+/// arrive in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Stamp {
+    /// Lamport clock: one past the latest stamp this instance had seen
+    clock: u64,
+    site: u64,
+}
+
+/// Sent one per line as JSON
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    /// The share code, which a joining peer sends first
+    Hello { code: String },
+    /// The host's whole sheet, sent when a peer has given the share code
+    Sheet { cells: Vec<Vec<String>>, stamps: Vec<(usize, usize, Stamp)> },
+    Set { row: usize, col: usize, value: String, stamp: Stamp },
+    Cursor { row: usize, col: usize },
+}
+
+/// What happened during a poll
+pub enum Event {
+    Joined(SocketAddr),
+    /// A connection that didn't give the share code was dropped
+    Refused(SocketAddr),
+    /// The host's sheet replaced this one
+    Synced,
+    /// Cells changed by the peer
+    Changed,
+    Left,
+}
+
+struct Peer {
+    stream: TcpStream,
+    addr: SocketAddr,
+    /// Bytes read but not yet making up a whole line
+    incoming: Vec<u8>,
+    /// Bytes not yet accepted by the socket
+    outgoing: Vec<u8>,
+    /// Whether it has given the share code; the host is trusted by whoever joins it
+    verified: bool,
+    connected: Instant,
+}
+
+impl Peer {
+    fn new(stream: TcpStream, verified: bool) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        let addr = stream.peer_addr()?;
+        Ok(Self { stream, addr, incoming: Vec::new(), outgoing: Vec::new(), verified, connected: Instant::now() })
+    }
+
+    fn send(&mut self, message: &Message) {
+        if let Ok(json) = serde_json::to_vec(message) {
+            self.outgoing.extend(json);
+            self.outgoing.push(b'\n');
+        }
+    }
+
+    /// Write what the socket takes without blocking
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages received since last time; an error once the peer has gone
+    fn receive(&mut self) -> io::Result<Vec<Message>> {
+        let mut buffer = [0; 16 * 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut messages = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            match serde_json::from_slice(&line) {
+                Ok(message) => messages.push(message),
+                Err(e) => eprintln!("Ignoring malformed collaboration message: {}", e),
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// A sheet shared with one other instance on the network: hosted with `:share`, or joined
+/// with `:join` and the host's share code. The connection isn't encrypted.
+pub struct Session {
+    /// Listening for a peer, when hosting
+    listener: Option<TcpListener>,
+    /// What a peer must send before it sees or edits the sheet, when hosting
+    code: Option<String>,
+    peer: Option<Peer>,
+    site: u64,
+    clock: u64,
+    stamps: HashMap<(usize, usize), Stamp>,
+    /// The cells as last synced, to find local edits since
+    cells: Vec<Vec<String>>,
+    /// Where the peer's cursor is
+    pub remote_cursor: Option<CellPosition>,
+    sent_cursor: Option<CellPosition>,
+}
+
+impl Session {
+    fn new(listener: Option<TcpListener>, code: Option<String>, peer: Option<Peer>, cells: &[Vec<String>]) -> Self {
+        // Only needs to differ between the two instances
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Self {
+            listener,
+            code,
+            peer,
+            site: nanos ^ (u64::from(std::process::id()) << 32),
+            clock: 0,
+            stamps: HashMap::new(),
+            cells: cells.to_vec(),
+            remote_cursor: None,
+            sent_cursor: None,
+        }
+    }
+
+    /// Share `cells`, waiting on `port` of every interface for a peer that gives the share code
+    pub fn host(port: u16, cells: &[Vec<String>]) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(Some(listener), Some(new_code()), None, cells))
+    }
+
+    /// Connect to a shared sheet at `address` (`host:port`, or just `host` for the default
+    /// port) with the share code it was started with
+    pub fn join(address: &str, code: &str, cells: &[Vec<String>]) -> io::Result<Self> {
+        let address = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, DEFAULT_PORT) };
+        let addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("No address for {}", address)))?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        let mut peer = Peer::new(stream, true)?;
+        peer.send(&Message::Hello { code: code.to_string() });
+        Ok(Self::new(None, None, Some(peer), cells))
+    }
+
+    pub fn is_hosting(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// The port being listened on, when hosting
+    pub fn port(&self) -> Option<u16> {
+        self.listener.as_ref().and_then(|l| l.local_addr().ok()).map(|addr| addr.port())
+    }
+
+    /// The code a peer must give to join, when hosting
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// Exchange edits with the peer: send cells changed in `cells` since last time and the
+    /// cursor, then apply what the peer sent to `cells`
+    pub fn poll(&mut self, cells: &mut [Vec<String>], cursor: CellPosition) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.accept();
+        self.send_changes(cells, cursor);
+
+        let Some(peer) = &mut self.peer else {
+            return events;
+        };
+        let received = peer.flush().and_then(|()| peer.receive());
+        let mut messages = match received {
+            Ok(messages) => messages,
+            // Gone before giving the code
+            Err(_) if !peer.verified => {
+                self.peer = None;
+                return events;
+            }
+            Err(_) => {
+                self.peer = None;
+                self.remote_cursor = None;
+                self.sent_cursor = None;
+                events.push(Event::Left);
+                return events;
+            }
+        };
+        if !peer.verified {
+            if messages.is_empty() {
+                if peer.connected.elapsed() > HANDSHAKE_TIMEOUT {
+                    events.push(Event::Refused(peer.addr));
+                    self.peer = None;
+                }
+                return events;
+            }
+            match messages.remove(0) {
+                Message::Hello { code } if self.code.as_deref().is_some_and(|expected| same_code(expected, &code)) => {
+                    peer.verified = true;
+                    // Edits made while waiting go out in the sheet itself
+                    self.cells = cells.to_vec();
+                    let stamps = self.stamps.iter().map(|(&(row, col), &stamp)| (row, col, stamp)).collect();
+                    peer.send(&Message::Sheet { cells: self.cells.clone(), stamps });
+                    events.push(Event::Joined(peer.addr));
+                }
+                _ => {
+                    events.push(Event::Refused(peer.addr));
+                    self.peer = None;
+                    return events;
+                }
+            }
+        }
+        let mut changed = false;
+        for message in messages {
+            match message {
+                Message::Hello { .. } => {}
+                Message::Sheet { cells: sheet, stamps } => {
+                    for (row, values) in cells.iter_mut().enumerate() {
+                        for (col, cell) in values.iter_mut().enumerate() {
+                            let value = sheet.get(row).and_then(|values| values.get(col));
+                            *cell = value.cloned().unwrap_or_default();
+                        }
+                    }
+                    self.cells = cells.to_vec();
+                    self.stamps = stamps.into_iter().map(|(row, col, stamp)| ((row, col), stamp)).collect();
+                    self.clock = self.stamps.values().map(|stamp| stamp.clock).max().unwrap_or(0);
+                    events.push(Event::Synced);
+                }
+                Message::Set { row, col, value, stamp } => {
+                    self.clock = self.clock.max(stamp.clock);
+                    if row >= GRID_ROWS || col >= GRID_COLS {
+                        continue;
+                    }
+                    if self.stamps.get(&(row, col)).is_none_or(|&current| stamp > current) {
+                        self.stamps.insert((row, col), stamp);
+                        cells[row][col].clone_from(&value);
+                        self.cells[row][col] = value;
+                        changed = true;
+                    }
+                }
+                Message::Cursor { row, col } => {
+                    self.remote_cursor = (row < GRID_ROWS && col < GRID_COLS).then(|| CellPosition::new(row, col));
+                }
+            }
+        }
+        if changed {
+            events.push(Event::Changed);
+        }
+        events
+    }
+
+    /// Take a waiting peer when hosting and none is connected. It's sent the sheet once it
+    /// gives the share code.
+    fn accept(&mut self) {
+        let (Some(listener), None) = (&self.listener, &self.peer) else {
+            return;
+        };
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+            Err(e) => {
+                eprintln!("Failed to accept collaborator: {}", e);
+                return;
+            }
+        };
+        match Peer::new(stream, false) {
+            Ok(peer) => self.peer = Some(peer),
+            Err(e) => eprintln!("Failed to accept collaborator: {}", e),
+        }
+    }
+
+    /// Stamp every cell that differs from the last sync, and queue them and the cursor for the peer
+    fn send_changes(&mut self, cells: &[Vec<String>], cursor: CellPosition) {
+        for (row, (old_row, new_row)) in self.cells.iter_mut().zip(cells).enumerate() {
+            if old_row == new_row {
+                continue;
+            }
+            for (col, (old, new)) in old_row.iter_mut().zip(new_row).enumerate() {
+                if old == new {
+                    continue;
+                }
+                old.clone_from(new);
+                self.clock += 1;
+                let stamp = Stamp { clock: self.clock, site: self.site };
+                self.stamps.insert((row, col), stamp);
+                if let Some(peer) = self.peer.as_mut().filter(|peer| peer.verified) {
+                    peer.send(&Message::Set { row, col, value: new.clone(), stamp });
+                }
+            }
+        }
+        if let Some(peer) = self.peer.as_mut().filter(|peer| peer.verified)
+            && self.sent_cursor != Some(cursor)
+        {
+            peer.send(&Message::Cursor { row: cursor.row, col: cursor.col });
+            self.sent_cursor = Some(cursor);
+        }
+    }
+}
+
+/// A share code: 64 bits from the standard library's randomly keyed hasher, in hex
+fn new_code() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+/// Compare share codes in time that doesn't depend on where they differ
+fn same_code(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
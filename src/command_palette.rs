@@ -1,9 +1,33 @@
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use gpui::prelude::FluentBuilder;
 use gpui::*;
 
+use crate::cell::CursorBlink;
+use crate::chart::ChartKind;
+use crate::date::DateFormat;
+use crate::delimiter::Delimiter;
+use crate::filter::FilterCondition;
+use crate::formula::CalcMode;
+use crate::generate::Generator;
+use crate::grid::{
+    ClearAll, ClearContents, ClearFormats, CloseFile, CollapseAllGroups, Crosshair, ExpandAllGroups, FormatCurrency, FormatPercent, GoToCell, MoveColumnLeft,
+    MoveColumnRight, MoveRowDown, MoveRowUp, NewFile, NewTab, NextTab, OpenFile, PasteTransposed, PreviousTab, Quit, Recalculate, RevertToSaved, SaveFile, SaveFileAs,
+    SelectAllCells, SelectDataBlock, ToggleBold, ToggleGroup, ToggleItalic, ToggleUnderline,
+};
+use crate::file_io::{LineEnding, Quoting};
+use crate::menu;
+use crate::number_format::NumberFormat;
+use crate::pivot::PivotRequest;
+use crate::plugin::Registry;
+use crate::sizing::{self, WidthLimits};
+use crate::source::DataSource;
+use crate::state::{CellPosition, CellRange, GRID_COLS, GRID_ROWS};
+use crate::style::{self, Alignment, StyleChange};
+use crate::value::ColumnType;
 use crate::Theme;
 
 actions!(
@@ -14,6 +38,7 @@ actions!(
         SelectNext,
         SelectPrevious,
         Confirm,
+        AcceptPathCompletion,
     ]
 );
 
@@ -22,8 +47,22 @@ actions!(
 pub struct Command {
     pub id: &'static str,
     pub name: &'static str,
-    pub shortcut: Option<&'static str>,
+    /// The action whose key binding is shown as the command's shortcut
+    pub action: Option<fn() -> Box<dyn Action>>,
     pub vim_alias: Option<&'static str>,
+    /// Ask for an argument after the command is chosen, instead of running it straight away
+    pub prompt: Option<Prompt>,
+}
+
+/// A follow-up input for a command that takes an argument
+#[derive(Clone, Copy, Debug)]
+pub struct Prompt {
+    /// Shown in place of the `>` before the input, e.g. `Sort by column`
+    pub label: &'static str,
+    /// The ex command the answer is appended to, e.g. `:sort`
+    pub command: &'static str,
+    /// Hide the answer as it's typed and keep it out of the history
+    pub secret: bool,
 }
 
 impl Command {
@@ -31,20 +70,40 @@ impl Command {
         Self {
             id,
             name,
-            shortcut: None,
+            action: None,
             vim_alias: None,
+            prompt: None,
         }
     }
 
-    pub const fn with_shortcut(mut self, shortcut: &'static str) -> Self {
-        self.shortcut = Some(shortcut);
+    pub const fn with_action(mut self, action: fn() -> Box<dyn Action>) -> Self {
+        self.action = Some(action);
         self
     }
 
+    /// The shortcut currently bound to the command's action in the grid, e.g. `⌘N`,
+    /// so custom keymaps show up as they are
+    pub fn shortcut(&self, window: &Window) -> Option<String> {
+        let action = (self.action?)();
+        let binding = window.highest_precedence_binding_for_action_in_context(action.as_ref(), KeyContext::parse("NormalMode").ok()?)?;
+        let keystrokes: Vec<String> = binding.keystrokes().iter().map(|keystroke| keystroke.to_string()).collect();
+        Some(keystrokes.join(" "))
+    }
+
     pub const fn with_vim(mut self, alias: &'static str) -> Self {
         self.vim_alias = Some(alias);
         self
     }
+
+    pub const fn with_prompt(mut self, label: &'static str, command: &'static str) -> Self {
+        self.prompt = Some(Prompt { label, command, secret: false });
+        self
+    }
+
+    pub const fn with_secret_prompt(mut self, label: &'static str, command: &'static str) -> Self {
+        self.prompt = Some(Prompt { label, command, secret: true });
+        self
+    }
 }
 
 /// Result of parsing a vim command
@@ -54,6 +113,8 @@ pub enum VimCommand {
     Write,
     /// :w <path> - save to path
     WriteTo(PathBuf),
+    /// :w !<command> - pipe the sheet as CSV into a shell command
+    WritePipe(String),
     /// :w! - force write (ignores read-only)
     ForceWrite,
     /// :wq - write and quit
@@ -66,10 +127,18 @@ pub enum VimCommand {
     Edit(PathBuf),
     /// :view <path> or :vi <path> - open file read-only
     View(PathBuf),
+    /// :mmap <path> - view a large CSV file read-only from a memory map
+    Mmap(PathBuf),
     /// :saveas <path> - save as
     SaveAs(PathBuf),
     /// :new - new file
     New,
+    /// :tabnew [path] or :tabe <path> - open an empty grid, or a file, in a new tab
+    TabNew(Option<PathBuf>),
+    /// :bnext or :tabnext - switch to the next tab
+    NextTab,
+    /// :bprev or :tabprev - switch to the previous tab
+    PreviousTab,
     /// :autofit - auto-fit all columns and rows
     AutoFitAll,
     /// :autofit col - auto-fit current column
@@ -84,6 +153,333 @@ pub enum VimCommand {
     AutoFitRowWatch,
     /// :resetsize - reset all column widths and row heights to defaults
     ResetAllSizes,
+    /// :colwidth min=<px> max=<px> - bound the selected columns' widths (either may be omitted);
+    /// `:colwidth none` removes the bounds
+    WidthLimits(Option<WidthLimits>),
+    /// :freeze <rows> [cols] - freeze the top rows and left columns
+    Freeze { rows: usize, cols: usize },
+    /// :freeze - freeze rows above and columns left of the cursor
+    FreezeAtCursor,
+    /// :unfreeze - remove frozen panes
+    Unfreeze,
+    /// :group rows <start>-<end> - group rows into a collapsible outline group (1-based)
+    GroupRows(usize, usize),
+    /// :group cols <start>-<end> - group columns into a collapsible outline group (letters)
+    GroupCols(usize, usize),
+    /// :ungroup rows - remove the row group containing the cursor
+    UngroupRows,
+    /// :ungroup cols - remove the column group containing the cursor
+    UngroupCols,
+    /// :audit - show or hide the panel of recorded cell changes
+    ToggleAuditPanel,
+    /// :audit export <path> - write the recorded cell changes to a CSV file
+    ExportAudit(PathBuf),
+    /// :snapshot take <name> - keep a copy of the sheet in the history directory
+    TakeSnapshot(String),
+    /// :snapshot - pick a snapshot to preview beside the grid
+    BrowseSnapshots,
+    /// :snapshot preview <snapshot> - show a snapshot beside the grid
+    PreviewSnapshot(String),
+    /// :snapshot restore [snapshot] - put a snapshot back, by default the one previewed
+    RestoreSnapshot(Option<String>),
+    /// :clear [contents|formats|all] - empty the selected cells, reset their formatting, or both
+    Clear { contents: bool, formats: bool },
+    /// :insrow [n] - insert `n` rows (or a count typed before `:`, or as many as are selected)
+    /// above the selection
+    InsertRows(Option<usize>),
+    /// :inscol [n] - insert columns left of the selection, counted as for `:insrow`
+    InsertCols(Option<usize>),
+    /// :delrow [n] - delete `n` rows from the top of the selection, or the selected rows
+    DeleteRows(Option<usize>),
+    /// :delcol [n] - delete `n` columns from the left of the selection, or the selected columns
+    DeleteCols(Option<usize>),
+    /// :moverow <start>[-<end>] to <row> - move rows (1-based) so the first lands at `row`
+    MoveRows { start: usize, end: usize, to: usize },
+    /// :movecol <start>[:<end>] to <col> - move columns (letters) so the first lands at `col`
+    MoveCols { start: usize, end: usize, to: usize },
+    /// :header - toggle the cursor row as the header row
+    ToggleHeaderRow,
+    /// :header <row> - designate a row (1-based) as the header row
+    SetHeaderRow(usize),
+    /// :noheader - clear the header row
+    ClearHeaderRow,
+    /// :sort [column] / :sort! [column] - sort rows by a column (header label or letters),
+    /// defaulting to the cursor's column
+    Sort { column: Option<String>, descending: bool },
+    /// :filter - open the cursor column's filter dropdown
+    OpenFilterMenu,
+    /// :filter <condition> - filter the cursor's column (contains, >, <, =, empty, nonempty)
+    Filter(FilterCondition),
+    /// :filter <expression> - hide rows not matching e.g. `C > 100 && A contains "foo"`.
+    /// Kept as text since column names may be header labels, which only the grid can resolve.
+    FilterExpression(String),
+    /// :autofilter - toggle filter buttons on the column headers
+    ToggleFilterButtons,
+    /// :nofilter - clear all column filters and filter expressions
+    ClearFilters,
+    /// :stats - count, distinct, empty, and numeric min/max/mean of the cursor's column
+    ColumnStats,
+    /// :profile - toggle the side panel summarizing every used column
+    ToggleProfile,
+    /// :pivot rows=<cols> [cols=<cols>] [sum|count|avg=<col>] - summarize the selection, or the
+    /// data around the cursor, into a pivot table in a new tab
+    Pivot(PivotRequest),
+    /// :pivot refresh - rebuild this tab's pivot table from its source
+    RefreshPivot,
+    /// :chart [line|bar|scatter] - float a chart of the selection, or of the data around the
+    /// cursor, over the grid
+    InsertChart(ChartKind),
+    /// :chart export [path] - save the newest chart as a PNG image, asking where if no path
+    ExportChart(Option<PathBuf>),
+    /// :nochart - close every chart
+    CloseCharts,
+    /// :tail / :notail - start or stop appending rows another process writes to the open file
+    Tail(bool),
+    /// :share [port] - let another instance on the network join and edit this sheet (experimental)
+    Share(Option<u16>),
+    /// :join <host[:port]> <code> - edit a sheet shared by another instance, with the code it
+    /// showed when sharing
+    Join { address: String, code: String },
+    /// :unshare - end the shared session
+    Unshare,
+    /// :gen <seq|rand|date|name> [args] - fill the selection with generated test data
+    Generate(Generator),
+    /// :script <name> - run `<name>.rhai` from the scripts directory; without a name, list them
+    RunScript(Option<String>),
+    /// :source <url|json|cmd|sql> <spec> - fill the grid from a data source at the cursor and
+    /// keep the region bound to it; `:json <url> [path]` is short for `:source json ...`
+    BindSource(DataSource),
+    /// :refresh - re-fetch every bound data source
+    RefreshSources,
+    /// :nosource - unbind the data source under the cursor, keeping its data
+    UnbindSource,
+    /// :<name> - a command a plugin registered under that name, by its id
+    Plugin(&'static str),
+    /// :transpose [target] - swap rows and columns of the selection, in place or into `target`
+    Transpose(Option<CellPosition>),
+    /// :goto <cell> - move the cursor to a cell, e.g. `:goto B12`
+    Goto(CellPosition),
+    /// :<range><command> - an ex command over rows, e.g. `:5,20d`, `:'<,'>fill`, or `:%!sort`
+    Ranged { range: ExRange, command: RangeCommand },
+    /// :split <delimiter> [overwrite] - text to columns: split the cursor column's cells into
+    /// adjacent columns, inserting columns unless `overwrite` is given
+    TextToColumns { delimiter: Delimiter, overwrite: bool },
+    /// :wrap / :nowrap - turn word wrap on or off for the selected columns
+    SetWrap(bool),
+    /// :bold, :italic, :underline - toggle text styling on the selection;
+    /// :color <name|#hex> / :color none - set or clear the selection's text color
+    Style(StyleChange),
+    /// :format <pattern> - number format for the selected cells, e.g. `0.00`, `#,##0`,
+    /// `$#,##0.00`, `0%`, `0.00E+00`; `:format col <pattern>` sets it for the selected columns.
+    /// `general` clears the format.
+    Format { format: Option<NumberFormat>, column: bool },
+    /// :dateformat <pattern> - how date cells display in the selected columns, e.g.
+    /// `YYYY-MM-DD`, `MMM D, YYYY`, or a preset (`iso`, `us`, `eu`, `uk`, `long`); `none` clears
+    DateFormat(Option<DateFormat>),
+    /// :align left|center|right - override alignment for the selected columns;
+    /// `:align auto` restores numbers right, text left
+    Align(Option<Alignment>),
+    /// :type integer|float|date|boolean|text - set how the selected columns align, sort, and
+    /// compare in filters; `:type auto` goes back to the type inferred on load
+    SetColumnType(Option<ColumnType>),
+    /// :type - show the cursor column's type
+    ShowColumnType,
+    /// :validate [col] list a,b,c | range <min> <max> | regex <pattern> - attach validation to
+    /// the selected cells (or columns with `col`); `:validate!` also rejects invalid input.
+    /// Kept as text so the grid can report parse errors.
+    Validate { spec: String, reject: bool, column: bool },
+    /// :novalidate [col] - remove validation from the selected cells (or columns)
+    ClearValidation { column: bool },
+    /// :protect [range] - lock a range (or the selection) so edit mode won't open on it
+    Protect(Option<CellRange>),
+    /// :unprotect [range|all] - unlock protected ranges overlapping a range (or the selection)
+    Unprotect(Option<CellRange>),
+    /// :diffsaved - toggle highlighting cells that differ from the saved file
+    DiffSaved,
+    /// :diffoff - stop highlighting differences
+    DiffOff,
+    /// :revert - restore the selected cells to their saved contents
+    Revert,
+    /// :compare <path> [key=<col>] - show another file beside the grid, rows paired by a key
+    /// column or by position, and highlight the cells that differ
+    Compare { path: PathBuf, key: Option<String> },
+    /// :compare off or :nocompare - close the comparison
+    NoCompare,
+    /// :set <option>=<value> - change a setting
+    Set(Setting),
+    /// :calc - recompute all formulas (needed in manual calculation mode)
+    Recalculate,
+    /// :passphrase <text> - answer the passphrase prompt, to open an encrypted file or
+    /// encrypt this one
+    Passphrase(String),
+}
+
+/// Options changed with `:set`
+#[derive(Clone, Copy, Debug)]
+pub enum Setting {
+    /// calc=auto|manual
+    Calculation(CalcMode),
+    /// refresh=<seconds>|off - how often volatile functions (NOW, TODAY, RAND) re-evaluate
+    Refresh(Option<Duration>),
+    /// toolbar=on|off - button row under the header
+    Toolbar(bool),
+    /// autofitmax=<px>|off - widest auto-fit may make a column
+    AutoFitMax(Option<f32>),
+    /// defaultcolwidth=<px> - width of this file's columns that haven't been resized
+    DefaultColWidth(f32),
+    /// defaultrowheight=<px> - height of this file's rows that haven't been resized
+    DefaultRowHeight(f32),
+    /// embed=on|off - save layout in the file's first line instead of a `.zsheets` sidecar
+    EmbedMetadata(bool),
+    /// protection=on|off - whether protected ranges refuse edit mode
+    Protection(bool),
+    /// remember_geometry[=on|off] or noremember_geometry - reopen the window where it was left
+    RememberGeometry(bool),
+    /// expandselected=on|off - show the cursor cell's whole content when it doesn't fit
+    ExpandSelected(bool),
+    /// gridlines=on|off - borders between cells
+    Gridlines(bool),
+    /// zebra=on|off - shade every other row
+    Zebra(bool),
+    /// cursor=fade|blink|steady - how the editing cursor animates
+    CursorBlink(CursorBlink),
+    /// crosshair=off|row|col|both - highlight the cursor's row and/or column
+    Crosshair(Crosshair),
+    /// scrollspeed=<n> - rows or columns one mouse wheel notch scrolls
+    ScrollSpeed(usize),
+    /// autocomplete=on|off - offer to finish typed text with a value from the same column
+    Autocomplete(bool),
+    /// quote=minimal|always|never - which fields are quoted when the file is saved
+    Quoting(Quoting),
+    /// eol=lf|crlf - line endings written on save
+    LineEnding(LineEnding),
+    /// finalnewline=on|off - end the file with a line ending
+    FinalNewline(bool),
+    /// padrows=on|off - write every row out to the last used column
+    PadRows(bool),
+    /// appendonly=on|off - save by appending rows added since the last save
+    AppendOnly(bool),
+    /// memstats=on|off - show roughly how much memory the cells take in the footer
+    MemoryStats(bool),
+    /// perf[=on|off] or noperf - overlay frame time, formula time, and cells drawn
+    Perf(bool),
+    /// encrypt or noencrypt - save the file encrypted with a passphrase
+    Encrypt(bool),
+    /// audit[=on|off] or noaudit - record every cell change to the file's audit log
+    Audit(bool),
+}
+
+impl Setting {
+    fn parse(input: &str) -> Option<Self> {
+        // Boolean options also take vim's bare `name` / `noname` forms
+        match input.trim() {
+            "remember_geometry" => return Some(Setting::RememberGeometry(true)),
+            "noremember_geometry" => return Some(Setting::RememberGeometry(false)),
+            "perf" => return Some(Setting::Perf(true)),
+            "noperf" => return Some(Setting::Perf(false)),
+            "encrypt" => return Some(Setting::Encrypt(true)),
+            "noencrypt" => return Some(Setting::Encrypt(false)),
+            "audit" => return Some(Setting::Audit(true)),
+            "noaudit" => return Some(Setting::Audit(false)),
+            _ => {}
+        }
+        let (name, value) = input.split_once('=')?;
+        match name.trim() {
+            "calc" | "calculation" => Some(Setting::Calculation(CalcMode::parse(value)?)),
+            "refresh" => match value.trim() {
+                "off" | "0" => Some(Setting::Refresh(None)),
+                seconds => {
+                    let seconds: f64 = seconds.parse().ok()?;
+                    (seconds > 0.0).then(|| Setting::Refresh(Some(Duration::from_secs_f64(seconds))))
+                }
+            },
+            "toolbar" => match value.trim() {
+                "on" => Some(Setting::Toolbar(true)),
+                "off" => Some(Setting::Toolbar(false)),
+                _ => None,
+            },
+            "autofitmax" => match value.trim() {
+                "off" | "none" => Some(Setting::AutoFitMax(None)),
+                width => Some(Setting::AutoFitMax(Some(sizing::parse_width(width)?))),
+            },
+            "defaultcolwidth" => Some(Setting::DefaultColWidth(sizing::parse_width(value)?)),
+            "defaultrowheight" => Some(Setting::DefaultRowHeight(sizing::parse_width(value)?)),
+            "embed" => match value.trim() {
+                "on" => Some(Setting::EmbedMetadata(true)),
+                "off" => Some(Setting::EmbedMetadata(false)),
+                _ => None,
+            },
+            "protection" => match value.trim() {
+                "on" => Some(Setting::Protection(true)),
+                "off" => Some(Setting::Protection(false)),
+                _ => None,
+            },
+            "autocomplete" => match value.trim() {
+                "on" => Some(Setting::Autocomplete(true)),
+                "off" => Some(Setting::Autocomplete(false)),
+                _ => None,
+            },
+            "quote" | "quoting" => Some(Setting::Quoting(Quoting::parse(value)?)),
+            "eol" | "lineending" => Some(Setting::LineEnding(LineEnding::parse(value)?)),
+            "finalnewline" => match value.trim() {
+                "on" => Some(Setting::FinalNewline(true)),
+                "off" => Some(Setting::FinalNewline(false)),
+                _ => None,
+            },
+            "perf" => match value.trim() {
+                "on" => Some(Setting::Perf(true)),
+                "off" => Some(Setting::Perf(false)),
+                _ => None,
+            },
+            "audit" => match value.trim() {
+                "on" => Some(Setting::Audit(true)),
+                "off" => Some(Setting::Audit(false)),
+                _ => None,
+            },
+            "memstats" => match value.trim() {
+                "on" => Some(Setting::MemoryStats(true)),
+                "off" => Some(Setting::MemoryStats(false)),
+                _ => None,
+            },
+            "appendonly" => match value.trim() {
+                "on" => Some(Setting::AppendOnly(true)),
+                "off" => Some(Setting::AppendOnly(false)),
+                _ => None,
+            },
+            "padrows" => match value.trim() {
+                "on" => Some(Setting::PadRows(true)),
+                "off" => Some(Setting::PadRows(false)),
+                _ => None,
+            },
+            "zebra" => match value.trim() {
+                "on" => Some(Setting::Zebra(true)),
+                "off" => Some(Setting::Zebra(false)),
+                _ => None,
+            },
+            "crosshair" => Some(Setting::Crosshair(Crosshair::parse(value)?)),
+            "scrollspeed" => {
+                let speed: usize = value.trim().parse().ok()?;
+                (speed > 0).then_some(Setting::ScrollSpeed(speed))
+            }
+            "cursor" => Some(Setting::CursorBlink(CursorBlink::parse(value)?)),
+            "gridlines" => match value.trim() {
+                "on" => Some(Setting::Gridlines(true)),
+                "off" => Some(Setting::Gridlines(false)),
+                _ => None,
+            },
+            "expandselected" => match value.trim() {
+                "on" => Some(Setting::ExpandSelected(true)),
+                "off" => Some(Setting::ExpandSelected(false)),
+                _ => None,
+            },
+            "remember_geometry" => match value.trim() {
+                "on" => Some(Setting::RememberGeometry(true)),
+                "off" => Some(Setting::RememberGeometry(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl VimCommand {
@@ -94,6 +490,11 @@ impl VimCommand {
         }
 
         let input = &input[1..]; // Remove leading ':'
+
+        // A leading range, e.g. `:5,20d`
+        if let Some((range, rest)) = ExRange::parse(input) {
+            return Some(VimCommand::Ranged { range, command: RangeCommand::parse(rest)? });
+        }
         let parts: Vec<&str> = input.splitn(3, ' ').collect();
         let cmd = parts[0];
         let arg = parts.get(1).map(|s| s.trim());
@@ -101,15 +502,27 @@ impl VimCommand {
 
         match cmd {
             "w" if arg.is_none() => Some(VimCommand::Write),
-            "w" if arg.is_some() => Some(VimCommand::WriteTo(PathBuf::from(arg.unwrap()))),
+            "w" if arg.is_some_and(|arg| arg.starts_with('!')) => {
+                let command = input[cmd.len()..].trim().strip_prefix('!')?.trim();
+                Some(VimCommand::WritePipe(command.to_string())).filter(|_| !command.is_empty())
+            }
+            "w" if arg.is_some() => Some(VimCommand::WriteTo(expand_home(arg.unwrap()))),
             "w!" => Some(VimCommand::ForceWrite),
             "wq" => Some(VimCommand::WriteQuit),
             "q" => Some(VimCommand::Quit),
             "q!" => Some(VimCommand::ForceQuit),
-            "e" | "edit" if arg.is_some() => Some(VimCommand::Edit(PathBuf::from(arg.unwrap()))),
-            "vi" | "view" if arg.is_some() => Some(VimCommand::View(PathBuf::from(arg.unwrap()))),
-            "saveas" if arg.is_some() => Some(VimCommand::SaveAs(PathBuf::from(arg.unwrap()))),
+            "e" | "edit" if arg.is_some() => Some(VimCommand::Edit(expand_home(arg.unwrap()))),
+            "vi" | "view" if arg.is_some() => Some(VimCommand::View(expand_home(arg.unwrap()))),
+            // Taken whole, spaces and all
+            "passphrase" if arg.is_some() => Some(VimCommand::Passphrase(input[cmd.len() + 1..].to_string())),
+            "mmap" if arg.is_some() => Some(VimCommand::Mmap(expand_home(arg.unwrap()))),
+            "saveas" if arg.is_some() => Some(VimCommand::SaveAs(expand_home(arg.unwrap()))),
             "new" => Some(VimCommand::New),
+            "tabnew" | "tabe" | "tabedit" => Some(VimCommand::TabNew(arg.map(expand_home))),
+            "bn" | "bnext" | "tabn" | "tabnext" => Some(VimCommand::NextTab),
+            "bp" | "bprev" | "bprevious" | "bN" | "bNext" | "tabp" | "tabprev" | "tabprevious" => {
+                Some(VimCommand::PreviousTab)
+            }
             // Auto-fit commands
             "autofit" if arg.is_none() => Some(VimCommand::AutoFitAll),
             "autofit" if arg == Some("col") && arg2.is_none() => Some(VimCommand::AutoFitColumn),
@@ -118,43 +531,740 @@ impl VimCommand {
             "autofit" if arg == Some("col") && arg2 == Some("watch") => Some(VimCommand::AutoFitColumnWatch),
             "autofit" if arg == Some("row") && arg2 == Some("watch") => Some(VimCommand::AutoFitRowWatch),
             "resetsize" => Some(VimCommand::ResetAllSizes),
+            "colwidth" if arg == Some("none") => Some(VimCommand::WidthLimits(None)),
+            "colwidth" => Some(VimCommand::WidthLimits(Some(WidthLimits::parse(input[cmd.len()..].trim())?))),
+            // Frozen panes
+            "freeze" if arg.is_none() => Some(VimCommand::FreezeAtCursor),
+            "freeze" => {
+                let rows = arg?.parse().ok()?;
+                let cols = match arg2 {
+                    Some(cols) => cols.parse().ok()?,
+                    None => 0,
+                };
+                Some(VimCommand::Freeze { rows, cols })
+            }
+            "unfreeze" => Some(VimCommand::Unfreeze),
+            // Outline groups
+            "group" if matches!(arg, Some("rows" | "row")) => {
+                let (start, end) = parse_span(arg2?, parse_row_number)?;
+                Some(VimCommand::GroupRows(start, end))
+            }
+            "group" if matches!(arg, Some("cols" | "col")) => {
+                let (start, end) = parse_span(arg2?, CellPosition::letter_to_col)?;
+                Some(VimCommand::GroupCols(start, end))
+            }
+            "ungroup" if matches!(arg, Some("rows" | "row")) => Some(VimCommand::UngroupRows),
+            "ungroup" if matches!(arg, Some("cols" | "col")) => Some(VimCommand::UngroupCols),
+            "audit" if arg.is_none() => Some(VimCommand::ToggleAuditPanel),
+            "audit" if arg == Some("export") => Some(VimCommand::ExportAudit(expand_home(arg2.filter(|path| !path.is_empty())?))),
+            // Snapshots (names may have spaces, and may be quoted)
+            "snapshot" | "snapshots" => {
+                let rest = arg2.map(|rest| rest.trim_matches('"').to_string()).filter(|rest| !rest.is_empty());
+                match arg.unwrap_or("") {
+                    "" | "list" => Some(VimCommand::BrowseSnapshots),
+                    "take" => Some(VimCommand::TakeSnapshot(rest.unwrap_or_else(|| "snapshot".to_string()))),
+                    "preview" => Some(VimCommand::PreviewSnapshot(rest?)),
+                    "restore" => Some(VimCommand::RestoreSnapshot(rest)),
+                    _ => None,
+                }
+            }
+            "clear" => match arg.unwrap_or("contents") {
+                "contents" => Some(VimCommand::Clear { contents: true, formats: false }),
+                "formats" => Some(VimCommand::Clear { contents: false, formats: true }),
+                "all" => Some(VimCommand::Clear { contents: true, formats: true }),
+                _ => None,
+            },
+            // Structural edits, optionally counted
+            "insrow" => Some(VimCommand::InsertRows(parse_count(arg)?)),
+            "inscol" => Some(VimCommand::InsertCols(parse_count(arg)?)),
+            "delrow" => Some(VimCommand::DeleteRows(parse_count(arg)?)),
+            "delcol" => Some(VimCommand::DeleteCols(parse_count(arg)?)),
+            // Reordering
+            "moverow" => {
+                let (start, end, to) = parse_move(&input[cmd.len()..], parse_row_number)?;
+                Some(VimCommand::MoveRows { start, end, to })
+            }
+            "movecol" => {
+                let (start, end, to) = parse_move(&input[cmd.len()..], CellPosition::letter_to_col)?;
+                Some(VimCommand::MoveCols { start, end, to })
+            }
+            // Header row
+            "header" if arg.is_none() => Some(VimCommand::ToggleHeaderRow),
+            "header" => Some(VimCommand::SetHeaderRow(parse_row_number(arg?)?)),
+            "noheader" => Some(VimCommand::ClearHeaderRow),
+            // Sorting (the column may be a multi-word header label)
+            "sort" | "sort!" => {
+                let column = input[cmd.len()..].trim();
+                Some(VimCommand::Sort {
+                    column: (!column.is_empty()).then(|| column.to_string()),
+                    descending: cmd == "sort!",
+                })
+            }
+            // Filtering
+            "filter" if arg.is_none() => Some(VimCommand::OpenFilterMenu),
+            "filter" => {
+                let rest = input[cmd.len()..].trim();
+                Some(match FilterCondition::parse(rest) {
+                    Some(condition) => VimCommand::Filter(condition),
+                    None => VimCommand::FilterExpression(rest.to_string()),
+                })
+            }
+            "autofilter" => Some(VimCommand::ToggleFilterButtons),
+            "nofilter" => Some(VimCommand::ClearFilters),
+            "stats" => Some(VimCommand::ColumnStats),
+            "profile" => Some(VimCommand::ToggleProfile),
+            "pivot" if arg == Some("refresh") => Some(VimCommand::RefreshPivot),
+            "pivot" => Some(VimCommand::Pivot(PivotRequest::parse(input[cmd.len()..].trim())?)),
+            "chart" if arg == Some("export") => {
+                Some(VimCommand::ExportChart(arg2.filter(|path| !path.is_empty()).map(expand_home)))
+            }
+            "chart" => Some(VimCommand::InsertChart(match arg.filter(|kind| !kind.is_empty()) {
+                Some(kind) => ChartKind::parse(kind)?,
+                None => ChartKind::Line,
+            })),
+            "nochart" => Some(VimCommand::CloseCharts),
+            "tail" => Some(VimCommand::Tail(true)),
+            "notail" => Some(VimCommand::Tail(false)),
+            "share" => Some(VimCommand::Share(arg.map(|port| port.parse()).transpose().ok()?)),
+            "join" => Some(VimCommand::Join {
+                address: arg.filter(|address| !address.is_empty())?.to_string(),
+                code: arg2.filter(|code| !code.is_empty())?.to_string(),
+            }),
+            "unshare" => Some(VimCommand::Unshare),
+            "script" => Some(VimCommand::RunScript(arg.filter(|name| !name.is_empty()).map(str::to_string))),
+            "gen" | "generate" => Some(VimCommand::Generate(Generator::parse(input[cmd.len()..].trim())?)),
+            "source" => Some(VimCommand::BindSource(DataSource::parse(&input[cmd.len()..])?)),
+            "json" => Some(VimCommand::BindSource(DataSource::parse(input)?)),
+            "refresh" if arg.is_none() => Some(VimCommand::RefreshSources),
+            "nosource" => Some(VimCommand::UnbindSource),
+            // Text to columns (the delimiter may contain spaces, e.g. a regex)
+            "split" => {
+                let rest = input[cmd.len()..].trim();
+                let (delimiter, overwrite) = match rest.strip_suffix(" overwrite") {
+                    Some(delimiter) => (delimiter.trim(), true),
+                    None => (rest, false),
+                };
+                Some(VimCommand::TextToColumns {
+                    delimiter: Delimiter::parse(delimiter).ok()?,
+                    overwrite,
+                })
+            }
+            "wrap" => Some(VimCommand::SetWrap(true)),
+            "nowrap" => Some(VimCommand::SetWrap(false)),
+            // Text styling
+            "bold" => Some(VimCommand::Style(StyleChange::Bold)),
+            "italic" => Some(VimCommand::Style(StyleChange::Italic)),
+            "underline" => Some(VimCommand::Style(StyleChange::Underline)),
+            "color" if arg == Some("none") => Some(VimCommand::Style(StyleChange::Color(None))),
+            "color" => Some(VimCommand::Style(StyleChange::Color(Some(style::parse_color(arg?)?)))),
+            "format" => {
+                let (column, pattern) = match (arg?, arg2) {
+                    ("col" | "column", Some(pattern)) => (true, pattern),
+                    _ => (false, input[cmd.len()..].trim()),
+                };
+                let format = match pattern {
+                    "general" | "none" => None,
+                    pattern => Some(NumberFormat::parse(pattern)?),
+                };
+                Some(VimCommand::Format { format, column })
+            }
+            "dateformat" => match input[cmd.len()..].trim() {
+                "none" | "general" => Some(VimCommand::DateFormat(None)),
+                pattern => Some(VimCommand::DateFormat(Some(DateFormat::parse(pattern)?))),
+            },
+            "align" if arg == Some("auto") => Some(VimCommand::Align(None)),
+            "align" => Some(VimCommand::Align(Some(Alignment::parse(arg?)?))),
+            "type" if arg.is_none() => Some(VimCommand::ShowColumnType),
+            "type" if arg == Some("auto") => Some(VimCommand::SetColumnType(None)),
+            "type" => Some(VimCommand::SetColumnType(Some(ColumnType::parse(arg?)?))),
+            "validate" | "validate!" => {
+                let rest = input[cmd.len()..].trim();
+                let (column, spec) = match rest.split_once(' ') {
+                    Some(("col" | "column", spec)) => (true, spec.trim()),
+                    _ => (false, rest),
+                };
+                Some(VimCommand::Validate { spec: spec.to_string(), reject: cmd == "validate!", column })
+            }
+            "novalidate" => Some(VimCommand::ClearValidation { column: matches!(arg, Some("col" | "column")) }),
+            "protect" if arg.is_none() => Some(VimCommand::Protect(None)),
+            "protect" => Some(VimCommand::Protect(Some(CellRange::from_reference(arg?)?))),
+            "unprotect" if arg.is_none() => Some(VimCommand::Unprotect(None)),
+            "unprotect" if arg == Some("all") => Some(VimCommand::Unprotect(Some(CellRange::new(
+                CellPosition::new(0, 0),
+                CellPosition::new(GRID_ROWS - 1, GRID_COLS - 1),
+            )))),
+            "unprotect" => Some(VimCommand::Unprotect(Some(CellRange::from_reference(arg?)?))),
+            "diffsaved" => Some(VimCommand::DiffSaved),
+            "diffoff" => Some(VimCommand::DiffOff),
+            "revert" => Some(VimCommand::Revert),
+            "nocompare" => Some(VimCommand::NoCompare),
+            "compare" if arg == Some("off") => Some(VimCommand::NoCompare),
+            "compare" => {
+                let rest = input[cmd.len()..].trim();
+                let keyed = rest.rsplit_once(' ').and_then(|(path, key)| Some((path, key.strip_prefix("key=")?)));
+                let (path, key) = match keyed {
+                    Some((path, key)) => (path.trim(), Some(key.to_string())),
+                    None => (rest, None),
+                };
+                (!path.is_empty()).then(|| VimCommand::Compare { path: expand_home(path), key })
+            }
+            "set" => Some(VimCommand::Set(Setting::parse(input[cmd.len()..].trim())?)),
+            "calc" => Some(VimCommand::Recalculate),
+            "goto" | "go" => Some(VimCommand::Goto(CellPosition::from_reference(arg?)?)),
+            "transpose" if arg.is_none() => Some(VimCommand::Transpose(None)),
+            "transpose" => Some(VimCommand::Transpose(Some(CellPosition::from_reference(arg?)?))),
+            _ => {
+                let registry = Registry::get();
+                if let Some(id) = registry.vim_command(cmd) {
+                    return Some(VimCommand::Plugin(id));
+                }
+                // An alias expands to a built-in or plugin command, never another alias
+                let expansion = registry.expand_alias(cmd)?;
+                let expanded_cmd = expansion.split(' ').next().unwrap_or_default();
+                if registry.expand_alias(expanded_cmd).is_some() {
+                    return None;
+                }
+                Self::parse(&format!(":{}{}", expansion, &input[cmd.len()..]))
+            }
+        }
+    }
+}
+
+/// A row in an ex range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// A row index (written 1-based)
+    Row(usize),
+    /// `.` - the cursor row
+    Cursor,
+    /// `$` - the last used row
+    Last,
+    /// `'<` - the first row of the selection
+    SelectionStart,
+    /// `'>` - the last row of the selection
+    SelectionEnd,
+}
+
+/// The rows an ex command applies to: `%` for every used row, or one or two addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExRange {
+    All,
+    Rows(Address, Address),
+}
+
+impl ExRange {
+    /// Parse a leading range, returning it with the rest of the input
+    fn parse(input: &str) -> Option<(Self, &str)> {
+        if let Some(rest) = input.strip_prefix('%') {
+            return Some((Self::All, rest));
+        }
+        let (start, rest) = parse_address(input)?;
+        match rest.strip_prefix(',') {
+            Some(rest) => {
+                let (end, rest) = parse_address(rest)?;
+                Some((Self::Rows(start, end), rest))
+            }
+            None => Some((Self::Rows(start, start), rest)),
+        }
+    }
+
+    /// Whether the range names the selection, so commands also keep to its columns
+    pub fn uses_selection(self) -> bool {
+        let marks = [Address::SelectionStart, Address::SelectionEnd];
+        matches!(self, Self::Rows(start, end) if marks.contains(&start) || marks.contains(&end))
+    }
+}
+
+fn parse_address(input: &str) -> Option<(Address, &str)> {
+    let digits = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    if digits > 0 {
+        return Some((Address::Row(parse_row_number(&input[..digits])?), &input[digits..]));
+    }
+    for (prefix, address) in [
+        (".", Address::Cursor),
+        ("$", Address::Last),
+        ("'<", Address::SelectionStart),
+        ("'>", Address::SelectionEnd),
+    ] {
+        if let Some(rest) = input.strip_prefix(prefix) {
+            return Some((address, rest));
+        }
+    }
+    None
+}
+
+/// What an ex range command does to its rows
+#[derive(Clone, Debug, PartialEq)]
+pub enum RangeCommand {
+    /// `:5` - move the cursor to the (last) row
+    Goto,
+    /// `d` - delete the rows
+    Delete,
+    /// `sort[!] [column]` - sort just these rows
+    Sort { column: Option<String>, descending: bool },
+    /// `>` / `<` (repeated for more) - shift the rows' cells right, or left over empty leading cells
+    Shift(isize),
+    /// `fill` - copy the first row down over the rest
+    Fill,
+    /// `!<command>` - pipe the rows as CSV through a shell command and replace them with its output
+    Filter(String),
+}
+
+impl RangeCommand {
+    fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Some(command) = input.strip_prefix('!') {
+            let command = command.trim();
+            return (!command.is_empty()).then(|| Self::Filter(command.to_string()));
+        }
+        let (cmd, rest) = input.split_once(' ').unwrap_or((input, ""));
+        match cmd {
+            "" => Some(Self::Goto),
+            "d" | "delete" => Some(Self::Delete),
+            "sort" | "sort!" => Some(Self::Sort {
+                column: (!rest.trim().is_empty()).then(|| rest.trim().to_string()),
+                descending: cmd == "sort!",
+            }),
+            "fill" => Some(Self::Fill),
+            _ if cmd.chars().all(|c| c == '>') => Some(Self::Shift(cmd.len() as isize)),
+            _ if cmd.chars().all(|c| c == '<') => Some(Self::Shift(-(cmd.len() as isize))),
             _ => None,
         }
     }
 }
 
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Most path completions shown under the palette input
+const MAX_PATH_COMPLETIONS: usize = 8;
+
+/// A file or directory offered while typing the path of `:e`, `:w`, `:view`, `:saveas`, `:tabe`, or `:compare`
+#[derive(Clone, Debug)]
+struct PathCompletion {
+    /// The path argument as it would read after accepting, e.g. `~/Documents/`
+    text: String,
+    is_dir: bool,
+}
+
+/// The byte offset and text of the path argument, if the input is a command that takes a path
+fn path_argument(input: &str) -> Option<(usize, &str)> {
+    let (cmd, arg) = input.strip_prefix(':')?.split_once(' ')?;
+    if !matches!(cmd, "e" | "edit" | "w" | "vi" | "view" | "mmap" | "saveas" | "compare" | "tabnew" | "tabe" | "tabedit") {
+        return None;
+    }
+    let arg = arg.trim_start();
+    // `:w !command` pipes rather than naming a file
+    if arg.starts_with('!') {
+        return None;
+    }
+    Some((input.len() - arg.len(), arg))
+}
+
+/// Entries of the directory part of `partial` whose names start with its last component,
+/// directories first. Hidden entries are only listed once the name starts with `.`.
+fn list_path_completions(partial: &str) -> Vec<PathCompletion> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(slash) => partial.split_at(slash + 1),
+        None => ("", partial),
+    };
+    let listed = if dir.is_empty() { Path::new(".").to_path_buf() } else { expand_home(dir) };
+    let Ok(entries) = std::fs::read_dir(&listed) else {
+        return Vec::new();
+    };
+    let mut completions: Vec<PathCompletion> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Follows symlinks, so a link to a directory completes like one
+            let is_dir = entry.path().is_dir();
+            let separator = if is_dir { "/" } else { "" };
+            Some(PathCompletion { text: format!("{}{}{}", dir, name, separator), is_dir })
+        })
+        .collect();
+    completions.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.text.to_lowercase().cmp(&b.text.to_lowercase())));
+    completions.truncate(MAX_PATH_COMPLETIONS);
+    completions
+}
+
+/// Parse a 1-based row number into a row index
+fn parse_row_number(s: &str) -> Option<usize> {
+    s.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// Parse an index span like `5-10` or `B:D` using `parse` for each end
+fn parse_span(s: &str, parse: fn(&str) -> Option<usize>) -> Option<(usize, usize)> {
+    let (start, end) = s.split_once(['-', ':'])?;
+    Some((parse(start.trim())?, parse(end.trim())?))
+}
+
+/// Parse an optional count argument: `Some(None)` without one, `None` if it isn't a number
+fn parse_count(arg: Option<&str>) -> Option<Option<usize>> {
+    match arg.filter(|arg| !arg.is_empty()) {
+        Some(arg) => Some(Some(arg.parse().ok().filter(|&count| count > 0)?)),
+        None => Some(None),
+    }
+}
+
+/// Parse `<index> to <index>` or `<span> to <index>` for `:moverow` and `:movecol`
+fn parse_move(s: &str, parse: fn(&str) -> Option<usize>) -> Option<(usize, usize, usize)> {
+    let (from, to) = s.split_once(" to ")?;
+    let from = from.trim();
+    let (start, end) = parse_span(from, parse).or_else(|| parse(from).map(|index| (index, index)))?;
+    Some((start.min(end), start.max(end), parse(to.trim())?))
+}
+
+/// The built-in commands followed by those plugins registered
+fn commands() -> &'static [Command] {
+    static ALL: OnceLock<Vec<Command>> = OnceLock::new();
+    ALL.get_or_init(|| COMMANDS.iter().chain(Registry::get().commands()).cloned().collect())
+}
+
 /// All available commands
 pub const COMMANDS: &[Command] = &[
     // File commands
     Command::new("new_file", "New File")
-        .with_shortcut("⌘N")
+        .with_action(|| Box::new(NewFile))
         .with_vim(":new"),
     Command::new("open_file", "Open File...")
-        .with_shortcut("⌘O")
+        .with_action(|| Box::new(OpenFile))
         .with_vim(":e"),
+    Command::new("view_mapped", "View Large File...")
+        .with_vim(":mmap")
+        .with_prompt("File to view", ":mmap"),
     Command::new("save_file", "Save")
-        .with_shortcut("⌘S")
+        .with_action(|| Box::new(SaveFile))
         .with_vim(":w"),
     Command::new("save_file_as", "Save As...")
-        .with_shortcut("⇧⌘S")
+        .with_action(|| Box::new(SaveFileAs))
         .with_vim(":saveas"),
+    Command::new("encrypt_file", "Encrypt with Passphrase...")
+        .with_vim(":set encrypt")
+        .with_secret_prompt("Passphrase", ":passphrase"),
+    Command::new("decrypt_file", "Remove Encryption")
+        .with_vim(":set noencrypt"),
+    Command::new("diff_saved", "Compare with Saved File")
+        .with_vim(":diffsaved"),
+    Command::new("revert_to_saved", "Revert Selection to Saved")
+        .with_action(|| Box::new(RevertToSaved))
+        .with_vim(":revert"),
+    Command::new("compare_file", "Compare with Another File...")
+        .with_vim(":compare")
+        .with_prompt("File to compare with", ":compare"),
+    Command::new("close_comparison", "Close Comparison")
+        .with_vim(":nocompare"),
+    Command::new("embed_metadata", "Save Layout Inside the File")
+        .with_vim(":set embed=on"),
+    Command::new("sidecar_metadata", "Save Layout in a .zsheets File")
+        .with_vim(":set embed=off"),
     Command::new("force_write", "Force Write")
         .with_vim(":w!"),
     Command::new("close_file", "Close")
-        .with_shortcut("⌘W")
+        .with_action(|| Box::new(CloseFile))
         .with_vim(":q"),
+    Command::new("remember_geometry", "Remember Window Size and Position")
+        .with_vim(":set remember_geometry"),
+    Command::new("forget_geometry", "Always Open Window Centered")
+        .with_vim(":set noremember_geometry"),
+    Command::new("new_tab", "New Tab")
+        .with_action(|| Box::new(NewTab))
+        .with_vim(":tabnew"),
+    Command::new("next_tab", "Next Tab")
+        .with_action(|| Box::new(NextTab))
+        .with_vim(":bnext"),
+    Command::new("previous_tab", "Previous Tab")
+        .with_action(|| Box::new(PreviousTab))
+        .with_vim(":bprev"),
     Command::new("quit", "Quit")
-        .with_shortcut("⌘Q")
+        .with_action(|| Box::new(Quit))
         .with_vim(":q!"),
     // Edit commands
-    Command::new("undo", "Undo").with_shortcut("⌘Z"),
-    Command::new("redo", "Redo").with_shortcut("⇧⌘Z"),
-    Command::new("cut", "Cut").with_shortcut("⌘X"),
-    Command::new("copy", "Copy").with_shortcut("⌘C"),
-    Command::new("paste", "Paste").with_shortcut("⌘V"),
+    Command::new("undo", "Undo").with_action(|| Box::new(menu::Undo)),
+    Command::new("redo", "Redo").with_action(|| Box::new(menu::Redo)),
+    Command::new("cut", "Cut").with_action(|| Box::new(menu::Cut)),
+    Command::new("copy", "Copy").with_action(|| Box::new(menu::Copy)),
+    Command::new("paste", "Paste").with_action(|| Box::new(menu::Paste)),
+    Command::new("paste_transposed", "Paste Transposed")
+        .with_action(|| Box::new(PasteTransposed)),
+    Command::new("goto", "Go to Cell...")
+        .with_action(|| Box::new(GoToCell))
+        .with_vim(":goto")
+        .with_prompt("Go to cell", ":goto"),
+    Command::new("select_all", "Select All")
+        .with_action(|| Box::new(SelectAllCells)),
+    Command::new("select_data_block", "Select Current Data Block")
+        .with_action(|| Box::new(SelectDataBlock)),
+    Command::new("transpose", "Transpose Selection")
+        .with_vim(":transpose"),
+    Command::new("text_to_columns", "Text to Columns (Split on Comma)")
+        .with_vim(":split"),
+    Command::new("text_to_columns_on", "Text to Columns...")
+        .with_vim(":split")
+        .with_prompt("Split on delimiter", ":split"),
+    // Format commands
+    Command::new("toggle_bold", "Toggle Bold")
+        .with_action(|| Box::new(ToggleBold))
+        .with_vim(":bold"),
+    Command::new("toggle_italic", "Toggle Italic")
+        .with_action(|| Box::new(ToggleItalic))
+        .with_vim(":italic"),
+    Command::new("toggle_underline", "Toggle Underline")
+        .with_action(|| Box::new(ToggleUnderline))
+        .with_vim(":underline"),
+    Command::new("text_color", "Set Text Color...")
+        .with_vim(":color")
+        .with_prompt("Text color", ":color"),
+    Command::new("clear_color", "Clear Text Color")
+        .with_vim(":color none"),
+    Command::new("format_general", "Number Format: General")
+        .with_vim(":format general"),
+    Command::new("format_decimal", "Number Format: 0.00")
+        .with_vim(":format 0.00"),
+    Command::new("format_thousands", "Number Format: #,##0")
+        .with_vim(":format #,##0"),
+    Command::new("format_currency", "Number Format: Currency")
+        .with_action(|| Box::new(FormatCurrency))
+        .with_vim(":format $#,##0.00"),
+    Command::new("format_percent", "Number Format: Percent")
+        .with_action(|| Box::new(FormatPercent))
+        .with_vim(":format 0%"),
+    Command::new("format_scientific", "Number Format: Scientific")
+        .with_vim(":format 0.00E+00"),
+    Command::new("format_custom", "Number Format: Custom...")
+        .with_vim(":format")
+        .with_prompt("Number format", ":format"),
+    Command::new("date_format_iso", "Date Format: YYYY-MM-DD")
+        .with_vim(":dateformat iso"),
+    Command::new("date_format_us", "Date Format: MM/DD/YYYY")
+        .with_vim(":dateformat us"),
+    Command::new("date_format_eu", "Date Format: DD.MM.YYYY")
+        .with_vim(":dateformat eu"),
+    Command::new("date_format_long", "Date Format: January 5, 2024")
+        .with_vim(":dateformat long"),
+    Command::new("align_left", "Align Column Left")
+        .with_vim(":align left"),
+    Command::new("align_center", "Align Column Center")
+        .with_vim(":align center"),
+    Command::new("align_right", "Align Column Right")
+        .with_vim(":align right"),
+    Command::new("align_auto", "Align Column Automatically (Numbers Right)")
+        .with_vim(":align auto"),
+    Command::new("show_column_type", "Show Column Type")
+        .with_vim(":type"),
+    Command::new("type_integer", "Column Type: Integer")
+        .with_vim(":type integer"),
+    Command::new("type_float", "Column Type: Float")
+        .with_vim(":type float"),
+    Command::new("type_date", "Column Type: Date")
+        .with_vim(":type date"),
+    Command::new("type_boolean", "Column Type: Boolean")
+        .with_vim(":type boolean"),
+    Command::new("type_text", "Column Type: Text")
+        .with_vim(":type text"),
+    Command::new("type_auto", "Column Type: Inferred")
+        .with_vim(":type auto"),
+    Command::new("validate", "Validate Selection...")
+        .with_vim(":validate")
+        .with_prompt("Allowed values", ":validate"),
+    Command::new("clear_validation", "Remove Validation from Selection")
+        .with_vim(":novalidate"),
+    // Formula commands
+    Command::new("recalculate", "Recalculate Formulas")
+        .with_action(|| Box::new(Recalculate))
+        .with_vim(":calc"),
+    Command::new("calc_manual", "Manual Calculation")
+        .with_vim(":set calc=manual"),
+    Command::new("calc_auto", "Automatic Calculation")
+        .with_vim(":set calc=auto"),
+    Command::new("refresh_every_second", "Refresh NOW()/RAND() Every Second")
+        .with_vim(":set refresh=1"),
+    Command::new("refresh_off", "Stop Refreshing NOW()/RAND()")
+        .with_vim(":set refresh=off"),
     // View commands
+    Command::new("protect_selection", "Protect Selection")
+        .with_vim(":protect"),
+    Command::new("unprotect_selection", "Unprotect Selection")
+        .with_vim(":unprotect"),
+    Command::new("protection_on", "Turn Protection On")
+        .with_vim(":set protection=on"),
+    Command::new("protection_off", "Turn Protection Off")
+        .with_vim(":set protection=off"),
     Command::new("toggle_read_only", "Toggle Read-Only")
         .with_vim(":view"),
+    Command::new("show_toolbar", "Show Toolbar")
+        .with_vim(":set toolbar=on"),
+    Command::new("hide_toolbar", "Hide Toolbar")
+        .with_vim(":set toolbar=off"),
+    Command::new("show_gridlines", "Show Gridlines")
+        .with_vim(":set gridlines=on"),
+    Command::new("hide_gridlines", "Hide Gridlines")
+        .with_vim(":set gridlines=off"),
+    Command::new("zebra_on", "Stripe Alternate Rows")
+        .with_vim(":set zebra=on"),
+    Command::new("zebra_off", "Don't Stripe Rows")
+        .with_vim(":set zebra=off"),
+    Command::new("crosshair_on", "Highlight Cursor Row and Column")
+        .with_vim(":set crosshair=both"),
+    Command::new("crosshair_off", "Don't Highlight Cursor Row and Column")
+        .with_vim(":set crosshair=off"),
+    Command::new("cursor_steady", "Steady Cursor (Reduce Motion)")
+        .with_vim(":set cursor=steady"),
+    Command::new("cursor_fade", "Fading Cursor")
+        .with_vim(":set cursor=fade"),
+    Command::new("autocomplete_on", "Autocomplete From Column Values")
+        .with_vim(":set autocomplete=on"),
+    Command::new("autocomplete_off", "Don't Autocomplete From Column Values")
+        .with_vim(":set autocomplete=off"),
+    Command::new("expand_selected_on", "Show Full Content of Selected Cell")
+        .with_vim(":set expandselected=on"),
+    Command::new("expand_selected_off", "Clip Selected Cell to Its Size")
+        .with_vim(":set expandselected=off"),
+    Command::new("toggle_wrap", "Toggle Word Wrap for Column")
+        .with_vim(":wrap"),
+    Command::new("freeze_panes", "Freeze Panes at Cursor")
+        .with_vim(":freeze"),
+    Command::new("freeze_rows_cols", "Freeze Rows and Columns...")
+        .with_vim(":freeze")
+        .with_prompt("Rows and columns to freeze", ":freeze"),
+    Command::new("unfreeze_panes", "Unfreeze Panes")
+        .with_vim(":unfreeze"),
+    Command::new("toggle_header_row", "Toggle Header Row at Cursor")
+        .with_vim(":header"),
+    Command::new("set_header_row", "Set Header Row...")
+        .with_vim(":header")
+        .with_prompt("Header row", ":header"),
+    Command::new("toggle_group", "Toggle Outline Group at Cursor")
+        .with_action(|| Box::new(ToggleGroup)),
+    Command::new("expand_all_groups", "Expand All Outline Groups")
+        .with_action(|| Box::new(ExpandAllGroups)),
+    Command::new("collapse_all_groups", "Collapse All Outline Groups")
+        .with_action(|| Box::new(CollapseAllGroups)),
+    Command::new("ungroup_rows", "Remove Row Group at Cursor")
+        .with_vim(":ungroup rows"),
+    Command::new("ungroup_cols", "Remove Column Group at Cursor")
+        .with_vim(":ungroup cols"),
+    // Data commands
+    Command::new("audit_on", "Record Cell Changes")
+        .with_vim(":set audit"),
+    Command::new("audit_off", "Stop Recording Cell Changes")
+        .with_vim(":set noaudit"),
+    Command::new("toggle_audit_panel", "Toggle Cell Change Log")
+        .with_vim(":audit"),
+    Command::new("export_audit", "Export Cell Change Log...")
+        .with_vim(":audit export")
+        .with_prompt("Export changes to", ":audit export"),
+    Command::new("take_snapshot", "Take Snapshot...")
+        .with_vim(":snapshot take")
+        .with_prompt("Snapshot name", ":snapshot take"),
+    Command::new("browse_snapshots", "Browse Snapshots...")
+        .with_vim(":snapshot")
+        .with_prompt("Preview snapshot", ":snapshot preview"),
+    Command::new("restore_snapshot", "Restore Snapshot...")
+        .with_vim(":snapshot restore")
+        .with_prompt("Restore snapshot", ":snapshot restore"),
+    Command::new("clear_contents", "Clear Contents")
+        .with_action(|| Box::new(ClearContents))
+        .with_vim(":clear"),
+    Command::new("clear_formats", "Clear Formats")
+        .with_action(|| Box::new(ClearFormats))
+        .with_vim(":clear formats"),
+    Command::new("clear_all", "Clear All")
+        .with_action(|| Box::new(ClearAll))
+        .with_vim(":clear all"),
+    Command::new("insert_rows", "Insert Rows Above")
+        .with_vim(":insrow"),
+    Command::new("insert_columns", "Insert Columns Left")
+        .with_vim(":inscol"),
+    Command::new("delete_rows", "Delete Selected Rows")
+        .with_vim(":delrow"),
+    Command::new("delete_columns", "Delete Selected Columns")
+        .with_vim(":delcol"),
+    Command::new("move_row_up", "Move Row Up")
+        .with_action(|| Box::new(MoveRowUp)),
+    Command::new("move_row_down", "Move Row Down")
+        .with_action(|| Box::new(MoveRowDown)),
+    Command::new("move_column_left", "Move Column Left")
+        .with_action(|| Box::new(MoveColumnLeft)),
+    Command::new("move_column_right", "Move Column Right")
+        .with_action(|| Box::new(MoveColumnRight)),
+    Command::new("move_rows", "Move Rows To...")
+        .with_vim(":moverow")
+        .with_prompt("Move rows (5-7 to 2)", ":moverow"),
+    Command::new("move_columns", "Move Columns To...")
+        .with_vim(":movecol")
+        .with_prompt("Move columns (B:C to F)", ":movecol"),
+    Command::new("sort_ascending", "Sort Rows by Current Column (Ascending)")
+        .with_vim(":sort"),
+    Command::new("sort_descending", "Sort Rows by Current Column (Descending)")
+        .with_vim(":sort!"),
+    Command::new("sort_by", "Sort Rows by Column...")
+        .with_vim(":sort")
+        .with_prompt("Sort by column", ":sort"),
+    Command::new("sort_by_descending", "Sort Rows by Column (Descending)...")
+        .with_vim(":sort!")
+        .with_prompt("Sort by column, descending", ":sort!"),
+    Command::new("filter_column", "Filter Current Column...")
+        .with_vim(":filter"),
+    Command::new("toggle_filter_buttons", "Toggle Auto-Filter Buttons")
+        .with_vim(":autofilter"),
+    Command::new("clear_filters", "Clear All Filters")
+        .with_vim(":nofilter"),
+    Command::new("column_stats", "Show Current Column Statistics")
+        .with_vim(":stats"),
+    Command::new("toggle_profile", "Toggle Data Profile Panel")
+        .with_vim(":profile"),
+    Command::new("pivot", "Create Pivot Table...")
+        .with_vim(":pivot")
+        .with_prompt("Pivot (rows=Region cols=Quarter sum=Amount)", ":pivot"),
+    Command::new("refresh_pivot", "Refresh Pivot Table")
+        .with_vim(":pivot refresh"),
+    Command::new("insert_chart", "Insert Chart")
+        .with_vim(":chart"),
+    Command::new("insert_bar_chart", "Insert Bar Chart")
+        .with_vim(":chart bar"),
+    Command::new("insert_scatter_chart", "Insert Scatter Chart")
+        .with_vim(":chart scatter"),
+    Command::new("export_chart", "Export Chart as PNG...")
+        .with_vim(":chart export"),
+    Command::new("close_charts", "Close All Charts")
+        .with_vim(":nochart"),
+    Command::new("run_script", "Run Script...")
+        .with_vim(":script")
+        .with_prompt("Script", ":script"),
+    Command::new("generate", "Fill Selection With Test Data...")
+        .with_vim(":gen")
+        .with_prompt("Generate (seq 1 1, rand 1 100, date 2024-01-01 7, name)", ":gen"),
+    Command::new("bind_source", "Load Data From Source...")
+        .with_vim(":source")
+        .with_prompt("Source (url <url>, cmd <command>, sql <database> <query>)", ":source"),
+    Command::new("tail_file", "Tail File (Append New Rows)")
+        .with_vim(":tail"),
+    Command::new("stop_tail", "Stop Tailing File")
+        .with_vim(":notail"),
+    Command::new("share", "Share Sheet on Network (Experimental)")
+        .with_vim(":share"),
+    Command::new("join", "Join Shared Sheet...")
+        .with_vim(":join")
+        .with_prompt("Join sheet at (host:port code)", ":join"),
+    Command::new("unshare", "Stop Sharing")
+        .with_vim(":unshare"),
+    Command::new("fetch_json", "Fetch JSON Into Grid...")
+        .with_vim(":json")
+        .with_prompt("JSON (url [path], e.g. https://api.example.com/users $.data[*])", ":json"),
+    Command::new("refresh_sources", "Refresh Data Sources")
+        .with_vim(":refresh"),
+    Command::new("unbind_source", "Unbind Data Source")
+        .with_vim(":nosource"),
     // Sizing commands
     Command::new("autofit_all", "Auto-fit All Columns & Rows")
         .with_vim(":autofit"),
@@ -166,15 +1276,59 @@ pub const COMMANDS: &[Command] = &[
         .with_vim(":autofit watch"),
     Command::new("reset_sizes", "Reset All Column & Row Sizes")
         .with_vim(":resetsize"),
+    Command::new("limit_column_width", "Limit Column Width...")
+        .with_vim(":colwidth")
+        .with_prompt("Width limits (min=40 max=300)", ":colwidth"),
+    Command::new("clear_width_limits", "Remove Column Width Limits")
+        .with_vim(":colwidth none"),
+    Command::new("default_column_width", "Set Default Column Width...")
+        .with_vim(":set defaultcolwidth=")
+        .with_prompt("Default column width", ":set defaultcolwidth="),
+    Command::new("default_row_height", "Set Default Row Height...")
+        .with_vim(":set defaultrowheight=")
+        .with_prompt("Default row height", ":set defaultrowheight="),
+    Command::new("scroll_speed", "Set Scroll Speed...")
+        .with_vim(":set scrollspeed=")
+        .with_prompt("Rows per mouse wheel notch", ":set scrollspeed="),
 ];
 
+/// Most commands kept in the palette's history
+const MAX_HISTORY: usize = 100;
+
+/// A command run from the palette, for `@:` and the `q:` history list
+#[derive(Clone, Debug)]
+struct HistoryEntry {
+    /// The ex command as typed, or the name of a command picked from the list
+    text: String,
+    /// `vim_command` for ex commands, otherwise the picked command's id
+    cmd_id: &'static str,
+}
+
+/// Looks up the choices for a prompt by command id
+type ChoicesProvider = Box<dyn Fn(&str, &App) -> Vec<String> + 'static>;
+
 pub struct CommandPalette {
     focus_handle: FocusHandle,
     input: String,
     cursor_pos: usize,
+    /// Text being composed with an input method, not yet committed
+    marked_range: Option<Range<usize>>,
+    // Where the input was last drawn, for placing the input method's candidate window
+    last_layout: Option<ShapedLine>,
+    last_bounds: Option<Bounds<Pixels>>,
     selected_index: usize,
     filtered_commands: Vec<usize>,
     vim_command: Option<VimCommand>,
+    path_completions: Vec<PathCompletion>,
+    completion_index: usize,
+    /// The command waiting for its argument, with the choices offered for it
+    prompt: Option<Prompt>,
+    choices: Vec<String>,
+    filtered_choices: Vec<usize>,
+    choices_provider: Option<ChoicesProvider>,
+    history: Vec<HistoryEntry>,
+    /// Picking from the history (`q:`) rather than the command list
+    browsing_history: bool,
     on_command: Option<Box<dyn Fn(&str, Option<VimCommand>, &mut Window, &mut App) + 'static>>,
 }
 
@@ -184,9 +1338,20 @@ impl CommandPalette {
             focus_handle: cx.focus_handle(),
             input: String::new(),
             cursor_pos: 0,
+            marked_range: None,
+            last_layout: None,
+            last_bounds: None,
             selected_index: 0,
             filtered_commands: Vec::new(),
             vim_command: None,
+            path_completions: Vec::new(),
+            completion_index: 0,
+            prompt: None,
+            choices: Vec::new(),
+            filtered_choices: Vec::new(),
+            choices_provider: None,
+            history: Vec::new(),
+            browsing_history: false,
             on_command: None,
         };
         palette.update_filter();
@@ -200,11 +1365,24 @@ impl CommandPalette {
         self.on_command = Some(Box::new(handler));
     }
 
+    /// Supplies the choices for a command's prompt, by command id
+    pub fn set_choices_provider<F>(&mut self, provider: F)
+    where
+        F: Fn(&str, &App) -> Vec<String> + 'static,
+    {
+        self.choices_provider = Some(Box::new(provider));
+    }
+
     pub fn reset(&mut self, cx: &mut Context<Self>) {
         self.input.clear();
         self.cursor_pos = 0;
+        self.marked_range = None;
         self.selected_index = 0;
         self.vim_command = None;
+        self.path_completions.clear();
+        self.prompt = None;
+        self.browsing_history = false;
+        self.choices.clear();
         self.update_filter();
         cx.notify();
     }
@@ -212,10 +1390,26 @@ impl CommandPalette {
     fn update_filter(&mut self) {
         let query = self.input.to_lowercase();
 
+        // Answering a prompt or browsing history: narrow those choices instead of the command list
+        if self.prompt.is_some() || self.browsing_history {
+            self.vim_command = None;
+            self.filtered_commands.clear();
+            self.filtered_choices = (0..self.choices.len())
+                .filter(|&idx| {
+                    let choice = self.choices[idx].to_lowercase();
+                    if self.browsing_history { choice.contains(query.trim()) } else { choice.starts_with(query.trim()) }
+                })
+                .collect();
+            if self.selected_index >= self.filtered_choices.len() {
+                self.selected_index = 0;
+            }
+            return;
+        }
+
         // Check if it's a vim command
         self.vim_command = VimCommand::parse(&self.input);
 
-        self.filtered_commands = COMMANDS
+        self.filtered_commands = commands()
             .iter()
             .enumerate()
             .filter(|(_, cmd)| {
@@ -243,50 +1437,193 @@ impl CommandPalette {
         }
     }
 
+    /// List matching paths in the background; results are dropped if the input changed meanwhile
+    fn update_path_completions(&mut self, cx: &mut Context<Self>) {
+        let Some((_, partial)) = path_argument(&self.input) else {
+            self.path_completions.clear();
+            return;
+        };
+        let input = self.input.clone();
+        let partial = partial.to_string();
+        let listing = cx.background_executor().spawn(async move { list_path_completions(&partial) });
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            let completions = listing.await;
+            this.update(cx, |palette, cx| {
+                if palette.input == input {
+                    palette.path_completions = completions;
+                    palette.completion_index = 0;
+                    cx.notify();
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// Replace the path argument with the highlighted completion; a directory then lists its contents
+    fn accept_path_completion(&mut self, _: &AcceptPathCompletion, _window: &mut Window, cx: &mut Context<Self>) {
+        let (Some((start, _)), Some(completion)) =
+            (path_argument(&self.input), self.path_completions.get(self.completion_index))
+        else {
+            return;
+        };
+        self.input = format!("{}{}", &self.input[..start], completion.text);
+        self.cursor_pos = self.input.len();
+        self.marked_range = None;
+        self.on_input_changed(cx);
+    }
+
     fn select_next(&mut self, _: &SelectNext, _window: &mut Window, cx: &mut Context<Self>) {
-        if !self.filtered_commands.is_empty() {
+        if !self.path_completions.is_empty() {
+            self.completion_index = (self.completion_index + 1) % self.path_completions.len();
+            cx.notify();
+        } else if !self.filtered_commands.is_empty() {
             self.selected_index = (self.selected_index + 1) % self.filtered_commands.len();
             cx.notify();
+        } else if !self.filtered_choices.is_empty() {
+            self.selected_index = (self.selected_index + 1) % self.filtered_choices.len();
+            cx.notify();
         }
     }
 
     fn select_previous(&mut self, _: &SelectPrevious, _window: &mut Window, cx: &mut Context<Self>) {
-        if !self.filtered_commands.is_empty() {
+        if !self.path_completions.is_empty() {
+            let len = self.path_completions.len();
+            self.completion_index = (self.completion_index + len - 1) % len;
+            cx.notify();
+        } else if !self.filtered_commands.is_empty() {
             if self.selected_index == 0 {
                 self.selected_index = self.filtered_commands.len() - 1;
             } else {
                 self.selected_index -= 1;
             }
             cx.notify();
+        } else if !self.filtered_choices.is_empty() {
+            let len = self.filtered_choices.len();
+            self.selected_index = (self.selected_index + len - 1) % len;
+            cx.notify();
         }
     }
 
     fn confirm(&mut self, _: &Confirm, window: &mut Window, cx: &mut Context<Self>) {
+        // A history entry goes back into the input to be edited and run
+        if self.browsing_history {
+            if let Some(&idx) = self.filtered_choices.get(self.selected_index) {
+                self.input = self.choices[idx].clone();
+                self.cursor_pos = self.input.len();
+                self.marked_range = None;
+                self.browsing_history = false;
+                self.choices.clear();
+                self.selected_index = 0;
+                self.on_input_changed(cx);
+            }
+            return;
+        }
+
+        // Answer the pending prompt: the highlighted choice, or the text as typed
+        if let Some(prompt) = self.prompt {
+            let answer = match self.filtered_choices.get(self.selected_index) {
+                Some(&idx) => self.choices[idx].clone(),
+                None => self.input.trim().to_string(),
+            };
+            let input = format!("{} {}", prompt.command, answer);
+            match VimCommand::parse(&input) {
+                Some(vim_cmd) => {
+                    if !prompt.secret {
+                        self.record("vim_command", input);
+                    }
+                    if let Some(handler) = &self.on_command {
+                        handler("vim_command", Some(vim_cmd), window, cx);
+                    }
+                }
+                None if prompt.secret => eprintln!("Invalid answer for {}", prompt.label.to_lowercase()),
+                None => eprintln!("Invalid answer for {}: {}", prompt.label.to_lowercase(), answer),
+            }
+            return;
+        }
+
         // If there's a vim command, execute it directly
         if let Some(vim_cmd) = self.vim_command.take() {
+            if !matches!(vim_cmd, VimCommand::Passphrase(_)) {
+                self.record("vim_command", self.input.trim().to_string());
+            }
             if let Some(handler) = &self.on_command {
                 handler("vim_command", Some(vim_cmd), window, cx);
             }
             return;
         }
 
-        // Otherwise execute the selected command
+        // Otherwise execute the selected command, or ask for its argument first
         if let Some(&cmd_idx) = self.filtered_commands.get(self.selected_index) {
-            let cmd_id = COMMANDS[cmd_idx].id;
+            let cmd_id = commands()[cmd_idx].id;
+            if commands()[cmd_idx].prompt.is_some() {
+                self.start_prompt(cmd_id, cx);
+                return;
+            }
+            self.record(cmd_id, commands()[cmd_idx].name.to_string());
             if let Some(handler) = &self.on_command {
                 handler(cmd_id, None, window, cx);
             }
         }
     }
 
+    /// Remember a command that ran, skipping a repeat of the previous one
+    fn record(&mut self, cmd_id: &'static str, text: String) {
+        if self.history.last().is_some_and(|last| last.text == text) {
+            return;
+        }
+        self.history.push(HistoryEntry { text, cmd_id });
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+    }
+
+    /// The last command run from the palette, as passed to the command handler
+    pub fn last_command(&self) -> Option<(&'static str, Option<VimCommand>)> {
+        let entry = self.history.last()?;
+        match entry.cmd_id {
+            "vim_command" => Some((entry.cmd_id, Some(VimCommand::parse(&entry.text)?))),
+            cmd_id => Some((cmd_id, None)),
+        }
+    }
+
+    /// List past commands, most recent first, to pick one to edit and run again
+    pub fn show_history(&mut self, cx: &mut Context<Self>) {
+        self.choices = self.history.iter().rev().map(|entry| entry.text.clone()).collect();
+        self.browsing_history = true;
+        self.input.clear();
+        self.cursor_pos = 0;
+        self.marked_range = None;
+        self.selected_index = 0;
+        self.on_input_changed(cx);
+    }
+
+    /// Ask for the argument of a command that has a prompt
+    pub fn start_prompt(&mut self, cmd_id: &str, cx: &mut Context<Self>) {
+        let Some(prompt) = commands().iter().find(|cmd| cmd.id == cmd_id).and_then(|cmd| cmd.prompt) else {
+            return;
+        };
+        self.choices = match &self.choices_provider {
+            Some(provider) => provider(cmd_id, cx),
+            None => Vec::new(),
+        };
+        self.prompt = Some(prompt);
+        self.input.clear();
+        self.cursor_pos = 0;
+        self.marked_range = None;
+        self.selected_index = 0;
+        self.on_input_changed(cx);
+    }
+
     fn on_input_changed(&mut self, cx: &mut Context<Self>) {
         self.update_filter();
+        self.update_path_completions(cx);
         cx.notify();
     }
 }
 
 impl Render for CommandPalette {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let theme = cx.global::<Theme>();
 
         div()
@@ -295,6 +1632,7 @@ impl Render for CommandPalette {
             .on_action(cx.listener(Self::select_next))
             .on_action(cx.listener(Self::select_previous))
             .on_action(cx.listener(Self::confirm))
+            .on_action(cx.listener(Self::accept_path_completion))
             .flex()
             .flex_col()
             .w(px(400.))
@@ -306,14 +1644,26 @@ impl Render for CommandPalette {
             .shadow_lg()
             .overflow_hidden()
             .child(self.render_input(cx))
-            .child(self.render_results(cx))
+            .map(|d| {
+                if self.prompt.is_some() || self.browsing_history {
+                    d.child(self.render_choices(cx))
+                } else if self.path_completions.is_empty() {
+                    d.child(self.render_results(window, cx))
+                } else {
+                    d.child(self.render_path_completions(cx))
+                }
+            })
     }
 }
 
 impl CommandPalette {
     fn render_input(&self, cx: &mut Context<Self>) -> impl IntoElement {
         let theme = cx.global::<Theme>();
-        let input = self.input.clone();
+        // A secret answer shows as one `*` per byte, so the cursor still lines up
+        let input = match self.prompt {
+            Some(prompt) if prompt.secret => "*".repeat(self.input.len()),
+            _ => self.input.clone(),
+        };
 
         div()
             .flex()
@@ -327,9 +1677,12 @@ impl CommandPalette {
             .child(
                 div()
                     .text_color(theme.subtext0)
-                    .text_size(px(16.))
                     .mr(px(8.))
-                    .child(">")
+                    .map(|d| match self.prompt {
+                        Some(prompt) => d.text_size(px(14.)).child(format!("{}:", prompt.label)),
+                        None if self.browsing_history => d.text_size(px(14.)).child("History:"),
+                        None => d.text_size(px(16.)).child(">"),
+                    })
             )
             .child(
                 div()
@@ -340,11 +1693,12 @@ impl CommandPalette {
                     .child(CommandPaletteInput {
                         palette: cx.entity().clone(),
                         content: input,
+                        marked_range: self.marked_range.clone(),
                     })
             )
     }
 
-    fn render_results(&self, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render_results(&self, window: &Window, cx: &mut Context<Self>) -> impl IntoElement {
         let theme = cx.global::<Theme>();
 
         div()
@@ -354,7 +1708,7 @@ impl CommandPalette {
             .overflow_hidden()
             .children(
                 self.filtered_commands.iter().enumerate().map(|(idx, &cmd_idx)| {
-                    let cmd = &COMMANDS[cmd_idx];
+                    let cmd = &commands()[cmd_idx];
                     let is_selected = idx == self.selected_index;
 
                     div()
@@ -401,7 +1755,7 @@ impl CommandPalette {
                                     )
                                 })
                         )
-                        .when_some(cmd.shortcut, |d, shortcut| {
+                        .when_some(cmd.shortcut(window), |d, shortcut| {
                             d.child(
                                 div()
                                     .text_size(px(12.))
@@ -414,6 +1768,93 @@ impl CommandPalette {
     }
 }
 
+impl CommandPalette {
+    fn render_choices(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        div()
+            .flex()
+            .flex_col()
+            .flex_1()
+            .overflow_hidden()
+            .children(self.filtered_choices.iter().enumerate().map(|(idx, &choice_idx)| {
+                let is_selected = idx == self.selected_index;
+
+                div()
+                    .id(ElementId::Name(format!("choice-{}", choice_idx).into()))
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .w_full()
+                    .h(px(32.))
+                    .px(px(12.))
+                    .when(is_selected, |d| d.bg(theme.surface0))
+                    .cursor_pointer()
+                    .on_mouse_down(MouseButton::Left, {
+                        let entity = cx.entity().clone();
+                        move |_, window, app| {
+                            entity.update(app, |palette, cx| {
+                                palette.selected_index = idx;
+                                cx.notify();
+                            });
+                            window.dispatch_action(Box::new(Confirm), app);
+                        }
+                    })
+                    .child(
+                        div()
+                            .text_size(px(14.))
+                            .text_color(theme.text)
+                            .child(self.choices[choice_idx].clone()),
+                    )
+            }))
+    }
+
+    fn render_path_completions(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        div()
+            .flex()
+            .flex_col()
+            .flex_1()
+            .overflow_hidden()
+            .children(self.path_completions.iter().enumerate().map(|(idx, completion)| {
+                let is_selected = idx == self.completion_index;
+                // Show just the entry's name; the typed directory is already in the input
+                let name = completion.text.trim_end_matches('/');
+                let name = match name.rfind('/') {
+                    Some(slash) => &completion.text[slash + 1..],
+                    None => completion.text.as_str(),
+                };
+
+                div()
+                    .id(ElementId::Name(format!("path-{}", idx).into()))
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .w_full()
+                    .h(px(32.))
+                    .px(px(12.))
+                    .when(is_selected, |d| d.bg(theme.surface0))
+                    .cursor_pointer()
+                    .on_mouse_down(MouseButton::Left, {
+                        let entity = cx.entity().clone();
+                        move |_, window, app| {
+                            entity.update(app, |palette, cx| {
+                                palette.completion_index = idx;
+                                palette.accept_path_completion(&AcceptPathCompletion, window, cx);
+                            });
+                        }
+                    })
+                    .child(
+                        div()
+                            .text_size(px(14.))
+                            .text_color(if completion.is_dir { theme.accent } else { theme.text })
+                            .child(name.to_string()),
+                    )
+            }))
+    }
+}
+
 impl Focusable for CommandPalette {
     fn focus_handle(&self, _: &App) -> FocusHandle {
         self.focus_handle.clone()
@@ -424,6 +1865,7 @@ impl Focusable for CommandPalette {
 pub struct CommandPaletteInput {
     palette: Entity<CommandPalette>,
     content: String,
+    marked_range: Option<Range<usize>>,
 }
 
 impl IntoElement for CommandPaletteInput {
@@ -488,10 +1930,31 @@ impl Element for CommandPaletteInput {
             strikethrough: None,
         };
 
+        // Underline text still being composed, as input methods expect
+        let runs = match &self.marked_range {
+            Some(marked) if !self.content.is_empty() => [
+                TextRun { len: marked.start, ..run.clone() },
+                TextRun {
+                    len: marked.len(),
+                    underline: Some(UnderlineStyle {
+                        color: Some(text_color),
+                        thickness: px(1.),
+                        wavy: false,
+                    }),
+                    ..run.clone()
+                },
+                TextRun { len: display_text.len() - marked.end, ..run },
+            ]
+            .into_iter()
+            .filter(|run| run.len > 0)
+            .collect(),
+            _ => vec![run],
+        };
+
         window.text_system().shape_line(
             display_text,
             font_size,
-            &[run],
+            &runs,
             None,
         )
     }
@@ -516,6 +1979,12 @@ impl Element for CommandPaletteInput {
         prepaint.paint(bounds.origin, window.line_height(), gpui::TextAlign::Left, None, window, cx)
             .unwrap();
 
+        let layout = (!self.content.is_empty()).then(|| prepaint.clone());
+        self.palette.update(cx, |palette, _| {
+            palette.last_layout = layout;
+            palette.last_bounds = Some(bounds);
+        });
+
         // Draw cursor
         if focus_handle.is_focused(window) {
             let theme = cx.global::<Theme>();
@@ -570,10 +2039,12 @@ impl EntityInputHandler for CommandPalette {
         _window: &mut Window,
         _cx: &mut Context<Self>,
     ) -> Option<Range<usize>> {
-        None
+        self.marked_range.as_ref().map(|range| self.range_to_utf16(range))
     }
 
-    fn unmark_text(&mut self, _window: &mut Window, _cx: &mut Context<Self>) {}
+    fn unmark_text(&mut self, _window: &mut Window, _cx: &mut Context<Self>) {
+        self.marked_range = None;
+    }
 
     fn replace_text_in_range(
         &mut self,
@@ -585,6 +2056,7 @@ impl EntityInputHandler for CommandPalette {
         let range = range_utf16
             .as_ref()
             .map(|r| self.range_from_utf16(r))
+            .or(self.marked_range.take())
             .unwrap_or(self.cursor_pos..self.cursor_pos);
 
         self.input = self.input[..range.start].to_owned() + new_text + &self.input[range.end..];
@@ -596,30 +2068,52 @@ impl EntityInputHandler for CommandPalette {
         &mut self,
         range_utf16: Option<Range<usize>>,
         new_text: &str,
-        _new_selected_range_utf16: Option<Range<usize>>,
-        window: &mut Window,
+        new_selected_range_utf16: Option<Range<usize>>,
+        _window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        self.replace_text_in_range(range_utf16, new_text, window, cx);
+        let range = range_utf16
+            .as_ref()
+            .map(|r| self.range_from_utf16(r))
+            .or(self.marked_range.clone())
+            .unwrap_or(self.cursor_pos..self.cursor_pos);
+
+        self.input = self.input[..range.start].to_owned() + new_text + &self.input[range.end..];
+        self.marked_range = (!new_text.is_empty()).then(|| range.start..range.start + new_text.len());
+        // The input method places its cursor relative to the composed text
+        self.cursor_pos = range.start
+            + new_selected_range_utf16
+                .map(|selected| utf8_offset(new_text, selected.end))
+                .unwrap_or(new_text.len());
+        self.on_input_changed(cx);
     }
 
     fn bounds_for_range(
         &mut self,
-        _range_utf16: Range<usize>,
+        range_utf16: Range<usize>,
         bounds: Bounds<Pixels>,
         _window: &mut Window,
         _cx: &mut Context<Self>,
     ) -> Option<Bounds<Pixels>> {
-        Some(bounds)
+        let Some(line) = &self.last_layout else {
+            return Some(Bounds::new(bounds.origin, size(px(0.), bounds.size.height)));
+        };
+        let range = self.range_from_utf16(&range_utf16);
+        Some(Bounds::from_corners(
+            point(bounds.left() + line.x_for_index(range.start), bounds.top()),
+            point(bounds.left() + line.x_for_index(range.end), bounds.bottom()),
+        ))
     }
 
     fn character_index_for_point(
         &mut self,
-        _point: gpui::Point<Pixels>,
+        point: gpui::Point<Pixels>,
         _window: &mut Window,
         _cx: &mut Context<Self>,
     ) -> Option<usize> {
-        Some(self.offset_to_utf16(self.cursor_pos))
+        let local = self.last_bounds?.localize(&point)?;
+        let index = self.last_layout.as_ref()?.index_for_x(local.x)?;
+        Some(self.offset_to_utf16(index.min(self.input.len())))
     }
 }
 
@@ -662,3 +2156,14 @@ impl CommandPalette {
         self.offset_from_utf16(range_utf16.start)..self.offset_from_utf16(range_utf16.end)
     }
 }
+
+/// Byte offset in `text` of a UTF-16 offset, clamped to its end
+fn utf8_offset(text: &str, offset_utf16: usize) -> usize {
+    let mut utf16_count = 0;
+    text.char_indices()
+        .find(|(_, ch)| {
+            utf16_count += ch.len_utf16();
+            utf16_count > offset_utf16
+        })
+        .map_or(text.len(), |(index, _)| index)
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::diff::CellDiff;
+
+/// Another CSV file shown beside the grid, its rows aligned to the grid's
+pub struct Comparison {
+    pub path: PathBuf,
+    pub cells: Vec<Vec<String>>,
+    /// Column whose values pair rows up; rows pair by position without one
+    pub key: Option<usize>,
+    // Row of each key value in the other file (the first, if repeated)
+    index: HashMap<String, usize>,
+}
+
+impl Comparison {
+    pub fn new(path: PathBuf, cells: Vec<Vec<String>>, key: Option<usize>) -> Self {
+        let mut index = HashMap::new();
+        if let Some(key) = key {
+            for (row, values) in cells.iter().enumerate() {
+                if !values[key].is_empty() {
+                    index.entry(values[key].clone()).or_insert(row);
+                }
+            }
+        }
+        Self { path, cells, key, index }
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// The other file's row paired with a grid row
+    pub fn aligned_row(&self, cells: &[Vec<String>], row: usize) -> Option<usize> {
+        match self.key {
+            Some(key) => self.index.get(&cells[row][key]).copied(),
+            None => Some(row),
+        }
+    }
+
+    /// How a grid cell differs from its counterpart; a filled row with no counterpart is added
+    pub fn cell_diff(&self, cells: &[Vec<String>], row: usize, col: usize) -> Option<CellDiff> {
+        match self.aligned_row(cells, row) {
+            Some(other) => CellDiff::between(&self.cells[other][col], &cells[row][col]),
+            None => (!cells[row][col].is_empty()).then_some(CellDiff::Added),
+        }
+    }
+
+    /// Count cells that differ, and filled rows of the other file no grid row pairs with
+    pub fn summary(&self, cells: &[Vec<String>]) -> (usize, usize) {
+        let mut differing = 0;
+        let mut paired = HashSet::new();
+        for row in 0..cells.len() {
+            if let Some(other) = self.aligned_row(cells, row)
+                && cells[row].iter().any(|cell| !cell.is_empty())
+            {
+                paired.insert(other);
+            }
+            differing += (0..cells[row].len()).filter(|&col| self.cell_diff(cells, row, col).is_some()).count();
+        }
+        let unpaired = (0..self.cells.len())
+            .filter(|row| !paired.contains(row) && self.cells[*row].iter().any(|cell| !cell.is_empty()))
+            .count();
+        (differing, unpaired)
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Where `.zsheets` metadata files are kept
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataStore {
+    /// Next to the CSV file, as `<name>.csv.zsheets`
+    #[default]
+    Sidecar,
+    /// In the app support directory, named by a hash of the CSV file's path
+    Central,
+}
+
+/// User settings read from `config.json` in the app support directory
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub metadata_store: MetadataStore,
+    /// Hex color, e.g. `#ff6b6b`, used instead of the system accent
+    pub accent_color: Option<String>,
+    /// Avoid animation; unset follows the system setting where there is one
+    pub reduced_motion: Option<bool>,
+    /// Rows or columns one notch of a mouse wheel scrolls
+    pub scroll_speed: Option<usize>,
+    /// Headers sent with HTTP requests to each host, e.g.
+    /// `{"api.example.com": {"Authorization": "Bearer ..."}}`
+    pub http_headers: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Config {
+    /// The settings, loaded on first use; a missing or unreadable file gives the defaults
+    pub fn get() -> &'static Config {
+        static CONFIG: OnceLock<Config> = OnceLock::new();
+        CONFIG.get_or_init(|| {
+            let Some(path) = app_support_dir().map(|dir| dir.join("config.json")) else {
+                return Config::default();
+            };
+            match std::fs::read_to_string(&path) {
+                Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                    eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                    Config::default()
+                }),
+                Err(_) => Config::default(),
+            }
+        })
+    }
+}
+
+/// Whether to avoid animation, from the config file or else the system
+pub fn reduced_motion() -> bool {
+    Config::get().reduced_motion.unwrap_or_else(system_reduced_motion)
+}
+
+#[cfg(target_os = "macos")]
+fn system_reduced_motion() -> bool {
+    objc2_app_kit::NSWorkspace::sharedWorkspace().accessibilityDisplayShouldReduceMotion()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn system_reduced_motion() -> bool {
+    false
+}
+
+/// Where the user keeps scripts and plugin settings: `~/.config/zsheets`
+pub fn config_dir() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("zsheets"))
+}
+
+/// The app's own data directory
+pub fn app_support_dir() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library/Application Support/zsheets"))
+    } else {
+        let data = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".local/share"));
+        Some(data.join("zsheets"))
+    }
+}
@@ -0,0 +1,129 @@
+use gpui::{Pixels, Point};
+
+/// What a context menu was opened on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuTarget {
+    Cell,
+    Rows,
+    Columns,
+}
+
+/// An open right-click menu, drawn at the pointer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContextMenu {
+    pub target: MenuTarget,
+    pub position: Point<Pixels>,
+}
+
+/// Entries of the right-click menu. They act on the selection, which right-clicking sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextAction {
+    Cut,
+    Copy,
+    Paste,
+    ClearContents,
+    ClearFormats,
+    ClearAll,
+    InsertBefore,
+    InsertAfter,
+    DeleteRows,
+    DeleteColumns,
+    AutoFit,
+    ResetSize,
+    Hide,
+    Unhide,
+    SortAscending,
+    SortDescending,
+    Filter,
+    Bold,
+    Italic,
+    Underline,
+    FormatGeneral,
+    FormatCurrency,
+    FormatPercent,
+}
+
+impl MenuTarget {
+    /// Menu entries in order, with `None` for separators
+    pub fn items(self) -> &'static [Option<ContextAction>] {
+        use ContextAction::*;
+        match self {
+            MenuTarget::Cell => &[
+                Some(Cut), Some(Copy), Some(Paste), None,
+                Some(ClearContents), Some(ClearFormats), Some(ClearAll), None,
+                Some(InsertBefore), Some(DeleteRows), Some(DeleteColumns), None,
+                Some(SortAscending), Some(SortDescending), Some(Filter), None,
+                Some(Bold), Some(Italic), Some(Underline), None,
+                Some(FormatGeneral), Some(FormatCurrency), Some(FormatPercent),
+            ],
+            MenuTarget::Rows => &[
+                Some(Cut), Some(Copy), Some(Paste), None,
+                Some(ClearContents), Some(ClearFormats), Some(ClearAll), None,
+                Some(InsertBefore), Some(InsertAfter), Some(DeleteRows), None,
+                Some(AutoFit), Some(ResetSize), Some(Hide), Some(Unhide), None,
+                Some(Bold), Some(Italic), Some(Underline), None,
+                Some(FormatGeneral), Some(FormatCurrency), Some(FormatPercent),
+            ],
+            MenuTarget::Columns => &[
+                Some(Cut), Some(Copy), Some(Paste), None,
+                Some(ClearContents), Some(ClearFormats), Some(ClearAll), None,
+                Some(InsertBefore), Some(InsertAfter), Some(DeleteColumns), None,
+                Some(AutoFit), Some(ResetSize), Some(Hide), Some(Unhide), None,
+                Some(SortAscending), Some(SortDescending), Some(Filter), None,
+                Some(Bold), Some(Italic), Some(Underline), None,
+                Some(FormatGeneral), Some(FormatCurrency), Some(FormatPercent),
+            ],
+        }
+    }
+}
+
+impl ContextAction {
+    pub fn label(self, target: MenuTarget) -> &'static str {
+        let columns = target == MenuTarget::Columns;
+        match self {
+            ContextAction::Cut => "Cut",
+            ContextAction::Copy => "Copy",
+            ContextAction::Paste => "Paste",
+            ContextAction::ClearContents => "Clear Contents",
+            ContextAction::ClearFormats => "Clear Formats",
+            ContextAction::ClearAll => "Clear All",
+            ContextAction::InsertBefore if columns => "Insert Columns Left",
+            ContextAction::InsertBefore => "Insert Rows Above",
+            ContextAction::InsertAfter if columns => "Insert Columns Right",
+            ContextAction::InsertAfter => "Insert Rows Below",
+            ContextAction::DeleteRows => "Delete Rows",
+            ContextAction::DeleteColumns => "Delete Columns",
+            ContextAction::AutoFit if columns => "Auto-fit Width",
+            ContextAction::AutoFit => "Auto-fit Height",
+            ContextAction::ResetSize if columns => "Reset Width",
+            ContextAction::ResetSize => "Reset Height",
+            ContextAction::Hide if columns => "Hide Columns",
+            ContextAction::Hide => "Hide Rows",
+            ContextAction::Unhide if columns => "Unhide Columns",
+            ContextAction::Unhide => "Unhide Rows",
+            ContextAction::SortAscending => "Sort Ascending",
+            ContextAction::SortDescending => "Sort Descending",
+            ContextAction::Filter => "Filter Column",
+            ContextAction::Bold => "Bold",
+            ContextAction::Italic => "Italic",
+            ContextAction::Underline => "Underline",
+            ContextAction::FormatGeneral => "Format: General",
+            ContextAction::FormatCurrency => "Format: Currency",
+            ContextAction::FormatPercent => "Format: Percent",
+        }
+    }
+
+    /// Whether the entry changes cells (and so is unavailable in read-only files).
+    /// Sizing and hiding are view settings, like dragging a header edge.
+    pub fn edits(self) -> bool {
+        !matches!(
+            self,
+            ContextAction::Copy
+                | ContextAction::AutoFit
+                | ContextAction::ResetSize
+                | ContextAction::Hide
+                | ContextAction::Unhide
+                | ContextAction::Filter
+        )
+    }
+}
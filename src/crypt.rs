@@ -0,0 +1,42 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use age::secrecy::SecretString;
+
+use crate::file_io;
+
+/// How every age-encrypted file starts, in binary or armored form
+const AGE_MAGIC: &[&[u8]] = &[b"age-encryption.org/v1", b"-----BEGIN AGE ENCRYPTED FILE-----"];
+
+/// Whether a file is encrypted with a passphrase, judging by its first bytes
+pub fn is_encrypted_file(path: &Path) -> bool {
+    let mut start = [0u8; 34];
+    let Ok(read) = file_io::open_reader(path).and_then(|mut file| file.read(&mut start)) else {
+        return false;
+    };
+    AGE_MAGIC.iter().any(|magic| start[..read].starts_with(magic))
+}
+
+/// Encrypt with a passphrase, in the age format so files can also be opened with `age -d`
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_user_passphrase(SecretString::from(passphrase.to_string()));
+    let mut output = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut output)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(output)
+}
+
+pub fn decrypt(ciphertext: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(ciphertext)).map_err(io::Error::other)?;
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_string()));
+    let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).map_err(|e| match e {
+        age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys => {
+            io::Error::new(io::ErrorKind::PermissionDenied, "Wrong passphrase")
+        }
+        e => io::Error::other(e),
+    })?;
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
@@ -0,0 +1,326 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+const MONTHS: &[&str] = &[
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+const WEEKDAYS: &[&str] = &["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+/// A date and/or time of day recognized in cell text. Compares chronologically;
+/// values with only a time sort before values with a date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    /// Days since 1970-01-01, when the text has a date
+    days: Option<i64>,
+    /// Seconds since midnight, when the text has a time
+    seconds: Option<u32>,
+}
+
+impl DateTime {
+    /// Recognize a date/time: `2024-01-31`, `2024-01-31T14:05:00`, `1/31/2024` (day first when
+    /// the first number is over 12), `31.01.2024`, `Jan 31, 2024`, `31 January 2024`,
+    /// `14:05`, `2:05 PM`, or a date followed by a time
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        // ISO 8601 joins date and time with `T`
+        let text = match text.get(10..11) {
+            Some("T") if text.as_bytes()[4] == b'-' => format!("{} {}", &text[..10], &text[11..]),
+            _ => text.to_string(),
+        };
+        let words: Vec<&str> = text
+            .split_whitespace()
+            .map(|word| word.trim_end_matches(','))
+            .filter(|word| !word.is_empty())
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+
+        let time_start = words.iter().position(|word| word.contains(':')).unwrap_or(words.len());
+        let (date_words, time_words) = words.split_at(time_start);
+        let seconds = if time_words.is_empty() { None } else { Some(parse_time(time_words)?) };
+        let days = if date_words.is_empty() { None } else { Some(parse_date(date_words)?) };
+        if days.is_none() && seconds.is_none() {
+            return None;
+        }
+        Some(Self { days, seconds })
+    }
+
+    /// The current local date and time
+    pub fn now() -> Self {
+        // Local wall-clock time counted as if it were UTC, so days split at local midnight
+        let timestamp = chrono::Local::now().naive_local().and_utc().timestamp();
+        Self {
+            days: Some(timestamp.div_euclid(86400)),
+            seconds: Some(timestamp.rem_euclid(86400) as u32),
+        }
+    }
+
+    /// Just the date, without the time of day
+    pub fn date(self) -> Self {
+        Self { days: self.days, seconds: None }
+    }
+
+    /// Spreadsheet serial number: days since 1899-12-30, with the time as a fraction
+    pub fn to_serial(self) -> f64 {
+        let days = self.days.map_or(0, |days| days + 25569);
+        days as f64 + self.seconds.unwrap_or(0) as f64 / 86400.0
+    }
+
+    /// Inverse of `to_serial`; a whole number has no time of day
+    pub fn from_serial(serial: f64) -> Self {
+        let days = serial.floor();
+        let seconds = ((serial - days) * 86400.0).round() as u32;
+        let (days, seconds) = if seconds >= 86400 { (days + 1.0, 0) } else { (days, seconds) };
+        Self {
+            days: Some(days as i64 - 25569),
+            seconds: (seconds > 0).then_some(seconds),
+        }
+    }
+
+    /// A date from its parts, rolling over out-of-range months and days as `DATE` does:
+    /// month 13 is January of the next year, day 0 the last day of the previous month
+    pub fn from_parts(year: i64, month: i64, day: i64) -> Self {
+        let months = year * 12 + month - 1;
+        let first = days_from_civil(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1);
+        Self { days: Some(first + day - 1), seconds: None }
+    }
+
+    /// The same time of day `days` days later (or earlier, when negative)
+    pub fn add_days(self, days: i64) -> Self {
+        Self { days: self.days.map(|d| d + days), seconds: self.seconds }
+    }
+
+    /// Whether there is a date part, not just a time of day
+    pub fn has_date(self) -> bool {
+        self.days.is_some()
+    }
+
+    /// (year, month, day); a time alone falls on serial day 0, 1899-12-30
+    pub fn parts(self) -> (i64, u32, u32) {
+        civil_from_days(self.days.unwrap_or(-25569))
+    }
+
+    /// The last day of the month `months` months away
+    pub fn end_of_month(self, months: i64) -> Self {
+        let (year, month, _) = self.parts();
+        Self::from_parts(year, month as i64 + months + 1, 0)
+    }
+}
+
+/// ISO 8601 with a space before the time: `2024-01-31`, `14:05:00`, or `2024-01-31 14:05:00`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(days) = self.days {
+            let (year, month, day) = civil_from_days(days);
+            write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+        }
+        if let Some(seconds) = self.seconds {
+            let separator = if self.days.is_some() { " " } else { "" };
+            write!(f, "{}{:02}:{:02}:{:02}", separator, seconds / 3600, seconds / 60 % 60, seconds % 60)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the date words of a date/time, returning days since the epoch
+fn parse_date(words: &[&str]) -> Option<i64> {
+    // Skip a leading weekday, e.g. "Mon, Jan 5 2024"
+    let words = match words.first().and_then(|word| lookup_name(word, WEEKDAYS)) {
+        Some(_) if words.len() > 1 => &words[1..],
+        _ => words,
+    };
+
+    let (year, month, day) = match words {
+        [date] => parse_numeric_date(date)?,
+        [first, second, year] => {
+            let year = parse_year(year)?;
+            match lookup_name(first, MONTHS) {
+                Some(month) => (year, month, second.parse().ok()?),
+                None => (year, lookup_name(second, MONTHS)?, first.parse().ok()?),
+            }
+        }
+        _ => return None,
+    };
+    (day >= 1 && day <= days_in_month(year, month)).then(|| days_from_civil(year, month, day))
+}
+
+/// Parse `2024-01-31`, `1/31/2024`, `31.01.2024`, or `31-Jan-2024`
+fn parse_numeric_date(text: &str) -> Option<(i64, u32, u32)> {
+    let separator = text.chars().find(|c| matches!(c, '-' | '/' | '.'))?;
+    let parts: Vec<&str> = text.split(separator).collect();
+    let [a, b, c] = parts[..] else {
+        return None;
+    };
+
+    if a.len() == 4 {
+        // Year first: ISO order
+        return Some((a.parse().ok()?, b.parse().ok()?, c.parse().ok()?));
+    }
+    let year = parse_year(c)?;
+    if let Some(month) = lookup_name(b, MONTHS) {
+        return Some((year, month, a.parse().ok()?));
+    }
+    let (a, b): (u32, u32) = (a.parse().ok()?, b.parse().ok()?);
+    // Slashes read month first unless that can't be a month; dots and dashes read day first
+    if separator == '/' && a <= 12 {
+        Some((year, a, b))
+    } else {
+        Some((year, b, a))
+    }
+}
+
+/// Parse a four-digit year, or a two-digit one (70-99 -> 1900s, otherwise 2000s)
+fn parse_year(text: &str) -> Option<i64> {
+    if !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = text.parse().ok()?;
+    match text.len() {
+        4 => Some(year),
+        2 if year >= 70 => Some(1900 + year),
+        2 => Some(2000 + year),
+        _ => None,
+    }
+}
+
+/// Parse `14:05`, `14:05:30`, `2:05 PM`, or `2:05pm` into seconds since midnight
+fn parse_time(words: &[&str]) -> Option<u32> {
+    let joined = words.join("").to_lowercase();
+    let (clock, meridiem) = match joined.strip_suffix("am").or_else(|| joined.strip_suffix("a.m.")) {
+        Some(clock) => (clock, Some(false)),
+        None => match joined.strip_suffix("pm").or_else(|| joined.strip_suffix("p.m.")) {
+            Some(clock) => (clock, Some(true)),
+            None => (joined.trim_end_matches('z'), None),
+        },
+    };
+
+    let parts: Vec<u32> = clock.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let (mut hour, minute, second) = match parts[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return None,
+    };
+    match meridiem {
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some(pm) => hour = hour % 12 + if pm { 12 } else { 0 },
+        None => {}
+    }
+    (hour < 24 && minute < 60 && second < 60).then_some(hour * 3600 + minute * 60 + second)
+}
+
+/// 1-based index of a month or weekday name, full or abbreviated to three letters
+fn lookup_name(word: &str, names: &[&str]) -> Option<u32> {
+    let word = word.trim_end_matches('.').to_lowercase();
+    if word.len() < 3 {
+        return None;
+    }
+    names
+        .iter()
+        .position(|name| name.to_lowercase().starts_with(&word) && (word.len() == 3 || word.len() == name.len()))
+        .map(|index| index as u32 + 1)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`: (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Longest first so `YYYY` wins over `YY` and `MMMM` over `MM`
+const TOKENS: &[&str] = &[
+    "YYYY", "YY", "MMMM", "MMM", "MM", "M", "DDDD", "DDD", "DD", "D", "HH", "hh", "mm", "ss", "A",
+];
+
+/// A display pattern for date cells, e.g. `YYYY-MM-DD` or `MMM D, YYYY`. Tokens: `YYYY`/`YY`
+/// year, `MMMM`/`MMM`/`MM`/`M` month, `DD`/`D` day, `DDDD`/`DDD` weekday, `HH` (24h) or `hh`
+/// (12h) hour, `mm` minute, `ss` second, `A` AM/PM; anything else is copied literally.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct DateFormat(String);
+
+impl DateFormat {
+    /// Parse a pattern, or one of the presets `iso`, `us`, `eu`, `uk`, `long`
+    pub fn parse(input: &str) -> Option<Self> {
+        let pattern = match input.trim().to_lowercase().as_str() {
+            "iso" => "YYYY-MM-DD",
+            "us" => "MM/DD/YYYY",
+            "eu" => "DD.MM.YYYY",
+            "uk" => "DD/MM/YYYY",
+            "long" => "MMMM D, YYYY",
+            _ => input.trim(),
+        };
+        let has_token = pattern.contains(['Y', 'M', 'D', 'H', 'h', 'm', 's']);
+        has_token.then(|| Self(pattern.to_string()))
+    }
+
+    /// Display a value in this format. Values without a date (a bare time) are left to
+    /// the caller, since most patterns would show a meaningless date for them.
+    pub fn format(&self, value: DateTime) -> Option<String> {
+        let (year, month, day) = civil_from_days(value.days?);
+        let seconds = value.seconds.unwrap_or(0);
+        let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        let month_name = MONTHS[month as usize - 1];
+        let weekday_name = WEEKDAYS[(value.days? + 4).rem_euclid(7) as usize];
+
+        let mut result = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(c) = rest.chars().next() {
+            let Some(token) = TOKENS.iter().find(|token| rest.starts_with(**token)) else {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            let text = match *token {
+                "YYYY" => year.to_string(),
+                "YY" => format!("{:02}", year.rem_euclid(100)),
+                "MMMM" => month_name.to_string(),
+                "MMM" => month_name[..3].to_string(),
+                "MM" => format!("{:02}", month),
+                "M" => month.to_string(),
+                "DDDD" => weekday_name.to_string(),
+                "DDD" => weekday_name[..3].to_string(),
+                "DD" => format!("{:02}", day),
+                "D" => day.to_string(),
+                "HH" => format!("{:02}", hour),
+                "hh" => format!("{:02}", (hour + 11) % 12 + 1),
+                "mm" => format!("{:02}", minute),
+                "ss" => format!("{:02}", second),
+                _ => (if hour < 12 { "AM" } else { "PM" }).to_string(),
+            };
+            result.push_str(&text);
+            rest = &rest[token.len()..];
+        }
+        Some(result)
+    }
+}
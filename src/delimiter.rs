@@ -0,0 +1,37 @@
+use regex::Regex;
+
+/// What to split cell text on for text-to-columns
+#[derive(Clone, Debug)]
+pub enum Delimiter {
+    Text(String),
+    Regex(Regex),
+}
+
+impl Delimiter {
+    /// Parse a delimiter argument: `/pattern/` for a regex, the names `tab`, `space`,
+    /// `comma`, `semicolon`, `pipe`, or any literal text
+    pub fn parse(input: &str) -> Result<Self, String> {
+        if let Some(pattern) = input.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            return Regex::new(pattern)
+                .map(Self::Regex)
+                .map_err(|e| format!("Invalid regex: {}", e));
+        }
+        let text = match input.to_lowercase().as_str() {
+            "" => return Err("Missing delimiter".to_string()),
+            "tab" => "\t",
+            "space" => " ",
+            "comma" => ",",
+            "semicolon" => ";",
+            "pipe" => "|",
+            _ => input,
+        };
+        Ok(Self::Text(text.to_string()))
+    }
+
+    pub fn split(&self, text: &str) -> Vec<String> {
+        match self {
+            Self::Text(delimiter) => text.split(delimiter.as_str()).map(str::to_string).collect(),
+            Self::Regex(regex) => regex.split(text).map(str::to_string).collect(),
+        }
+    }
+}
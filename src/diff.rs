@@ -0,0 +1,75 @@
+/// How a cell or row differs from the version it's compared against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellDiff {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl CellDiff {
+    /// Compare a cell's text with its text in the other version
+    pub fn between(old: &str, new: &str) -> Option<Self> {
+        match (old.is_empty(), new.is_empty()) {
+            _ if old == new => None,
+            (true, false) => Some(CellDiff::Added),
+            (false, true) => Some(CellDiff::Removed),
+            _ => Some(CellDiff::Changed),
+        }
+    }
+
+    /// Summarize several differences: one kind if they agree, else `Changed`
+    pub fn combine(diffs: impl IntoIterator<Item = CellDiff>) -> Option<Self> {
+        diffs.into_iter().reduce(|a, b| if a == b { a } else { CellDiff::Changed })
+    }
+}
+
+/// Mark each row of `new` that differs from `old`: rows matched in order are unchanged,
+/// and unmatched rows are `Changed` where they stand in for removed rows, else `Added`
+pub fn diff_rows(old: &[Vec<String>], new: &[Vec<String>]) -> Vec<Option<CellDiff>> {
+    let old: Vec<&[String]> = old[..used_rows(old)].iter().map(|row| trim_row(row)).collect();
+    let new_used: Vec<&[String]> = new[..used_rows(new)].iter().map(|row| trim_row(row)).collect();
+
+    // Longest common subsequence of rows, from the end
+    let (m, n) = (old.len(), new_used.len());
+    let mut lcs = vec![vec![0usize; n + 1]; m + 1];
+    for i in (0..m).rev() {
+        for j in (0..n).rev() {
+            lcs[i][j] = if old[i] == new_used[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut marks = vec![None; new.len()];
+    let (mut i, mut j) = (0, 0);
+    // Rows removed in the current run of differences, which added rows replace first
+    let mut removed = 0;
+    while j < n {
+        if i < m && old[i] == new_used[j] {
+            removed = 0;
+            i += 1;
+            j += 1;
+        } else if i < m && lcs[i + 1][j] >= lcs[i][j + 1] {
+            removed += 1;
+            i += 1;
+        } else {
+            marks[j] = Some(if removed > 0 {
+                removed -= 1;
+                CellDiff::Changed
+            } else {
+                CellDiff::Added
+            });
+            j += 1;
+        }
+    }
+    marks
+}
+
+/// Number of rows up to the last one with any content
+fn used_rows(rows: &[Vec<String>]) -> usize {
+    rows.iter().rposition(|row| row.iter().any(|cell| !cell.is_empty())).map_or(0, |last| last + 1)
+}
+
+/// A row without its trailing empty cells, so padding doesn't count as a difference
+fn trim_row(row: &[String]) -> &[String] {
+    let len = row.iter().rposition(|cell| !cell.is_empty()).map_or(0, |last| last + 1);
+    &row[..len]
+}
@@ -1,14 +1,347 @@
-use std::io;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use serde::{Deserialize, Serialize};
+
 use crate::state::{GRID_COLS, GRID_ROWS};
+use crate::value::ColumnType;
+
+/// Starts the first line of a CSV file that carries its own layout metadata
+pub const EMBEDDED_METADATA_PREFIX: &str = "#zsheets ";
+
+/// Split a file's embedded metadata line, if any, from its CSV data
+pub fn split_embedded_metadata(content: &str) -> (Option<&str>, &str) {
+    match content.strip_prefix(EMBEDDED_METADATA_PREFIX) {
+        Some(rest) => {
+            let (line, data) = rest.split_once('\n').unwrap_or((rest, ""));
+            (Some(line.trim_end_matches('\r')), data)
+        }
+        None => (None, content),
+    }
+}
+
+/// Compression a file is stored with, going by its last extension (`sales.csv.gz`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).as_deref() {
+            Some("gz" | "gzip") => Self::Gzip,
+            Some("zst" | "zstd") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Open a file for reading, decompressing it as it's read
+pub fn open_reader(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+    })
+}
+
+/// A file's contents, decompressed
+pub fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open_reader(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// A file's text, decompressed
+pub fn read_text(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    open_reader(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// A file being written, compressed as it's written if its extension asks for it. Call
+/// `finish` once done so the compressed stream is ended properly.
+pub enum FileWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl FileWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_path(path) {
+            Compression::None => Self::Plain(file),
+            Compression::Gzip => Self::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Self::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Self::Plain(file) => file,
+            Self::Gzip(encoder) => encoder.finish()?,
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Write a whole file, compressed if its extension asks for it
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut writer = FileWriter::create(path)?;
+    writer.write_all(contents)?;
+    writer.finish()
+}
+
+/// Which fields are written in quotes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quoting {
+    /// Only fields that need it: those holding commas, quotes, or line breaks
+    #[default]
+    Minimal,
+    Always,
+    /// No field, even if that makes the file ambiguous
+    Never,
+}
+
+impl Quoting {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim() {
+            "minimal" => Some(Self::Minimal),
+            "always" | "all" => Some(Self::Always),
+            "never" | "none" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim() {
+            "lf" | "unix" => Some(Self::Lf),
+            "crlf" | "dos" => Some(Self::Crlf),
+            _ => None,
+        }
+    }
+
+    fn terminator(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+}
+
+/// How a file is written when saved; read from the file on load so saving keeps its style
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct WriteOptions {
+    pub quoting: Quoting,
+    pub line_ending: LineEnding,
+    /// End the last row with a line ending
+    pub final_newline: bool,
+    /// Write every row out to the last used column, rather than to its own last value
+    pub pad_rows: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self { quoting: Quoting::Minimal, line_ending: LineEnding::Lf, final_newline: true, pad_rows: true }
+    }
+}
 
-/// Read a CSV file into a 2D grid of strings
+impl WriteOptions {
+    /// The options a file was evidently written with. `Never` quoting can't be told apart
+    /// from `Minimal`, so it's never detected.
+    pub fn detect(content: &str) -> Self {
+        let (_, data) = split_embedded_metadata(content);
+        if data.is_empty() {
+            return Self::default();
+        }
+        let line_ending = match data.find('\n') {
+            Some(end) if data[..end].ends_with('\r') => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        };
+        let records = parse_records(data).unwrap_or_default();
+        let pad_rows = records.windows(2).all(|pair| pair[0].len() == pair[1].len());
+        // Quoted throughout if the first line is exactly its fields, each in quotes
+        let first_line = data.lines().next().unwrap_or_default();
+        let quoted = records.first().is_some_and(|fields| {
+            let quote = |field: &String| format!("\"{}\"", field.replace('"', "\"\""));
+            !fields.is_empty() && fields.iter().map(quote).collect::<Vec<_>>().join(",") == first_line
+        });
+        Self {
+            quoting: if quoted { Quoting::Always } else { Quoting::Minimal },
+            line_ending,
+            final_newline: data.ends_with('\n'),
+            pad_rows,
+        }
+    }
+}
+
+/// Read a CSV file into a 2D grid of strings, skipping any embedded metadata line
 pub fn read_csv(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    parse_csv(&read_text(path)?)
+}
+
+/// A CSV file as read: its cells, and how it was written so saving can keep to it
+pub struct CsvFile {
+    pub cells: Vec<Vec<String>>,
+    pub write_options: WriteOptions,
+    pub layout: CsvLayout,
+}
+
+/// Read a CSV file along with how it was written
+pub fn read_csv_file(path: &Path) -> io::Result<CsvFile> {
+    parse_csv_file(&read_text(path)?)
+}
+
+/// Parse a CSV file's text, e.g. once decrypted, along with how it was written
+pub fn parse_csv_file(content: &str) -> io::Result<CsvFile> {
+    let (_, data) = split_embedded_metadata(content);
+    Ok(CsvFile {
+        cells: parse_csv(content)?,
+        write_options: WriteOptions::detect(content),
+        layout: CsvLayout::parse(data),
+    })
+}
+
+/// A file's records exactly as written, so saving reproduces the fields that didn't change
+#[derive(Clone, Debug, Default)]
+pub struct CsvLayout {
+    /// Blank lines before the first record, which readers skip
+    prefix: String,
+    records: Vec<RawRecord>,
+    /// Last row and column that held a value
+    used_bounds: (usize, usize),
+}
+
+#[derive(Clone, Debug)]
+struct RawRecord {
+    fields: Vec<RawField>,
+    /// The line ending, followed by any blank lines after the record
+    ending: String,
+}
+
+#[derive(Clone, Debug)]
+struct RawField {
+    value: String,
+    /// The field as it appeared in the file, quotes and all
+    raw: String,
+}
+
+impl RawField {
+    fn new(raw: &str) -> Self {
+        let value = match raw.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\"\"", "\""),
+            None => raw.to_string(),
+        };
+        Self { value, raw: raw.to_string() }
+    }
+}
+
+impl CsvLayout {
+    /// How many of the file's rows differ from the grid's
+    pub fn edited_rows(&self, cells: &[Vec<String>]) -> usize {
+        self.records
+            .iter()
+            .zip(cells)
+            .filter(|(record, row)| {
+                row.iter().enumerate().any(|(col, cell)| match record.fields.get(col) {
+                    Some(field) => field.value != *cell,
+                    None => !cell.is_empty(),
+                })
+            })
+            .count()
+    }
+
+    /// Split CSV data into raw records the way the reader does, blank lines included
+    pub fn parse(data: &str) -> Self {
+        let mut layout = Self::default();
+        let mut fields = Vec::new();
+        let mut start = 0;
+        let mut in_quotes = false;
+        for (i, byte) in data.bytes().enumerate() {
+            match byte {
+                b'"' => in_quotes = !in_quotes,
+                b',' if !in_quotes => {
+                    fields.push(RawField::new(&data[start..i]));
+                    start = i + 1;
+                }
+                b'\n' if !in_quotes => {
+                    let end = if data[start..i].ends_with('\r') { i - 1 } else { i };
+                    if fields.is_empty() && end == start {
+                        // A blank line isn't a record; keep it with what comes before
+                        match layout.records.last_mut() {
+                            Some(record) => record.ending.push_str(&data[start..=i]),
+                            None => layout.prefix.push_str(&data[start..=i]),
+                        }
+                    } else {
+                        fields.push(RawField::new(&data[start..end]));
+                        let ending = data[end..=i].to_string();
+                        layout.records.push(RawRecord { fields: std::mem::take(&mut fields), ending });
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if start < data.len() {
+            fields.push(RawField::new(&data[start..]));
+            layout.records.push(RawRecord { fields, ending: String::new() });
+        }
+        for (row, record) in layout.records.iter().enumerate() {
+            if let Some(col) = record.fields.iter().rposition(|field| !field.value.is_empty()) {
+                layout.used_bounds = (row, layout.used_bounds.1.max(col));
+            }
+        }
+        layout
+    }
+}
+
+/// Parse CSV text into a 2D grid of strings, skipping any embedded metadata line
+pub fn parse_csv(content: &str) -> io::Result<Vec<Vec<String>>> {
+    let (_, data) = split_embedded_metadata(content);
     let mut reader = csv::ReaderBuilder::new()
         .has_headers(false)
         .flexible(true)
-        .from_path(path)?;
+        .from_reader(data.as_bytes());
 
     let mut cells: Vec<Vec<String>> = (0..GRID_ROWS)
         .map(|_| (0..GRID_COLS).map(|_| String::new()).collect())
@@ -30,28 +363,325 @@ pub fn read_csv(path: &Path) -> io::Result<Vec<Vec<String>>> {
     Ok(cells)
 }
 
-/// Write a 2D grid of strings to a CSV file
-pub fn write_csv(path: &Path, cells: &[Vec<String>]) -> io::Result<()> {
-    let mut writer = csv::WriterBuilder::new()
+/// Parse CSV text into just the rows it holds
+pub fn parse_records(content: &str) -> io::Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
         .has_headers(false)
-        .from_path(path)?;
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    reader
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+        .collect()
+}
+
+/// Rows laid out in a grid's worth of cells, dropping what falls past its edges
+pub fn fit_to_grid(rows: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    let mut cells = vec![vec![String::new(); GRID_COLS]; GRID_ROWS];
+    for (row, values) in cells.iter_mut().zip(rows) {
+        for (cell, value) in row.iter_mut().zip(values) {
+            *cell = value;
+        }
+    }
+    cells
+}
+
+/// Formats `zsheets convert` reads and writes, picked by file extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    Xlsx,
+    Parquet,
+}
 
-    // Find the actual used bounds to avoid writing empty trailing rows/cols
+impl Format {
+    /// The format under any compression, so `sales.csv.gz` is CSV
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = match Compression::from_path(path) {
+            Compression::None => path.to_path_buf(),
+            _ => path.with_extension(""),
+        };
+        match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "tsv" | "tab" => Some(Self::Tsv),
+            "json" => Some(Self::Json),
+            "xlsx" => Some(Self::Xlsx),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// Read just the rows a file holds. JSON is an array of arrays, or of objects whose keys
+/// become a header row.
+pub fn read_rows(path: &Path, format: Format) -> io::Result<Vec<Vec<String>>> {
+    match format {
+        Format::Csv => {
+            let content = read_text(path)?;
+            parse_records(split_embedded_metadata(&content).1)
+        }
+        Format::Tsv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .delimiter(b'\t')
+                .from_reader(open_reader(path)?);
+            reader
+                .records()
+                .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+                .collect()
+        }
+        Format::Json => {
+            let items: Vec<serde_json::Value> = serde_json::from_reader(open_reader(path)?)?;
+            Ok(json_rows(&items))
+        }
+        Format::Xlsx => crate::xlsx::read(path),
+        Format::Parquet => crate::parquet::read(path),
+    }
+}
+
+/// Write rows in `format`. JSON is an array of objects keyed by the first row, or of
+/// arrays when the first row can't serve as keys. Formats that store typed values go by
+/// `column_types` where a column has one.
+pub fn write_rows(path: &Path, rows: &[Vec<String>], format: Format, column_types: &BTreeMap<usize, ColumnType>) -> io::Result<()> {
+    match format {
+        Format::Csv | Format::Tsv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .delimiter(if format == Format::Tsv { b'\t' } else { b',' })
+                .from_writer(FileWriter::create(path)?);
+            for row in rows {
+                writer.write_record(row)?;
+            }
+            writer.into_inner().map_err(|e| e.into_error())?.finish()
+        }
+        Format::Json => {
+            use serde_json::{Map, Value};
+
+            let header = rows.first().map(Vec::as_slice).unwrap_or_default();
+            let keyed = !header.is_empty()
+                && header.iter().enumerate().all(|(i, key)| !key.is_empty() && !header[..i].contains(key));
+            let items: Vec<Value> = if keyed {
+                rows[1..]
+                    .iter()
+                    .map(|row| {
+                        let mut object = Map::new();
+                        for (col, key) in header.iter().enumerate() {
+                            object.insert(key.clone(), Value::String(row.get(col).cloned().unwrap_or_default()));
+                        }
+                        Value::Object(object)
+                    })
+                    .collect()
+            } else {
+                rows.iter().map(|row| Value::from(row.clone())).collect()
+            };
+            let mut file = FileWriter::create(path)?;
+            serde_json::to_writer_pretty(&mut file, &items)?;
+            writeln!(file)?;
+            file.finish()
+        }
+        Format::Xlsx => crate::xlsx::write(path, rows, column_types),
+        Format::Parquet => crate::parquet::write(path, rows, column_types),
+    }
+}
+
+/// Rows from JSON items: arrays become rows as they are, and objects line up under a
+/// header row of their keys in the order first seen
+pub fn json_rows(items: &[serde_json::Value]) -> Vec<Vec<String>> {
+    use serde_json::Value;
+
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    // Keys in the order they're first seen
+    let mut keys: Vec<&String> = Vec::new();
+    for item in items {
+        if let Value::Object(object) = item {
+            for key in object.keys() {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+    let mut rows = Vec::new();
+    if !keys.is_empty() {
+        rows.push(keys.iter().map(|key| key.to_string()).collect());
+    }
+    for item in items {
+        rows.push(match item {
+            Value::Object(object) => keys.iter().map(|&key| object.get(key).map(text).unwrap_or_default()).collect(),
+            Value::Array(values) => values.iter().map(text).collect(),
+            value => vec![text(value)],
+        });
+    }
+    rows
+}
+
+/// Complete lines appended to a file since `offset`, moving `offset` past them. A trailing
+/// partial line is left for the next read. Fails if the file shrank.
+pub fn read_appended(path: &Path, offset: &mut u64) -> io::Result<Option<String>> {
+    let len = std::fs::metadata(path)?.len();
+    if len < *offset {
+        return Err(io::Error::other("File was truncated"));
+    }
+    if len == *offset {
+        return Ok(None);
+    }
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut bytes = Vec::new();
+    file.take(len - *offset).read_to_end(&mut bytes)?;
+    let Some(end) = bytes.iter().rposition(|&byte| byte == b'\n') else {
+        return Ok(None);
+    };
+    *offset += end as u64 + 1;
+    Ok(Some(String::from_utf8_lossy(&bytes[..=end]).into_owned()))
+}
+
+/// Write a 2D grid of strings to a CSV file, led by an embedded metadata line if given, and
+/// encrypted if there's a passphrase. Fields unchanged since `layout` was read are written as
+/// they were, and its empty trailing rows and columns are kept unless content was removed
+/// from that edge. Rows and columns past the grid's edge are written as read. Returns the
+/// layout of what was written.
+pub fn write_csv(
+    path: &Path,
+    cells: &[Vec<String>],
+    metadata: Option<&str>,
+    options: &WriteOptions,
+    layout: &CsvLayout,
+    passphrase: Option<&str>,
+) -> io::Result<CsvLayout> {
+    let terminator = options.line_ending.terminator();
     let (max_row, max_col) = find_used_bounds(cells);
+    let is_empty = max_row == 0 && cells[0].iter().all(String::is_empty);
+    // The grid can't have removed content it doesn't reach, so those edges stay where they are
+    let grid_rows = cells.len();
+    let rows_past_grid = layout.used_bounds.0 >= grid_rows;
+    let cols_past_grid = layout.used_bounds.1 >= cells[0].len();
+    let row_count = match is_empty {
+        true => layout.records.len(),
+        false if rows_past_grid || max_row >= layout.used_bounds.0 => (max_row + 1).max(layout.records.len()),
+        false => max_row + 1,
+    };
+    let keep_cols = cols_past_grid || max_col >= layout.used_bounds.1;
 
-    for row in 0..=max_row {
-        let row_data: Vec<&str> = (0..=max_col)
-            .map(|col| cells[row][col].as_str())
-            .collect();
-        writer.write_record(&row_data)?;
+    let mut data = String::new();
+    if row_count > 0 {
+        data.push_str(&layout.prefix);
+    }
+    for row in 0..row_count {
+        let original = layout.records.get(row);
+        let cells = cells.get(row).map_or(&[][..], Vec::as_slice);
+        let used_width = cells.iter().rposition(|cell| !cell.is_empty()).map_or(0, |col| col + 1);
+        let original_width = original.map_or(0, |record| record.fields.len());
+        let width = if options.pad_rows { max_col + 1 } else { used_width };
+        let width = match keep_cols || row >= grid_rows {
+            true => width.max(original_width),
+            false => width.max(original_width.min(max_col + 1)),
+        };
+        push_row(&mut data, cells, width, original, options.quoting);
+
+        let ending = original.map(|record| record.ending.as_str()).filter(|ending| !ending.is_empty());
+        if row + 1 < row_count || options.final_newline {
+            data.push_str(ending.unwrap_or(terminator));
+        }
+    }
+
+    let mut content = String::new();
+    if let Some(metadata) = metadata {
+        content.push_str(EMBEDDED_METADATA_PREFIX);
+        content.push_str(metadata);
+        content.push_str(terminator);
+    }
+    content.push_str(&data);
+    match passphrase {
+        Some(passphrase) => write_file(path, &crate::crypt::encrypt(content.as_bytes(), passphrase)?)?,
+        None => write_file(path, content.as_bytes())?,
+    }
+    Ok(CsvLayout::parse(&data))
+}
+
+/// Write a row's first `width` fields, those unchanged from `original` as they were
+fn push_row(data: &mut String, cells: &[String], width: usize, original: Option<&RawRecord>, quoting: Quoting) {
+    // An empty line would be skipped on reading, so an empty row is one empty field
+    let width = width.max(1);
+    for col in 0..width {
+        if col > 0 {
+            data.push(',');
+        }
+        let original = original.and_then(|record| record.fields.get(col));
+        match (cells.get(col), original) {
+            (Some(cell), Some(field)) if *cell == field.value => data.push_str(&field.raw),
+            // Past the grid's last column; the file's own field survives
+            (None, Some(field)) => data.push_str(&field.raw),
+            (cell, _) => push_field(data, cell.map_or("", String::as_str), quoting, width == 1),
+        }
+    }
+}
+
+/// Add the rows below those in `layout` to the end of the file, leaving the rest of it
+/// untouched, and extend `layout` with them. Returns how many rows were added.
+pub fn append_csv(path: &Path, cells: &[Vec<String>], options: &WriteOptions, layout: &mut CsvLayout) -> io::Result<usize> {
+    let terminator = options.line_ending.terminator();
+    let (max_row, max_col) = find_used_bounds(cells);
+    let first = layout.records.len();
+    if max_row < first || (max_row == 0 && cells[0].iter().all(String::is_empty)) {
+        return Ok(0);
     }
+    let mut data = String::new();
+    for row in &cells[first..=max_row] {
+        let width = match options.pad_rows {
+            true => max_col + 1,
+            false => row.iter().rposition(|cell| !cell.is_empty()).map_or(0, |col| col + 1),
+        };
+        push_row(&mut data, row, width, None, options.quoting);
+        data.push_str(terminator);
+    }
+    if !options.final_newline {
+        data.truncate(data.len() - terminator.len());
+    }
+    // A file without a final newline needs one before the new rows
+    let separator = match layout.records.last() {
+        Some(record) if record.ending.is_empty() => terminator,
+        _ => "",
+    };
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+    file.write_all(format!("{}{}", separator, data).as_bytes())?;
 
-    writer.flush()?;
-    Ok(())
+    if let Some(record) = layout.records.last_mut() {
+        record.ending.push_str(separator);
+    }
+    let appended = CsvLayout::parse(&data);
+    layout.used_bounds = (first + appended.used_bounds.0, layout.used_bounds.1.max(appended.used_bounds.1));
+    layout.records.extend(appended.records);
+    Ok(max_row + 1 - first)
+}
+
+/// Write a field quoted as `quoting` asks; a lone empty field is quoted unless quoting is
+/// off, so its row isn't read back as a blank line
+fn push_field(data: &mut String, value: &str, quoting: Quoting, alone: bool) {
+    let needs_quotes = value.contains([',', '"', '\n', '\r']) || (alone && value.is_empty());
+    match quoting {
+        Quoting::Always => {}
+        Quoting::Minimal if needs_quotes => {}
+        Quoting::Minimal | Quoting::Never => {
+            data.push_str(value);
+            return;
+        }
+    }
+    data.push('"');
+    data.push_str(&value.replace('"', "\"\""));
+    data.push('"');
 }
 
 /// Find the bounds of non-empty cells
-fn find_used_bounds(cells: &[Vec<String>]) -> (usize, usize) {
+pub fn find_used_bounds(cells: &[Vec<String>]) -> (usize, usize) {
     let mut max_row = 0;
     let mut max_col = 0;
 
@@ -66,3 +696,43 @@ fn find_used_bounds(cells: &[Vec<String>]) -> (usize, usize) {
 
     (max_row, max_col)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_keeps_rows_and_columns_past_the_grid() {
+        let content: String = (0..150)
+            .map(|row| {
+                let fields: Vec<String> = (0..120).map(|col| format!("r{}c{}", row, col)).collect();
+                fields.join(",") + "\n"
+            })
+            .collect();
+        let file = parse_csv_file(&content).unwrap();
+        assert_eq!((file.cells.len(), file.cells[0].len()), (GRID_ROWS, GRID_COLS));
+
+        let path = std::env::temp_dir().join(format!("zsheets-wide-{}.csv", std::process::id()));
+        let save = |cells: &[Vec<String>]| {
+            let written = write_csv(&path, cells, None, &file.write_options, &file.layout, None);
+            let saved = std::fs::read_to_string(&path);
+            let _ = std::fs::remove_file(&path);
+            written.unwrap();
+            saved.unwrap()
+        };
+        assert_eq!(save(&file.cells), content);
+
+        // Clearing the grid's last row and column leaves what's past them alone
+        let mut cells = file.cells.clone();
+        cells[0][0] = "edited".to_string();
+        cells[GRID_ROWS - 1].iter_mut().for_each(String::clear);
+        cells.iter_mut().for_each(|row| row[GRID_COLS - 1].clear());
+        let saved = save(&cells);
+        let lines: Vec<&str> = saved.lines().collect();
+        assert_eq!(lines.len(), 150);
+        assert!(lines[0].starts_with("edited,r0c1,"));
+        assert!(lines[0].ends_with(",r0c100,r0c101,r0c102,r0c103,r0c104,r0c105,r0c106,r0c107,r0c108,r0c109,r0c110,r0c111,r0c112,r0c113,r0c114,r0c115,r0c116,r0c117,r0c118,r0c119"));
+        assert_eq!(lines[GRID_ROWS - 1].split(',').filter(|field| !field.is_empty()).count(), 20);
+        assert_eq!(lines[149], content.lines().nth(149).unwrap());
+    }
+}
@@ -1,10 +1,20 @@
 use std::path::PathBuf;
 
+use crate::file_io::{CsvLayout, WriteOptions};
+
 #[derive(Clone, Debug)]
 pub struct FileState {
     pub current_path: Option<PathBuf>,
     pub is_dirty: bool,
     pub is_read_only: bool,
+    /// How the file is written on save, carried over from how it was read
+    pub write_options: WriteOptions,
+    /// The file's records as last read or written, so unchanged fields save byte for byte
+    pub layout: CsvLayout,
+    /// Save by appending rows added since the last save, leaving the rest of the file alone
+    pub append_only: bool,
+    /// Encrypt the file with this passphrase when saving (`:set encrypt`)
+    pub passphrase: Option<String>,
 }
 
 impl Default for FileState {
@@ -19,6 +29,10 @@ impl FileState {
             current_path: None,
             is_dirty: false,
             is_read_only: false,
+            write_options: WriteOptions::default(),
+            layout: CsvLayout::default(),
+            append_only: false,
+            passphrase: None,
         }
     }
 
@@ -0,0 +1,299 @@
+use std::collections::BTreeSet;
+
+use crate::date::DateTime;
+use crate::value::{self, ColumnType};
+
+/// A condition a column's cells must meet for their row to stay visible
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterCondition {
+    /// Cell text (trimmed) is one of the given values; "" stands for blank cells
+    Values(BTreeSet<String>),
+    /// Cell text contains the needle (case-insensitive)
+    Contains(String),
+    /// Cell is a number greater than the bound
+    GreaterThan(f64),
+    /// Cell is a number less than the bound
+    LessThan(f64),
+    Empty,
+    NonEmpty,
+}
+
+impl FilterCondition {
+    /// Parse the argument of `:filter`, e.g. `contains foo`, `> 10`, `< 5`, `= done`, `empty`, `nonempty`
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let (op, rest) = match input.find(|c: char| c.is_whitespace()) {
+            Some(pos) => (&input[..pos], input[pos..].trim()),
+            None => split_operator(input),
+        };
+
+        match op.to_lowercase().as_str() {
+            "contains" | "has" if !rest.is_empty() => Some(Self::Contains(rest.to_string())),
+            ">" => value::parse_number(rest).map(Self::GreaterThan),
+            "<" => value::parse_number(rest).map(Self::LessThan),
+            "=" | "is" => Some(Self::Values(BTreeSet::from([rest.to_string()]))),
+            "empty" | "blank" if rest.is_empty() => Some(Self::Empty),
+            "nonempty" | "notempty" | "nonblank" if rest.is_empty() => Some(Self::NonEmpty),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.trim();
+        match self {
+            Self::Values(values) => values.contains(text),
+            Self::Contains(needle) => text.to_lowercase().contains(&needle.to_lowercase()),
+            Self::GreaterThan(bound) => value::parse_number(text).is_some_and(|n| n > *bound),
+            Self::LessThan(bound) => value::parse_number(text).is_some_and(|n| n < *bound),
+            Self::Empty => text.is_empty(),
+            Self::NonEmpty => !text.is_empty(),
+        }
+    }
+
+    /// Short human-readable form shown in the filter dropdown
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Values(values) => format!("{} value(s) selected", values.len()),
+            Self::Contains(needle) => format!("contains \"{}\"", needle),
+            Self::GreaterThan(bound) => format!("> {}", bound),
+            Self::LessThan(bound) => format!("< {}", bound),
+            Self::Empty => "empty".to_string(),
+            Self::NonEmpty => "non-empty".to_string(),
+        }
+    }
+}
+
+/// Split a leading comparison operator off input written without a space, e.g. `>10`
+fn split_operator(input: &str) -> (&str, &str) {
+    match input.chars().next() {
+        Some('>' | '<' | '=') => (&input[..1], input[1..].trim()),
+        _ => (input, ""),
+    }
+}
+
+/// Comparison used by a `:filter` expression term
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Empty,
+    NonEmpty,
+}
+
+/// A row predicate typed into `:filter`, e.g. `C > 100 && A contains "foo"`.
+/// Terms compare a column (letters or header label) against a value; they combine with
+/// `&&`/`and`, `||`/`or`, `!`/`not`, and parentheses.
+#[derive(Clone, Debug)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Compare { col: usize, op: CompareOp, value: String },
+}
+
+impl FilterExpr {
+    /// Parse an expression, resolving column names with `resolve`
+    pub fn parse(input: &str, resolve: impl Fn(&str) -> Option<usize>) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = ExprParser { tokens, pos: 0, resolve: &resolve };
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected '{}' in filter", token.text())),
+        }
+    }
+
+    /// Whether a row (its cells, indexed by column) satisfies the expression, comparing each
+    /// column as `column_type` says it's typed
+    pub fn matches(&self, row: &[String], column_type: &impl Fn(usize) -> Option<ColumnType>) -> bool {
+        match self {
+            Self::And(a, b) => a.matches(row, column_type) && b.matches(row, column_type),
+            Self::Or(a, b) => a.matches(row, column_type) || b.matches(row, column_type),
+            Self::Not(expr) => !expr.matches(row, column_type),
+            Self::Compare { col, op, value } => compare(row[*col].trim(), *op, value, column_type(*col)),
+        }
+    }
+}
+
+/// Compare cell text against a value: numerically when both parse as numbers,
+/// chronologically when both parse as dates, otherwise as case-insensitive text.
+/// A number never equals or orders against text. Text columns always compare as text.
+fn compare(cell: &str, op: CompareOp, value: &str, column_type: Option<ColumnType>) -> bool {
+    match op {
+        CompareOp::Empty => return cell.is_empty(),
+        CompareOp::NonEmpty => return !cell.is_empty(),
+        CompareOp::Contains => return cell.to_lowercase().contains(&value.to_lowercase()),
+        _ => {}
+    }
+
+    let ordering = match (value::parse_number(cell), value::parse_number(value)) {
+        _ if column_type == Some(ColumnType::Text) => Some(cell.to_lowercase().cmp(&value.to_lowercase())),
+        (Some(a), Some(b)) => a.partial_cmp(&b),
+        (None, None) => match (DateTime::parse(cell), DateTime::parse(value)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => Some(cell.to_lowercase().cmp(&value.to_lowercase())),
+        },
+        _ => None,
+    };
+    match ordering {
+        Some(ordering) => match op {
+            CompareOp::Eq => ordering.is_eq(),
+            CompareOp::Ne => ordering.is_ne(),
+            CompareOp::Lt => ordering.is_lt(),
+            CompareOp::Le => ordering.is_le(),
+            CompareOp::Gt => ordering.is_gt(),
+            CompareOp::Ge => ordering.is_ge(),
+            _ => false,
+        },
+        None => op == CompareOp::Ne,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Bare word: column name, number, or keyword
+    Word(String),
+    /// Double-quoted string
+    Quoted(String),
+    /// Operator or parenthesis
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn text(&self) -> &str {
+        match self {
+            Self::Word(s) | Self::Quoted(s) => s,
+            Self::Symbol(s) => s,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Self::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+// Longest first so `>=` wins over `>`
+const SYMBOLS: &[&str] = &["&&", "||", ">=", "<=", "!=", "<>", "==", ">", "<", "=", "!", "(", ")"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or("Unterminated string in filter")?;
+            tokens.push(Token::Quoted(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "\"()!<>=&|".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("Unexpected '{}' in filter", &rest[..1]));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    resolve: &'a dyn Fn(&str) -> Option<usize>,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is `symbol` or its keyword spelling
+    fn eat(&mut self, symbol: &'static str, keyword: &str) -> bool {
+        let matched = self.peek()
+            .is_some_and(|token| *token == Token::Symbol(symbol) || token.is_keyword(keyword));
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.parse_and()?;
+        while self.eat("||", "or") {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.eat("&&", "and") {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr, String> {
+        if self.eat("!", "not") {
+            return Ok(FilterExpr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.peek() == Some(&Token::Symbol("(")) {
+            self.pos += 1;
+            let expr = self.parse_or()?;
+            if self.next() != Some(Token::Symbol(")")) {
+                return Err("Missing ')' in filter".to_string());
+            }
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<FilterExpr, String> {
+        let name = match self.next() {
+            Some(Token::Word(name) | Token::Quoted(name)) => name,
+            Some(token) => return Err(format!("Expected a column, found '{}'", token.text())),
+            None => return Err("Expected a column".to_string()),
+        };
+        let col = (self.resolve)(&name).ok_or_else(|| format!("Unknown column: {}", name))?;
+
+        let op = match self.next() {
+            Some(Token::Symbol("=" | "==")) => CompareOp::Eq,
+            Some(Token::Symbol("!=" | "<>")) => CompareOp::Ne,
+            Some(Token::Symbol("<")) => CompareOp::Lt,
+            Some(Token::Symbol("<=")) => CompareOp::Le,
+            Some(Token::Symbol(">")) => CompareOp::Gt,
+            Some(Token::Symbol(">=")) => CompareOp::Ge,
+            Some(token) if token.is_keyword("contains") => CompareOp::Contains,
+            Some(token) if token.is_keyword("empty") => {
+                return Ok(FilterExpr::Compare { col, op: CompareOp::Empty, value: String::new() });
+            }
+            Some(token) if token.is_keyword("nonempty") => {
+                return Ok(FilterExpr::Compare { col, op: CompareOp::NonEmpty, value: String::new() });
+            }
+            Some(token) => return Err(format!("Expected a comparison after {}, found '{}'", name, token.text())),
+            None => return Err(format!("Expected a comparison after {}", name)),
+        };
+
+        match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => Ok(FilterExpr::Compare { col, op, value }),
+            _ => Err(format!("Expected a value after {}", name)),
+        }
+    }
+}
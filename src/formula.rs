@@ -0,0 +1,1319 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::fmt;
+use std::ops::Range;
+
+use crate::date::DateTime;
+use crate::sparkline::{Sparkline, SparklineKind};
+use crate::state::{CellPosition, CellRange};
+use crate::value;
+
+/// Built-in functions, used for evaluation and for autocomplete in the cell editor
+pub const FUNCTIONS: &[&str] = &[
+    "ABS", "AND", "AVERAGE", "CONCAT", "COUNT", "COUNTA", "DATE", "DATEDIF", "DAY", "EOMONTH",
+    "HLOOKUP", "IF", "INDEX", "LEFT", "LEN", "LOWER", "MATCH", "MAX", "MID", "MIN", "MOD", "MONTH",
+    "NOT", "NOW", "OR", "POWER", "RAND", "RIGHT", "ROUND", "SPARKLINE", "SQRT", "SUBSTITUTE", "SUM", "TEXTJOIN",
+    "TODAY", "TRIM", "UPPER", "VLOOKUP", "XLOOKUP", "YEAR",
+];
+
+/// Whether cell text is a formula (`=` followed by an expression)
+pub fn is_formula(text: &str) -> bool {
+    text.len() > 1 && text.starts_with('=')
+}
+
+/// When formulas recompute: on every change, or only on request (`:calc` / F9)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CalcMode {
+    #[default]
+    Automatic,
+    Manual,
+}
+
+impl CalcMode {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "auto" | "automatic" => Some(Self::Automatic),
+            "manual" => Some(Self::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// The result of evaluating a formula
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Empty,
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    /// A date and/or time; counts as its serial number in arithmetic
+    Date(DateTime),
+    /// A chart drawn in the cell in place of text
+    Sparkline(Sparkline),
+    Error(FormulaError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormulaError {
+    /// The formula doesn't parse
+    Syntax,
+    /// Unknown function or name
+    Name,
+    /// Reference outside the grid, or to a sheet that isn't open
+    Ref,
+    /// Wrong type of argument, e.g. text where a number is needed
+    Value,
+    DivZero,
+    /// A lookup found no match
+    NotAvailable,
+    /// The formula depends on itself
+    Cycle,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Syntax => "#ERROR!",
+            Self::Name => "#NAME?",
+            Self::Ref => "#REF!",
+            Self::Value => "#VALUE!",
+            Self::DivZero => "#DIV/0!",
+            Self::NotAvailable => "#N/A",
+            Self::Cycle => "#CYCLE!",
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => Ok(()),
+            Self::Number(n) => f.write_str(&format_general(*n)),
+            Self::Text(text) => f.write_str(text),
+            Self::Bool(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+            Self::Date(date) => date.fmt(f),
+            Self::Sparkline(_) => Ok(()),
+            Self::Error(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Numbers without a number format: integers as-is, otherwise up to 10 decimals
+fn format_general(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        return format!("{}", n as i64);
+    }
+    let text = format!("{:.10}", n);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// A sheet of the workbook that formulas can refer into, as `Name!A1` or `'Name'!A1:B2`
+pub struct Sheet<'a> {
+    /// What it can be called, matched ignoring case
+    pub names: Vec<String>,
+    pub cells: &'a [Vec<String>],
+}
+
+/// Evaluate every formula in the grid. Entries are `None` for cells that aren't formulas.
+pub fn evaluate_all(cells: &[Vec<String>]) -> Vec<Vec<Option<Value>>> {
+    evaluate_sheet(&[Sheet { names: Vec::new(), cells }], 0)
+}
+
+/// Evaluate every formula in `sheets[index]`, following references into the other sheets
+pub fn evaluate_sheet(sheets: &[Sheet], index: usize) -> Vec<Vec<Option<Value>>> {
+    let mut evaluator = Evaluator {
+        sheets,
+        sheet: index,
+        results: sheets.iter().map(|sheet| sheet.cells.iter().map(|row| vec![None; row.len()]).collect()).collect(),
+        in_progress: HashSet::new(),
+    };
+    for (row, cols) in sheets[index].cells.iter().enumerate() {
+        for (col, text) in cols.iter().enumerate() {
+            if is_formula(text) {
+                evaluator.cell_value(index, CellPosition::new(row, col));
+            }
+        }
+    }
+    evaluator.results.swap_remove(index)
+}
+
+/// Whether any formula in `cells` refers to another sheet
+pub fn refers_to_sheets(cells: &[Vec<String>]) -> bool {
+    cells.iter().flatten().any(|text| is_formula(text) && text.contains('!'))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Ref(CellPosition),
+    Range(CellRange),
+    /// A `Ref` or `Range` in the named sheet
+    Sheet(String, Box<Expr>),
+    Neg(Box<Expr>),
+    Percent(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Error(FormulaError),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    /// Function name, cell reference, or TRUE/FALSE
+    Ident(String),
+    Op(&'static str),
+}
+
+// Longest first so `<=` wins over `<`
+const OPERATORS: &[&str] = &[
+    "<>", "<=", ">=", "+", "-", "*", "/", "^", "&", "=", "<", ">", "(", ")", ",", ":", "%", "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FormulaError> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        let (token, len) = next_token(rest)?;
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// The token at the start of `rest` and its length in bytes
+fn next_token(rest: &str) -> Result<(Token, usize), FormulaError> {
+    let c = rest.chars().next().ok_or(FormulaError::Syntax)?;
+    if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+        Ok((Token::Op(op), op.len()))
+    } else if c == '"' {
+        // Strings use "" for a literal quote
+        let mut text = String::new();
+        let mut chars = rest[1..].char_indices();
+        loop {
+            match chars.next() {
+                Some((i, '"')) if rest[1 + i + 1..].starts_with('"') => {
+                    text.push('"');
+                    chars.next();
+                }
+                Some((i, '"')) => return Ok((Token::Text(text), 1 + i + 1)),
+                Some((_, c)) => text.push(c),
+                None => return Err(FormulaError::Syntax),
+            }
+        }
+    } else if c == '\'' {
+        // Quoted sheet name, e.g. 'Q1 Sales'!A1
+        let end = rest[1..].find('\'').ok_or(FormulaError::Syntax)? + 1;
+        Ok((Token::Ident(rest[1..end].to_string()), end + 1))
+    } else if c.is_ascii_digit() || c == '.' {
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                !(c.is_ascii_digit() || c == '.'
+                    || ((c == 'e' || c == 'E') && i > 0)
+                    || ((c == '+' || c == '-') && rest[..i].ends_with(['e', 'E'])))
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let number = rest[..end].parse().map_err(|_| FormulaError::Syntax)?;
+        Ok((Token::Number(number), end))
+    } else if c.is_alphabetic() || c == '_' || c == '$' {
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(rest.len());
+        Ok((Token::Ident(rest[..end].to_string()), end))
+    } else {
+        Err(FormulaError::Syntax)
+    }
+}
+
+/// What a highlighted part of a formula is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    Function,
+    /// A cell or range reference, indexing `Highlight::references`
+    Reference(usize),
+    Text,
+    Number,
+}
+
+/// Syntax highlighting for formula text
+#[derive(Default)]
+pub struct Highlight {
+    /// Byte ranges of the text and what they are; operators and unknown names are omitted
+    pub spans: Vec<(Range<usize>, SpanKind)>,
+    /// The distinct cells and ranges referenced, in order of first appearance
+    pub references: Vec<CellRange>,
+}
+
+/// Highlight a formula as typed so far. Incomplete input is fine: an unterminated string
+/// runs to the end, and characters that don't form a token are skipped.
+pub fn highlight(text: &str) -> Highlight {
+    let mut tokens = Vec::new();
+    let mut pos = if is_formula(text) { 1 } else { text.len() };
+    while pos < text.len() {
+        let rest = text[pos..].trim_start();
+        pos = text.len() - rest.len();
+        if rest.is_empty() {
+            break;
+        }
+        match next_token(rest) {
+            Ok((token, len)) => {
+                tokens.push((token, pos..pos + len));
+                pos += len;
+            }
+            Err(_) if rest.starts_with('"') => {
+                tokens.push((Token::Text(String::new()), pos..text.len()));
+                break;
+            }
+            Err(_) => pos += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    let mut highlight = Highlight::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        let (token, span) = &tokens[i];
+        let mut span = span.clone();
+        let kind = match token {
+            Token::Number(_) => Some(SpanKind::Number),
+            Token::Text(_) => Some(SpanKind::Text),
+            Token::Ident(_) if matches!(tokens.get(i + 1), Some((Token::Op("("), _))) => Some(SpanKind::Function),
+            // References into other sheets aren't cells of this grid
+            Token::Ident(_) if i > 0 && tokens[i - 1].0 == Token::Op("!") => {
+                if matches!(tokens.get(i + 1), Some((Token::Op(":"), _))) {
+                    i += 2;
+                }
+                None
+            }
+            Token::Ident(name) => match parse_reference(name) {
+                Some(Ok(start)) => {
+                    // `A1:B2` highlights as a single reference
+                    let mut cells = CellRange::new(start, start);
+                    if let (Some((Token::Op(":"), _)), Some((Token::Ident(end), end_span))) = (tokens.get(i + 1), tokens.get(i + 2))
+                        && let Some(Ok(end)) = parse_reference(end)
+                    {
+                        cells = CellRange::new(start, end);
+                        span.end = end_span.end;
+                        i += 2;
+                    }
+                    let index = match highlight.references.iter().position(|&r| r == cells) {
+                        Some(index) => index,
+                        None => {
+                            highlight.references.push(cells);
+                            highlight.references.len() - 1
+                        }
+                    };
+                    Some(SpanKind::Reference(index))
+                }
+                _ => None,
+            },
+            Token::Op(_) => None,
+        };
+        if let Some(kind) = kind {
+            highlight.spans.push((span, kind));
+        }
+        i += 1;
+    }
+    highlight
+}
+
+/// Rewrite a formula's cell references with `map`, leaving the rest of the text as typed.
+/// References mapped off the grid (`None`) become `#REF!`; `$` markers are kept.
+pub fn map_references(text: &str, map: impl Fn(CellPosition) -> Option<CellPosition>) -> String {
+    if !is_formula(text) {
+        return text.to_string();
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut pos = 1;
+    let mut previous: Option<Token> = None;
+    // Whether the last reference was into another sheet, so `Sheet2!A1:B2` skips `B2` too
+    let mut other_sheet = false;
+    while pos < text.len() {
+        let rest = text[pos..].trim_start();
+        pos = text.len() - rest.len();
+        if rest.is_empty() {
+            break;
+        }
+        let Ok((token, len)) = next_token(rest) else {
+            return text.to_string();
+        };
+        if let Token::Ident(name) = &token {
+            let after_sheet = previous == Some(Token::Op("!")) || (other_sheet && previous == Some(Token::Op(":")));
+            let is_call = rest[len..].trim_start().starts_with('(');
+            if !after_sheet
+                && !is_call
+                && let Some(Ok(cell)) = parse_reference(name)
+            {
+                result.push_str(&text[copied..pos]);
+                match map(cell) {
+                    Some(cell) => result.push_str(&reference_text(name, cell)),
+                    None => result.push_str("#REF!"),
+                }
+                copied = pos + len;
+            }
+            other_sheet = after_sheet;
+        }
+        pos += len;
+        previous = Some(token);
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// `cell` written as a reference, with the `$` markers of `original`
+fn reference_text(original: &str, cell: CellPosition) -> String {
+    let absolute_col = original.starts_with('$');
+    let absolute_row = original.trim_start_matches('$').contains('$');
+    format!(
+        "{}{}{}{}",
+        if absolute_col { "$" } else { "" },
+        CellPosition::col_to_letter(cell.col),
+        if absolute_row { "$" } else { "" },
+        cell.row + 1,
+    )
+}
+
+/// Parse a formula's expression (the text after `=`)
+fn parse(source: &str) -> Result<Expr, FormulaError> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let expr = parser.parse_comparison()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(FormulaError::Syntax);
+    }
+    Ok(expr)
+}
+
+/// A cell reference like `B3` or `$B$3` (the `$` markers are accepted but not yet meaningful)
+fn parse_reference(ident: &str) -> Option<Result<CellPosition, FormulaError>> {
+    let bare: String = ident.chars().filter(|&c| c != '$').collect();
+    let split = bare.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = bare.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(CellPosition::from_reference(&bare).ok_or(FormulaError::Ref))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is one of `ops`, returning it
+    fn eat(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.parse_concat()?;
+        while let Some(op) = self.eat(&["=", "<>", "<", "<=", ">", ">="]) {
+            let op = match op {
+                "=" => BinaryOp::Eq,
+                "<>" => BinaryOp::Ne,
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::Le,
+                ">" => BinaryOp::Gt,
+                _ => BinaryOp::Ge,
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_concat()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_concat(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.parse_additive()?;
+        while self.eat(&["&"]).is_some() {
+            expr = Expr::Binary(BinaryOp::Concat, Box::new(expr), Box::new(self.parse_additive()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.parse_term()?;
+        while let Some(op) = self.eat(&["+", "-"]) {
+            let op = if op == "+" { BinaryOp::Add } else { BinaryOp::Sub };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_term()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.parse_power()?;
+        while let Some(op) = self.eat(&["*", "/"]) {
+            let op = if op == "*" { BinaryOp::Mul } else { BinaryOp::Div };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_power()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_power(&mut self) -> Result<Expr, FormulaError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat(&["^"]).is_some() {
+            expr = Expr::Binary(BinaryOp::Pow, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, FormulaError> {
+        match self.eat(&["-", "+"]) {
+            Some("-") => Ok(Expr::Neg(Box::new(self.parse_unary()?))),
+            Some(_) => self.parse_unary(),
+            None => {
+                let mut expr = self.parse_primary()?;
+                while self.eat(&["%"]).is_some() {
+                    expr = Expr::Percent(Box::new(expr));
+                }
+                Ok(expr)
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, FormulaError> {
+        match self.next().ok_or(FormulaError::Syntax)? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Text(text) => Ok(Expr::Text(text)),
+            Token::Op("(") => {
+                let expr = self.parse_comparison()?;
+                self.eat(&[")"]).ok_or(FormulaError::Syntax)?;
+                Ok(expr)
+            }
+            Token::Ident(sheet) if self.eat(&["!"]).is_some() => match self.parse_primary()? {
+                expr @ (Expr::Ref(_) | Expr::Range(_)) => Ok(Expr::Sheet(sheet, Box::new(expr))),
+                Expr::Error(e) => Ok(Expr::Error(e)),
+                _ => Err(FormulaError::Syntax),
+            },
+            Token::Ident(name) if self.eat(&["("]).is_some() => {
+                let mut args = Vec::new();
+                if self.eat(&[")"]).is_none() {
+                    loop {
+                        args.push(self.parse_comparison()?);
+                        match self.eat(&[",", ")"]) {
+                            Some(",") => continue,
+                            Some(_) => break,
+                            None => return Err(FormulaError::Syntax),
+                        }
+                    }
+                }
+                Ok(Expr::Call(name.to_uppercase(), args))
+            }
+            Token::Ident(name) if name.eq_ignore_ascii_case("TRUE") => Ok(Expr::Bool(true)),
+            Token::Ident(name) if name.eq_ignore_ascii_case("FALSE") => Ok(Expr::Bool(false)),
+            Token::Ident(name) => match parse_reference(&name) {
+                Some(Ok(start)) if self.eat(&[":"]).is_some() => match self.next() {
+                    Some(Token::Ident(end)) => match parse_reference(&end) {
+                        Some(Ok(end)) => Ok(Expr::Range(CellRange::new(start, end))),
+                        Some(Err(e)) => Ok(Expr::Error(e)),
+                        None => Err(FormulaError::Syntax),
+                    },
+                    _ => Err(FormulaError::Syntax),
+                },
+                Some(Ok(position)) => Ok(Expr::Ref(position)),
+                Some(Err(e)) => Ok(Expr::Error(e)),
+                None => Ok(Expr::Error(FormulaError::Name)),
+            },
+            Token::Op(_) => Err(FormulaError::Syntax),
+        }
+    }
+}
+
+/// Evaluates formulas against the workbook, memoizing each cell and detecting cycles
+struct Evaluator<'a> {
+    sheets: &'a [Sheet<'a>],
+    /// The sheet of the formula being evaluated, which unqualified references are into
+    sheet: usize,
+    results: Vec<Vec<Vec<Option<Value>>>>,
+    in_progress: HashSet<(usize, usize, usize)>,
+}
+
+/// A function argument value, remembering whether it came from a range; aggregates skip
+/// text and blanks inside ranges but coerce values passed directly
+struct Arg {
+    value: Value,
+    from_range: bool,
+}
+
+impl Evaluator<'_> {
+    fn cell_value(&mut self, sheet: usize, position: CellPosition) -> Value {
+        let CellPosition { row, col } = position;
+        let text = &self.sheets[sheet].cells[row][col];
+        if !is_formula(text) {
+            return literal(text);
+        }
+        if let Some(value) = &self.results[sheet][row][col] {
+            return value.clone();
+        }
+        if !self.in_progress.insert((sheet, row, col)) {
+            return Value::Error(FormulaError::Cycle);
+        }
+        let outer = std::mem::replace(&mut self.sheet, sheet);
+        let value = match parse(&text[1..]) {
+            Ok(expr) => self.eval(&expr),
+            Err(e) => Value::Error(e),
+        };
+        self.sheet = outer;
+        self.in_progress.remove(&(sheet, row, col));
+        self.results[sheet][row][col] = Some(value.clone());
+        value
+    }
+
+    /// A cell's value as a single-cell result
+    fn referenced(&mut self, sheet: usize, position: CellPosition) -> Value {
+        match self.cell_value(sheet, position) {
+            // A blank cell referenced directly reads as 0
+            Value::Empty => Value::Number(0.0),
+            value => value,
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Text(text) => Value::Text(text.clone()),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Ref(position) => self.referenced(self.sheet, *position),
+            Expr::Range(_) => Value::Error(FormulaError::Value),
+            Expr::Sheet(name, expr) => match (self.sheet_index(name), &**expr) {
+                (Ok(sheet), Expr::Ref(position)) => self.referenced(sheet, *position),
+                (Ok(_), _) => Value::Error(FormulaError::Value),
+                (Err(e), _) => Value::Error(e),
+            },
+            Expr::Neg(expr) => self.number(expr).map_or_else(Value::Error, |n| Value::Number(-n)),
+            Expr::Percent(expr) => self.number(expr).map_or_else(Value::Error, |n| Value::Number(n / 100.0)),
+            Expr::Binary(op, a, b) => self.eval_binary(*op, a, b),
+            Expr::Call(name, args) => self.call(name, args),
+            Expr::Error(e) => Value::Error(*e),
+        }
+    }
+
+    fn number(&mut self, expr: &Expr) -> Result<f64, FormulaError> {
+        to_number(&self.eval(expr))
+    }
+
+    fn eval_binary(&mut self, op: BinaryOp, a: &Expr, b: &Expr) -> Value {
+        let (a, b) = (self.eval(a), self.eval(b));
+        let result = match op {
+            BinaryOp::Concat => to_text(&a).and_then(|a| Ok(Value::Text(a + to_text(&b)?.as_str()))),
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                compare_values(&a, &b).map(|ordering| {
+                    Value::Bool(match op {
+                        BinaryOp::Eq => ordering.is_eq(),
+                        BinaryOp::Ne => ordering.is_ne(),
+                        BinaryOp::Lt => ordering.is_lt(),
+                        BinaryOp::Le => ordering.is_le(),
+                        BinaryOp::Gt => ordering.is_gt(),
+                        _ => ordering.is_ge(),
+                    })
+                })
+            }
+            _ => (|| {
+                let (x, y) = (to_number(&a)?, to_number(&b)?);
+                let n = match op {
+                    BinaryOp::Add => x + y,
+                    BinaryOp::Sub => x - y,
+                    BinaryOp::Mul => x * y,
+                    BinaryOp::Div if y == 0.0 => return Err(FormulaError::DivZero),
+                    BinaryOp::Div => x / y,
+                    _ => x.powf(y),
+                };
+                // A date moved by a number of days is still a date; the difference of two is a number
+                let is_date = |value: &Value| matches!(value, Value::Date(date) if date.has_date());
+                let shifted = match op {
+                    BinaryOp::Add => is_date(&a) != is_date(&b),
+                    BinaryOp::Sub => is_date(&a) && !is_date(&b),
+                    _ => false,
+                };
+                if shifted && n.is_finite() {
+                    return Ok(Value::Date(DateTime::from_serial(n)));
+                }
+                finite(n)
+            })(),
+        };
+        result.unwrap_or_else(Value::Error)
+    }
+
+    /// Evaluate arguments, expanding ranges into their cells
+    fn args(&mut self, args: &[Expr]) -> Vec<Arg> {
+        let mut values = Vec::new();
+        for arg in args {
+            match arg {
+                arg if is_range(arg) => match self.range_arg(arg) {
+                    Ok((sheet, range)) => {
+                        for row in range.rows() {
+                            for col in range.cols() {
+                                let value = self.cell_value(sheet, CellPosition::new(row, col));
+                                values.push(Arg { value, from_range: true });
+                            }
+                        }
+                    }
+                    Err(e) => values.push(Arg { value: Value::Error(e), from_range: false }),
+                },
+                arg => values.push(Arg { value: self.eval(arg), from_range: false }),
+            }
+        }
+        values
+    }
+
+    /// Numbers for an aggregate: numbers in ranges, plus direct arguments coerced to numbers
+    fn numbers(&mut self, args: &[Expr]) -> Result<Vec<f64>, FormulaError> {
+        let mut numbers = Vec::new();
+        for Arg { value, from_range } in self.args(args) {
+            match value {
+                Value::Number(n) => numbers.push(n),
+                Value::Date(date) => numbers.push(date.to_serial()),
+                Value::Error(e) => return Err(e),
+                _ if from_range => {}
+                Value::Empty => {}
+                value => numbers.push(to_number(&value)?),
+            }
+        }
+        Ok(numbers)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) -> Value {
+        let result = match name {
+            "SUM" => self.numbers(args).map(|n| Value::Number(n.iter().sum())),
+            "AVERAGE" => self.numbers(args).and_then(|n| {
+                if n.is_empty() {
+                    Err(FormulaError::DivZero)
+                } else {
+                    Ok(Value::Number(n.iter().sum::<f64>() / n.len() as f64))
+                }
+            }),
+            "MIN" => self.numbers(args).map(|n| Value::Number(n.into_iter().reduce(f64::min).unwrap_or(0.0))),
+            "MAX" => self.numbers(args).map(|n| Value::Number(n.into_iter().reduce(f64::max).unwrap_or(0.0))),
+            "COUNT" => Ok(Value::Number(
+                self.args(args).iter().filter(|arg| matches!(arg.value, Value::Number(_) | Value::Date(_))).count() as f64,
+            )),
+            "COUNTA" => Ok(Value::Number(
+                self.args(args).iter().filter(|arg| arg.value != Value::Empty).count() as f64,
+            )),
+            "IF" => match args {
+                [condition, then, rest @ ..] if rest.len() <= 1 => {
+                    to_bool(&self.eval(condition)).map(|condition| match (condition, rest.first()) {
+                        (true, _) => self.eval(then),
+                        (false, Some(otherwise)) => self.eval(otherwise),
+                        (false, None) => Value::Bool(false),
+                    })
+                }
+                _ => Err(FormulaError::Value),
+            },
+            "AND" | "OR" => self
+                .args(args)
+                .iter()
+                .filter(|arg| !(arg.from_range && matches!(arg.value, Value::Empty | Value::Text(_))))
+                .map(|arg| to_bool(&arg.value))
+                .collect::<Result<Vec<bool>, _>>()
+                .map(|bools| {
+                    Value::Bool(if name == "AND" { bools.iter().all(|&b| b) } else { bools.iter().any(|&b| b) })
+                }),
+            "NOT" => self.unary(args, |value| to_bool(value).map(|b| Value::Bool(!b))),
+            "ABS" => self.unary(args, |value| to_number(value).map(|n| Value::Number(n.abs()))),
+            "SQRT" => self.unary(args, |value| match to_number(value)? {
+                n if n < 0.0 => Err(FormulaError::Value),
+                n => Ok(Value::Number(n.sqrt())),
+            }),
+            "ROUND" => match args {
+                [x] | [x, _] => (|| {
+                    let x = self.number(x)?;
+                    let digits = match args.get(1) {
+                        Some(digits) => self.number(digits)?.trunc() as i32,
+                        None => 0,
+                    };
+                    let scale = 10f64.powi(digits);
+                    finite((x * scale).round() / scale)
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "MOD" | "POWER" => match args {
+                [a, b] => (|| {
+                    let (a, b) = (self.number(a)?, self.number(b)?);
+                    match name {
+                        "MOD" if b == 0.0 => Err(FormulaError::DivZero),
+                        // The result takes the divisor's sign, as in spreadsheets
+                        "MOD" => finite(a - b * (a / b).floor()),
+                        _ => finite(a.powf(b)),
+                    }
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "LEN" => self.unary(args, |value| to_text(value).map(|text| Value::Number(text.chars().count() as f64))),
+            "UPPER" => self.unary(args, |value| to_text(value).map(|text| Value::Text(text.to_uppercase()))),
+            "LOWER" => self.unary(args, |value| to_text(value).map(|text| Value::Text(text.to_lowercase()))),
+            "TRIM" => self.unary(args, |value| {
+                to_text(value).map(|text| Value::Text(text.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")))
+            }),
+            "LEFT" | "RIGHT" => match args {
+                [text] | [text, _] => (|| {
+                    let chars: Vec<char> = to_text(&self.eval(text))?.chars().collect();
+                    let count = match args.get(1) {
+                        Some(count) => self.length(count)?.min(chars.len()),
+                        None => chars.len().min(1),
+                    };
+                    let taken = if name == "LEFT" { &chars[..count] } else { &chars[chars.len() - count..] };
+                    Ok(Value::Text(taken.iter().collect()))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "MID" => match args {
+                [text, start, count] => (|| {
+                    let text = to_text(&self.eval(text))?;
+                    let start = self.number(start)?.trunc();
+                    if start < 1.0 {
+                        return Err(FormulaError::Value);
+                    }
+                    let count = self.length(count)?;
+                    Ok(Value::Text(text.chars().skip(start as usize - 1).take(count).collect()))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "SUBSTITUTE" => match args {
+                [text, old, new, rest @ ..] if rest.len() <= 1 => (|| {
+                    let text = to_text(&self.eval(text))?;
+                    let (old, new) = (to_text(&self.eval(old))?, to_text(&self.eval(new))?);
+                    if old.is_empty() {
+                        return Ok(Value::Text(text));
+                    }
+                    let Some(instance) = rest.first() else {
+                        return Ok(Value::Text(text.replace(&old, &new)));
+                    };
+                    // Only the nth occurrence
+                    let instance = self.number(instance)?.trunc();
+                    if instance < 1.0 {
+                        return Err(FormulaError::Value);
+                    }
+                    Ok(Value::Text(match text.match_indices(&old).nth(instance as usize - 1) {
+                        Some((at, _)) => format!("{}{}{}", &text[..at], new, &text[at + old.len()..]),
+                        None => text,
+                    }))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "TEXTJOIN" => match args {
+                [delimiter, ignore_empty, values @ ..] if !values.is_empty() => (|| {
+                    let delimiter = to_text(&self.eval(delimiter))?;
+                    let ignore_empty = to_bool(&self.eval(ignore_empty))?;
+                    let texts = self
+                        .args(values)
+                        .iter()
+                        .map(|arg| to_text(&arg.value))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let texts: Vec<String> = texts.into_iter().filter(|text| !(ignore_empty && text.is_empty())).collect();
+                    Ok(Value::Text(texts.join(&delimiter)))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "CONCAT" => self
+                .args(args)
+                .iter()
+                .map(|arg| to_text(&arg.value))
+                .collect::<Result<String, _>>()
+                .map(Value::Text),
+            "VLOOKUP" | "HLOOKUP" => match args {
+                [target, table, index, rest @ ..] if rest.len() <= 1 => (|| {
+                    let target = self.lookup_target(target)?;
+                    let (sheet, table) = self.range_arg(table)?;
+                    let index = self.number(index)?.trunc();
+                    let approximate = match rest.first() {
+                        Some(arg) => to_bool(&self.eval(arg))?,
+                        None => true,
+                    };
+                    let vertical = name == "VLOOKUP";
+                    let (keys, width) = if vertical {
+                        (table.rows().map(|row| CellPosition::new(row, table.start.col)).collect::<Vec<_>>(), table.cols().len())
+                    } else {
+                        (table.cols().map(|col| CellPosition::new(table.start.row, col)).collect(), table.rows().len())
+                    };
+                    if index < 1.0 {
+                        return Err(FormulaError::Value);
+                    }
+                    if index > width as f64 {
+                        return Err(FormulaError::Ref);
+                    }
+                    let mode = if approximate { MatchMode::NextSmaller } else { MatchMode::Exact };
+                    let found = self.find(&target, sheet, &keys, mode)?;
+                    let offset = index as usize - 1;
+                    Ok(self.referenced(sheet, if vertical {
+                        CellPosition::new(table.start.row + found, table.start.col + offset)
+                    } else {
+                        CellPosition::new(table.start.row + offset, table.start.col + found)
+                    }))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "XLOOKUP" => match args {
+                [target, lookup, returns, rest @ ..] if rest.len() <= 3 => (|| {
+                    let target = self.lookup_target(target)?;
+                    let ((lookup_sheet, lookup), (return_sheet, returns)) = (self.range_arg(lookup)?, self.range_arg(returns)?);
+                    let mut keys = line_cells(lookup).ok_or(FormulaError::Value)?;
+                    let mode = match rest.get(1) {
+                        Some(arg) => match self.number(arg)? {
+                            0.0 => MatchMode::Exact,
+                            -1.0 => MatchMode::NextSmaller,
+                            1.0 => MatchMode::NextLarger,
+                            _ => return Err(FormulaError::Value),
+                        },
+                        None => MatchMode::Exact,
+                    };
+                    let reverse = match rest.get(2) {
+                        Some(arg) => match self.number(arg)? {
+                            1.0 => false,
+                            -1.0 => true,
+                            _ => return Err(FormulaError::Value),
+                        },
+                        None => false,
+                    };
+                    if reverse {
+                        keys.reverse();
+                    }
+                    let found = match (self.find(&target, lookup_sheet, &keys, mode), rest.first()) {
+                        (Ok(found), _) => found,
+                        (Err(FormulaError::NotAvailable), Some(otherwise)) => return Ok(self.eval(otherwise)),
+                        (Err(e), _) => return Err(e),
+                    };
+                    let found = if reverse { keys.len() - 1 - found } else { found };
+                    // The result comes from the same offset along the return range
+                    let position = if lookup.cols().len() == 1 && lookup.rows().len() > 1 {
+                        CellPosition::new(returns.start.row + found, returns.start.col)
+                    } else {
+                        CellPosition::new(returns.start.row, returns.start.col + found)
+                    };
+                    if !returns.contains(position) {
+                        return Err(FormulaError::Value);
+                    }
+                    Ok(self.referenced(return_sheet, position))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "MATCH" => match args {
+                [target, range, rest @ ..] if rest.len() <= 1 => (|| {
+                    let target = self.lookup_target(target)?;
+                    let (sheet, range) = self.range_arg(range)?;
+                    let keys = line_cells(range).ok_or(FormulaError::NotAvailable)?;
+                    let mode = match rest.first() {
+                        Some(arg) => match self.number(arg)? {
+                            0.0 => MatchMode::Exact,
+                            n if n > 0.0 => MatchMode::NextSmaller,
+                            _ => MatchMode::NextLarger,
+                        },
+                        None => MatchMode::NextSmaller,
+                    };
+                    Ok(Value::Number((self.find(&target, sheet, &keys, mode)? + 1) as f64))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "INDEX" => match args {
+                [range, row, rest @ ..] if rest.len() <= 1 => (|| {
+                    let (sheet, range) = self.range_arg(range)?;
+                    let (rows, cols) = (range.rows().len(), range.cols().len());
+                    let (row, col) = match rest.first() {
+                        Some(col) => (self.number(row)?.trunc(), self.number(col)?.trunc()),
+                        // A single index walks along a one-row range
+                        None if rows == 1 => (1.0, self.number(row)?.trunc()),
+                        None => (self.number(row)?.trunc(), 1.0),
+                    };
+                    if row < 1.0 || col < 1.0 || row > rows as f64 || col > cols as f64 {
+                        return Err(FormulaError::Ref);
+                    }
+                    Ok(self.referenced(sheet, CellPosition::new(
+                        range.start.row + row as usize - 1,
+                        range.start.col + col as usize - 1,
+                    )))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "DATE" => match args {
+                [year, month, day] => (|| {
+                    let year = self.number(year)?.trunc() as i64;
+                    let (month, day) = (self.number(month)?.trunc() as i64, self.number(day)?.trunc() as i64);
+                    // Two-digit and other small years count from 1900, as in other spreadsheets
+                    let year = if (0..1900).contains(&year) { year + 1900 } else { year };
+                    Ok(Value::Date(DateTime::from_parts(year, month, day)))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "YEAR" => self.unary(args, |value| to_date(value).map(|date| Value::Number(date.parts().0 as f64))),
+            "MONTH" => self.unary(args, |value| to_date(value).map(|date| Value::Number(date.parts().1 as f64))),
+            "DAY" => self.unary(args, |value| to_date(value).map(|date| Value::Number(date.parts().2 as f64))),
+            "EOMONTH" => match args {
+                [start, months] => (|| {
+                    let start = to_date(&self.eval(start))?;
+                    let months = self.number(months)?.trunc() as i64;
+                    Ok(Value::Date(start.end_of_month(months)))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "DATEDIF" => match args {
+                [start, end, unit] => (|| {
+                    let (start, end) = (to_date(&self.eval(start))?, to_date(&self.eval(end))?);
+                    let unit = to_text(&self.eval(unit))?;
+                    date_difference(start, end, &unit.to_uppercase()).map(|n| Value::Number(n as f64))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            "SPARKLINE" => match args {
+                [values] | [values, _] => (|| {
+                    let kind = match args.get(1) {
+                        Some(kind) => SparklineKind::parse(&to_text(&self.eval(kind))?).ok_or(FormulaError::Value)?,
+                        None => SparklineKind::Line,
+                    };
+                    Ok(Value::Sparkline(Sparkline { kind, values: self.numbers(std::slice::from_ref(values))? }))
+                })(),
+                _ => Err(FormulaError::Value),
+            },
+            // Volatile: a new value on every recalculation
+            "NOW" | "TODAY" | "RAND" if !args.is_empty() => Err(FormulaError::Value),
+            "NOW" => Ok(Value::Date(DateTime::now())),
+            "TODAY" => Ok(Value::Date(DateTime::now().date())),
+            "RAND" => Ok(Value::Number(random())),
+            _ => Err(FormulaError::Name),
+        };
+        result.unwrap_or_else(Value::Error)
+    }
+
+    /// A character count argument: truncated, and not negative
+    fn length(&mut self, expr: &Expr) -> Result<usize, FormulaError> {
+        match self.number(expr)?.trunc() {
+            n if n < 0.0 => Err(FormulaError::Value),
+            n => Ok(n as usize),
+        }
+    }
+
+    /// The value being looked up; a blank cell can't be found
+    fn lookup_target(&mut self, expr: &Expr) -> Result<Value, FormulaError> {
+        match self.eval(expr) {
+            Value::Error(e) => Err(e),
+            Value::Empty => Err(FormulaError::NotAvailable),
+            value => Ok(value),
+        }
+    }
+
+    /// Index of the first key equal to `target`, otherwise (unless exact) of the closest key
+    /// on the requested side. Keys of a different type than the target are skipped.
+    fn find(&mut self, target: &Value, sheet: usize, keys: &[CellPosition], mode: MatchMode) -> Result<usize, FormulaError> {
+        let mut best: Option<(usize, Value)> = None;
+        for (i, &position) in keys.iter().enumerate() {
+            let key = self.cell_value(sheet, position);
+            if !same_kind(&key, target) {
+                continue;
+            }
+            let ordering = compare_values(&key, target)?;
+            if ordering.is_eq() {
+                return Ok(i);
+            }
+            let closer = |wanted: Ordering| {
+                ordering == wanted
+                    && best.as_ref().is_none_or(|(_, best)| compare_values(&key, best) == Ok(wanted.reverse()))
+            };
+            let closer = match mode {
+                MatchMode::Exact => false,
+                MatchMode::NextSmaller => closer(Ordering::Less),
+                MatchMode::NextLarger => closer(Ordering::Greater),
+            };
+            if closer {
+                best = Some((i, key));
+            }
+        }
+        best.map(|(i, _)| i).ok_or(FormulaError::NotAvailable)
+    }
+
+    /// The sheet called `name`; one that isn't open is a broken reference
+    fn sheet_index(&self, name: &str) -> Result<usize, FormulaError> {
+        self.sheets
+            .iter()
+            .position(|sheet| sheet.names.iter().any(|known| known.eq_ignore_ascii_case(name)))
+            .ok_or(FormulaError::Ref)
+    }
+
+    /// A range argument to a lookup, and the sheet it's in; a single reference is a one-cell range
+    fn range_arg(&self, expr: &Expr) -> Result<(usize, CellRange), FormulaError> {
+        match expr {
+            Expr::Range(range) => Ok((self.sheet, *range)),
+            Expr::Ref(position) => Ok((self.sheet, CellRange::new(*position, *position))),
+            Expr::Sheet(name, expr) => {
+                let sheet = self.sheet_index(name)?;
+                match **expr {
+                    Expr::Range(range) => Ok((sheet, range)),
+                    Expr::Ref(position) => Ok((sheet, CellRange::new(position, position))),
+                    _ => Err(FormulaError::Value),
+                }
+            }
+            Expr::Error(e) => Err(*e),
+            _ => Err(FormulaError::Value),
+        }
+    }
+
+    /// Apply a one-argument function
+    fn unary(&mut self, args: &[Expr], f: impl Fn(&Value) -> Result<Value, FormulaError>) -> Result<Value, FormulaError> {
+        match args {
+            [arg] => f(&self.eval(arg)),
+            _ => Err(FormulaError::Value),
+        }
+    }
+}
+
+/// How a lookup treats a target that isn't present
+#[derive(Clone, Copy)]
+enum MatchMode {
+    Exact,
+    /// The largest key below the target, for ascending tables
+    NextSmaller,
+    /// The smallest key above the target
+    NextLarger,
+}
+
+/// Whether an argument is a range, in this sheet or another
+fn is_range(expr: &Expr) -> bool {
+    match expr {
+        Expr::Range(_) => true,
+        Expr::Sheet(_, expr) => matches!(**expr, Expr::Range(_)),
+        _ => false,
+    }
+}
+
+/// The cells of a one-row or one-column range, in order
+fn line_cells(range: CellRange) -> Option<Vec<CellPosition>> {
+    if range.cols().len() == 1 {
+        Some(range.rows().map(|row| CellPosition::new(row, range.start.col)).collect())
+    } else if range.rows().len() == 1 {
+        Some(range.cols().map(|col| CellPosition::new(range.start.row, col)).collect())
+    } else {
+        None
+    }
+}
+
+/// Whether a lookup key can match the target: numbers with dates, text with text, booleans with booleans
+fn same_kind(a: &Value, b: &Value) -> bool {
+    matches!(
+        (a, b),
+        (Value::Number(_) | Value::Date(_), Value::Number(_) | Value::Date(_))
+            | (Value::Text(_), Value::Text(_))
+            | (Value::Bool(_), Value::Bool(_))
+    )
+}
+
+/// The value of a non-formula cell
+fn literal(text: &str) -> Value {
+    if text.trim().is_empty() {
+        Value::Empty
+    } else if let Some(n) = value::parse_number(text) {
+        Value::Number(n)
+    } else if let Some(date) = DateTime::parse(text) {
+        Value::Date(date)
+    } else {
+        Value::Text(text.to_string())
+    }
+}
+
+/// Whole days, months, or years from `start` to `end` for DATEDIF: `Y`, `M`, `D`, or the
+/// remainder after whole years (`YM`, `YD`) or whole months (`MD`)
+fn date_difference(start: DateTime, end: DateTime, unit: &str) -> Result<i64, FormulaError> {
+    let days = |from: DateTime, to: DateTime| (to.to_serial().floor() - from.to_serial().floor()) as i64;
+    if days(start, end) < 0 {
+        return Err(FormulaError::Value);
+    }
+    let ((y1, m1, d1), (y2, m2, d2)) = (start.parts(), end.parts());
+    // Whole months, not counting a final month whose day hasn't come round yet
+    let months = (y2 - y1) * 12 + m2 as i64 - m1 as i64 - i64::from(d2 < d1);
+    match unit {
+        "Y" => Ok(months / 12),
+        "M" => Ok(months),
+        "D" => Ok(days(start, end)),
+        "YM" => Ok(months % 12),
+        "MD" => {
+            // Clamp to the month's last day, so Jan 31 plus a month is Feb 28/29
+            let month_start = DateTime::from_parts(y1, m1 as i64 + months, 1);
+            let anchor = DateTime::from_parts(y1, m1 as i64 + months, d1 as i64).min(month_start.end_of_month(0));
+            Ok(days(anchor, end))
+        }
+        "YD" => {
+            let anchor = DateTime::from_parts(y2, m1 as i64, d1 as i64);
+            let anchor = if days(anchor, end) < 0 { DateTime::from_parts(y2 - 1, m1 as i64, d1 as i64) } else { anchor };
+            Ok(days(anchor, end))
+        }
+        _ => Err(FormulaError::Value),
+    }
+}
+
+/// A uniformly distributed number in [0, 1), from the standard library's randomly keyed hasher
+pub fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn finite(n: f64) -> Result<Value, FormulaError> {
+    if n.is_finite() { Ok(Value::Number(n)) } else { Err(FormulaError::Value) }
+}
+
+fn to_number(value: &Value) -> Result<f64, FormulaError> {
+    match value {
+        Value::Empty => Ok(0.0),
+        Value::Number(n) => Ok(*n),
+        Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        Value::Date(date) => Ok(date.to_serial()),
+        Value::Text(text) => value::parse_number(text).ok_or(FormulaError::Value),
+        Value::Sparkline(_) => Err(FormulaError::Value),
+        Value::Error(e) => Err(*e),
+    }
+}
+
+/// A date argument: a date, date text, or a serial number
+fn to_date(value: &Value) -> Result<DateTime, FormulaError> {
+    match value {
+        Value::Date(date) => Ok(*date),
+        Value::Text(text) => DateTime::parse(text).map_or_else(|| to_number(value).map(DateTime::from_serial), Ok),
+        value => to_number(value).map(DateTime::from_serial),
+    }
+}
+
+fn to_text(value: &Value) -> Result<String, FormulaError> {
+    match value {
+        Value::Error(e) => Err(*e),
+        value => Ok(value.to_string()),
+    }
+}
+
+fn to_bool(value: &Value) -> Result<bool, FormulaError> {
+    match value {
+        Value::Empty => Ok(false),
+        Value::Number(n) => Ok(*n != 0.0),
+        Value::Date(date) => Ok(date.to_serial() != 0.0),
+        Value::Bool(b) => Ok(*b),
+        Value::Text(text) if text.eq_ignore_ascii_case("TRUE") => Ok(true),
+        Value::Text(text) if text.eq_ignore_ascii_case("FALSE") => Ok(false),
+        Value::Text(_) | Value::Sparkline(_) => Err(FormulaError::Value),
+        Value::Error(e) => Err(*e),
+    }
+}
+
+/// Order two values for comparison operators: numbers and dates numerically, text case-insensitively,
+/// booleans false < true. Blanks compare as 0 or "" to match the other side; numbers sort
+/// before text, and text before booleans.
+fn compare_values(a: &Value, b: &Value) -> Result<Ordering, FormulaError> {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Number(_) | Value::Date(_) | Value::Empty => 0,
+            Value::Text(_) => 1,
+            _ => 2,
+        }
+    }
+    match (a, b) {
+        (Value::Error(e), _) | (_, Value::Error(e)) => Err(*e),
+        (Value::Empty, Value::Text(text)) => Ok("".cmp(text.as_str())),
+        (Value::Text(text), Value::Empty) => Ok(text.as_str().cmp("")),
+        (Value::Text(x), Value::Text(y)) => Ok(x.to_lowercase().cmp(&y.to_lowercase())),
+        (Value::Bool(x), Value::Bool(y)) => Ok(x.cmp(y)),
+        (Value::Date(x), Value::Date(y)) => Ok(x.cmp(y)),
+        (Value::Number(_) | Value::Date(_) | Value::Empty, Value::Number(_) | Value::Date(_) | Value::Empty) => {
+            Ok(to_number(a)?.total_cmp(&to_number(b)?))
+        }
+        _ => Ok(rank(a).cmp(&rank(b))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `formula` shows, with `row` filling the cells below it from A2
+    fn eval_with(formula: &str, row: &[&str]) -> String {
+        let cells = vec![vec![formula.to_string()], row.iter().map(|text| text.to_string()).collect()];
+        evaluate_all(&cells)[0][0].as_ref().map(ToString::to_string).unwrap_or_default()
+    }
+
+    fn eval(formula: &str) -> String {
+        eval_with(formula, &[])
+    }
+
+    #[test]
+    fn left_right_and_mid_counts() {
+        assert_eq!(eval("=LEFT(\"abc\")"), "a");
+        assert_eq!(eval("=LEFT(\"abc\", 0)"), "");
+        assert_eq!(eval("=LEFT(\"abc\", -1)"), "#VALUE!");
+        assert_eq!(eval("=LEFT(\"abc\", 10)"), "abc");
+        assert_eq!(eval("=RIGHT(\"abc\", 0)"), "");
+        assert_eq!(eval("=RIGHT(\"abc\", -1)"), "#VALUE!");
+        assert_eq!(eval("=RIGHT(\"abc\", 10)"), "abc");
+        assert_eq!(eval("=RIGHT(\"naïve\", 3)"), "ïve");
+        assert_eq!(eval("=MID(\"abc\", 2, 0)"), "");
+        assert_eq!(eval("=MID(\"abc\", 2, -1)"), "#VALUE!");
+        assert_eq!(eval("=MID(\"abc\", 0, 1)"), "#VALUE!");
+        assert_eq!(eval("=MID(\"abc\", 2, 10)"), "bc");
+        assert_eq!(eval("=MID(\"abc\", 5, 2)"), "");
+    }
+
+    #[test]
+    fn substitute_with_an_empty_needle_changes_nothing() {
+        assert_eq!(eval("=SUBSTITUTE(\"abc\", \"\", \"x\")"), "abc");
+        assert_eq!(eval("=SUBSTITUTE(\"abc\", \"\", \"x\", 1)"), "abc");
+        assert_eq!(eval("=SUBSTITUTE(\"a-b-c\", \"-\", \"+\", 2)"), "a-b+c");
+    }
+
+    #[test]
+    fn textjoin_skips_empty_values_when_asked() {
+        let row = ["a", "", "b", ""];
+        assert_eq!(eval_with("=TEXTJOIN(\"-\", TRUE, A2:D2)", &row), "a-b");
+        assert_eq!(eval_with("=TEXTJOIN(\"-\", FALSE, A2:D2)", &row), "a--b-");
+        assert_eq!(eval_with("=TEXTJOIN(\", \", TRUE, A2, \"\", C2)", &row), "a, b");
+    }
+
+    #[test]
+    fn date_rolls_over_months_and_days() {
+        assert_eq!(eval("=DATE(2024, 13, 1)"), "2025-01-01");
+        assert_eq!(eval("=DATE(2023, 0, 1)"), "2022-12-01");
+        assert_eq!(eval("=DATE(2024, 1, 32)"), "2024-02-01");
+        assert_eq!(eval("=DATE(2024, 3, 0)"), "2024-02-29");
+        assert_eq!(eval("=DATE(2024, 14, 31)"), "2025-03-03");
+    }
+
+    #[test]
+    fn datedif_needs_start_before_end() {
+        assert_eq!(eval("=DATEDIF(DATE(2024, 5, 1), DATE(2024, 4, 1), \"D\")"), "#VALUE!");
+        assert_eq!(eval("=DATEDIF(DATE(2024, 5, 1), DATE(2023, 5, 1), \"Y\")"), "#VALUE!");
+        assert_eq!(eval("=DATEDIF(DATE(2024, 5, 1), DATE(2024, 5, 1), \"D\")"), "0");
+    }
+
+    #[test]
+    fn eomonth_across_years_and_leap_years() {
+        assert_eq!(eval("=EOMONTH(DATE(2023, 12, 15), 1)"), "2024-01-31");
+        assert_eq!(eval("=EOMONTH(DATE(2024, 1, 15), -1)"), "2023-12-31");
+        assert_eq!(eval("=EOMONTH(DATE(2023, 11, 30), 3)"), "2024-02-29");
+        assert_eq!(eval("=EOMONTH(DATE(2024, 1, 31), 1)"), "2024-02-29");
+        assert_eq!(eval("=EOMONTH(DATE(2023, 1, 31), 1)"), "2023-02-28");
+        assert_eq!(eval("=EOMONTH(DATE(2000, 2, 1), 0)"), "2000-02-29");
+        assert_eq!(eval("=EOMONTH(DATE(2100, 2, 1), 0)"), "2100-02-28");
+    }
+}
@@ -0,0 +1,78 @@
+use crate::date::DateTime;
+use crate::formula::{self, Value};
+use crate::value;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Ben", "Chloe", "Diego", "Elena", "Farah", "Gus", "Hana", "Ivan", "Jade",
+    "Kofi", "Lena", "Mateo", "Nora", "Omar", "Priya", "Quinn", "Rosa", "Sam", "Tariq",
+];
+const LAST_NAMES: &[&str] = &[
+    "Abbott", "Brandt", "Castillo", "Dubois", "Eriksen", "Fischer", "Garcia", "Horvat", "Ito", "Jensen",
+    "Kowalski", "Lindqvist", "Moreau", "Nakamura", "Okafor", "Patel", "Rossi", "Schmidt", "Tanaka", "Novak",
+];
+
+/// What `:gen` fills a range with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    /// Numbers counting from `start` by `step`
+    Sequence { start: f64, step: f64 },
+    /// Numbers spread evenly between `min` and `max`: whole numbers when both bounds are,
+    /// otherwise to two decimal places
+    Random { min: f64, max: f64 },
+    /// Days counting from `start` by `step`
+    Dates { start: DateTime, step: i64 },
+    /// Full names drawn from a small list
+    Names,
+}
+
+impl Generator {
+    /// Parse `seq [start] [step]`, `rand [min] [max]` (1 to 100 by default),
+    /// `date [start] [step]` (today onward by default), or `name`
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut words = input.split_whitespace();
+        let kind = words.next()?;
+        let args: Vec<&str> = words.collect();
+        if args.len() > 2 {
+            return None;
+        }
+        let number = |i: usize, default: f64| match args.get(i) {
+            Some(arg) => value::parse_number(arg),
+            None => Some(default),
+        };
+        Some(match kind {
+            "seq" | "sequence" => Self::Sequence { start: number(0, 1.0)?, step: number(1, 1.0)? },
+            "rand" | "random" => {
+                let (min, max) = (number(0, 1.0)?, number(1, 100.0)?);
+                Self::Random { min: min.min(max), max: min.max(max) }
+            }
+            "date" | "dates" => Self::Dates {
+                start: match args.first() {
+                    Some(arg) => DateTime::parse(arg).filter(|date| date.has_date())?.date(),
+                    None => DateTime::now().date(),
+                },
+                step: number(1, 1.0)? as i64,
+            },
+            "name" | "names" if args.is_empty() => Self::Names,
+            _ => return None,
+        })
+    }
+
+    /// The first `count` values, as cell text
+    pub fn values(self, count: usize) -> Vec<String> {
+        let pick = |list: &[&str]| list[(formula::random() * list.len() as f64) as usize % list.len()].to_string();
+        (0..count)
+            .map(|i| match self {
+                Self::Sequence { start, step } => Value::Number(start + step * i as f64).to_string(),
+                Self::Random { min, max } if min.fract() == 0.0 && max.fract() == 0.0 => {
+                    Value::Number(min + (formula::random() * (max - min + 1.0)).floor()).to_string()
+                }
+                Self::Random { min, max } => {
+                    let n = min + formula::random() * (max - min);
+                    Value::Number((n * 100.0).round() / 100.0).to_string()
+                }
+                Self::Dates { start, step } => start.add_days(step * i as i64).to_string(),
+                Self::Names => format!("{} {}", pick(FIRST_NAMES), pick(LAST_NAMES)),
+            })
+            .collect()
+    }
+}
@@ -1,15 +1,58 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
-
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
 use gpui::prelude::FluentBuilder;
 use gpui::*;
-
-use crate::cell::CellInput;
-use crate::command_palette::{CommandPalette, HideCommandPalette, ShowCommandPalette, VimCommand};
-use crate::file_io;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditEntry, AuditLog};
+use crate::cell::{self, CellInput, CursorBlink};
+use crate::chart::{Chart, ChartData, ChartKind};
+use crate::cli::ExitOutput;
+use crate::collab::{self, Session};
+use crate::command_palette::{
+    Address, CommandPalette, ExRange, HideCommandPalette, RangeCommand, Setting, ShowCommandPalette, VimCommand,
+};
+use crate::compare::Comparison;
+use crate::context_menu::{ContextAction, ContextMenu, MenuTarget};
+use crate::crypt;
+use crate::file_io::{self, CsvFile, CsvLayout, WriteOptions};
 use crate::file_state::FileState;
-use crate::metadata::SpreadsheetMetadata;
-use crate::state::{CellPosition, Mode, GRID_COLS, GRID_ROWS};
+use crate::date::{DateFormat, DateTime};
+use crate::delimiter::Delimiter;
+use crate::diff::{self, CellDiff};
+use crate::filter::{FilterCondition, FilterExpr};
+use crate::formula::{self, CalcMode};
+use crate::generate::Generator;
+use crate::inbox;
+use crate::metadata::{SavedSource, SpreadsheetMetadata};
+use crate::number_format::NumberFormat;
+use crate::outline::Outline;
+use crate::perf::{self, PerfStats};
+use crate::pipe;
+use crate::pivot::{PivotRequest, PivotSpec};
+use crate::plugin::{PluginAction, Registry};
+use crate::script::{self, ScriptGrid};
+use crate::sizing::WidthLimits;
+use crate::snapshot::{self, Snapshot, SnapshotEntry};
+use crate::source::{DataSource, Rows, SourceBinding};
+use crate::stats::ColumnStats;
+use crate::menu::{Copy, Cut, Paste, Redo, Undo};
+use crate::state::{CellPosition, CellRange, Mode, GRID_COLS, GRID_ROWS};
+use crate::style::{self, Alignment, CellStyle, StyleChange, StyledCell};
+use crate::undo;
+use crate::validation::{ValidatedCell, Validation};
+use crate::value::{self, ColumnType, ValueKind};
+use crate::viewer::{self, MappedFile, Viewer};
+use crate::window_state::{self, SavedBounds, WindowState};
+use crate::wrap;
 use crate::Theme;
 
 pub const DEFAULT_CELL_WIDTH: f32 = 100.0;
@@ -20,12 +63,40 @@ pub const RESIZE_HANDLE_WIDTH: f32 = 5.0;
 pub const ROW_HEADER_WIDTH: f32 = 50.0;
 pub const COLUMN_HEADER_HEIGHT: f32 = 24.0;
 pub const HEADER_HEIGHT: f32 = 32.0;
+pub const TOOLBAR_HEIGHT: f32 = 32.0;
+pub const TAB_STRIP_HEIGHT: f32 = 28.0;
 pub const FOOTER_HEIGHT: f32 = 24.0;
-
-// Minimum window size: enough for header + column headers + 1 cell row + footer (height)
-// and row header + 1 cell column (width)
-pub const MIN_WINDOW_WIDTH: f32 = ROW_HEADER_WIDTH + DEFAULT_CELL_WIDTH;
-pub const MIN_WINDOW_HEIGHT: f32 = HEADER_HEIGHT + COLUMN_HEADER_HEIGHT + DEFAULT_CELL_HEIGHT + FOOTER_HEIGHT;
+pub const SCROLLBAR_SIZE: f32 = 12.0;
+pub const PROFILE_PANEL_WIDTH: f32 = 260.0;
+pub const AUDIT_PANEL_WIDTH: f32 = 300.0;
+/// Most recent changes the audit panel lists
+const AUDIT_PANEL_ENTRIES: usize = 500;
+pub const MIN_SCROLLBAR_THUMB: f32 = 20.0;
+// How often `:tail` checks the file for new rows
+const TAIL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often edits are exchanged with a collaborator
+const COLLAB_INTERVAL: Duration = Duration::from_millis(50);
+// How long a footer notice stays up
+const FOOTER_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+// How long the window must stay put before its geometry is saved
+const GEOMETRY_SAVE_DELAY: Duration = Duration::from_millis(500);
+// How long a keyboard jump takes to scroll to its destination, and how often it redraws
+const SCROLL_ANIMATION_DURATION: Duration = Duration::from_millis(100);
+const SCROLL_ANIMATION_STEP: Duration = Duration::from_millis(16);
+// Rows or columns a mouse wheel notch scrolls, unless the config file says otherwise
+const DEFAULT_SCROLL_SPEED: usize = 3;
+// Size of a floating chart, and of its plot when exported as an image
+const CHART_WIDTH: f32 = 440.0;
+const CHART_HEIGHT: f32 = 300.0;
+const CHART_EXPORT_SIZE: (u32, u32) = (880, 560);
+// Largest count a command takes, so runaway digits stay sensible
+const MAX_COUNT: usize = 99_999;
+
+// Minimum window size: enough for header + column headers + 1 cell row + scrollbar + footer (height)
+// and row header + 1 cell column + scrollbar (width)
+pub const MIN_WINDOW_WIDTH: f32 = ROW_HEADER_WIDTH + DEFAULT_CELL_WIDTH + SCROLLBAR_SIZE;
+pub const MIN_WINDOW_HEIGHT: f32 =
+    HEADER_HEIGHT + COLUMN_HEADER_HEIGHT + DEFAULT_CELL_HEIGHT + SCROLLBAR_SIZE + FOOTER_HEIGHT;
 
 /// Target for resize operation
 #[derive(Clone, Copy, Debug)]
@@ -34,6 +105,30 @@ pub enum ResizeTarget {
     Row(usize),
 }
 
+/// What a drag with the left button down is selecting across
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionDrag {
+    Cells,
+    Rows,
+    Columns,
+}
+
+/// A drag of selected row or column headers to reorder them: the header grabbed and the one
+/// now under the pointer
+#[derive(Clone, Copy, Debug)]
+pub struct HeaderDrag {
+    pub kind: SelectionDrag,
+    pub grabbed: usize,
+    pub over: usize,
+}
+
+/// A drag of the selection's border: the cell grabbed and the cell now under the pointer
+#[derive(Clone, Copy, Debug)]
+pub struct MoveDrag {
+    pub grabbed: CellPosition,
+    pub over: CellPosition,
+}
+
 /// State for active resize operation
 #[derive(Clone, Copy, Debug)]
 pub struct ResizeState {
@@ -42,8 +137,66 @@ pub struct ResizeState {
     pub original_size: f32,
 }
 
+/// Scrollbar orientation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollbarAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// State for active scrollbar thumb drag
+#[derive(Clone, Copy, Debug)]
+pub struct ScrollbarDragState {
+    pub axis: ScrollbarAxis,
+    pub start_mouse_pos: f32,
+    pub start_scroll_pos: f32,
+    /// Content pixels per track pixel, captured at drag start so the thumb doesn't jitter
+    /// while the scrollable extent grows
+    pub scale: f32,
+}
+
+/// Geometry of a scrollbar track and thumb, in pixels along the scroll axis
+#[derive(Clone, Copy, Debug)]
+pub struct ScrollbarMetrics {
+    pub track_len: f32,
+    /// Length of the scrolling viewport (excludes frozen panes)
+    pub view_len: f32,
+    pub thumb_start: f32,
+    pub thumb_len: f32,
+    pub content_len: f32,
+}
+
+/// A tracked file's contents at git HEAD, for marking rows changed since the last commit
+pub struct GitBaseline {
+    head: Vec<Vec<String>>,
+    // The cells `rows` was computed for
+    cells: Vec<Vec<String>>,
+    rows: Vec<Option<CellDiff>>,
+}
+
+impl GitBaseline {
+    /// The file as committed at HEAD, or `None` if it isn't in a git repository or isn't tracked
+    fn load(path: &Path) -> Option<Self> {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = path.file_name()?.to_str()?;
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["show", &format!("HEAD:./{}", name)])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let head = file_io::parse_csv(&String::from_utf8(output.stdout).ok()?).ok()?;
+        Some(Self { head, cells: Vec::new(), rows: vec![None; GRID_ROWS] })
+    }
+}
+
 /// Auto-fit watch mode configuration
-#[derive(Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "lowercase")]
 pub enum AutoFitWatch {
     #[default]
     None,
@@ -52,6 +205,37 @@ pub enum AutoFitWatch {
     Rows(HashSet<usize>),
 }
 
+/// Which lines through the cursor cell are highlighted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Crosshair {
+    #[default]
+    Off,
+    Row,
+    Column,
+    Both,
+}
+
+impl Crosshair {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim() {
+            "off" => Some(Self::Off),
+            "row" => Some(Self::Row),
+            "col" | "column" => Some(Self::Column),
+            "on" | "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    fn highlights(self, cursor: CellPosition, row: usize, col: usize) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Row => row == cursor.row,
+            Self::Column => col == cursor.col,
+            Self::Both => row == cursor.row || col == cursor.col,
+        }
+    }
+}
+
 // Actions for Normal mode
 actions!(
     normal_mode,
@@ -60,7 +244,35 @@ actions!(
         MoveDown,
         MoveLeft,
         MoveRight,
+        PickFromList,
+        MoveRowUp,
+        MoveRowDown,
+        MoveColumnLeft,
+        MoveColumnRight,
+        ClearContents,
+        ClearFormats,
+        ClearAll,
+        GoToFirstRow,
+        GoToLastRow,
+        GoToFirstCell,
+        GoToLastCell,
+        PageUp,
+        PageDown,
         EnterEditMode,
+        ExtendUp,
+        ExtendDown,
+        ExtendLeft,
+        ExtendRight,
+        ToggleVisualMode,
+        ClearSelection,
+        SelectAllCells,
+        SelectDataBlock,
+        PasteTransposed,
+        RevertToSaved,
+        Increment,
+        Decrement,
+        IncrementSequence,
+        DecrementSequence,
     ]
 );
 
@@ -73,11 +285,64 @@ actions!(
         ExitAndMoveDown,
         ExitAndMoveLeft,
         ExitAndMoveRight,
+        PointUp,
+        PointDown,
+        PointLeft,
+        PointRight,
+        PointExtendUp,
+        PointExtendDown,
+        PointExtendLeft,
+        PointExtendRight,
     ]
 );
 
 // Global actions
-actions!(spreadsheet, [Quit, ToggleKeepCursorInView]);
+actions!(
+    spreadsheet,
+    [
+        Quit,
+        ToggleKeepCursorInView,
+        ToggleToolbar,
+        ToggleGridlines,
+        FreezePanes,
+        UnfreezePanes,
+        Recalculate,
+        InsertChart,
+        GoToCell,
+        ConfirmNameBox,
+        CancelNameBox,
+        RepeatLastCommand,
+        ShowCommandHistory,
+    ]
+);
+
+// Cell text formatting actions
+actions!(
+    format,
+    [
+        ToggleBold,
+        ToggleItalic,
+        ToggleUnderline,
+        FormatGeneral,
+        FormatDecimal,
+        FormatThousands,
+        FormatCurrency,
+        FormatPercent,
+        FormatScientific,
+    ]
+);
+
+// Outline group actions (vim fold-style)
+actions!(
+    outline,
+    [
+        ToggleGroup,
+        OpenGroup,
+        CloseGroup,
+        ExpandAllGroups,
+        CollapseAllGroups,
+    ]
+);
 
 // File operation actions
 actions!(
@@ -91,18 +356,248 @@ actions!(
         CloseFile,
         ToggleReadOnly,
         ForceQuit,
+        NewTab,
+        NextTab,
+        PreviousTab,
     ]
 );
 
+/// Requests from a grid to the window holding its tab
+pub enum TabEvent {
+    New,
+    Open { path: PathBuf, read_only: bool },
+    /// Build a pivot table of the sending grid in a new tab
+    Pivot { source: WeakEntity<SpreadsheetGrid>, spec: PivotSpec },
+    Next,
+    Previous,
+    Close,
+    /// Its cells changed, so formulas in other sheets may need recalculating
+    CellsChanged,
+}
+
+/// An open file, and the app's subscriptions to its grid
+struct Tab {
+    grid: Entity<SpreadsheetGrid>,
+    _subscriptions: [Subscription; 2],
+}
+
 /// The main spreadsheet application component
 pub struct SpreadsheetApp {
-    grid: Entity<SpreadsheetGrid>,
+    tabs: Vec<Tab>,
+    active: usize,
+    // Bumped on each move or resize, so only the last of a burst is saved
+    geometry_epoch: usize,
+    _window_bounds: Subscription,
 }
 
 impl SpreadsheetApp {
-    pub fn new(cx: &mut Context<Self>) -> Self {
+    pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
+        let window_bounds = cx.observe_window_bounds(window, |app, window, cx| app.save_geometry(window, cx));
+        let mut app = Self { tabs: Vec::new(), active: 0, geometry_epoch: 0, _window_bounds: window_bounds };
+        app.add_tab(window, cx);
+        app.watch_inbox(window, cx);
+        // `--stdout` / `--pipe-out`: hand the sheet on when the app quits
+        if let Some(output) = cx.try_global::<ExitOutput>().cloned() {
+            cx.on_app_quit(move |app, cx| {
+                let rows = app.tabs[app.active].grid.read(cx).output_rows();
+                output.emit(&rows);
+                async {}
+            })
+            .detach();
+        }
+        app
+    }
+
+    /// Remember where the window is for the current displays, once it stops moving
+    fn save_geometry(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let bounds = SavedBounds::from_window_bounds(window.window_bounds());
+        let key = window_state::display_key(cx);
+        self.geometry_epoch += 1;
+        let epoch = self.geometry_epoch;
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            cx.background_executor().timer(GEOMETRY_SAVE_DELAY).await;
+            let settled = this.read_with(cx, |app, _| app.geometry_epoch == epoch).unwrap_or(false);
+            // Re-read the file so a `:set remember_geometry` change since launch is kept
+            let mut state = WindowState::load();
+            if settled && state.remember_geometry {
+                state.geometry.insert(key, bounds);
+                if let Err(e) = state.save() {
+                    eprintln!("Failed to save window geometry: {}", e);
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Open what other apps ask to, as it arrives
+    fn watch_inbox(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(mut requests) = inbox::receiver() else {
+            return;
+        };
+        let window = window.window_handle();
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            while let Some(request) = requests.next().await {
+                let result = window.update(cx, |_, window, cx| {
+                    this.update(cx, |app, cx| app.open_request(request, window, cx))
+                });
+                if !matches!(result, Ok(Ok(()))) {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Text goes in a new tab. A link switches to the tab that has its file open, else opens
+    /// it in a new one, then jumps to its cell.
+    fn open_request(&mut self, request: inbox::Request, window: &mut Window, cx: &mut Context<Self>) {
+        match request {
+            inbox::Request::Text(text) => {
+                let grid = self.add_tab(window, cx);
+                grid.update(cx, |grid, cx| grid.load_rows(inbox::parse_table(&text), cx));
+            }
+            inbox::Request::Link { path, cell } => {
+                let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+                let wanted = canonical(&path);
+                let open = self.tabs.iter().position(|tab| {
+                    tab.grid.read(cx).file_state.current_path.as_deref().is_some_and(|open| canonical(open) == wanted)
+                });
+                let grid = match open {
+                    Some(index) => {
+                        self.activate(index, window, cx);
+                        self.tabs[index].grid.clone()
+                    }
+                    None => {
+                        let grid = self.add_tab(window, cx);
+                        grid.update(cx, |grid, cx| grid.load_file(path, false, window, cx));
+                        grid
+                    }
+                };
+                if let Some(cell) = cell {
+                    grid.update(cx, |grid, cx| grid.goto(cell, cx));
+                }
+            }
+        }
+    }
+
+    /// Open an empty grid in a new tab after the others, and switch to it
+    fn add_tab(&mut self, window: &mut Window, cx: &mut Context<Self>) -> Entity<SpreadsheetGrid> {
         let grid = cx.new(|cx| SpreadsheetGrid::new(cx));
-        Self { grid }
+        let subscriptions = [
+            cx.subscribe_in(&grid, window, Self::on_tab_event),
+            // Redraw the tab strip as the file's name and dirty state change
+            cx.observe(&grid, |_, _, cx| cx.notify()),
+        ];
+        self.tabs.push(Tab { grid: grid.clone(), _subscriptions: subscriptions });
+        self.link_sheets(cx);
+        self.activate(self.tabs.len() - 1, window, cx);
+        grid
+    }
+
+    /// Give every grid the workbook's sheets as tabs open and close, recalculating them since
+    /// `Sheet<N>` follows tab positions
+    fn link_sheets(&mut self, cx: &mut Context<Self>) {
+        let sheets: Vec<_> = self.tabs.iter().map(|tab| tab.grid.downgrade()).collect();
+        for tab in &self.tabs {
+            tab.grid.update(cx, |grid, cx| {
+                grid.sheets = sheets.clone();
+                grid.other_sheet_changed(cx);
+            });
+        }
+    }
+
+    fn activate(&mut self, index: usize, window: &mut Window, cx: &mut Context<Self>) {
+        self.active = index;
+        // Grids leave room for the tab strip while there's more than one tab
+        let show_tab_strip = self.tabs.len() > 1;
+        for tab in &self.tabs {
+            tab.grid.update(cx, |grid, cx| {
+                grid.show_tab_strip = show_tab_strip;
+                cx.notify();
+            });
+        }
+        self.tabs[index].grid.update(cx, |grid, cx| {
+            grid.focus_handle.focus(window, cx);
+            grid.refresh_menu(cx);
+        });
+        cx.notify();
+    }
+
+    /// Close a grid's tab; the last tab is cleared instead
+    fn close_tab(&mut self, grid: &Entity<SpreadsheetGrid>, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(index) = self.tabs.iter().position(|tab| &tab.grid == grid) else {
+            return;
+        };
+        if self.tabs.len() == 1 {
+            grid.update(cx, |grid, cx| grid.new_file(&NewFile, window, cx));
+            return;
+        }
+        self.tabs.remove(index);
+        self.link_sheets(cx);
+        let active = if index < self.active { self.active - 1 } else { self.active.min(self.tabs.len() - 1) };
+        self.activate(active, window, cx);
+    }
+
+    fn on_tab_event(&mut self, grid: &Entity<SpreadsheetGrid>, event: &TabEvent, window: &mut Window, cx: &mut Context<Self>) {
+        let count = self.tabs.len();
+        match event {
+            TabEvent::New => {
+                self.add_tab(window, cx);
+            }
+            TabEvent::Open { path, read_only } => {
+                let grid = self.add_tab(window, cx);
+                grid.update(cx, |grid, cx| grid.load_file(path.clone(), *read_only, window, cx));
+            }
+            TabEvent::Pivot { source, spec } => {
+                let grid = self.add_tab(window, cx);
+                grid.update(cx, |grid, cx| {
+                    grid.pivot = Some((source.clone(), spec.clone()));
+                    grid.refresh_pivot(cx);
+                });
+            }
+            TabEvent::Next => self.activate((self.active + 1) % count, window, cx),
+            TabEvent::Previous => self.activate((self.active + count - 1) % count, window, cx),
+            TabEvent::Close => self.close_tab(grid, window, cx),
+            TabEvent::CellsChanged => {
+                for tab in self.tabs.iter().filter(|tab| &tab.grid != grid) {
+                    tab.grid.update(cx, |grid, cx| grid.other_sheet_changed(cx));
+                }
+            }
+        }
+    }
+
+    fn render_tab_strip(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        div()
+            .flex()
+            .flex_row()
+            .w_full()
+            .h(px(TAB_STRIP_HEIGHT))
+            .flex_none()
+            .bg(theme.mantle)
+            .border_b_1()
+            .border_color(theme.surface0)
+            .text_size(px(12.))
+            .children(self.tabs.iter().enumerate().map(|(index, tab)| {
+                let grid = tab.grid.read(cx);
+                let is_active = index == self.active;
+                div()
+                    .id(ElementId::Name(format!("tab-{}", index).into()))
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap(px(6.))
+                    .h_full()
+                    .px(px(12.))
+                    .border_r_1()
+                    .border_color(theme.surface0)
+                    .when(is_active, |d| d.bg(theme.base).text_color(theme.text))
+                    .when(!is_active, |d| d.text_color(theme.subtext0).hover(|d| d.bg(theme.surface0)))
+                    .child(grid.tab_title(cx))
+                    .when(grid.file_state.is_dirty, |d| d.child(div().text_color(theme.accent).child("[+]")))
+                    .on_click(cx.listener(move |app, _event, window, cx| app.activate(index, window, cx)))
+            }))
     }
 }
 
@@ -117,7 +612,8 @@ impl Render for SpreadsheetApp {
             .bg(theme.base)
             .text_color(theme.text)
             .font_family("Berkeley Mono")
-            .child(self.grid.clone())
+            .when(self.tabs.len() > 1, |d| d.child(self.render_tab_strip(cx)))
+            .child(self.tabs[self.active].grid.clone())
     }
 }
 
@@ -126,7 +622,44 @@ pub struct SpreadsheetGrid {
     focus_handle: FocusHandle,
     active_input: Entity<CellInput>,
     cells: Vec<Vec<String>>,
+    // Results of formula cells (`None` elsewhere), recalculated after each edit unless
+    // calculation is manual
+    computed: Vec<Vec<Option<formula::Value>>>,
+    calc_mode: CalcMode,
+    history: undo::History,
+    // Bumped when the volatile-function refresh interval changes, stopping the previous tick
+    refresh_epoch: usize,
+    // Whether `:tail` is appending rows written to the file, and a counter that stops the
+    // previous watch when it's restarted or turned off
+    tailing: bool,
+    tail_epoch: usize,
+    // The sheet shared over the network (`:share`, `:join`), and a counter that stops the
+    // previous session's polling when it ends
+    collab: Option<Session>,
+    collab_epoch: usize,
+    // A short notice shown in the footer, and a counter so only the latest one is cleared
+    footer_message: Option<SharedString>,
+    footer_message_epoch: usize,
+    // Last row and column with data, refreshed after each edit
+    used_bounds: (usize, usize),
+    // Where a running scroll animation ends, and a counter that stops an interrupted one
+    scroll_animation: Option<(f32, f32)>,
+    scroll_animation_epoch: usize,
+    // In manual mode, the cells as of the last recalculation, to tell when results are stale
+    calculated_cells: Vec<Vec<String>>,
+    // Cells referenced by the formula being edited, outlined in matching colors
+    edit_references: Vec<CellRange>,
+    // Anchor and moving corner of the range last pointed at while editing a formula
+    pointer: Option<(CellPosition, CellPosition)>,
     selected: CellPosition,
+    // Other corner of the range selection (the cursor is the moving corner)
+    selection_anchor: Option<CellPosition>,
+    // Earlier ranges of a discontiguous selection, added to with cmd-click
+    extra_selections: Vec<CellRange>,
+    // Visual mode: plain movement extends the selection instead of clearing it
+    visual_mode: bool,
+    // Vim count typed before a command, e.g. the 5 of `5 ctrl-a`
+    pending_count: Option<usize>,
     scroll_row: usize,
     scroll_col: usize,
     mode: Mode,
@@ -137,16 +670,115 @@ pub struct SpreadsheetGrid {
     file_state: FileState,
     command_palette: Entity<CommandPalette>,
     show_command_palette: bool,
+    // The cell reference in the header, typed into to jump to a cell or range
+    name_box: Entity<CellInput>,
+    editing_name_box: bool,
     // Scroll pixel offsets for smooth scrolling
     scroll_offset_x: f32,
     scroll_offset_y: f32,
     // When true, scrolling moves the cursor to stay in view
     // When false, cursor stays put; arrow keys snap viewport back to cursor
     keep_cursor_in_view: bool,
+    // Rows or columns per mouse wheel notch
+    scroll_speed: usize,
+    // Button row under the header, for mouse users
+    show_toolbar: bool,
+    // Borders between cells; hidden for presentation-style viewing, saved per file
+    show_gridlines: bool,
+    // Alternate row shading, and highlighted lines through the cursor, for reading wide sheets
+    zebra_stripes: bool,
+    // Side panel summarizing every used column (`:profile`)
+    show_profile: bool,
+    // Cell changes being recorded for this file (`:set audit`), and whether their panel shows
+    audit: Option<AuditLog>,
+    show_audit: bool,
+    crosshair: Crosshair,
+    // Draw the cursor cell's whole content over its neighbors when it doesn't fit
+    expand_selected: bool,
+    // Whether the footer shows roughly how much memory the cells take (`:set memstats`)
+    show_memory_stats: bool,
+    // Frame timings for the `:set perf` overlay, shared with the canvas that marks the end of paint
+    perf: Option<Rc<RefCell<PerfStats>>>,
+    // A file too big to load, shown read-only from a memory map in place of the cells
+    viewer: Option<Viewer>,
+    // An encrypted file waiting on its passphrase to open, and whether to open it read-only
+    pending_decrypt: Option<(PathBuf, bool)>,
+    // Offer to finish typed text with a value already in the column
+    autocomplete: bool,
+    // Editing with the column's values offered in a dropdown (`alt-down`)
+    picking_from_list: bool,
+    // Frozen panes: the top `frozen_rows` rows and left `frozen_cols` columns never scroll
+    frozen_rows: usize,
+    frozen_cols: usize,
+    // Row whose values label the columns (bold, pinned when scrolled past, excluded from sorts)
+    header_row: Option<usize>,
+    // Auto-filter: per-column conditions, a row expression from `:filter`, the data rows
+    // they hide, and the open dropdown's column
+    filters: BTreeMap<usize, FilterCondition>,
+    filter_expr: Option<FilterExpr>,
+    filtered_out: Vec<bool>,
+    show_filter_buttons: bool,
+    filter_menu: Option<usize>,
+    // Columns whose cells word-wrap to their width
+    wrap_columns: HashSet<usize>,
+    // Rows and columns hidden from the context menu (outline groups and filters hide others)
+    hidden_rows: HashSet<usize>,
+    hidden_cols: HashSet<usize>,
+    context_menu: Option<ContextMenu>,
+    // Per-cell text styles, parallel to `cells`
+    cell_styles: Vec<Vec<CellStyle>>,
+    // Number formats for whole columns (a cell's own format takes precedence)
+    column_formats: BTreeMap<usize, NumberFormat>,
+    // How recognized dates display, per column
+    date_formats: BTreeMap<usize, DateFormat>,
+    // Alignment overrides per column (otherwise numbers and dates right, text left)
+    column_alignments: BTreeMap<usize, Alignment>,
+    // Column types inferred from the values when the file opened, and ones set with `:type`
+    inferred_types: BTreeMap<usize, ColumnType>,
+    column_types: BTreeMap<usize, ColumnType>,
+    // Data validation: per-cell rules (parallel to `cells`) and whole-column rules
+    cell_validations: Vec<Vec<Option<Validation>>>,
+    column_validations: BTreeMap<usize, Validation>,
+    // Collapsible outline groups
+    row_outline: Outline,
+    col_outline: Outline,
     // Resizing support
     column_widths: Vec<f32>,
     row_heights: Vec<f32>,
+    // Bounds on column widths, and the widest auto-fit may make any column
+    column_width_limits: BTreeMap<usize, WidthLimits>,
+    autofit_max_width: Option<f32>,
+    // Size of columns and rows that haven't been resized, set per file
+    default_col_width: f32,
+    default_row_height: f32,
+    // Save layout metadata in the CSV file's first line instead of a `.zsheets` sidecar
+    embed_metadata: bool,
+    // The file as saved on disk while `:diffsaved` highlights differences from it
+    diff_saved: Option<Vec<Vec<String>>>,
+    // The file as committed at git HEAD, when it's tracked, with per-row change markers
+    git_baseline: Option<GitBaseline>,
+    // Whether the window shows its tab strip above this grid (it has more than one tab)
+    show_tab_strip: bool,
+    // Another file shown beside the grid by `:compare`, its differing cells highlighted
+    comparison: Option<Comparison>,
+    // The grid and layout this tab's pivot table was built from, for `:pivot refresh`
+    pivot: Option<(WeakEntity<SpreadsheetGrid>, PivotSpec)>,
+    // The workbook's grids, this one included, in tab order: what `=Sheet2!A1` refers into
+    sheets: Vec<WeakEntity<SpreadsheetGrid>>,
+    // Charts floating over the grid (`:chart`), and the one whose title bar is being dragged,
+    // with where it was grabbed relative to its corner
+    charts: Vec<Chart>,
+    chart_drag: Option<(usize, Point<Pixels>)>,
+    // Regions filled from a URL, command, or query, re-fetched by `:refresh`
+    data_sources: Vec<SourceBinding>,
+    // Ranges locked against edit mode, enforced while protection is on
+    protected_ranges: Vec<CellRange>,
+    protection_enabled: bool,
     resize_state: Option<ResizeState>,
+    selection_drag: Option<SelectionDrag>,
+    move_drag: Option<MoveDrag>,
+    header_drag: Option<HeaderDrag>,
+    scrollbar_drag: Option<ScrollbarDragState>,
     autofit_watch: AutoFitWatch,
 }
 
@@ -154,7 +786,11 @@ impl SpreadsheetGrid {
     pub fn new(cx: &mut Context<Self>) -> Self {
         let focus_handle = cx.focus_handle();
         let active_input = cx.new(|cx| CellInput::new(cx));
+        // The grid draws the editor's popups, so it re-renders as the input changes
+        let grid_id = cx.entity_id();
+        active_input.update(cx, |input, _cx| input.observer = Some(grid_id));
         let command_palette = cx.new(|cx| CommandPalette::new(cx));
+        let name_box = cx.new(CellInput::new);
 
         // Initialize 100x100 grid with empty strings
         let cells = (0..GRID_ROWS)
@@ -165,12 +801,69 @@ impl SpreadsheetGrid {
             focus_handle,
             active_input,
             cells,
+            computed: vec![vec![None; GRID_COLS]; GRID_ROWS],
+            history: undo::History::new(&vec![vec![String::new(); GRID_COLS]; GRID_ROWS]),
+            calc_mode: CalcMode::Automatic,
+            refresh_epoch: 0,
+            tailing: false,
+            tail_epoch: 0,
+            collab: None,
+            collab_epoch: 0,
+            footer_message: None,
+            footer_message_epoch: 0,
+            used_bounds: (0, 0),
+            scroll_animation: None,
+            scroll_animation_epoch: 0,
+            calculated_cells: Vec::new(),
+            edit_references: Vec::new(),
+            pointer: None,
             selected: CellPosition::new(0, 0),
+            selection_anchor: None,
+            extra_selections: Vec::new(),
+            visual_mode: false,
+            pending_count: None,
             scroll_row: 0,
             scroll_col: 0,
             scroll_offset_x: 0.0,
             scroll_offset_y: 0.0,
             keep_cursor_in_view: false,
+            scroll_speed: crate::config::Config::get().scroll_speed.unwrap_or(DEFAULT_SCROLL_SPEED).max(1),
+            show_gridlines: true,
+            zebra_stripes: false,
+            show_profile: false,
+            audit: None,
+            show_audit: false,
+            crosshair: Crosshair::Off,
+            expand_selected: false,
+            show_memory_stats: false,
+            perf: None,
+            viewer: None,
+            pending_decrypt: None,
+            autocomplete: true,
+            picking_from_list: false,
+            show_toolbar: false,
+            frozen_rows: 0,
+            frozen_cols: 0,
+            header_row: None,
+            filters: BTreeMap::new(),
+            filter_expr: None,
+            filtered_out: vec![false; GRID_ROWS],
+            show_filter_buttons: false,
+            filter_menu: None,
+            wrap_columns: HashSet::new(),
+            hidden_rows: HashSet::new(),
+            hidden_cols: HashSet::new(),
+            context_menu: None,
+            cell_styles: vec![vec![CellStyle::default(); GRID_COLS]; GRID_ROWS],
+            column_formats: BTreeMap::new(),
+            date_formats: BTreeMap::new(),
+            column_alignments: BTreeMap::new(),
+            inferred_types: BTreeMap::new(),
+            column_types: BTreeMap::new(),
+            cell_validations: vec![vec![None; GRID_COLS]; GRID_ROWS],
+            column_validations: BTreeMap::new(),
+            row_outline: Outline::default(),
+            col_outline: Outline::default(),
             mode: Mode::Normal,
             visible_rows: 20,
             visible_cols: 10,
@@ -178,10 +871,32 @@ impl SpreadsheetGrid {
             grid_width: 0.0,
             file_state: FileState::new(),
             command_palette,
+            name_box,
+            editing_name_box: false,
             show_command_palette: false,
             column_widths: vec![DEFAULT_CELL_WIDTH; GRID_COLS],
             row_heights: vec![DEFAULT_CELL_HEIGHT; GRID_ROWS],
+            column_width_limits: BTreeMap::new(),
+            autofit_max_width: None,
+            default_col_width: DEFAULT_CELL_WIDTH,
+            default_row_height: DEFAULT_CELL_HEIGHT,
+            embed_metadata: false,
+            diff_saved: None,
+            git_baseline: None,
+            show_tab_strip: false,
+            comparison: None,
+            pivot: None,
+            sheets: Vec::new(),
+            charts: Vec::new(),
+            data_sources: Vec::new(),
+            chart_drag: None,
+            protected_ranges: Vec::new(),
+            protection_enabled: true,
             resize_state: None,
+            selection_drag: None,
+            move_drag: None,
+            header_drag: None,
+            scrollbar_drag: None,
             autofit_watch: AutoFitWatch::None,
         }
     }
@@ -202,7 +917,26 @@ impl SpreadsheetGrid {
         self.move_selection(0, 1, window, cx);
     }
 
-    fn move_selection(&mut self, delta_row: isize, delta_col: isize, _window: &mut Window, cx: &mut Context<Self>) {
+    fn move_selection(&mut self, delta_row: isize, delta_col: isize, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(viewer) = &mut self.viewer {
+            viewer.move_by(delta_row);
+            cx.notify();
+            return;
+        }
+        if !self.visual_mode {
+            self.selection_anchor = None;
+            self.extra_selections.clear();
+        }
+        self.move_cursor(delta_row, delta_col, window, cx);
+    }
+
+    /// Move the cursor while keeping the other corner of the selection in place
+    fn extend_selection(&mut self, delta_row: isize, delta_col: isize, window: &mut Window, cx: &mut Context<Self>) {
+        self.selection_anchor.get_or_insert(self.selected);
+        self.move_cursor(delta_row, delta_col, window, cx);
+    }
+
+    fn move_cursor(&mut self, delta_row: isize, delta_col: isize, _window: &mut Window, cx: &mut Context<Self>) {
         // Calculate new position with bounds clamping
         let new_row = (self.selected.row as isize + delta_row)
             .max(0)
@@ -211,955 +945,7105 @@ impl SpreadsheetGrid {
             .max(0)
             .min((GRID_COLS - 1) as isize) as usize;
 
+        // Hop over rows/columns hidden by collapsed groups
+        let new_row = self.skip_hidden_rows(new_row, delta_row.signum(), self.selected.row);
+        let new_col = self.skip_hidden_cols(new_col, delta_col.signum(), self.selected.col);
+
         self.selected = CellPosition::new(new_row, new_col);
         self.ensure_visible();
         cx.notify();
     }
 
-    fn enter_edit_mode(&mut self, _: &EnterEditMode, window: &mut Window, cx: &mut Context<Self>) {
-        self.mode = Mode::Edit;
-
-        // Load current cell content into the input
-        let content = self.cells[self.selected.row][self.selected.col].clone();
-        self.active_input.update(cx, |input, cx| {
-            input.set_content(content, cx);
-        });
-
-        // Focus the input
-        let focus_handle = self.active_input.focus_handle(cx);
-        focus_handle.focus(window, cx);
-        cx.notify();
+    fn go_to_first_row(&mut self, _: &GoToFirstRow, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(viewer) = &mut self.viewer {
+            viewer.move_to(0);
+            cx.notify();
+            return;
+        }
+        self.jump_to(CellPosition::new(0, self.selected.col), 1, cx);
     }
 
-    fn exit_edit_mode(&mut self, _: &ExitEditMode, window: &mut Window, cx: &mut Context<Self>) {
-        self.save_and_exit_edit_mode(window, cx);
+    /// Jump to the last row with data (vim's `G`)
+    fn go_to_last_row(&mut self, _: &GoToLastRow, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(viewer) = &mut self.viewer {
+            viewer.move_to(usize::MAX);
+            cx.notify();
+            return;
+        }
+        self.jump_to(CellPosition::new(self.used_bounds.0, self.selected.col), -1, cx);
     }
 
-    fn exit_and_move_up(&mut self, _: &ExitAndMoveUp, window: &mut Window, cx: &mut Context<Self>) {
-        self.save_and_exit_edit_mode(window, cx);
-        self.move_selection(-1, 0, window, cx);
+    fn go_to_first_cell(&mut self, _: &GoToFirstCell, _window: &mut Window, cx: &mut Context<Self>) {
+        self.jump_to(CellPosition::new(0, 0), 1, cx);
     }
 
-    fn exit_and_move_down(&mut self, _: &ExitAndMoveDown, window: &mut Window, cx: &mut Context<Self>) {
-        self.save_and_exit_edit_mode(window, cx);
-        self.move_selection(1, 0, window, cx);
+    /// Jump to the bottom-right corner of the data
+    fn go_to_last_cell(&mut self, _: &GoToLastCell, _window: &mut Window, cx: &mut Context<Self>) {
+        let (row, col) = self.used_bounds;
+        self.jump_to(CellPosition::new(row, col), -1, cx);
     }
 
-    fn exit_and_move_left(&mut self, _: &ExitAndMoveLeft, window: &mut Window, cx: &mut Context<Self>) {
-        self.save_and_exit_edit_mode(window, cx);
-        self.move_selection(0, -1, window, cx);
+    fn page_up(&mut self, _: &PageUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.page(-1, cx);
     }
 
-    fn exit_and_move_right(&mut self, _: &ExitAndMoveRight, window: &mut Window, cx: &mut Context<Self>) {
-        self.save_and_exit_edit_mode(window, cx);
-        self.move_selection(0, 1, window, cx);
+    fn page_down(&mut self, _: &PageDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.page(1, cx);
     }
 
-    fn save_and_exit_edit_mode(&mut self, window: &mut Window, cx: &mut Context<Self>) {
-        // Save the content from the input back to the cell
-        let content = self.active_input.read(cx).get_content();
-        let old_content = &self.cells[self.selected.row][self.selected.col];
-        let content_changed = &content != old_content;
-        if content_changed {
-            self.cells[self.selected.row][self.selected.col] = content;
-            self.file_state.mark_dirty();
-            // Check if auto-fit watch mode should resize this cell
-            let row = self.selected.row;
-            let col = self.selected.col;
-            self.check_autofit_watch(row, col, cx);
+    /// Scroll a screen up or down, taking the cursor along by as many rows
+    fn page(&mut self, direction: isize, cx: &mut Context<Self>) {
+        if let Some(viewer) = &mut self.viewer {
+            let rows = (self.grid_height / DEFAULT_CELL_HEIGHT).floor().max(1.0) as isize;
+            viewer.move_by(direction * rows);
+            cx.notify();
+            return;
         }
-
-        self.mode = Mode::Normal;
-        self.focus_handle.focus(window, cx);
+        self.finish_scroll_animation();
+        let from = (self.scroll_pos_x(), self.scroll_pos_y());
+        let rows = self.last_fully_visible_row().saturating_sub(self.scroll_row).max(1) as isize;
+        self.scroll_to_pos_y(from.1 + direction as f32 * self.scroll_area_height());
+        let row = (self.selected.row as isize + direction * rows).clamp(0, GRID_ROWS as isize - 1) as usize;
+        self.move_cursor_to(CellPosition::new(row, self.selected.col), direction);
+        self.ensure_visible();
+        self.animate_scroll_from(from, cx);
         cx.notify();
     }
 
-    // File operations
-    fn new_file(&mut self, _: &NewFile, window: &mut Window, cx: &mut Context<Self>) {
-        // Reset all cells
-        self.cells = (0..GRID_ROWS)
-            .map(|_| (0..GRID_COLS).map(|_| String::new()).collect())
-            .collect();
-        self.selected = CellPosition::new(0, 0);
-        self.scroll_row = 0;
-        self.scroll_col = 0;
-        self.scroll_offset_x = 0.0;
-        self.scroll_offset_y = 0.0;
-        // Reset dimensions to defaults
-        self.column_widths = vec![DEFAULT_CELL_WIDTH; GRID_COLS];
-        self.row_heights = vec![DEFAULT_CELL_HEIGHT; GRID_ROWS];
-        self.autofit_watch = AutoFitWatch::None;
-        self.file_state = FileState::new();
-        self.focus_handle.focus(window, cx);
+    /// Move the cursor to a cell and scroll there
+    fn jump_to(&mut self, position: CellPosition, direction: isize, cx: &mut Context<Self>) {
+        let from = (self.scroll_pos_x(), self.scroll_pos_y());
+        self.move_cursor_to(position, direction);
+        self.ensure_visible();
+        self.animate_scroll_from(from, cx);
         cx.notify();
     }
 
-    fn open_file(&mut self, _: &OpenFile, window: &mut Window, cx: &mut Context<Self>) {
-        self.open_file_dialog(false, window, cx);
-    }
-
-    fn open_file_dialog(&mut self, read_only: bool, window: &mut Window, cx: &mut Context<Self>) {
-        let path = rfd::FileDialog::new()
-            .add_filter("CSV", &["csv"])
-            .add_filter("All Files", &["*"])
-            .pick_file();
-
-        if let Some(path) = path {
-            self.load_file(path, read_only, cx);
+    /// Put the cursor on a cell, stepping past hidden rows and columns in `direction`
+    fn move_cursor_to(&mut self, position: CellPosition, direction: isize) {
+        if !self.visual_mode {
+            self.selection_anchor = None;
+            self.extra_selections.clear();
         }
-
-        self.focus_handle.focus(window, cx);
+        self.selected = CellPosition::new(
+            self.skip_hidden_rows(position.row, direction, self.selected.row),
+            self.skip_hidden_cols(position.col, direction, self.selected.col),
+        );
     }
 
-    fn load_file(&mut self, path: PathBuf, read_only: bool, cx: &mut Context<Self>) {
-        match file_io::read_csv(&path) {
-            Ok(cells) => {
-                self.cells = cells;
-                self.selected = CellPosition::new(0, 0);
-                self.scroll_row = 0;
-                self.scroll_col = 0;
-                self.scroll_offset_x = 0.0;
-                self.scroll_offset_y = 0.0;
-
-                // Load metadata (column widths, row heights)
-                match SpreadsheetMetadata::load(&path) {
-                    Ok(metadata) => {
-                        self.column_widths = metadata.get_column_widths();
-                        self.row_heights = metadata.get_row_heights();
-                    }
-                    Err(_) => {
-                        // Reset to defaults if metadata can't be loaded
-                        self.column_widths = vec![DEFAULT_CELL_WIDTH; GRID_COLS];
-                        self.row_heights = vec![DEFAULT_CELL_HEIGHT; GRID_ROWS];
-                    }
+    /// Scroll smoothly from an earlier position to the current one, unless motion is reduced
+    fn animate_scroll_from(&mut self, from: (f32, f32), cx: &mut Context<Self>) {
+        let to = (self.scroll_pos_x(), self.scroll_pos_y());
+        if from == to || crate::config::reduced_motion() {
+            return;
+        }
+        self.scroll_to_pos_x(from.0);
+        self.scroll_to_pos_y(from.1);
+        self.scroll_animation = Some(to);
+        self.scroll_animation_epoch += 1;
+        let epoch = self.scroll_animation_epoch;
+        let start = std::time::Instant::now();
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                cx.background_executor().timer(SCROLL_ANIMATION_STEP).await;
+                let should_continue = this
+                    .update(cx, |grid, cx| {
+                        if grid.scroll_animation_epoch != epoch {
+                            return false;
+                        }
+                        let t = (start.elapsed().as_secs_f32() / SCROLL_ANIMATION_DURATION.as_secs_f32()).min(1.0);
+                        let eased = 1.0 - (1.0 - t).powi(3);
+                        grid.scroll_to_pos_x(from.0 + (to.0 - from.0) * eased);
+                        grid.scroll_to_pos_y(from.1 + (to.1 - from.1) * eased);
+                        if t >= 1.0 {
+                            grid.scroll_animation = None;
+                        }
+                        cx.notify();
+                        t < 1.0
+                    })
+                    .unwrap_or(false);
+                if !should_continue {
+                    break;
                 }
-
-                self.file_state = FileState::new();
-                self.file_state.set_path(path);
-                self.file_state.set_read_only(read_only);
-                self.autofit_watch = AutoFitWatch::None;
-                cx.notify();
-            }
-            Err(e) => {
-                eprintln!("Failed to open file: {}", e);
             }
-        }
+        })
+        .detach();
     }
 
-    fn save_file(&mut self, _: &SaveFile, window: &mut Window, cx: &mut Context<Self>) {
-        if self.file_state.is_read_only {
-            eprintln!("File is read-only. Use :w! to force write.");
-            return;
+    /// Skip a running scroll animation to where it ends
+    fn finish_scroll_animation(&mut self) {
+        if let Some((x, y)) = self.scroll_animation.take() {
+            self.scroll_animation_epoch += 1;
+            self.scroll_to_pos_x(x);
+            self.scroll_to_pos_y(y);
         }
+    }
 
-        if let Some(path) = self.file_state.current_path.clone() {
-            self.save_to_path(&path, cx);
-        } else {
-            self.save_file_as(&SaveFileAs, window, cx);
+    /// Stop a running scroll animation where it is
+    fn cancel_scroll_animation(&mut self) {
+        if self.scroll_animation.take().is_some() {
+            self.scroll_animation_epoch += 1;
         }
     }
 
-    fn save_file_as(&mut self, _: &SaveFileAs, window: &mut Window, cx: &mut Context<Self>) {
-        let path = rfd::FileDialog::new()
-            .add_filter("CSV", &["csv"])
-            .set_file_name("spreadsheet.csv")
-            .save_file();
-
-        if let Some(path) = path {
-            self.save_to_path(&path, cx);
-            self.file_state.set_path(path);
-        }
-
-        self.focus_handle.focus(window, cx);
+    fn extend_up(&mut self, _: &ExtendUp, window: &mut Window, cx: &mut Context<Self>) {
+        self.extend_selection(-1, 0, window, cx);
     }
 
-    fn force_write(&mut self, _: &ForceWrite, window: &mut Window, cx: &mut Context<Self>) {
-        let was_read_only = self.file_state.is_read_only;
-        self.file_state.set_read_only(false);
-
-        if let Some(path) = self.file_state.current_path.clone() {
-            self.save_to_path(&path, cx);
-        } else {
-            self.save_file_as(&SaveFileAs, window, cx);
-        }
+    fn extend_down(&mut self, _: &ExtendDown, window: &mut Window, cx: &mut Context<Self>) {
+        self.extend_selection(1, 0, window, cx);
+    }
 
-        self.file_state.set_read_only(was_read_only);
+    fn extend_left(&mut self, _: &ExtendLeft, window: &mut Window, cx: &mut Context<Self>) {
+        self.extend_selection(0, -1, window, cx);
     }
 
-    fn save_to_path(&mut self, path: &PathBuf, cx: &mut Context<Self>) {
-        match file_io::write_csv(path, &self.cells) {
-            Ok(()) => {
-                // Save metadata (column widths, row heights)
-                let metadata = SpreadsheetMetadata {
-                    column_widths: Some(self.column_widths.clone()),
-                    row_heights: Some(self.row_heights.clone()),
-                };
-                if let Err(e) = metadata.save(path) {
-                    eprintln!("Warning: Failed to save metadata: {}", e);
-                }
+    fn extend_right(&mut self, _: &ExtendRight, window: &mut Window, cx: &mut Context<Self>) {
+        self.extend_selection(0, 1, window, cx);
+    }
 
-                self.file_state.mark_clean();
-                self.file_state.set_path(path.clone());
-                cx.notify();
-            }
-            Err(e) => {
-                eprintln!("Failed to save file: {}", e);
-            }
-        }
+    /// Jump the cursor to a cell, dropping any selection
+    fn goto(&mut self, position: CellPosition, cx: &mut Context<Self>) {
+        self.selected = position;
+        self.visual_mode = false;
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.ensure_visible();
+        cx.notify();
     }
 
-    fn close_file(&mut self, _: &CloseFile, window: &mut Window, cx: &mut Context<Self>) {
-        if self.file_state.is_dirty {
-            eprintln!("File has unsaved changes. Use :q! to force quit.");
-            return;
-        }
-        self.new_file(&NewFile, window, cx);
+    fn toggle_visual_mode(&mut self, _: &ToggleVisualMode, _window: &mut Window, cx: &mut Context<Self>) {
+        self.visual_mode = !self.visual_mode;
+        self.selection_anchor = self.visual_mode.then_some(self.selected);
+        cx.notify();
     }
 
-    fn force_quit(&mut self, _: &ForceQuit, _window: &mut Window, cx: &mut Context<Self>) {
-        cx.quit();
+    fn clear_selection(&mut self, _: &ClearSelection, _window: &mut Window, cx: &mut Context<Self>) {
+        self.visual_mode = false;
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        cx.notify();
     }
 
-    fn toggle_read_only(&mut self, _: &ToggleReadOnly, _window: &mut Window, cx: &mut Context<Self>) {
-        self.file_state.set_read_only(!self.file_state.is_read_only);
+    /// Select the used range from A1, or the whole grid when that's already selected
+    fn select_all(&mut self, _: &SelectAllCells, _window: &mut Window, cx: &mut Context<Self>) {
+        let (max_row, max_col) = file_io::find_used_bounds(&self.cells);
+        let used = CellRange::new(CellPosition::new(0, 0), CellPosition::new(max_row, max_col));
+        let all = CellRange::new(CellPosition::new(0, 0), CellPosition::new(GRID_ROWS - 1, GRID_COLS - 1));
+        self.select_range(if self.selection_range() == used { all } else { used });
         cx.notify();
     }
 
-    fn toggle_keep_cursor_in_view(&mut self, _: &ToggleKeepCursorInView, _window: &mut Window, cx: &mut Context<Self>) {
-        self.keep_cursor_in_view = !self.keep_cursor_in_view;
-        crate::menu::setup_menu_with_state(cx, self.keep_cursor_in_view);
+    /// Select the block of filled cells around the cursor (vim's `vip`)
+    fn select_data_block(&mut self, _: &SelectDataBlock, _window: &mut Window, cx: &mut Context<Self>) {
+        self.select_range(data_block(&self.cells, self.selected));
+        self.ensure_visible();
         cx.notify();
     }
 
-    // Command palette
-    fn show_command_palette(&mut self, _: &ShowCommandPalette, window: &mut Window, cx: &mut Context<Self>) {
-        // Exit edit mode if active
-        if self.mode == Mode::Edit {
-            self.save_and_exit_edit_mode(window, cx);
-        }
+    /// The selected block: from the anchor to the cursor, or just the cursor cell
+    fn selection_range(&self) -> CellRange {
+        CellRange::new(self.selection_anchor.unwrap_or(self.selected), self.selected)
+    }
 
-        self.show_command_palette = true;
-        self.command_palette.update(cx, |palette, cx| {
-            palette.reset(cx);
-        });
+    /// Replace the selection with `range`, putting the cursor at its top-left
+    fn select_range(&mut self, range: CellRange) {
+        self.selected = range.start;
+        self.selection_anchor = (!range.is_single_cell()).then_some(range.end);
+        self.extra_selections.clear();
+    }
 
-        let palette_focus = self.command_palette.focus_handle(cx);
-        palette_focus.focus(window, cx);
-        cx.notify();
+    /// Every range of the selection, the current one last
+    fn selection_ranges(&self) -> impl Iterator<Item = CellRange> + '_ {
+        self.extra_selections.iter().copied().chain(std::iter::once(self.selection_range()))
     }
 
-    fn hide_command_palette(&mut self, _: &HideCommandPalette, window: &mut Window, cx: &mut Context<Self>) {
-        self.show_command_palette = false;
-        self.focus_handle.focus(window, cx);
-        cx.notify();
+    /// Columns any range of the selection touches
+    fn selected_cols(&self) -> BTreeSet<usize> {
+        self.selection_ranges().flat_map(|range| range.cols()).collect()
     }
 
-    fn handle_command(&mut self, cmd_id: &str, vim_cmd: Option<VimCommand>, window: &mut Window, cx: &mut Context<Self>) {
-        // Hide palette first
-        self.show_command_palette = false;
-        self.focus_handle.focus(window, cx);
+    fn is_in_selection(&self, pos: CellPosition) -> bool {
+        (self.selection_anchor.is_some() && self.selection_range().contains(pos))
+            || self.extra_selections.iter().any(|range| range.contains(pos))
+    }
 
-        // Handle vim commands
-        if let Some(vim_cmd) = vim_cmd {
-            match vim_cmd {
-                VimCommand::Write => self.save_file(&SaveFile, window, cx),
-                VimCommand::WriteTo(path) => {
-                    self.save_to_path(&path, cx);
-                    self.file_state.set_path(path);
+    /// Show a notice in the footer for a few seconds
+    fn show_footer_message(&mut self, message: impl Into<SharedString>, cx: &mut Context<Self>) {
+        self.footer_message = Some(message.into());
+        self.footer_message_epoch += 1;
+        let epoch = self.footer_message_epoch;
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            cx.background_executor().timer(FOOTER_MESSAGE_DURATION).await;
+            this.update(cx, |grid, cx| {
+                if grid.footer_message_epoch == epoch {
+                    grid.footer_message = None;
+                    cx.notify();
                 }
-                VimCommand::ForceWrite => self.force_write(&ForceWrite, window, cx),
-                VimCommand::WriteQuit => {
-                    self.save_file(&SaveFile, window, cx);
-                    cx.quit();
-                }
-                VimCommand::Quit => self.close_file(&CloseFile, window, cx),
-                VimCommand::ForceQuit => cx.quit(),
-                VimCommand::Edit(path) => self.load_file(path, false, cx),
-                VimCommand::View(path) => self.load_file(path, true, cx),
-                VimCommand::SaveAs(path) => {
-                    self.save_to_path(&path, cx);
-                    self.file_state.set_path(path);
-                }
-                VimCommand::New => self.new_file(&NewFile, window, cx),
-                // Auto-fit commands
-                VimCommand::AutoFitAll => self.auto_fit_all(cx),
-                VimCommand::AutoFitColumn => self.auto_fit_column(self.selected.col, cx),
-                VimCommand::AutoFitRow => self.auto_fit_row(self.selected.row, cx),
-                VimCommand::AutoFitWatch => self.toggle_autofit_watch_all(cx),
-                VimCommand::AutoFitColumnWatch => self.toggle_autofit_watch_column(self.selected.col, cx),
-                VimCommand::AutoFitRowWatch => self.toggle_autofit_watch_row(self.selected.row, cx),
-                VimCommand::ResetAllSizes => self.reset_all_sizes(cx),
-            }
-            cx.notify();
-            return;
+            })
+            .ok();
+        })
+        .detach();
+        cx.notify();
+    }
+
+    /// Whether edit mode may open on the cursor cell, explaining in the footer if not
+    fn can_edit_selected(&mut self, cx: &mut Context<Self>) -> bool {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return false;
         }
+        if self.is_protected(self.selected) {
+            let message = format!("{} is protected; :unprotect it or :set protection=off to edit", self.selected.to_reference());
+            self.show_footer_message(message, cx);
+            return false;
+        }
+        true
+    }
 
-        // Handle regular commands
-        match cmd_id {
-            "new_file" => self.new_file(&NewFile, window, cx),
-            "open_file" => self.open_file(&OpenFile, window, cx),
-            "save_file" => self.save_file(&SaveFile, window, cx),
-            "save_file_as" => self.save_file_as(&SaveFileAs, window, cx),
-            "force_write" => self.force_write(&ForceWrite, window, cx),
-            "close_file" => self.close_file(&CloseFile, window, cx),
-            "quit" => cx.quit(),
-            "toggle_read_only" => self.toggle_read_only(&ToggleReadOnly, window, cx),
-            // Auto-fit commands
-            "autofit_all" => self.auto_fit_all(cx),
-            "autofit_column" => self.auto_fit_column(self.selected.col, cx),
-            "autofit_row" => self.auto_fit_row(self.selected.row, cx),
-            "autofit_watch" => self.toggle_autofit_watch_all(cx),
-            "reset_sizes" => self.reset_all_sizes(cx),
-            _ => {}
+    // === Differences from the saved file ===
+
+    /// Start or stop highlighting cells that differ from the file on disk
+    fn toggle_diff_saved(&mut self, cx: &mut Context<Self>) {
+        if self.diff_saved.take().is_none() {
+            let Some(path) = self.file_state.current_path.clone() else {
+                self.show_footer_message("No saved file to compare with", cx);
+                return;
+            };
+            match file_io::read_csv(&path) {
+                Ok(cells) => self.diff_saved = Some(cells),
+                Err(e) => eprintln!("Failed to read saved file: {}", e),
+            }
         }
         cx.notify();
     }
 
-    fn ensure_visible(&mut self) {
-        // Vertical: cursor above viewport or partially hidden at top
-        if self.selected.row < self.scroll_row
-            || (self.selected.row == self.scroll_row && self.scroll_offset_y > 0.0)
-        {
-            self.scroll_row = self.selected.row;
-            self.scroll_offset_y = 0.0;
-        } else {
-            // Check if cursor row is partially clipped at the bottom
-            let last_full_row = self.last_fully_visible_row();
-            if self.selected.row > last_full_row {
-                // Scroll down so cursor row is fully visible at the bottom
-                self.scroll_to_show_row_at_bottom(self.selected.row);
+    /// How a cell differs from the saved file, while comparing
+    fn saved_diff(&self, row: usize, col: usize) -> Option<CellDiff> {
+        let saved = self.diff_saved.as_ref()?;
+        CellDiff::between(&saved[row][col], &self.cells[row][col])
+    }
+
+    /// How a row differs from the saved file, for its gutter marker
+    fn row_saved_diff(&self, row: usize) -> Option<CellDiff> {
+        let saved = self.diff_saved.as_ref()?;
+        CellDiff::combine((0..GRID_COLS).filter_map(|col| CellDiff::between(&saved[row][col], &self.cells[row][col])))
+    }
+
+    fn revert_to_saved(&mut self, _: &RevertToSaved, _window: &mut Window, cx: &mut Context<Self>) {
+        self.revert_selection(cx);
+    }
+
+    /// Restore the selected cells to their contents in the saved file
+    fn revert_selection(&mut self, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        let saved = match &self.diff_saved {
+            Some(saved) => saved.clone(),
+            None => match self.file_state.current_path.as_ref().map(|path| file_io::read_csv(path)) {
+                Some(Ok(saved)) => saved,
+                Some(Err(e)) => {
+                    eprintln!("Failed to read saved file: {}", e);
+                    return;
+                }
+                None => {
+                    self.show_footer_message("No saved file to revert to", cx);
+                    return;
+                }
+            },
+        };
+        let ranges: Vec<CellRange> = self.selection_ranges().collect();
+        for range in ranges {
+            for row in range.rows() {
+                for col in range.cols() {
+                    self.cells[row][col] = saved[row][col].clone();
+                }
             }
         }
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
 
-        // Horizontal: cursor left of viewport or partially hidden at left
-        if self.selected.col < self.scroll_col
-            || (self.selected.col == self.scroll_col && self.scroll_offset_x > 0.0)
+    /// Bring git row markers up to date with the cells, if they changed since last time
+    fn refresh_git_markers(&mut self) {
+        if let Some(git) = &mut self.git_baseline
+            && git.cells != self.cells
         {
-            self.scroll_col = self.selected.col;
-            self.scroll_offset_x = 0.0;
-        } else {
-            // Check if cursor col is partially clipped at the right
-            let last_full_col = self.last_fully_visible_col();
-            if self.selected.col > last_full_col {
-                // Scroll right so cursor col is fully visible at the right
-                self.scroll_to_show_col_at_right(self.selected.col);
-            }
+            git.rows = diff::diff_rows(&git.head, &self.cells);
+            git.cells = self.cells.clone();
         }
     }
 
-    /// Find the last row index that is fully visible in the viewport
-    fn last_fully_visible_row(&self) -> usize {
-        let grid_height = self.grid_height;
-        let mut total = 0.0;
-        for (i, row) in (self.scroll_row..GRID_ROWS).enumerate() {
-            let h = self.row_heights[row];
-            let visible_h = if i == 0 { h - self.scroll_offset_y } else { h };
-            total += visible_h;
-            if total > grid_height {
-                // This row is partially clipped; the previous row is the last fully visible
-                return if row > self.scroll_row { row - 1 } else { self.scroll_row };
+    // === Comparison with another file ===
+
+    /// Show `path` beside the grid, pairing rows by the `key` column's values or by position
+    fn compare_with(&mut self, path: PathBuf, key: Option<&str>, cx: &mut Context<Self>) {
+        let key = match key.map(|name| (name, self.find_column(name))) {
+            Some((name, None)) => {
+                self.show_footer_message(format!("Unknown column: {}", name), cx);
+                return;
             }
+            Some((_, col)) => col,
+            None => None,
+        };
+        match file_io::read_csv(&path) {
+            Ok(cells) => self.comparison = Some(Comparison::new(path, cells, key)),
+            Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
         }
-        (GRID_ROWS - 1).min(self.scroll_row + self.visible_rows - 1)
+        cx.notify();
     }
 
-    /// Find the last column index that is fully visible in the viewport
-    fn last_fully_visible_col(&self) -> usize {
-        let grid_width = self.grid_width;
-        let mut total = 0.0;
-        for (i, col) in (self.scroll_col..GRID_COLS).enumerate() {
-            let w = self.column_widths[col];
-            let visible_w = if i == 0 { w - self.scroll_offset_x } else { w };
-            total += visible_w;
-            if total > grid_width {
-                return if col > self.scroll_col { col - 1 } else { self.scroll_col };
-            }
-        }
-        (GRID_COLS - 1).min(self.scroll_col + self.visible_cols - 1)
+    /// How a cell differs from its counterpart in the compared file
+    fn compared_diff(&self, row: usize, col: usize) -> Option<CellDiff> {
+        self.comparison.as_ref()?.cell_diff(&self.cells, row, col)
     }
 
-    /// Scroll viewport by just enough pixels to fully reveal `target_row` at the bottom
-    fn scroll_to_show_row_at_bottom(&mut self, target_row: usize) {
-        // Compute how far the bottom edge of target_row extends past the viewport
-        let mut total = 0.0;
-        for (i, row) in (self.scroll_row..=target_row).enumerate() {
-            let h = self.row_heights[row];
-            let visible_h = if i == 0 { h - self.scroll_offset_y } else { h };
-            total += visible_h;
-        }
-        let overflow = total - self.grid_height;
-        if overflow > 0.0 {
-            self.apply_smooth_scroll(0.0, overflow);
+    // === Audit log ===
+
+    /// Start or stop recording cell changes for this file
+    fn set_audit(&mut self, on: bool, cx: &mut Context<Self>) {
+        if on == self.audit.is_some() {
+            return;
         }
+        let path = self.file_state.current_path.clone();
+        self.audit = on.then(|| AuditLog::new(&self.cells, path.as_deref()));
+        self.file_state.mark_dirty();
+        let message = match (on, path) {
+            (true, Some(path)) => format!("Recording cell changes to {}", audit::log_path(&path).display()),
+            (true, None) => "Recording cell changes; they're logged once the file is saved".to_string(),
+            (false, _) => "Stopped recording cell changes".to_string(),
+        };
+        self.show_footer_message(message, cx);
     }
 
-    /// Scroll viewport by just enough pixels to fully reveal `target_col` at the right
-    fn scroll_to_show_col_at_right(&mut self, target_col: usize) {
-        let mut total = 0.0;
-        for (i, col) in (self.scroll_col..=target_col).enumerate() {
-            let w = self.column_widths[col];
-            let visible_w = if i == 0 { w - self.scroll_offset_x } else { w };
-            total += visible_w;
+    /// Append recorded changes to the file's log. An encrypted file's changes stay in memory,
+    /// since the log would hold its values in plain text.
+    fn flush_audit(&mut self) {
+        if self.file_state.passphrase.is_some() {
+            return;
         }
-        let overflow = total - self.grid_width;
-        if overflow > 0.0 {
-            self.apply_smooth_scroll(overflow, 0.0);
+        let (Some(audit), Some(path)) = (&mut self.audit, &self.file_state.current_path) else {
+            return;
+        };
+        if let Err(e) = audit.flush(path) {
+            eprintln!("Failed to write audit log: {}", e);
         }
     }
 
-    /// Calculate number of visible rows from scroll position that fit in given height
-    fn calculate_visible_rows(&self, available_height: f32) -> usize {
-        let mut total_height = 0.0;
-        let mut count = 0;
-        for row in self.scroll_row..GRID_ROWS {
-            let row_h = self.row_heights[row];
-            // First row is partially hidden by scroll_offset_y
-            let visible_h = if count == 0 { row_h - self.scroll_offset_y } else { row_h };
-            total_height += visible_h;
-            count += 1;
-            if total_height >= available_height {
-                break;
+    /// `:audit export <path>` - write the recorded changes to a CSV file
+    fn export_audit(&mut self, path: &Path, cx: &mut Context<Self>) {
+        let Some(audit) = &self.audit else {
+            self.show_footer_message("Not recording cell changes; :set audit to start", cx);
+            return;
+        };
+        match audit.export(path) {
+            Ok(()) => {
+                let message = format!("Exported {} changes to {}", audit.entries.len(), path.display());
+                self.show_footer_message(message, cx);
             }
+            Err(e) => eprintln!("Failed to export audit log: {}", e),
         }
-        count.max(1)
     }
 
-    /// Calculate number of visible columns from scroll position that fit in given width
-    fn calculate_visible_cols(&self, available_width: f32) -> usize {
-        let mut total_width = 0.0;
-        let mut count = 0;
-        for col in self.scroll_col..GRID_COLS {
-            let col_w = self.column_widths[col];
-            // First column is partially hidden by scroll_offset_x
-            let visible_w = if count == 0 { col_w - self.scroll_offset_x } else { col_w };
-            total_width += visible_w;
-            count += 1;
-            if total_width >= available_width {
-                break;
-            }
-        }
-        count.max(1)
-    }
+    // === Snapshots ===
 
-    // === Resize handle detection helpers ===
+    /// A snapshot of the sheet as it is now: its cells and layout
+    fn snapshot(&self, name: &str) -> Snapshot {
+        Snapshot::new(name, &self.cells, self.layout_metadata())
+    }
 
-    /// Get the X position where a column ends (relative to grid area, after row header)
-    fn column_end_x(&self, col: usize) -> f32 {
-        let sum: f32 = self.column_widths[self.scroll_col..=col].iter().sum();
-        sum - self.scroll_offset_x
+    /// `:snapshot take <name>` - keep a named copy of the sheet in the history directory
+    fn take_snapshot(&mut self, name: &str, cx: &mut Context<Self>) {
+        match snapshot::take(self.file_state.current_path.as_deref(), &self.snapshot(name), false) {
+            Ok(()) => self.show_footer_message(format!("Snapshot \"{}\" taken", name), cx),
+            Err(e) => eprintln!("Failed to take snapshot: {}", e),
+        }
     }
 
-    /// Get the Y position where a row ends (relative to grid area, after column header)
-    fn row_end_y(&self, row: usize) -> f32 {
-        let sum: f32 = self.row_heights[self.scroll_row..=row].iter().sum();
-        sum - self.scroll_offset_y
+    /// Keep a copy of the sheet before a destructive operation, e.g. `before sort`
+    fn auto_snapshot(&self, name: &str) {
+        if let Err(e) = snapshot::take(self.file_state.current_path.as_deref(), &self.snapshot(name), true) {
+            eprintln!("Failed to take snapshot: {}", e);
+        }
     }
 
-    /// Find if x position is near a column resize border, returns the column index whose right edge is near
-    fn column_resize_target(&self, x: f32) -> Option<usize> {
-        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
-        for col in self.scroll_col..end_col {
-            let col_end = self.column_end_x(col);
-            if (x - col_end).abs() <= RESIZE_HANDLE_WIDTH {
-                return Some(col);
-            }
+    /// Show a snapshot beside the grid, its differences from the sheet highlighted
+    fn preview_snapshot(&mut self, label: &str, cx: &mut Context<Self>) {
+        let Some(entry) = snapshot::find(self.file_state.current_path.as_deref(), label) else {
+            self.show_footer_message(format!("No snapshot named {}", label), cx);
+            return;
+        };
+        match entry.load() {
+            Ok(snapshot) => self.comparison = Some(Comparison::new(PathBuf::from(entry.label()), snapshot.grid_cells(), None)),
+            Err(e) => eprintln!("Failed to read snapshot: {}", e),
         }
-        None
+        cx.notify();
     }
 
-    /// Find if y position is near a row resize border, returns the row index whose bottom edge is near
-    fn row_resize_target(&self, y: f32) -> Option<usize> {
-        let end_row = (self.scroll_row + self.visible_rows).min(GRID_ROWS);
-        for row in self.scroll_row..end_row {
-            let row_end = self.row_end_y(row);
-            if (y - row_end).abs() <= RESIZE_HANDLE_WIDTH {
-                return Some(row);
-            }
+    /// Put a snapshot's cells and layout back, or with no label the one being previewed.
+    /// The sheet as it was is snapshotted first, so a restore can itself be undone.
+    fn restore_snapshot(&mut self, label: Option<&str>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
         }
-        None
+        let previewed = self.comparison.as_ref().map(|comparison| comparison.file_name());
+        let Some(label) = label.or(previewed.as_deref()) else {
+            self.show_footer_message("No snapshot to restore; pick one with :snapshot", cx);
+            return;
+        };
+        let Some(entry) = snapshot::find(self.file_state.current_path.as_deref(), label) else {
+            self.show_footer_message(format!("No snapshot named {}", label), cx);
+            return;
+        };
+        let snapshot = match entry.load() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                eprintln!("Failed to read snapshot: {}", e);
+                return;
+            }
+        };
+        self.auto_snapshot("before restore");
+        self.cells = snapshot.grid_cells();
+        self.apply_layout(&snapshot.metadata);
+        self.comparison = None;
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.infer_column_types();
+        self.record_undo();
+        self.compute_formulas(cx);
+        self.track_cells(cx);
+        self.file_state.mark_dirty();
+        self.show_footer_message(format!("Restored snapshot \"{}\"", snapshot.name), cx);
+        self.on_filters_changed(cx);
     }
 
-    // === Resize operations ===
-
-    /// Start a column resize operation
-    fn start_column_resize(&mut self, col: usize, mouse_x: f32, _cx: &mut Context<Self>) {
-        self.resize_state = Some(ResizeState {
-            target: ResizeTarget::Column(col),
-            start_mouse_pos: mouse_x,
-            original_size: self.column_widths[col],
-        });
+    /// Highlight for a cell or row that differs from the saved file
+    fn diff_color(diff: CellDiff, theme: &Theme) -> Rgba {
+        match diff {
+            CellDiff::Added => theme.green,
+            CellDiff::Changed => theme.yellow,
+            CellDiff::Removed => theme.red,
+        }
     }
 
-    /// Start a row resize operation
-    fn start_row_resize(&mut self, row: usize, mouse_y: f32, _cx: &mut Context<Self>) {
-        self.resize_state = Some(ResizeState {
-            target: ResizeTarget::Row(row),
-            start_mouse_pos: mouse_y,
-            original_size: self.row_heights[row],
-        });
-    }
+    // === Protection ===
 
-    /// Update size during resize drag
-    fn update_resize(&mut self, current_pos: f32, cx: &mut Context<Self>) {
-        if let Some(state) = &self.resize_state {
-            let delta = current_pos - state.start_mouse_pos;
-            let new_size = (state.original_size + delta).max(MIN_CELL_WIDTH);
+    fn is_protected(&self, pos: CellPosition) -> bool {
+        self.protection_enabled && self.protected_ranges.iter().any(|range| range.contains(pos))
+    }
 
-            match state.target {
-                ResizeTarget::Column(col) => {
-                    self.column_widths[col] = new_size.max(MIN_CELL_WIDTH);
-                }
-                ResizeTarget::Row(row) => {
-                    self.row_heights[row] = new_size.max(MIN_CELL_HEIGHT);
-                }
+    /// Protect a range, or every range of the selection
+    fn protect(&mut self, range: Option<CellRange>, cx: &mut Context<Self>) {
+        let ranges: Vec<CellRange> = match range {
+            Some(range) => vec![range],
+            None => self.selection_ranges().collect(),
+        };
+        for range in ranges {
+            if !self.protected_ranges.contains(&range) {
+                self.protected_ranges.push(range);
             }
-            cx.notify();
         }
+        self.file_state.mark_dirty();
+        cx.notify();
     }
 
-    /// End resize operation
-    fn end_resize(&mut self, cx: &mut Context<Self>) {
-        self.resize_state = None;
+    /// Remove protected ranges overlapping a range, or the selection
+    fn unprotect(&mut self, range: Option<CellRange>, cx: &mut Context<Self>) {
+        let targets: Vec<CellRange> = match range {
+            Some(range) => vec![range],
+            None => self.selection_ranges().collect(),
+        };
+        self.protected_ranges
+            .retain(|protected| !targets.iter().any(|&target| ranges_overlap(*protected, target)));
         self.file_state.mark_dirty();
         cx.notify();
     }
 
-    /// Handle column header mouse down - start resize or double-click auto-fit
-    fn on_column_header_mouse_down(&mut self, event: &MouseDownEvent, header_x: f32, cx: &mut Context<Self>) {
-        // x position relative to column header area (after row header)
-        let x = f32::from(event.position.x) - ROW_HEADER_WIDTH - header_x;
+    /// Turn enforcement of protected ranges on or off, keeping the ranges
+    fn set_protection(&mut self, enabled: bool, cx: &mut Context<Self>) {
+        self.protection_enabled = enabled;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
 
-        if let Some(col) = self.column_resize_target(x) {
-            if event.click_count == 2 {
-                // Double-click: auto-fit column
-                self.auto_fit_column(col, cx);
-            } else {
-                // Single click: start resize
-                self.start_column_resize(col, f32::from(event.position.x), cx);
-            }
+    fn enter_edit_mode(&mut self, _: &EnterEditMode, window: &mut Window, cx: &mut Context<Self>) {
+        if !self.can_edit_selected(cx) {
+            return;
         }
-    }
+        self.mode = Mode::Edit;
 
-    /// Handle row header mouse down - start resize or double-click auto-fit
-    fn on_row_header_mouse_down(&mut self, event: &MouseDownEvent, header_y: f32, cx: &mut Context<Self>) {
-        // y position relative to row area (after column header)
-        let y = f32::from(event.position.y) - COLUMN_HEADER_HEIGHT - HEADER_HEIGHT - header_y;
+        // Load current cell content into the input
+        let content = self.cells[self.selected.row][self.selected.col].clone();
+        let values = self.autocomplete_values(self.selected);
+        self.active_input.update(cx, |input, cx| {
+            input.set_content(content, cx);
+            input.autocomplete_values = values;
+        });
 
-        if let Some(row) = self.row_resize_target(y) {
-            if event.click_count == 2 {
-                // Double-click: auto-fit row
-                self.auto_fit_row(row, cx);
-            } else {
-                // Single click: start resize
-                self.start_row_resize(row, f32::from(event.position.y), cx);
-            }
-        }
+        // Focus the input
+        let focus_handle = self.active_input.focus_handle(cx);
+        focus_handle.focus(window, cx);
+        cx.notify();
     }
 
-    // === Auto-fit methods (implemented in Phase 5) ===
+    /// Distinct values typed elsewhere in a cell's column, in row order (formulas aside)
+    fn column_values(&self, position: CellPosition) -> Vec<String> {
+        let mut seen = HashSet::new();
+        (0..GRID_ROWS)
+            .filter(|&row| row != position.row)
+            .map(|row| &self.cells[row][position.col])
+            .filter(|text| !text.trim().is_empty() && !text.starts_with('='))
+            .filter(|text| seen.insert(text.as_str()))
+            .cloned()
+            .collect()
+    }
 
-    /// Auto-fit a column width to its content
-    fn auto_fit_column(&mut self, col: usize, cx: &mut Context<Self>) {
-        // Find the maximum content width in this column
-        let mut max_width = DEFAULT_CELL_WIDTH;
-        for row in 0..GRID_ROWS {
-            let content = &self.cells[row][col];
-            if !content.is_empty() {
-                // Estimate width: approximately 8 pixels per character + padding
-                let estimated_width = content.len() as f32 * 8.0 + 16.0;
-                max_width = max_width.max(estimated_width);
-            }
+    /// Text in the cell's column to finish what's typed with, when autocomplete is on
+    fn autocomplete_values(&self, position: CellPosition) -> Vec<String> {
+        if !self.autocomplete {
+            return Vec::new();
         }
-        self.column_widths[col] = max_width.max(DEFAULT_CELL_WIDTH);
-        self.file_state.mark_dirty();
-        cx.notify();
+        let mut values = self.column_values(position);
+        values.retain(|text| text.trim().parse::<f64>().is_err());
+        values
     }
 
-    /// Auto-fit a row height to its content
-    fn auto_fit_row(&mut self, row: usize, cx: &mut Context<Self>) {
-        // For now, use default height. Multiline support will improve this.
-        let mut max_height = DEFAULT_CELL_HEIGHT;
-        for col in 0..GRID_COLS {
-            let content = &self.cells[row][col];
-            if !content.is_empty() {
-                // Count newlines to determine height
-                let line_count = content.lines().count().max(1);
-                let estimated_height = line_count as f32 * 20.0 + 8.0;
-                max_height = max_height.max(estimated_height);
-            }
+    /// `alt-down` - edit the cursor cell with a dropdown of the values already in its column
+    fn pick_from_list(&mut self, _: &PickFromList, window: &mut Window, cx: &mut Context<Self>) {
+        if self.column_values(self.selected).is_empty() {
+            self.show_footer_message("Nothing else in this column to pick from", cx);
+            return;
         }
-        self.row_heights[row] = max_height.max(DEFAULT_CELL_HEIGHT);
-        self.file_state.mark_dirty();
-        cx.notify();
+        self.enter_edit_mode(&EnterEditMode, window, cx);
+        self.picking_from_list = self.mode == Mode::Edit;
     }
 
-    /// Auto-fit all columns and rows
-    fn auto_fit_all(&mut self, cx: &mut Context<Self>) {
-        for col in 0..GRID_COLS {
-            let mut max_width = DEFAULT_CELL_WIDTH;
-            for row in 0..GRID_ROWS {
-                let content = &self.cells[row][col];
-                if !content.is_empty() {
-                    let estimated_width = content.len() as f32 * 8.0 + 16.0;
-                    max_width = max_width.max(estimated_width);
-                }
-            }
-            self.column_widths[col] = max_width.max(DEFAULT_CELL_WIDTH);
+    /// Collect a count typed before a command, e.g. the `5` of `5 ctrl-a`. Only keys no
+    /// binding handles arrive here, and any other one drops the count.
+    fn handle_key_down(&mut self, event: &KeyDownEvent, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.mode != Mode::Normal || self.show_command_palette || self.editing_name_box {
+            return;
         }
-        for row in 0..GRID_ROWS {
-            let mut max_height = DEFAULT_CELL_HEIGHT;
-            for col in 0..GRID_COLS {
-                let content = &self.cells[row][col];
-                if !content.is_empty() {
-                    let line_count = content.lines().count().max(1);
-                    let estimated_height = line_count as f32 * 20.0 + 8.0;
-                    max_height = max_height.max(estimated_height);
-                }
-            }
-            self.row_heights[row] = max_height.max(DEFAULT_CELL_HEIGHT);
+        let keystroke = &event.keystroke;
+        let digit = keystroke.key.parse::<usize>().ok().filter(|_| !keystroke.modifiers.modified());
+        let count = match digit {
+            // A leading zero isn't a count
+            Some(0) if self.pending_count.is_none() => None,
+            Some(digit) => Some(self.pending_count.unwrap_or(0).saturating_mul(10).saturating_add(digit).min(MAX_COUNT)),
+            None => None,
+        };
+        if count != self.pending_count {
+            self.pending_count = count;
+            cx.notify();
         }
-        self.file_state.mark_dirty();
-        cx.notify();
     }
 
-    /// Reset all column widths and row heights to defaults
-    fn reset_all_sizes(&mut self, cx: &mut Context<Self>) {
-        self.column_widths = vec![DEFAULT_CELL_WIDTH; GRID_COLS];
-        self.row_heights = vec![DEFAULT_CELL_HEIGHT; GRID_ROWS];
-        self.file_state.mark_dirty();
-        cx.notify();
+    /// `ctrl-a` - add the count (1 without one) to the number or date in each selected cell
+    fn increment(&mut self, _: &Increment, _window: &mut Window, cx: &mut Context<Self>) {
+        self.add_to_selection(1, false, cx);
     }
 
-    // === Watch mode methods ===
+    /// `ctrl-x` - subtract the count from the number or date in each selected cell
+    fn decrement(&mut self, _: &Decrement, _window: &mut Window, cx: &mut Context<Self>) {
+        self.add_to_selection(-1, false, cx);
+    }
 
-    /// Toggle auto-fit watch mode for all cells
-    fn toggle_autofit_watch_all(&mut self, cx: &mut Context<Self>) {
-        self.autofit_watch = match &self.autofit_watch {
-            AutoFitWatch::All => AutoFitWatch::None,
-            _ => AutoFitWatch::All,
-        };
-        cx.notify();
+    /// `g ctrl-a` in visual mode - number down the selection: the first number goes up by the
+    /// count, the next by twice the count, and so on
+    fn increment_sequence(&mut self, _: &IncrementSequence, _window: &mut Window, cx: &mut Context<Self>) {
+        self.add_to_selection(1, true, cx);
     }
 
-    /// Toggle auto-fit watch for a specific column
-    fn toggle_autofit_watch_column(&mut self, col: usize, cx: &mut Context<Self>) {
-        match &mut self.autofit_watch {
-            AutoFitWatch::Columns(cols) => {
-                if cols.contains(&col) {
-                    cols.remove(&col);
-                    if cols.is_empty() {
-                        self.autofit_watch = AutoFitWatch::None;
-                    }
-                } else {
-                    cols.insert(col);
-                }
-            }
-            AutoFitWatch::None => {
-                let mut cols = HashSet::new();
-                cols.insert(col);
-                self.autofit_watch = AutoFitWatch::Columns(cols);
-            }
-            _ => {
-                // If All or Rows mode, switch to just this column
-                let mut cols = HashSet::new();
-                cols.insert(col);
-                self.autofit_watch = AutoFitWatch::Columns(cols);
-            }
-        }
-        cx.notify();
+    /// `g ctrl-x` in visual mode - the descending version of `g ctrl-a`
+    fn decrement_sequence(&mut self, _: &DecrementSequence, _window: &mut Window, cx: &mut Context<Self>) {
+        self.add_to_selection(-1, true, cx);
     }
 
-    /// Toggle auto-fit watch for a specific row
-    fn toggle_autofit_watch_row(&mut self, row: usize, cx: &mut Context<Self>) {
-        match &mut self.autofit_watch {
-            AutoFitWatch::Rows(rows) => {
-                if rows.contains(&row) {
-                    rows.remove(&row);
-                    if rows.is_empty() {
-                        self.autofit_watch = AutoFitWatch::None;
+    /// Add `sign` times the count to each selected cell holding a number, skipping formulas and
+    /// protected or hidden cells. `progressive` multiplies it by the number's place in the
+    /// selection, row by row.
+    fn add_to_selection(&mut self, sign: i64, progressive: bool, cx: &mut Context<Self>) {
+        let step = sign * self.pending_count.take().unwrap_or(1) as i64;
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let ranges: Vec<CellRange> = self.selection_ranges().collect();
+        let mut changed: i64 = 0;
+        for &range in &ranges {
+            for row in range.rows() {
+                if self.is_row_hidden(row) {
+                    continue;
+                }
+                for col in range.cols() {
+                    let text = &self.cells[row][col];
+                    if text.starts_with('=') || self.is_protected(CellPosition::new(row, col)) {
+                        continue;
+                    }
+                    let delta = if progressive { step * (changed + 1) } else { step };
+                    if let Some(text) = value::increment(text, delta) {
+                        self.cells[row][col] = text;
+                        changed += 1;
                     }
-                } else {
-                    rows.insert(row);
                 }
             }
-            AutoFitWatch::None => {
-                let mut rows = HashSet::new();
-                rows.insert(row);
-                self.autofit_watch = AutoFitWatch::Rows(rows);
-            }
-            _ => {
-                // If All or Columns mode, switch to just this row
-                let mut rows = HashSet::new();
-                rows.insert(row);
-                self.autofit_watch = AutoFitWatch::Rows(rows);
-            }
         }
+        if changed == 0 {
+            self.show_footer_message("No number to change in the selection", cx);
+            return;
+        }
+        for range in ranges {
+            self.grow_to_fit(range);
+        }
+        self.visual_mode = false;
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
         cx.notify();
     }
 
-    /// Check if auto-fit should be applied for a cell, and apply it
-    fn check_autofit_watch(&mut self, row: usize, col: usize, cx: &mut Context<Self>) {
-        match &self.autofit_watch {
-            AutoFitWatch::None => {}
-            AutoFitWatch::All => {
-                self.auto_fit_column(col, cx);
-                self.auto_fit_row(row, cx);
-            }
-            AutoFitWatch::Columns(cols) => {
-                if cols.contains(&col) {
-                    self.auto_fit_column(col, cx);
-                }
-            }
-            AutoFitWatch::Rows(rows) => {
-                if rows.contains(&row) {
-                    self.auto_fit_row(row, cx);
+    /// Fill each column of the selection with generated values, from its top row down;
+    /// hidden rows and protected cells are passed over
+    fn fill_generated(&mut self, generator: Generator, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let ranges: Vec<CellRange> = self.selection_ranges().collect();
+        for &range in &ranges {
+            for col in range.cols() {
+                let rows: Vec<usize> = range
+                    .rows()
+                    .filter(|&row| !self.is_row_hidden(row) && !self.is_protected(CellPosition::new(row, col)))
+                    .collect();
+                for (row, text) in rows.iter().zip(generator.values(rows.len())) {
+                    self.cells[*row][col] = text;
                 }
             }
         }
+        for range in ranges {
+            self.grow_to_fit(range);
+        }
+        self.visual_mode = false;
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        cx.notify();
     }
 
-    // === Scroll wheel / trackpad ===
+    fn exit_edit_mode(&mut self, _: &ExitEditMode, window: &mut Window, cx: &mut Context<Self>) {
+        self.save_and_exit_edit_mode(window, cx);
+    }
 
-    fn handle_scroll_wheel(&mut self, event: &ScrollWheelEvent, _window: &mut Window, cx: &mut Context<Self>) {
-        match event.delta {
-            ScrollDelta::Lines(delta) => {
-                // Mouse wheel: jump by whole cells
-                self.scroll_offset_x = 0.0;
-                self.scroll_offset_y = 0.0;
+    fn exit_and_move_up(&mut self, _: &ExitAndMoveUp, window: &mut Window, cx: &mut Context<Self>) {
+        self.save_and_exit_edit_mode(window, cx);
+        self.move_selection(-1, 0, window, cx);
+    }
 
-                let row_delta = -delta.y.round() as isize;
-                let col_delta = -delta.x.round() as isize;
+    fn exit_and_move_down(&mut self, _: &ExitAndMoveDown, window: &mut Window, cx: &mut Context<Self>) {
+        self.save_and_exit_edit_mode(window, cx);
+        self.move_selection(1, 0, window, cx);
+    }
 
-                self.scroll_row = (self.scroll_row as isize + row_delta)
-                    .max(0)
-                    .min((GRID_ROWS - 1) as isize) as usize;
-                self.scroll_col = (self.scroll_col as isize + col_delta)
-                    .max(0)
-                    .min((GRID_COLS - 1) as isize) as usize;
-            }
-            ScrollDelta::Pixels(delta) => {
-                // Trackpad: smooth pixel scrolling
-                self.apply_smooth_scroll(f32::from(-delta.x), f32::from(-delta.y));
-            }
-        }
+    fn exit_and_move_left(&mut self, _: &ExitAndMoveLeft, window: &mut Window, cx: &mut Context<Self>) {
+        self.save_and_exit_edit_mode(window, cx);
+        self.move_selection(0, -1, window, cx);
+    }
 
-        if self.keep_cursor_in_view {
-            self.clamp_cursor_to_viewport();
-        }
+    fn exit_and_move_right(&mut self, _: &ExitAndMoveRight, window: &mut Window, cx: &mut Context<Self>) {
+        self.save_and_exit_edit_mode(window, cx);
+        self.move_selection(0, 1, window, cx);
+    }
 
+    // === Calculation ===
+
+    fn recalculate(&mut self, _: &Recalculate, _window: &mut Window, cx: &mut Context<Self>) {
+        self.compute_formulas(cx);
         cx.notify();
     }
 
-    fn apply_smooth_scroll(&mut self, dx: f32, dy: f32) {
-        // Accumulate vertical offset
-        self.scroll_offset_y += dy;
+    fn compute_formulas(&mut self, cx: &mut Context<Self>) {
+        let started = Instant::now();
+        let own = cx.entity_id();
+        let mut sheets = Vec::new();
+        let mut index = None;
+        for (i, sheet) in self.sheets.iter().enumerate() {
+            let grid = if sheet.entity_id() == own {
+                index = Some(sheets.len());
+                &*self
+            } else {
+                match sheet.upgrade() {
+                    Some(grid) => grid.read(cx),
+                    None => continue,
+                }
+            };
+            sheets.push(formula::Sheet { names: grid.sheet_names(i + 1), cells: &grid.cells });
+        }
+        let computed = match index {
+            Some(index) => formula::evaluate_sheet(&sheets, index),
+            None => formula::evaluate_all(&self.cells),
+        };
+        self.computed = computed;
+        if let Some(perf) = &self.perf {
+            perf.borrow_mut().formula_time = started.elapsed();
+        }
+        if self.calc_mode == CalcMode::Manual {
+            self.calculated_cells = self.cells.clone();
+        }
+    }
 
-        // Carry over to next/previous rows
-        while self.scroll_offset_y >= self.row_heights[self.scroll_row]
-            && self.scroll_row < GRID_ROWS - 1
-        {
-            self.scroll_offset_y -= self.row_heights[self.scroll_row];
-            self.scroll_row += 1;
+    /// Bring what's derived from the cells up to date after an edit: formula results when
+    /// calculation is automatic, then everything `track_cells` follows
+    fn cells_changed(&mut self, cx: &mut Context<Self>) {
+        self.record_undo();
+        if self.calc_mode == CalcMode::Automatic {
+            self.compute_formulas(cx);
         }
-        while self.scroll_offset_y < 0.0 && self.scroll_row > 0 {
-            self.scroll_row -= 1;
-            self.scroll_offset_y += self.row_heights[self.scroll_row];
+        self.track_cells(cx);
+    }
+
+    /// Catch up with the cells as they are now: the used area, git row markers, the audit log,
+    /// and formulas in other sheets
+    fn track_cells(&mut self, cx: &mut Context<Self>) {
+        self.used_bounds = file_io::find_used_bounds(&self.cells);
+        self.refresh_git_markers();
+        if self.audit.as_mut().is_some_and(|audit| audit.record(&self.cells)) {
+            self.flush_audit();
         }
+        cx.emit(TabEvent::CellsChanged);
+    }
 
-        // Accumulate horizontal offset
-        self.scroll_offset_x += dx;
+    /// `cells_changed` for changes that came from elsewhere, such as a collaborator or the
+    /// tailed file, which undo leaves alone
+    fn cells_changed_elsewhere(&mut self, cx: &mut Context<Self>) {
+        self.history.changed_elsewhere(&self.cells);
+        self.cells_changed(cx);
+    }
 
-        // Carry over to next/previous columns
-        while self.scroll_offset_x >= self.column_widths[self.scroll_col]
-            && self.scroll_col < GRID_COLS - 1
-        {
-            self.scroll_offset_x -= self.column_widths[self.scroll_col];
-            self.scroll_col += 1;
+    // === Undo ===
+
+    /// Remember the cells an edit changed, if any changed since the last one
+    fn record_undo(&mut self) {
+        self.history.record(&self.cells);
+    }
+
+    /// Forget edit history, for cells that were loaded rather than edited
+    fn reset_undo(&mut self) {
+        self.history.reset(&self.cells);
+    }
+
+    /// Put back the cells the last edit changed, except those changed elsewhere since. While a
+    /// cell is being edited, the editor undoes its own typing first and this only sees what it
+    /// has no history for.
+    fn undo(&mut self, _: &Undo, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.mode == Mode::Edit {
+            return;
         }
-        while self.scroll_offset_x < 0.0 && self.scroll_col > 0 {
-            self.scroll_col -= 1;
-            self.scroll_offset_x += self.column_widths[self.scroll_col];
+        if self.history.undo(&mut self.cells) {
+            self.restore_undone(cx);
+        } else {
+            self.show_footer_message("Nothing to undo", cx);
         }
+    }
 
-        self.clamp_scroll_position();
+    fn redo(&mut self, _: &Redo, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.mode == Mode::Edit {
+            return;
+        }
+        if self.history.redo(&mut self.cells) {
+            self.restore_undone(cx);
+        } else {
+            self.show_footer_message("Nothing to redo", cx);
+        }
     }
 
-    fn clamp_scroll_position(&mut self) {
-        // Clamp at top/left edges
-        if self.scroll_row == 0 && self.scroll_offset_y < 0.0 {
-            self.scroll_offset_y = 0.0;
+    /// Catch up after undo or redo changed the cells, without recording it as another edit
+    fn restore_undone(&mut self, cx: &mut Context<Self>) {
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// Recalculate after another sheet changed, if formulas here might refer to it
+    fn other_sheet_changed(&mut self, cx: &mut Context<Self>) {
+        if self.calc_mode == CalcMode::Automatic && formula::refers_to_sheets(&self.cells) {
+            self.compute_formulas(cx);
+            cx.notify();
         }
-        if self.scroll_col == 0 && self.scroll_offset_x < 0.0 {
-            self.scroll_offset_x = 0.0;
+    }
+
+    /// What formulas call this sheet: `Sheet<number>` by its tab's position, and its file's name
+    /// without the extension
+    fn sheet_names(&self, number: usize) -> Vec<String> {
+        let mut names = vec![format!("Sheet{}", number)];
+        if let Some(stem) = self.file_state.current_path.as_deref().and_then(Path::file_stem).and_then(|stem| stem.to_str()) {
+            names.push(stem.to_string());
         }
-        // Clamp at bottom/right edges
-        if self.scroll_row >= GRID_ROWS - 1 {
-            self.scroll_row = GRID_ROWS - 1;
-            if self.scroll_offset_y > 0.0 {
-                self.scroll_offset_y = 0.0;
+        names
+    }
+
+    fn set_calc_mode(&mut self, mode: CalcMode, cx: &mut Context<Self>) {
+        self.calc_mode = mode;
+        // Either way results start out current
+        self.compute_formulas(cx);
+        cx.notify();
+    }
+
+    /// Watch the open file and append rows another process writes to it, or stop with `false`
+    fn set_tail(&mut self, on: bool, cx: &mut Context<Self>) {
+        self.tail_epoch += 1;
+        let was_tailing = std::mem::replace(&mut self.tailing, false);
+        if !on {
+            if was_tailing {
+                self.show_footer_message("Stopped tailing", cx);
             }
+            return;
         }
-        if self.scroll_col >= GRID_COLS - 1 {
-            self.scroll_col = GRID_COLS - 1;
-            if self.scroll_offset_x > 0.0 {
-                self.scroll_offset_x = 0.0;
+        let Some(path) = self.file_state.current_path.clone() else {
+            self.show_footer_message("Nothing to tail; open a file first", cx);
+            return;
+        };
+        if file_io::Compression::from_path(&path) != file_io::Compression::None {
+            self.show_footer_message("Can't tail a compressed file", cx);
+            return;
+        }
+        let mut offset = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                self.show_footer_message(format!("Can't tail {}: {}", path.display(), e), cx);
+                return;
             }
+        };
+        self.tailing = true;
+        if !was_tailing {
+            self.show_footer_message(format!("Tailing {}; :notail stops", self.file_state.file_name()), cx);
         }
+        let epoch = self.tail_epoch;
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                cx.background_executor().timer(TAIL_INTERVAL).await;
+                let appended = file_io::read_appended(&path, &mut offset);
+                let running = this
+                    .update(cx, |grid, cx| {
+                        if grid.tail_epoch != epoch {
+                            return false;
+                        }
+                        match appended {
+                            Ok(Some(text)) => grid.append_tailed(&text, cx),
+                            Ok(None) => {}
+                            Err(e) => {
+                                grid.tailing = false;
+                                grid.show_footer_message(format!("Stopped tailing: {}", e), cx);
+                                return false;
+                            }
+                        }
+                        true
+                    })
+                    .unwrap_or(false);
+                if !running {
+                    break;
+                }
+            }
+        })
+        .detach();
     }
 
-    /// Move the cursor into the fully visible viewport (used when keep_cursor_in_view is enabled)
-    fn clamp_cursor_to_viewport(&mut self) {
-        // First fully visible row: if pixel offset hides part of scroll_row, skip it
-        let first_full_row = if self.scroll_offset_y > 0.0 {
-            (self.scroll_row + 1).min(GRID_ROWS - 1)
-        } else {
-            self.scroll_row
+    /// Add rows read from the tailed file below the last used row, following them if the
+    /// cursor was on that row
+    fn append_tailed(&mut self, text: &str, cx: &mut Context<Self>) {
+        let rows = match file_io::parse_records(text) {
+            Ok(rows) => rows,
+            Err(e) => {
+                eprintln!("Failed to parse tailed rows: {}", e);
+                return;
+            }
         };
-        let last_full_row = self.last_fully_visible_row();
+        let (last_row, _) = file_io::find_used_bounds(&self.cells);
+        let empty = last_row == 0 && self.cells[0].iter().all(String::is_empty);
+        let first = if empty { 0 } else { last_row + 1 };
+        let follow = self.selected.row >= last_row;
+        let count = rows.len().min(GRID_ROWS - first);
+        for (row, fields) in (first..GRID_ROWS).zip(rows) {
+            for (col, field) in (0..GRID_COLS).zip(fields) {
+                self.cells[row][col] = field;
+            }
+        }
+        if first + count >= GRID_ROWS {
+            self.set_tail(false, cx);
+            self.show_footer_message("Stopped tailing: the grid is full", cx);
+        }
+        if follow && count > 0 {
+            self.selected.row = first + count - 1;
+            self.selection_anchor = None;
+            self.ensure_visible();
+        }
+        self.cells_changed_elsewhere(cx);
+        // Filters decide whether the new rows show
+        self.on_filters_changed(cx);
+    }
 
-        if self.selected.row < first_full_row {
-            self.selected.row = first_full_row;
-        } else if self.selected.row > last_full_row {
-            self.selected.row = last_full_row;
+    // === Shared editing ===
+
+    /// `:share [port]` - let another instance on the network that has the share code join and
+    /// edit this sheet
+    fn share(&mut self, port: Option<u16>, cx: &mut Context<Self>) {
+        // The connection is plain TCP
+        if self.file_state.passphrase.is_some() {
+            self.show_footer_message("Can't share an encrypted sheet over an unencrypted connection", cx);
+            return;
         }
+        match Session::host(port.unwrap_or(collab::DEFAULT_PORT), &self.cells) {
+            Ok(session) => {
+                let port = session.port().unwrap_or_default();
+                let code = session.code().unwrap_or_default().to_string();
+                self.start_collab(session, cx);
+                self.show_footer_message(
+                    format!("Sharing on port {}; join with :join <this machine>:{} {}", port, port, code),
+                    cx,
+                );
+            }
+            Err(e) => self.show_footer_message(format!("Can't share: {}", e), cx),
+        }
+    }
 
-        let first_full_col = if self.scroll_offset_x > 0.0 {
-            (self.scroll_col + 1).min(GRID_COLS - 1)
-        } else {
-            self.scroll_col
-        };
-        let last_full_col = self.last_fully_visible_col();
+    /// `:join <host[:port]> <code>` - replace this sheet with one shared by another instance
+    /// and edit it together
+    fn join(&mut self, address: &str, code: &str, cx: &mut Context<Self>) {
+        // The sheet would be replaced, and later saved, unencrypted
+        if self.file_state.passphrase.is_some() {
+            self.show_footer_message("Can't join a shared sheet from an encrypted file", cx);
+            return;
+        }
+        match Session::join(address, code, &self.cells) {
+            Ok(session) => {
+                self.start_collab(session, cx);
+                self.show_footer_message(format!("Connected to {}; waiting for the sheet", address), cx);
+            }
+            Err(e) => self.show_footer_message(format!("Can't join {}: {}", address, e), cx),
+        }
+    }
 
-        if self.selected.col < first_full_col {
-            self.selected.col = first_full_col;
-        } else if self.selected.col > last_full_col {
-            self.selected.col = last_full_col;
+    /// `:unshare` - end the shared session, keeping the sheet as it is
+    fn unshare(&mut self, cx: &mut Context<Self>) {
+        self.collab_epoch += 1;
+        if self.collab.take().is_some() {
+            self.show_footer_message("Stopped sharing", cx);
+            cx.notify();
         }
     }
 
-    fn on_cell_click(&mut self, row: usize, col: usize, window: &mut Window, cx: &mut Context<Self>) {
-        // If clicking on a different cell while in edit mode, save and exit first
-        if self.mode == Mode::Edit && (row != self.selected.row || col != self.selected.col) {
-            self.save_and_exit_edit_mode(window, cx);
+    /// Exchange edits with the session's peer until it ends or is replaced
+    fn start_collab(&mut self, session: Session, cx: &mut Context<Self>) {
+        self.collab = Some(session);
+        self.collab_epoch += 1;
+        let epoch = self.collab_epoch;
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                cx.background_executor().timer(COLLAB_INTERVAL).await;
+                let running = this
+                    .update(cx, |grid, cx| grid.collab_epoch == epoch && grid.poll_collab(cx))
+                    .unwrap_or(false);
+                if !running {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    /// Send local edits and apply the peer's. Returns whether the session is still going.
+    fn poll_collab(&mut self, cx: &mut Context<Self>) -> bool {
+        let Some(session) = &mut self.collab else {
+            return false;
+        };
+        let cursor = session.remote_cursor;
+        let events = session.poll(&mut self.cells, self.selected);
+        let hosting = session.is_hosting();
+        if session.remote_cursor != cursor {
+            cx.notify();
+        }
+        for event in events {
+            match event {
+                collab::Event::Joined(addr) => self.show_footer_message(format!("{} joined", addr), cx),
+                collab::Event::Refused(addr) => {
+                    self.show_footer_message(format!("Refused {}: wrong or missing share code", addr), cx)
+                }
+                collab::Event::Synced => {
+                    self.file_state.mark_dirty();
+                    self.cells_changed_elsewhere(cx);
+                    self.show_footer_message("Joined the shared sheet", cx);
+                    cx.notify();
+                }
+                collab::Event::Changed => {
+                    self.file_state.mark_dirty();
+                    self.cells_changed_elsewhere(cx);
+                    cx.notify();
+                }
+                collab::Event::Left if hosting => {
+                    self.show_footer_message("Collaborator left; still sharing", cx);
+                    cx.notify();
+                }
+                collab::Event::Left => {
+                    self.collab = None;
+                    self.show_footer_message("Disconnected from the shared sheet", cx);
+                    cx.notify();
+                    return false;
+                }
+            }
         }
+        true
+    }
 
-        self.selected = CellPosition::new(row, col);
-        self.ensure_visible();
-        cx.notify();
+    /// Periodically recalculate so volatile functions (NOW, TODAY, RAND) stay current, or
+    /// stop with `None`. In manual mode the tick does nothing; `:calc` refreshes them.
+    fn set_refresh_interval(&mut self, interval: Option<Duration>, cx: &mut Context<Self>) {
+        self.refresh_epoch += 1;
+        let epoch = self.refresh_epoch;
+        if let Some(interval) = interval {
+            cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+                loop {
+                    cx.background_executor().timer(interval).await;
+                    let running = this
+                        .update(cx, |grid, cx| {
+                            if grid.refresh_epoch != epoch {
+                                return false;
+                            }
+                            if grid.calc_mode == CalcMode::Automatic {
+                                grid.compute_formulas(cx);
+                                cx.notify();
+                            }
+                            true
+                        })
+                        .unwrap_or(false);
+                    if !running {
+                        break;
+                    }
+                }
+            })
+            .detach();
+        }
     }
 
-    fn on_cell_double_click(&mut self, row: usize, col: usize, window: &mut Window, cx: &mut Context<Self>) {
-        self.selected = CellPosition::new(row, col);
-        self.ensure_visible();
+    /// Whether cells changed since formulas were last computed in manual mode
+    fn results_stale(&self) -> bool {
+        self.calc_mode == CalcMode::Manual && self.cells != self.calculated_cells
+    }
 
-        // Enter edit mode on double click
-        self.mode = Mode::Edit;
-        let content = self.cells[row][col].clone();
-        self.active_input.update(cx, |input, cx| {
-            input.set_content(content, cx);
-        });
-        let focus_handle = self.active_input.focus_handle(cx);
-        focus_handle.focus(window, cx);
-        cx.notify();
+    // === Pointing at references while editing a formula ===
+
+    fn point_up(&mut self, _: &PointUp, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(-1, 0, false, window, cx);
     }
 
-    fn render_header(&self, cx: &mut Context<Self>) -> impl IntoElement {
-        let theme = cx.global::<Theme>();
-        let cell_ref = self.selected.to_reference();
+    fn point_down(&mut self, _: &PointDown, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(1, 0, false, window, cx);
+    }
 
-        div()
-            .flex()
-            .flex_row()
-            .w_full()
-            .h(px(HEADER_HEIGHT))
-            .bg(theme.mantle)
-            .border_b_1()
-            .border_color(theme.surface0)
-            .items_center()
-            .px(px(8.))
-            .gap(px(8.))
-            .child(
-                // Cell reference label
-                div()
-                    .flex()
-                    .items_center()
-                    .justify_center()
-                    .w(px(60.))
-                    .h(px(24.))
-                    .bg(theme.surface0)
-                    .rounded(px(4.))
-                    .text_size(px(14.))
-                    .text_color(theme.subtext1)
-                    .child(cell_ref)
-            )
-            .child(
-                // Formula bar / content display
-                div()
-                    .flex_1()
-                    .h(px(24.))
-                    .bg(theme.surface0)
-                    .rounded(px(4.))
-                    .overflow_hidden()
-                    .px(px(8.))
-                    .items_center()
-                    .text_size(px(14.))
-                    .child(if self.mode == Mode::Edit {
-                        // Show input content in edit mode
-                        let content = self.active_input.read(cx).get_content();
-                        content
-                    } else {
-                        // Show cell content in normal mode
-                        self.cells[self.selected.row][self.selected.col].clone()
-                    })
-            )
+    fn point_left(&mut self, _: &PointLeft, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(0, -1, false, window, cx);
     }
 
-    fn render_column_headers(&self, cx: &mut Context<Self>) -> impl IntoElement {
-        let theme = cx.global::<Theme>();
-        let entity = cx.entity().clone();
-        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
-        let column_widths = self.column_widths.clone();
-        let selected_col = self.selected.col;
-        let offset_x = self.scroll_offset_x;
+    fn point_right(&mut self, _: &PointRight, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(0, 1, false, window, cx);
+    }
 
-        div()
-            .id("column-headers")
+    fn point_extend_up(&mut self, _: &PointExtendUp, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(-1, 0, true, window, cx);
+    }
+
+    fn point_extend_down(&mut self, _: &PointExtendDown, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(1, 0, true, window, cx);
+    }
+
+    fn point_extend_left(&mut self, _: &PointExtendLeft, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(0, -1, true, window, cx);
+    }
+
+    fn point_extend_right(&mut self, _: &PointExtendRight, window: &mut Window, cx: &mut Context<Self>) {
+        self.move_pointer(0, 1, true, window, cx);
+    }
+
+    /// The pointed range, if the reference it inserted is still the one at the cursor
+    fn current_pointer(&self, cx: &App) -> Option<(CellPosition, CellPosition)> {
+        self.pointer.filter(|_| self.active_input.read(cx).reference_range.is_some())
+    }
+
+    /// Move the reference pointer from the last pointed cell (or the edited cell), inserting
+    /// its reference into the formula; `extend` grows a range from the pointer's anchor
+    fn move_pointer(&mut self, delta_row: isize, delta_col: isize, extend: bool, window: &mut Window, cx: &mut Context<Self>) {
+        if !self.active_input.read(cx).is_pointing() {
+            cx.propagate();
+            return;
+        }
+        let (anchor, cursor) = self.current_pointer(cx).unwrap_or((self.selected, self.selected));
+        let cursor = CellPosition::new(
+            (cursor.row as isize + delta_row).clamp(0, GRID_ROWS as isize - 1) as usize,
+            (cursor.col as isize + delta_col).clamp(0, GRID_COLS as isize - 1) as usize,
+        );
+        self.point_at(if extend { anchor } else { cursor }, cursor, window, cx);
+    }
+
+    /// Insert the reference for the range between two corners into the formula being edited
+    fn point_at(&mut self, anchor: CellPosition, cursor: CellPosition, window: &mut Window, cx: &mut Context<Self>) {
+        self.pointer = Some((anchor, cursor));
+        let reference = CellRange::new(anchor, cursor).to_reference();
+        self.active_input
+            .update(cx, |input, cx| input.insert_reference(&reference, window, cx));
+        cx.notify();
+    }
+
+    /// Dragging across cells after pointing at one inserts a range instead
+    fn on_cell_drag(&mut self, row: usize, col: usize, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(drag) = &mut self.move_drag {
+            if drag.over != CellPosition::new(row, col) {
+                drag.over = CellPosition::new(row, col);
+                cx.notify();
+            }
+            return;
+        }
+        if self.mode != Mode::Edit {
+            // Dragging from a clicked cell selects the range to the cell under the pointer
+            let pos = CellPosition::new(row, col);
+            if self.selection_drag == Some(SelectionDrag::Cells) && pos != self.selected {
+                self.selection_anchor.get_or_insert(self.selected);
+                self.selected = pos;
+                cx.notify();
+            }
+            return;
+        }
+        if let Some((anchor, cursor)) = self.current_pointer(cx)
+            && cursor != CellPosition::new(row, col)
+        {
+            self.point_at(anchor, CellPosition::new(row, col), window, cx);
+        }
+    }
+
+    fn save_and_exit_edit_mode(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        // Save the content from the input back to the cell
+        let content = self.active_input.read(cx).get_content();
+        let old_content = &self.cells[self.selected.row][self.selected.col];
+        let mut content_changed = &content != old_content;
+        if content_changed
+            && let Some(validation) = self.validation_at(self.selected.row, self.selected.col)
+            && validation.reject
+            && !validation.is_valid(&content)
+        {
+            eprintln!(
+                "Rejected \"{}\": {} must be {}",
+                content,
+                self.selected.to_reference(),
+                validation.describe()
+            );
+            content_changed = false;
+        }
+        if content_changed {
+            self.cells[self.selected.row][self.selected.col] = content;
+            self.file_state.mark_dirty();
+            self.cells_changed(cx);
+            // Check if auto-fit watch mode should resize this cell
+            let row = self.selected.row;
+            let col = self.selected.col;
+            self.check_autofit_watch(row, col, cx);
+            let lines = self.cell_lines(row, col).len();
+            if lines > 1 {
+                self.grow_row_to_lines(row, lines);
+            }
+        }
+
+        self.mode = Mode::Normal;
+        self.picking_from_list = false;
+        // The cursor isn't shown outside edit mode, so stop animating it
+        self.active_input.update(cx, |input, _cx| input.stop_cursor_blink());
+        self.focus_handle.focus(window, cx);
+        cx.notify();
+    }
+
+    // File operations
+    fn new_file(&mut self, _: &NewFile, window: &mut Window, cx: &mut Context<Self>) {
+        // Reset all cells
+        self.cells = (0..GRID_ROWS)
+            .map(|_| (0..GRID_COLS).map(|_| String::new()).collect())
+            .collect();
+        self.selected = CellPosition::new(0, 0);
+        self.scroll_row = 0;
+        self.scroll_col = 0;
+        self.scroll_offset_x = 0.0;
+        self.scroll_offset_y = 0.0;
+        // Reset dimensions to defaults
+        self.default_col_width = DEFAULT_CELL_WIDTH;
+        self.default_row_height = DEFAULT_CELL_HEIGHT;
+        self.column_widths = vec![DEFAULT_CELL_WIDTH; GRID_COLS];
+        self.row_heights = vec![DEFAULT_CELL_HEIGHT; GRID_ROWS];
+        self.column_width_limits.clear();
+        self.autofit_max_width = None;
+        self.embed_metadata = false;
+        self.protected_ranges.clear();
+        self.protection_enabled = true;
+        self.show_gridlines = true;
+        self.refresh_menu(cx);
+        self.diff_saved = None;
+        self.git_baseline = None;
+        self.comparison = None;
+        self.pivot = None;
+        self.charts.clear();
+        self.chart_drag = None;
+        self.data_sources.clear();
+        self.set_tail(false, cx);
+        self.viewer = None;
+        self.autofit_watch = AutoFitWatch::None;
+        self.frozen_rows = 0;
+        self.frozen_cols = 0;
+        self.header_row = None;
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.visual_mode = false;
+        self.clear_filter_state();
+        self.context_menu = None;
+        self.row_outline = Outline::default();
+        self.col_outline = Outline::default();
+        self.wrap_columns.clear();
+        self.hidden_rows.clear();
+        self.hidden_cols.clear();
+        self.cell_styles = vec![vec![CellStyle::default(); GRID_COLS]; GRID_ROWS];
+        self.column_formats.clear();
+        self.date_formats.clear();
+        self.column_alignments.clear();
+        self.inferred_types.clear();
+        self.column_types.clear();
+        self.cell_validations = vec![vec![None; GRID_COLS]; GRID_ROWS];
+        self.column_validations.clear();
+        self.reset_undo();
+        self.compute_formulas(cx);
+        self.track_cells(cx);
+        self.file_state = FileState::new();
+        self.audit = None;
+        self.focus_handle.focus(window, cx);
+        cx.notify();
+    }
+
+    fn open_file(&mut self, _: &OpenFile, window: &mut Window, cx: &mut Context<Self>) {
+        self.open_file_dialog(false, window, cx);
+    }
+
+    fn open_file_dialog(&mut self, read_only: bool, window: &mut Window, cx: &mut Context<Self>) {
+        let imported = Registry::get().import_extensions();
+        let path = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .add_filter("Compressed CSV", &["gz", "zst"])
+            .add_filter("Imported", &imported)
+            .add_filter("All Files", &["*"])
+            .pick_file();
+
+        // Before loading, which may move focus to the passphrase prompt
+        self.focus_handle.focus(window, cx);
+        // An untouched empty grid takes the file, otherwise it opens in a new tab
+        match path {
+            Some(path) if self.file_state.current_path.is_none() && !self.file_state.is_dirty => {
+                self.load_file(path, read_only, window, cx)
+            }
+            Some(path) => cx.emit(TabEvent::Open { path, read_only }),
+            None => {}
+        }
+    }
+
+    fn load_file(&mut self, path: PathBuf, read_only: bool, window: &mut Window, cx: &mut Context<Self>) {
+        // Too big to load: look at it in place instead
+        let is_csv = matches!(file_io::Format::from_path(&path), Some(file_io::Format::Csv | file_io::Format::Tsv))
+            && file_io::Compression::from_path(&path) == file_io::Compression::None;
+        if is_csv && std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= viewer::VIEWER_MIN_SIZE) {
+            self.open_viewer(path, cx);
+            return;
+        }
+        // Opened once the passphrase is given
+        if crypt::is_encrypted_file(&path) {
+            self.pending_decrypt = Some((path, read_only));
+            self.ask_passphrase(window, cx);
+            return;
+        }
+        // Other formats go through a plugin's importer
+        let (file, imported) = match Registry::get().import(&path) {
+            Some(cells) => {
+                let file = cells.map(|rows| CsvFile {
+                    cells: file_io::fit_to_grid(rows),
+                    write_options: WriteOptions::default(),
+                    layout: CsvLayout::default(),
+                });
+                (file, true)
+            }
+            None => (file_io::read_csv_file(&path), false),
+        };
+        let metadata = SpreadsheetMetadata::load(&path);
+        self.open_loaded(path, file, metadata, imported, read_only, cx);
+    }
+
+    /// Decrypt a file with the passphrase given for it and open it, asking again if it's wrong
+    fn open_encrypted(&mut self, path: PathBuf, read_only: bool, passphrase: String, window: &mut Window, cx: &mut Context<Self>) {
+        let content = file_io::read_bytes(&path)
+            .and_then(|bytes| crypt::decrypt(&bytes, &passphrase))
+            .and_then(|plaintext| String::from_utf8(plaintext).map_err(std::io::Error::other));
+        let content = match content {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                self.pending_decrypt = Some((path, read_only));
+                self.show_footer_message(e.to_string(), cx);
+                self.ask_passphrase(window, cx);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Failed to open file: {}", e);
+                return;
+            }
+        };
+        let metadata = SpreadsheetMetadata::load_for_content(&path, &content);
+        self.open_loaded(path, file_io::parse_csv_file(&content), metadata, false, read_only, cx);
+        // Saving keeps it encrypted with the same passphrase
+        self.file_state.passphrase = Some(passphrase);
+        self.git_baseline = None;
+    }
+
+    /// Show a file that's been read, laid out by its metadata
+    fn open_loaded(
+        &mut self,
+        path: PathBuf,
+        file: std::io::Result<CsvFile>,
+        metadata: std::io::Result<SpreadsheetMetadata>,
+        imported: bool,
+        mut read_only: bool,
+        cx: &mut Context<Self>,
+    ) {
+        match file {
+            Ok(CsvFile { cells, mut write_options, layout }) => {
+                let mut append_only = false;
+                let mut audit = false;
+                self.cells = cells;
+                self.selected = CellPosition::new(0, 0);
+                self.scroll_row = 0;
+                self.scroll_col = 0;
+                self.scroll_offset_x = 0.0;
+                self.scroll_offset_y = 0.0;
+
+                // Load metadata (column widths, row heights)
+                match metadata {
+                    Ok(metadata) => {
+                        self.apply_layout(&metadata);
+                        read_only |= metadata.read_only.unwrap_or(false);
+                        self.embed_metadata = metadata.embedded;
+                        write_options = metadata.write_options.unwrap_or(write_options);
+                        append_only = metadata.append_only.unwrap_or(false);
+                        audit = metadata.audit.unwrap_or(false);
+                    }
+                    Err(_) => {
+                        // Reset to defaults if metadata can't be loaded
+                        self.default_col_width = DEFAULT_CELL_WIDTH;
+                        self.default_row_height = DEFAULT_CELL_HEIGHT;
+                        self.column_widths = vec![DEFAULT_CELL_WIDTH; GRID_COLS];
+                        self.row_heights = vec![DEFAULT_CELL_HEIGHT; GRID_ROWS];
+                        self.column_width_limits.clear();
+                        self.autofit_max_width = None;
+                        self.row_outline = Outline::default();
+                        self.col_outline = Outline::default();
+                        self.wrap_columns.clear();
+                        self.hidden_rows.clear();
+                        self.hidden_cols.clear();
+                        self.cell_styles = vec![vec![CellStyle::default(); GRID_COLS]; GRID_ROWS];
+                        self.column_formats.clear();
+                        self.date_formats.clear();
+                        self.column_alignments.clear();
+                        self.column_types.clear();
+                        self.cell_validations = vec![vec![None; GRID_COLS]; GRID_ROWS];
+                        self.column_validations.clear();
+                        self.autofit_watch = AutoFitWatch::None;
+                        self.frozen_rows = 0;
+                        self.frozen_cols = 0;
+                        self.header_row = None;
+                        self.embed_metadata = false;
+                        self.protected_ranges.clear();
+                        self.protection_enabled = true;
+                        self.show_gridlines = true;
+                        self.data_sources.clear();
+                    }
+                }
+
+                self.git_baseline = GitBaseline::load(&path);
+                self.viewer = None;
+                self.file_state = FileState::new();
+                // An imported file saves as CSV beside it rather than over it
+                self.file_state.set_path(if imported { path.with_extension("csv") } else { path });
+                self.file_state.set_read_only(read_only);
+                self.file_state.write_options = write_options;
+                self.file_state.layout = layout;
+                self.file_state.append_only = append_only;
+                self.audit = audit.then(|| AuditLog::new(&self.cells, self.file_state.current_path.as_deref()));
+                if imported {
+                    self.file_state.mark_dirty();
+                }
+                self.refresh_menu(cx);
+                self.diff_saved = None;
+                self.comparison = None;
+                self.pivot = None;
+                self.charts.clear();
+                self.chart_drag = None;
+                self.set_tail(false, cx);
+                self.selection_anchor = None;
+                self.extra_selections.clear();
+                self.visual_mode = false;
+                self.clear_filter_state();
+                self.context_menu = None;
+                self.infer_column_types();
+                self.reset_undo();
+                // Opening a file always computes its formulas, even in manual mode
+                self.compute_formulas(cx);
+                self.track_cells(cx);
+                cx.notify();
+            }
+            Err(e) => {
+                eprintln!("Failed to open file: {}", e);
+            }
+        }
+    }
+
+    /// Take a file's layout from its metadata: sizes, outlines, styles, formats, validations,
+    /// frozen panes, protection, and data sources
+    fn apply_layout(&mut self, metadata: &SpreadsheetMetadata) {
+        self.default_col_width = metadata.get_default_column_width();
+        self.default_row_height = metadata.get_default_row_height();
+        self.column_widths = metadata.get_column_widths();
+        self.row_heights = metadata.get_row_heights();
+        self.column_width_limits = metadata.get_column_width_limits();
+        self.autofit_max_width = metadata.get_autofit_max_width();
+        self.row_outline = Outline::from_groups(metadata.get_row_groups());
+        self.col_outline = Outline::from_groups(metadata.get_col_groups());
+        self.wrap_columns = metadata.get_wrap_columns().into_iter().collect();
+        self.hidden_rows = metadata.get_hidden_rows().into_iter().collect();
+        self.hidden_cols = metadata.get_hidden_cols().into_iter().collect();
+        self.cell_styles = metadata.get_cell_styles();
+        self.column_formats = metadata.get_column_formats();
+        self.date_formats = metadata.get_date_formats();
+        self.column_alignments = metadata.get_column_alignments();
+        self.column_types = metadata.get_column_types();
+        self.cell_validations = metadata.get_validations();
+        self.column_validations = metadata.get_column_validations();
+        self.autofit_watch = metadata.get_autofit_watch();
+        (self.frozen_rows, self.frozen_cols) = metadata.get_frozen();
+        self.header_row = metadata.get_header_row();
+        self.protected_ranges = metadata.get_protected_ranges();
+        self.protection_enabled = metadata.protection.unwrap_or(true);
+        self.show_gridlines = metadata.gridlines.unwrap_or(true);
+        self.data_sources = metadata.get_data_sources();
+    }
+
+    /// Show a CSV file read-only from a memory map, indexing its rows in the background,
+    /// instead of loading it into the grid
+    fn open_viewer(&mut self, path: PathBuf, cx: &mut Context<Self>) {
+        let file = match MappedFile::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Failed to open file: {}", e);
+                return;
+            }
+        };
+        self.cells = vec![vec![String::new(); GRID_COLS]; GRID_ROWS];
+        self.reset_undo();
+        self.compute_formulas(cx);
+        self.track_cells(cx);
+        self.diff_saved = None;
+        self.git_baseline = None;
+        self.comparison = None;
+        self.pivot = None;
+        self.charts.clear();
+        self.chart_drag = None;
+        self.data_sources.clear();
+        self.set_tail(false, cx);
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.visual_mode = false;
+        self.clear_filter_state();
+        self.context_menu = None;
+        self.file_state = FileState::new();
+        self.file_state.set_path(path);
+        self.file_state.set_read_only(true);
+        self.audit = None;
+        self.refresh_menu(cx);
+        self.viewer = Some(Viewer::new(file));
+        self.index_viewer(cx);
+        cx.notify();
+    }
+
+    /// Index the viewer's file a chunk at a time off the main thread, redrawing as rows appear
+    fn index_viewer(&mut self, cx: &mut Context<Self>) {
+        let Some(viewer) = &self.viewer else {
+            return;
+        };
+        let file = viewer.file.clone();
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            loop {
+                let chunk = file.clone();
+                let done = cx.background_executor().spawn(async move { chunk.index_chunk() }).await;
+                // Stop once the viewer has moved on to another file or closed
+                let current = this
+                    .update(cx, |grid, cx| {
+                        cx.notify();
+                        grid.viewer.as_ref().is_some_and(|viewer| Arc::ptr_eq(&viewer.file, &file))
+                    })
+                    .unwrap_or(false);
+                if done || !current {
+                    break;
+                }
+            }
+        })
+        .detach();
+    }
+
+    fn save_file(&mut self, _: &SaveFile, window: &mut Window, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            eprintln!("File is read-only. Use :w! to force write.");
+            return;
+        }
+
+        if let Some(path) = self.file_state.current_path.clone() {
+            self.save_to_path(&path, cx);
+        } else {
+            self.save_file_as(&SaveFileAs, window, cx);
+        }
+    }
+
+    fn save_file_as(&mut self, _: &SaveFileAs, window: &mut Window, cx: &mut Context<Self>) {
+        let path = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .add_filter("Compressed CSV", &["gz", "zst"])
+            .set_file_name("spreadsheet.csv")
+            .save_file();
+
+        if let Some(path) = path {
+            self.save_to_path(&path, cx);
+            self.file_state.set_path(path);
+        }
+
+        self.focus_handle.focus(window, cx);
+    }
+
+    /// Save even when read-only; the file stays read-only
+    fn force_write(&mut self, _: &ForceWrite, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(path) = self.file_state.current_path.clone() {
+            self.save_to_path(&path, cx);
+        } else {
+            self.save_file_as(&SaveFileAs, window, cx);
+        }
+    }
+
+    /// Layout metadata: column widths, row heights, styles, and view state
+    fn layout_metadata(&self) -> SpreadsheetMetadata {
+        SpreadsheetMetadata {
+            column_widths: Some(self.column_widths.clone()),
+            row_heights: Some(self.row_heights.clone()),
+            column_width_limits: Some(self.column_width_limits.clone()),
+            autofit_max_width: self.autofit_max_width,
+            default_column_width: Some(self.default_col_width),
+            default_row_height: Some(self.default_row_height),
+            row_groups: Some(self.row_outline.groups().to_vec()),
+            col_groups: Some(self.col_outline.groups().to_vec()),
+            wrap_columns: Some(sorted_indices(&self.wrap_columns)),
+            hidden_rows: Some(sorted_indices(&self.hidden_rows)),
+            hidden_cols: Some(sorted_indices(&self.hidden_cols)),
+            cell_styles: Some(self.styled_cells()),
+            column_formats: Some(self.column_formats.clone()),
+            date_formats: Some(self.date_formats.clone()),
+            column_alignments: Some(self.column_alignments.clone()),
+            column_types: Some(self.column_types.clone()),
+            validations: Some(self.validated_cells()),
+            column_validations: Some(self.column_validations.clone()),
+            autofit_watch: Some(self.autofit_watch.clone()),
+            frozen_rows: Some(self.frozen_rows),
+            frozen_cols: Some(self.frozen_cols),
+            header_row: self.header_row,
+            read_only: Some(self.file_state.is_read_only),
+            protected_ranges: Some(self.protected_ranges.iter().map(|range| range.to_reference()).collect()),
+            protection: Some(self.protection_enabled),
+            gridlines: Some(self.show_gridlines),
+            write_options: Some(self.file_state.write_options),
+            append_only: Some(self.file_state.append_only),
+            audit: Some(self.audit.is_some()),
+            data_sources: Some(
+                self.data_sources
+                    .iter()
+                    .map(|binding| SavedSource { range: binding.range.to_reference(), source: binding.source.clone() })
+                    .collect(),
+            ),
+            embedded: self.embed_metadata,
+        }
+    }
+
+    fn save_to_path(&mut self, path: &PathBuf, cx: &mut Context<Self>) {
+        if self.viewer.is_some() {
+            self.show_footer_message("The viewer can't save; open a smaller file to edit", cx);
+            return;
+        }
+        // An encrypted or compressed file is rewritten whole
+        if self.file_state.append_only
+            && self.file_state.passphrase.is_none()
+            && file_io::Compression::from_path(path) == file_io::Compression::None
+            && self.file_state.current_path.as_ref() == Some(path)
+            && path.exists()
+        {
+            self.append_to_file(path, cx);
+            return;
+        }
+        let metadata = self.layout_metadata();
+        let embedded_line = if self.embed_metadata {
+            match metadata.embedded_line() {
+                Ok(line) => Some(line),
+                Err(e) => {
+                    eprintln!("Failed to save file: {}", e);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        let file_state = &self.file_state;
+        match file_io::write_csv(
+            path,
+            &self.cells,
+            embedded_line.as_deref(),
+            &file_state.write_options,
+            &file_state.layout,
+            file_state.passphrase.as_deref(),
+        ) {
+            Ok(layout) => {
+                self.file_state.layout = layout;
+                // Embedded metadata replaces the sidecar file
+                let result = if self.embed_metadata {
+                    SpreadsheetMetadata::remove_sidecar(path)
+                } else {
+                    metadata.save(path)
+                };
+                if let Err(e) = result {
+                    eprintln!("Warning: Failed to save metadata: {}", e);
+                }
+
+                self.file_state.mark_clean();
+                self.file_state.set_path(path.clone());
+                // Changes made before the file had a path go into its log now
+                self.flush_audit();
+                // What's on disk now matches the grid
+                if self.diff_saved.is_some() {
+                    self.diff_saved = Some(self.cells.clone());
+                }
+                self.git_baseline = GitBaseline::load(path);
+                self.refresh_git_markers();
+                // The file was rewritten; keep tailing from its new end
+                if self.tailing {
+                    self.set_tail(true, cx);
+                }
+                cx.notify();
+            }
+            Err(e) => {
+                eprintln!("Failed to save file: {}", e);
+            }
+        }
+    }
+
+    /// Save in append-only mode: add the rows below the file's end and leave its existing rows
+    /// as they are on disk, even if they were edited here
+    fn append_to_file(&mut self, path: &PathBuf, cx: &mut Context<Self>) {
+        let edited = self.file_state.layout.edited_rows(&self.cells);
+        let file_state = &mut self.file_state;
+        match file_io::append_csv(path, &self.cells, &file_state.write_options, &mut file_state.layout) {
+            Ok(appended) => {
+                // An embedded metadata line can't change without rewriting the file
+                if !self.embed_metadata
+                    && let Err(e) = self.layout_metadata().save(path)
+                {
+                    eprintln!("Warning: Failed to save metadata: {}", e);
+                }
+                // Edits to saved rows aren't on disk, so they still need saving
+                if edited == 0 {
+                    self.file_state.mark_clean();
+                }
+                self.git_baseline = GitBaseline::load(path);
+                self.refresh_git_markers();
+                // Don't read our own rows back in
+                if self.tailing {
+                    self.set_tail(true, cx);
+                }
+                let message = match edited {
+                    0 => format!("Appended {} rows", appended),
+                    _ => format!("Appended {} rows; {} edited rows not saved (:set appendonly=off to rewrite)", appended, edited),
+                };
+                self.show_footer_message(message, cx);
+            }
+            Err(e) => {
+                eprintln!("Failed to save file: {}", e);
+            }
+        }
+    }
+
+    fn close_file(&mut self, _: &CloseFile, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.file_state.is_dirty {
+            eprintln!("File has unsaved changes. Use :q! to force quit.");
+            return;
+        }
+        cx.emit(TabEvent::Close);
+    }
+
+    fn new_tab(&mut self, _: &NewTab, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(TabEvent::New);
+    }
+
+    fn next_tab(&mut self, _: &NextTab, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(TabEvent::Next);
+    }
+
+    fn previous_tab(&mut self, _: &PreviousTab, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.emit(TabEvent::Previous);
+    }
+
+    fn force_quit(&mut self, _: &ForceQuit, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.quit();
+    }
+
+    fn toggle_read_only(&mut self, _: &ToggleReadOnly, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.viewer.is_some() {
+            self.show_footer_message("The viewer is read-only", cx);
+            return;
+        }
+        self.file_state.set_read_only(!self.file_state.is_read_only);
+        // A read-only file can't be saved normally, so record the flag in its metadata now
+        if let Some(path) = &self.file_state.current_path
+            && path.exists()
+        {
+            let result = SpreadsheetMetadata::load(path).and_then(|mut metadata| {
+                metadata.read_only = Some(self.file_state.is_read_only);
+                metadata.save(path)
+            });
+            if let Err(e) = result {
+                eprintln!("Warning: Failed to save metadata: {}", e);
+            }
+        }
+        cx.notify();
+    }
+
+    fn toggle_keep_cursor_in_view(&mut self, _: &ToggleKeepCursorInView, _window: &mut Window, cx: &mut Context<Self>) {
+        self.keep_cursor_in_view = !self.keep_cursor_in_view;
+        self.refresh_menu(cx);
+        cx.notify();
+    }
+
+    fn toggle_toolbar(&mut self, _: &ToggleToolbar, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_toolbar(!self.show_toolbar, cx);
+    }
+
+    fn set_toolbar(&mut self, show: bool, cx: &mut Context<Self>) {
+        self.show_toolbar = show;
+        self.refresh_menu(cx);
+        cx.notify();
+    }
+
+    fn toggle_gridlines(&mut self, _: &ToggleGridlines, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_gridlines(!self.show_gridlines, cx);
+    }
+
+    fn set_gridlines(&mut self, show: bool, cx: &mut Context<Self>) {
+        self.show_gridlines = show;
+        self.file_state.mark_dirty();
+        self.refresh_menu(cx);
+        cx.notify();
+    }
+
+    fn set_cursor_blink(&mut self, blink: CursorBlink, cx: &mut Context<Self>) {
+        self.active_input.update(cx, |input, _cx| input.cursor_blink = blink);
+        cx.notify();
+    }
+
+    fn set_zebra_stripes(&mut self, zebra: bool, cx: &mut Context<Self>) {
+        self.zebra_stripes = zebra;
+        cx.notify();
+    }
+
+    fn set_crosshair(&mut self, crosshair: Crosshair, cx: &mut Context<Self>) {
+        self.crosshair = crosshair;
+        cx.notify();
+    }
+
+    /// Whether a row falls on a shaded stripe, counting only displayed rows so filtering
+    /// and hiding keep the alternation
+    fn is_striped(&self, row: usize) -> bool {
+        self.zebra_stripes && (0..row).filter(|&r| !self.is_row_hidden(r)).count() % 2 == 1
+    }
+
+    /// Rebuild the menu bar so its checked items match this grid
+    fn refresh_menu(&self, cx: &mut App) {
+        crate::menu::setup_menu_with_state(cx, self.keep_cursor_in_view, self.show_toolbar, self.show_gridlines);
+    }
+
+    fn set_expand_selected(&mut self, expand: bool, cx: &mut Context<Self>) {
+        self.expand_selected = expand;
+        cx.notify();
+    }
+
+    /// Height of the tab strip, header, and toolbar above the column headers
+    fn chrome_height(&self) -> f32 {
+        HEADER_HEIGHT
+            + if self.show_toolbar { TOOLBAR_HEIGHT } else { 0.0 }
+            + if self.show_tab_strip { TAB_STRIP_HEIGHT } else { 0.0 }
+    }
+
+    fn freeze_panes(&mut self, _: &FreezePanes, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_frozen_panes(self.selected.row, self.selected.col, cx);
+    }
+
+    fn unfreeze_panes(&mut self, _: &UnfreezePanes, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_frozen_panes(0, 0, cx);
+    }
+
+    /// Freeze the top `rows` rows and left `cols` columns so they stay visible while scrolling
+    fn set_frozen_panes(&mut self, rows: usize, cols: usize, cx: &mut Context<Self>) {
+        // Always leave at least one scrollable row and column
+        self.frozen_rows = rows.min(GRID_ROWS - 1);
+        self.frozen_cols = cols.min(GRID_COLS - 1);
+        self.clamp_scroll_position();
+        self.ensure_visible();
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // === Window geometry ===
+
+    /// Turn remembering the window's size and position across launches on or off
+    fn set_remember_geometry(&mut self, remember: bool, cx: &mut Context<Self>) {
+        let mut state = WindowState::load();
+        state.remember_geometry = remember;
+        match state.save() {
+            Ok(()) if remember => self.show_footer_message("Window geometry will be remembered", cx),
+            Ok(()) => self.show_footer_message("Window will open centered", cx),
+            Err(e) => eprintln!("Failed to save window settings: {}", e),
+        }
+    }
+
+    // === Header row ===
+
+    /// Designate `row` as the header row, or clear it with `None`
+    fn set_header_row(&mut self, row: Option<usize>, cx: &mut Context<Self>) {
+        self.header_row = row.filter(|&row| row < GRID_ROWS);
+        // The header bounds the filtered rows
+        self.apply_filters();
+        self.ensure_visible();
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Make the cursor row the header row, or clear it if it already is
+    fn toggle_header_row(&mut self, cx: &mut Context<Self>) {
+        let row = self.selected.row;
+        let new_header = if self.header_row == Some(row) { None } else { Some(row) };
+        self.set_header_row(new_header, cx);
+    }
+
+    /// Resolve a column by header label (case-insensitive) or by column letters
+    fn find_column(&self, name: &str) -> Option<usize> {
+        let name = name.trim();
+        if let Some(row) = self.header_row {
+            let by_label = (0..GRID_COLS).find(|&col| self.cells[row][col].trim().eq_ignore_ascii_case(name));
+            if by_label.is_some() {
+                return by_label;
+            }
+        }
+        CellPosition::letter_to_col(name).filter(|&col| col < GRID_COLS)
+    }
+
+    /// The rows of an ex range, and for `'<,'>` the selection's columns. A backwards range is swapped.
+    fn resolve_range(&self, range: ExRange) -> (RangeInclusive<usize>, Option<Range<usize>>) {
+        let (last_row, _) = file_io::find_used_bounds(&self.cells);
+        let selection = self.selection_range();
+        let row = |address: Address| match address {
+            Address::Row(row) => row.min(GRID_ROWS - 1),
+            Address::Cursor => self.selected.row,
+            Address::Last => last_row,
+            Address::SelectionStart => selection.start.row,
+            Address::SelectionEnd => selection.end.row,
+        };
+        let (start, end) = match range {
+            ExRange::All => (0, last_row),
+            ExRange::Rows(start, end) => (row(start), row(end)),
+        };
+        let cols = range.uses_selection().then(|| selection.cols());
+        (start.min(end)..=start.max(end), cols)
+    }
+
+    /// Run an ex command over a range of rows
+    fn run_range_command(&mut self, range: ExRange, command: RangeCommand, cx: &mut Context<Self>) {
+        let (rows, cols) = self.resolve_range(range);
+        let (first, last) = (*rows.start(), *rows.end());
+        if !matches!(command, RangeCommand::Goto) && self.file_state.is_read_only {
+            return;
+        }
+        match command {
+            RangeCommand::Goto => {
+                self.goto(CellPosition::new(last, self.selected.col), cx);
+                return;
+            }
+            RangeCommand::Delete => {
+                self.auto_snapshot("before delete");
+                self.delete_rows(first, last - first + 1);
+            }
+            RangeCommand::Sort { column, descending } => {
+                let col = match column {
+                    Some(name) => match self.find_column(&name) {
+                        Some(col) => col,
+                        None => {
+                            eprintln!("Unknown column: {}", name);
+                            return;
+                        }
+                    },
+                    None => self.selected.col,
+                };
+                self.auto_snapshot("before sort");
+                self.sort_rows(rows, col, descending);
+            }
+            RangeCommand::Shift(count) => {
+                for row in rows {
+                    self.shift_row(row, count);
+                }
+            }
+            RangeCommand::Fill => {
+                let cols = cols.unwrap_or_else(|| 0..file_io::find_used_bounds(&self.cells).1 + 1);
+                for row in first + 1..=last {
+                    for col in cols.clone() {
+                        self.cells[row][col] = self.cells[first][col].clone();
+                        self.cell_styles[row][col] = self.cell_styles[first][col];
+                    }
+                }
+            }
+            RangeCommand::Filter(command) => {
+                self.auto_snapshot("before filter");
+                if !self.filter_rows(first, last, cols, &command, cx) {
+                    return;
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// The sheet as it's piped out: every row up to the last used one, up to the last used
+    /// column, leaving out rows the filters hide
+    pub fn output_rows(&self) -> Vec<Vec<String>> {
+        let (last_row, last_col) = file_io::find_used_bounds(&self.cells);
+        (0..=last_row)
+            .filter(|&row| !self.filtered_out[row])
+            .map(|row| self.cells[row][..=last_col].to_vec())
+            .collect()
+    }
+
+    /// `:w !command` - pipe the sheet as CSV into a shell command, showing the last line it printed
+    fn write_to_command(&mut self, command: &str, cx: &mut Context<Self>) {
+        let rows = self.output_rows();
+        match pipe::write(command, &rows) {
+            Ok(output) => {
+                let message = match output.lines().rev().find(|line| !line.trim().is_empty()) {
+                    Some(line) => format!("!{}: {}", command, line.trim()),
+                    None => format!("Sent {} rows to {}", rows.len(), command),
+                };
+                self.show_footer_message(message, cx);
+            }
+            Err(e) => {
+                eprintln!("!{} failed: {}", command, e);
+                self.show_footer_message(format!("!{}: {}", command, e), cx);
+            }
+        }
+    }
+
+    /// Pipe rows `first..=last` (just `cols` of them, if given) through a shell command and put
+    /// its output in their place. Whole rows are added or removed to fit the output; a block of
+    /// columns is cleared and overwritten. Returns whether the command succeeded.
+    fn filter_rows(&mut self, first: usize, last: usize, cols: Option<Range<usize>>, command: &str, cx: &mut Context<Self>) -> bool {
+        let whole_rows = cols.is_none();
+        let cols = cols.unwrap_or_else(|| 0..file_io::find_used_bounds(&self.cells).1 + 1);
+        let input: Vec<Vec<String>> = (first..=last).map(|row| self.cells[row][cols.clone()].to_vec()).collect();
+        let output = match pipe::filter(command, &input) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("!{} failed: {}", command, e);
+                self.show_footer_message(format!("!{}: {}", command, e), cx);
+                return false;
+            }
+        };
+
+        let count = last - first + 1;
+        if whole_rows {
+            match output.len().cmp(&count) {
+                Ordering::Greater => self.insert_rows(last + 1, output.len() - count),
+                Ordering::Less => self.delete_rows(first + output.len(), count - output.len()),
+                Ordering::Equal => {}
+            }
+        }
+        // Whole rows now number as many as the output; a block keeps its original rows
+        let cleared = if whole_rows { first..first + output.len() } else { first..last + 1 };
+        for row in cleared.start..cleared.end.min(GRID_ROWS) {
+            for col in cols.clone() {
+                self.cells[row][col].clear();
+            }
+        }
+        for (row, fields) in (first..GRID_ROWS).zip(&output) {
+            for (col, field) in (cols.start..GRID_COLS).zip(fields) {
+                self.cells[row][col] = field.clone();
+            }
+        }
+        self.show_footer_message(format!("{} rows in, {} out", count, output.len()), cx);
+        true
+    }
+
+    /// Move a row's cells `count` columns right (dropping any pushed past the edge), or left
+    /// by at most the number of empty leading cells, so no content is lost
+    fn shift_row(&mut self, row: usize, count: isize) {
+        let count = if count < 0 {
+            let leading = self.cells[row].iter().take_while(|cell| cell.is_empty()).count();
+            -(count.unsigned_abs().min(leading) as isize)
+        } else {
+            count
+        };
+        shift_cells(&mut self.cells[row], count, String::new());
+        shift_cells(&mut self.cell_styles[row], count, CellStyle::default());
+        shift_cells(&mut self.cell_validations[row], count, None);
+    }
+
+    /// Choices offered when the palette prompts for a command's argument
+    fn prompt_choices(&self, cmd_id: &str) -> Vec<String> {
+        match cmd_id {
+            "sort_by" | "sort_by_descending" => {
+                let (_, max_col) = file_io::find_used_bounds(&self.cells);
+                match self.header_row {
+                    Some(row) => self.cells[row][..=max_col]
+                        .iter()
+                        .map(|label| label.trim().to_string())
+                        .filter(|label| !label.is_empty())
+                        .collect(),
+                    None => (0..=max_col).map(CellPosition::col_to_letter).collect(),
+                }
+            }
+            "run_script" => script::script_names(),
+            "browse_snapshots" | "restore_snapshot" => snapshot::list(self.file_state.current_path.as_deref())
+                .iter()
+                .map(SnapshotEntry::label)
+                .collect(),
+            "text_color" => style::COLOR_NAMES.iter().chain(["none"].iter()).map(|name| name.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Rows holding records: below the header row (if any) through the last used row
+    fn data_rows(&self) -> RangeInclusive<usize> {
+        let (max_row, _) = file_io::find_used_bounds(&self.cells);
+        let first = self.header_row
+            .filter(|&row| row <= max_row)
+            .map_or(0, |row| row + 1);
+        first..=max_row
+    }
+
+    // === Clipboard ===
+
+    /// Cells of a range as tab-separated rows
+    fn range_to_tsv(&self, range: CellRange) -> String {
+        range.rows()
+            .map(|row| self.cells[row][range.cols()].join("\t"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Clipboard text for the selection. Ranges of a discontiguous selection spanning the same
+    /// rows are joined side by side; otherwise they're stacked top to bottom as bands of rows.
+    fn selection_to_tsv(&self) -> String {
+        let mut ranges: Vec<CellRange> = self.selection_ranges().collect();
+        let rows = ranges[0].rows();
+        if ranges.iter().all(|range| range.rows() == rows) {
+            ranges.sort_by_key(|range| range.start.col);
+            rows.map(|row| {
+                ranges.iter().map(|range| self.cells[row][range.cols()].join("\t")).collect::<Vec<_>>().join("\t")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+        } else {
+            ranges.sort_by_key(|range| (range.start.row, range.start.col));
+            ranges.into_iter().map(|range| self.range_to_tsv(range)).collect::<Vec<_>>().join("\n")
+        }
+    }
+
+    fn copy_selection(&mut self, _: &Copy, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.write_to_clipboard(ClipboardItem::new_string(self.selection_to_tsv()));
+    }
+
+    fn cut_selection(&mut self, _: &Cut, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        cx.write_to_clipboard(ClipboardItem::new_string(self.selection_to_tsv()));
+        for range in self.selection_ranges().collect::<Vec<_>>() {
+            for row in range.rows() {
+                for col in range.cols() {
+                    self.cells[row][col].clear();
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        cx.notify();
+    }
+
+    /// `delete` / `x` - empty the selected cells, keeping their formatting
+    fn clear_contents(&mut self, _: &ClearContents, _window: &mut Window, cx: &mut Context<Self>) {
+        self.clear_cells(true, false, cx);
+    }
+
+    /// Reset the selected cells' styles and number formats, keeping their values
+    fn clear_formats(&mut self, _: &ClearFormats, _window: &mut Window, cx: &mut Context<Self>) {
+        self.clear_cells(false, true, cx);
+    }
+
+    /// Empty the selected cells and reset their formatting
+    fn clear_all(&mut self, _: &ClearAll, _window: &mut Window, cx: &mut Context<Self>) {
+        self.clear_cells(true, true, cx);
+    }
+
+    /// Clear the values, the formatting, or both, of the selected cells, skipping protected ones
+    fn clear_cells(&mut self, contents: bool, formats: bool, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        for range in self.selection_ranges().collect::<Vec<_>>() {
+            for row in range.rows() {
+                for col in range.cols() {
+                    if self.is_protected(CellPosition::new(row, col)) {
+                        continue;
+                    }
+                    if contents {
+                        self.cells[row][col].clear();
+                    }
+                    if formats {
+                        self.cell_styles[row][col] = CellStyle::default();
+                    }
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        cx.notify();
+    }
+
+    /// Tab-separated clipboard text as rows of cells
+    fn clipboard_block(cx: &Context<Self>) -> Option<Vec<Vec<String>>> {
+        let text = cx.read_from_clipboard()?.text()?;
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        Some(text.split('\n')
+            .map(|line| line.trim_end_matches('\r').split('\t').map(str::to_string).collect())
+            .collect())
+    }
+
+    fn paste_at_cursor(&mut self, _: &Paste, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(block) = Self::clipboard_block(cx) {
+            self.write_block(self.selected, block, cx);
+        }
+    }
+
+    /// Paste special: write the clipboard block with rows and columns swapped
+    fn paste_transposed(&mut self, _: &PasteTransposed, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(block) = Self::clipboard_block(cx) {
+            self.write_block(self.selected, transpose_block(block), cx);
+        }
+    }
+
+    /// Write rows of values with their top-left at `origin`, clipping at the grid edge,
+    /// and select the written block
+    fn write_block(&mut self, origin: CellPosition, block: Vec<Vec<String>>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only || block.is_empty() {
+            return;
+        }
+        let mut end = origin;
+        for (row, values) in (origin.row..GRID_ROWS).zip(block) {
+            for (col, value) in (origin.col..GRID_COLS).zip(values) {
+                self.cells[row][col] = value;
+                end = CellPosition::new(row.max(end.row), col.max(end.col));
+            }
+        }
+        let range = CellRange::new(origin, end);
+        self.grow_to_fit(range);
+        self.select_range(range);
+        self.ensure_visible();
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        cx.notify();
+    }
+
+    /// Widen columns and heighten rows in `range` that are too small for their new content.
+    /// Sizes are never reduced, so manual adjustments survive.
+    fn grow_to_fit(&mut self, range: CellRange) {
+        for row in range.rows() {
+            for col in range.cols() {
+                let content = &self.cells[row][col];
+                let longest_line = content.lines().map(wrap::text_width).max().unwrap_or(0);
+                let width = self.autofit_width(col, longest_line as f32 * wrap::CHAR_WIDTH + 16.0);
+                let height = content.lines().count().max(1) as f32 * 20.0 + 8.0;
+                self.column_widths[col] = self.column_widths[col].max(width);
+                self.row_heights[row] = self.row_heights[row].max(height);
+            }
+        }
+    }
+
+    /// Swap the rows and columns of the selection, either in place (anchored at its
+    /// top-left corner, clearing cells the result no longer covers) or into `target`
+    fn transpose_selection(&mut self, target: Option<CellPosition>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        let range = self.selection_range();
+        let block: Vec<Vec<String>> = range.rows()
+            .map(|row| self.cells[row][range.cols()].to_vec())
+            .collect();
+
+        let origin = match target {
+            Some(target) => target,
+            None => {
+                for row in range.rows() {
+                    for col in range.cols() {
+                        self.cells[row][col].clear();
+                    }
+                }
+                range.start
+            }
+        };
+        self.write_block(origin, transpose_block(block), cx);
+    }
+
+    // === Word wrap ===
+
+    /// Lines a cell renders as: wrapped to the column width when the column wraps
+    fn cell_lines(&self, row: usize, col: usize) -> Vec<String> {
+        let content = self.display_text(row, col);
+        if self.wrap_columns.contains(&col) {
+            wrap::wrap_text(&content, wrap::chars_per_line(self.column_widths[col]))
+        } else {
+            content.lines().map(str::to_string).collect()
+        }
+    }
+
+    /// Whether a cell's text is clipped by its column width or row height
+    fn is_truncated(&self, row: usize, col: usize) -> bool {
+        lines_overflow(&self.cell_lines(row, col), self.column_widths[col], self.row_heights[row])
+    }
+
+    /// Grow a row (never shrink it) so it fits `lines` lines of text
+    fn grow_row_to_lines(&mut self, row: usize, lines: usize) {
+        let height = lines as f32 * wrap::LINE_HEIGHT + 8.0;
+        self.row_heights[row] = self.row_heights[row].max(height);
+    }
+
+    /// Row height as drawn: the row being edited grows to fit every line of the input
+    fn display_row_height(&self, row: usize, cx: &App) -> f32 {
+        let height = self.row_heights[row];
+        if self.mode == Mode::Edit && row == self.selected.row {
+            let lines = self.active_input.read(cx).line_count();
+            height.max(lines as f32 * cell::LINE_HEIGHT + 4.0)
+        } else {
+            height
+        }
+    }
+
+    /// Scroll the cell being edited fully into view, as drawn, once it has grown past the
+    /// bottom of the viewport (by new lines, auto-fit, or a smaller window)
+    fn keep_edit_cell_visible(&mut self, cx: &App) {
+        let row = self.selected.row;
+        if self.mode != Mode::Edit || row < self.frozen_rows || row < self.scroll_row {
+            return;
+        }
+        let top = self.rows_height(self.scroll_row..row) - self.scroll_offset_y;
+        let overflow = top + self.display_row_height(row, cx) - self.scroll_area_height();
+        // Never so far that the top of the cell goes out of view
+        let scroll = overflow.min(top);
+        if scroll > 0.0 {
+            self.apply_smooth_scroll(0.0, scroll);
+        }
+    }
+
+    /// Height that fits every cell in a row: wrapped columns count the lines they wrap to
+    fn fitted_row_height(&self, row: usize) -> f32 {
+        (0..GRID_COLS)
+            .filter(|&col| !self.cells[row][col].is_empty())
+            .map(|col| {
+                if self.wrap_columns.contains(&col) {
+                    self.cell_lines(row, col).len().max(1) as f32 * wrap::LINE_HEIGHT + 8.0
+                } else {
+                    self.cells[row][col].lines().count().max(1) as f32 * 20.0 + 8.0
+                }
+            })
+            .fold(self.default_row_height, f32::max)
+    }
+
+    /// Recompute the heights of rows with content in a wrapped column after its width changed
+    fn fit_rows_to_wrapped_column(&mut self, col: usize) {
+        if !self.wrap_columns.contains(&col) {
+            return;
+        }
+        for row in 0..GRID_ROWS {
+            if !self.cells[row][col].is_empty() {
+                self.row_heights[row] = self.fitted_row_height(row);
+            }
+        }
+    }
+
+    /// Turn word wrap on or off for the selected columns
+    fn set_wrap(&mut self, enabled: bool, cx: &mut Context<Self>) {
+        for col in self.selected_cols() {
+            if enabled {
+                self.wrap_columns.insert(col);
+                self.fit_rows_to_wrapped_column(col);
+            } else {
+                self.wrap_columns.remove(&col);
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // === Moving cells ===
+
+    /// Where the selection would land for a border drag, kept inside the grid
+    fn move_drag_target(&self, drag: MoveDrag) -> CellRange {
+        let range = self.selection_range();
+        let (height, width) = (range.end.row - range.start.row, range.end.col - range.start.col);
+        let row = (range.start.row + drag.over.row).saturating_sub(drag.grabbed.row).min(GRID_ROWS - 1 - height);
+        let col = (range.start.col + drag.over.col).saturating_sub(drag.grabbed.col).min(GRID_COLS - 1 - width);
+        CellRange::new(CellPosition::new(row, col), CellPosition::new(row + height, col + width))
+    }
+
+    /// Move `range` (with `copy`, duplicate it) so its top-left lands at `to`, which must leave
+    /// room for it. Moving updates formulas anywhere that refer to the moved cells; copied
+    /// formulas have their references shifted by the distance, as pasting would.
+    fn move_block(&mut self, range: CellRange, to: CellPosition, copy: bool, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only || to == range.start {
+            return;
+        }
+        let offset = |pos: CellPosition| {
+            let row = (pos.row + to.row).checked_sub(range.start.row)?;
+            let col = (pos.col + to.col).checked_sub(range.start.col)?;
+            (row < GRID_ROWS && col < GRID_COLS).then(|| CellPosition::new(row, col))
+        };
+        if !copy {
+            for text in self.cells.iter_mut().flatten() {
+                if formula::is_formula(text) {
+                    *text = formula::map_references(text, |pos| if range.contains(pos) { offset(pos) } else { Some(pos) });
+                }
+            }
+        }
+
+        let mut cells: Vec<Vec<String>> = range.rows().map(|row| self.cells[row][range.cols()].to_vec()).collect();
+        let styles: Vec<Vec<CellStyle>> = range.rows().map(|row| self.cell_styles[row][range.cols()].to_vec()).collect();
+        let validations: Vec<Vec<Option<Validation>>> =
+            range.rows().map(|row| self.cell_validations[row][range.cols()].to_vec()).collect();
+        if copy {
+            for text in cells.iter_mut().flatten() {
+                *text = formula::map_references(text, offset);
+            }
+        } else {
+            for row in range.rows() {
+                for col in range.cols() {
+                    self.cells[row][col].clear();
+                    self.cell_styles[row][col] = CellStyle::default();
+                    self.cell_validations[row][col] = None;
+                }
+            }
+        }
+        for (row, ((cells, styles), validations)) in (to.row..).zip(cells.into_iter().zip(styles).zip(validations)) {
+            let cols = to.col..to.col + cells.len();
+            self.cells[row].splice(cols.clone(), cells);
+            self.cell_styles[row].splice(cols.clone(), styles);
+            self.cell_validations[row].splice(cols, validations);
+        }
+
+        let end = CellPosition::new(to.row + range.end.row - range.start.row, to.col + range.end.col - range.start.col);
+        self.select_range(CellRange::new(to, end));
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    // === Cell styles ===
+
+    /// Apply a formatting change to the selection. Toggles turn off only when every
+    /// selected cell already has the attribute.
+    fn apply_style(&mut self, change: StyleChange, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        let ranges: Vec<CellRange> = self.selection_ranges().collect();
+        let on = !ranges.iter().all(|range| {
+            range.rows().all(|row| range.cols().all(|col| change.is_set(&self.cell_styles[row][col])))
+        });
+        for range in ranges {
+            for row in range.rows() {
+                for col in range.cols() {
+                    change.apply(&mut self.cell_styles[row][col], on);
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    fn toggle_bold(&mut self, _: &ToggleBold, _window: &mut Window, cx: &mut Context<Self>) {
+        self.apply_style(StyleChange::Bold, cx);
+    }
+
+    fn toggle_italic(&mut self, _: &ToggleItalic, _window: &mut Window, cx: &mut Context<Self>) {
+        self.apply_style(StyleChange::Italic, cx);
+    }
+
+    fn toggle_underline(&mut self, _: &ToggleUnderline, _window: &mut Window, cx: &mut Context<Self>) {
+        self.apply_style(StyleChange::Underline, cx);
+    }
+
+    /// Set the number format of the selected cells, or of their whole columns
+    fn set_number_format(&mut self, format: Option<NumberFormat>, column: bool, cx: &mut Context<Self>) {
+        if !column {
+            self.apply_style(StyleChange::NumberFormat(format), cx);
+            return;
+        }
+        if self.file_state.is_read_only {
+            return;
+        }
+        for col in self.selected_cols() {
+            match format {
+                Some(format) => self.column_formats.insert(col, format),
+                None => self.column_formats.remove(&col),
+            };
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    fn format_general(&mut self, _: &FormatGeneral, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(None, false, cx);
+    }
+
+    fn format_decimal(&mut self, _: &FormatDecimal, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(NumberFormat::parse("0.00"), false, cx);
+    }
+
+    fn format_thousands(&mut self, _: &FormatThousands, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(NumberFormat::parse("#,##0"), false, cx);
+    }
+
+    fn format_currency(&mut self, _: &FormatCurrency, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(NumberFormat::parse("$#,##0.00"), false, cx);
+    }
+
+    fn format_percent(&mut self, _: &FormatPercent, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(NumberFormat::parse("0%"), false, cx);
+    }
+
+    fn format_scientific(&mut self, _: &FormatScientific, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_number_format(NumberFormat::parse("0.00E+00"), false, cx);
+    }
+
+    /// Set how dates display in the selected columns
+    fn set_date_format(&mut self, format: Option<DateFormat>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        for col in self.selected_cols() {
+            match &format {
+                Some(format) => self.date_formats.insert(col, format.clone()),
+                None => self.date_formats.remove(&col),
+            };
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Override alignment for the selected columns; `None` returns them to automatic
+    fn set_alignment(&mut self, alignment: Option<Alignment>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        for col in self.selected_cols() {
+            match alignment {
+                Some(alignment) => self.column_alignments.insert(col, alignment),
+                None => self.column_alignments.remove(&col),
+            };
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Infer each column's type from its data rows, leaving formulas out
+    fn infer_column_types(&mut self) {
+        let rows = self.data_rows();
+        self.inferred_types = (0..GRID_COLS)
+            .filter_map(|col| {
+                let values = rows.clone().map(|row| self.cells[row][col].as_str()).filter(|text| !formula::is_formula(text));
+                ColumnType::infer(values).map(|column_type| (col, column_type))
+            })
+            .collect();
+    }
+
+    /// A column's type: set with `:type`, else inferred on load; `None` for mixed columns,
+    /// whose cells are each taken as they come
+    fn column_type(&self, col: usize) -> Option<ColumnType> {
+        self.column_types.get(&col).or_else(|| self.inferred_types.get(&col)).copied()
+    }
+
+    /// Set or clear (`None`, back to the inferred type) the type of the selected columns
+    fn set_column_type(&mut self, column_type: Option<ColumnType>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        for col in self.selected_cols() {
+            match column_type {
+                Some(column_type) => self.column_types.insert(col, column_type),
+                None => self.column_types.remove(&col),
+            };
+        }
+        self.file_state.mark_dirty();
+        self.on_filters_changed(cx);
+    }
+
+    /// Say in the footer what type the cursor's column is and where that came from
+    fn show_column_type(&mut self, cx: &mut Context<Self>) {
+        let col = self.selected.col;
+        let source = if self.column_types.contains_key(&col) { "set" } else { "inferred" };
+        let message = match self.column_type(col) {
+            Some(column_type) => format!("{}: {} ({})", CellPosition::col_to_letter(col), column_type.name(), source),
+            None => format!("{}: mixed values, each taken as it comes", CellPosition::col_to_letter(col)),
+        };
+        self.show_footer_message(message, cx);
+    }
+
+    /// How a cell's text aligns: the column's override, else centered for the header row,
+    /// then by the column's type, else right for numbers and dates and left for text
+    fn cell_alignment(&self, row: usize, col: usize) -> Alignment {
+        if let Some(&alignment) = self.column_alignments.get(&col) {
+            return alignment;
+        }
+        if self.header_row == Some(row) {
+            return Alignment::Center;
+        }
+        match self.column_type(col) {
+            Some(ColumnType::Integer | ColumnType::Float | ColumnType::Date) => return Alignment::Right,
+            Some(ColumnType::Boolean) => return Alignment::Center,
+            Some(ColumnType::Text) => return Alignment::Left,
+            None => {}
+        }
+        match self.computed[row][col].as_ref().filter(|_| formula::is_formula(&self.cells[row][col])) {
+            Some(formula::Value::Number(_) | formula::Value::Date(_)) => return Alignment::Right,
+            Some(formula::Value::Bool(_) | formula::Value::Error(_)) => return Alignment::Center,
+            Some(_) => return Alignment::Left,
+            None => {}
+        }
+        match value::classify(&self.cells[row][col]) {
+            ValueKind::Number | ValueKind::Date => Alignment::Right,
+            ValueKind::Empty | ValueKind::Text => Alignment::Left,
+        }
+    }
+
+    /// Text a cell displays: formulas show their result, numbers go through the cell's (or
+    /// its column's) number format, and dates through the column's date format
+    fn display_text(&self, row: usize, col: usize) -> String {
+        let content = &self.cells[row][col];
+        // Stale results (manual calculation) may linger on cells that are no longer formulas
+        let computed = self.computed[row][col].as_ref().filter(|_| formula::is_formula(content));
+        let number = match computed {
+            Some(formula::Value::Number(n)) => Some(*n),
+            Some(_) => None,
+            None => value::parse_number(content),
+        };
+        let format = self.cell_styles[row][col]
+            .number_format
+            .or_else(|| self.column_formats.get(&col).copied());
+        if let (Some(format), Some(number)) = (format, number) {
+            return format.format(number);
+        }
+        let date = match computed {
+            Some(formula::Value::Date(date)) => Some(*date),
+            Some(value) => return value.to_string(),
+            None => DateTime::parse(content),
+        };
+        self.date_formats
+            .get(&col)
+            .and_then(|format| format.format(date?))
+            .or_else(|| computed.map(ToString::to_string))
+            .unwrap_or_else(|| content.clone())
+    }
+
+    /// Styled cells in row-major order, for the metadata sidecar
+    fn styled_cells(&self) -> Vec<StyledCell> {
+        self.cell_styles
+            .iter()
+            .enumerate()
+            .flat_map(|(row, styles)| {
+                styles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, style)| !style.is_default())
+                    .map(move |(col, &style)| StyledCell { row, col, style })
+            })
+            .collect()
+    }
+
+    // === Data validation ===
+
+    /// The rule a cell follows: its own, else its column's
+    fn validation_at(&self, row: usize, col: usize) -> Option<&Validation> {
+        self.cell_validations[row][col]
+            .as_ref()
+            .or_else(|| self.column_validations.get(&col))
+    }
+
+    fn is_cell_invalid(&self, row: usize, col: usize) -> bool {
+        self.validation_at(row, col)
+            .is_some_and(|validation| !validation.is_valid(&self.cells[row][col]))
+    }
+
+    /// Attach a validation parsed from `spec` to the selected cells, or to their columns
+    fn set_validation(&mut self, spec: &str, reject: bool, column: bool, cx: &mut Context<Self>) {
+        let validation = match Validation::parse(spec, reject) {
+            Ok(validation) => validation,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        self.apply_validation(Some(validation), column, cx);
+    }
+
+    fn apply_validation(&mut self, validation: Option<Validation>, column: bool, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        let range = self.selection_range();
+        if column {
+            for col in range.cols() {
+                match &validation {
+                    Some(validation) => self.column_validations.insert(col, validation.clone()),
+                    None => self.column_validations.remove(&col),
+                };
+            }
+        } else {
+            for row in range.rows() {
+                for col in range.cols() {
+                    self.cell_validations[row][col] = validation.clone();
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Values offered by the dropdown while editing a list-validated cell, or the column's values
+    /// when picking from the list: those containing the typed text, or all of them until the
+    /// text narrows the list
+    fn validation_choices(&self, cx: &App) -> Vec<String> {
+        let values = match self.validation_at(self.selected.row, self.selected.col).and_then(Validation::list_values) {
+            Some(values) => values.to_vec(),
+            None if self.picking_from_list => {
+                let mut values = self.column_values(self.selected);
+                values.sort_by_key(|value| value.to_lowercase());
+                values
+            }
+            None => return Vec::new(),
+        };
+        let typed = self.active_input.read(cx).get_content().trim().to_lowercase();
+        let matching: Vec<String> = values
+            .iter()
+            .filter(|value| value.to_lowercase().contains(&typed))
+            .cloned()
+            .collect();
+        if matching.is_empty() || values.iter().any(|value| value.to_lowercase() == typed) {
+            values.to_vec()
+        } else {
+            matching
+        }
+    }
+
+    /// Pick a value from the validation dropdown and leave edit mode
+    fn choose_validation_value(&mut self, value: String, window: &mut Window, cx: &mut Context<Self>) {
+        self.active_input.update(cx, |input, cx| input.set_content(value, cx));
+        self.save_and_exit_edit_mode(window, cx);
+    }
+
+    /// Per-cell validations in row-major order, for the metadata sidecar
+    fn validated_cells(&self) -> Vec<ValidatedCell> {
+        self.cell_validations
+            .iter()
+            .enumerate()
+            .flat_map(|(row, validations)| {
+                validations.iter().enumerate().filter_map(move |(col, validation)| {
+                    Some(ValidatedCell { row, col, validation: validation.clone()? })
+                })
+            })
+            .collect()
+    }
+
+    // === Structural edits ===
+
+    /// Insert `count` empty columns before `at`, shifting cells, styles, widths, filters,
+    /// formats, alignments, validations, and outline groups right. Columns pushed past the grid edge are dropped.
+    fn insert_columns(&mut self, at: usize, count: usize) {
+        if at >= GRID_COLS || count == 0 {
+            return;
+        }
+        for row in &mut self.cells {
+            row.splice(at..at, std::iter::repeat_n(String::new(), count));
+            row.truncate(GRID_COLS);
+        }
+        for row in &mut self.cell_styles {
+            row.splice(at..at, std::iter::repeat_n(CellStyle::default(), count));
+            row.truncate(GRID_COLS);
+        }
+        for row in &mut self.cell_validations {
+            row.splice(at..at, std::iter::repeat_n(None, count));
+            row.truncate(GRID_COLS);
+        }
+        self.column_widths.splice(at..at, std::iter::repeat_n(self.default_col_width, count));
+        self.column_widths.truncate(GRID_COLS);
+        shift_columns(&mut self.filters, at, count);
+        shift_columns(&mut self.column_formats, at, count);
+        shift_columns(&mut self.date_formats, at, count);
+        shift_columns(&mut self.column_alignments, at, count);
+        shift_columns(&mut self.inferred_types, at, count);
+        shift_columns(&mut self.column_types, at, count);
+        shift_columns(&mut self.column_validations, at, count);
+        shift_columns(&mut self.column_width_limits, at, count);
+        self.col_outline.insert(at, count);
+        self.wrap_columns = insert_indices(&self.wrap_columns, at, count, GRID_COLS);
+        self.hidden_cols = insert_indices(&self.hidden_cols, at, count, GRID_COLS);
+        shift_ranges(&mut self.protected_ranges, false, |start, end| insert_into_span(start, end, at, count, GRID_COLS));
+        self.file_state.mark_dirty();
+    }
+
+    /// Delete `count` columns starting at `at`, shifting cells, styles, widths, filters, formats,
+    /// alignments, validations, and outline groups left. Empty columns fill in at the right.
+    fn delete_columns(&mut self, at: usize, count: usize) {
+        let count = count.min(GRID_COLS.saturating_sub(at));
+        if count == 0 {
+            return;
+        }
+        let removed = at..at + count;
+        for row in &mut self.cells {
+            row.drain(removed.clone());
+            row.extend(std::iter::repeat_n(String::new(), count));
+        }
+        for row in &mut self.cell_styles {
+            row.drain(removed.clone());
+            row.extend(std::iter::repeat_n(CellStyle::default(), count));
+        }
+        for row in &mut self.cell_validations {
+            row.drain(removed.clone());
+            row.extend(std::iter::repeat_n(None, count));
+        }
+        self.column_widths.drain(removed.clone());
+        self.column_widths.extend(std::iter::repeat_n(self.default_col_width, count));
+        remove_columns(&mut self.filters, at, count);
+        remove_columns(&mut self.column_formats, at, count);
+        remove_columns(&mut self.date_formats, at, count);
+        remove_columns(&mut self.column_alignments, at, count);
+        remove_columns(&mut self.inferred_types, at, count);
+        remove_columns(&mut self.column_types, at, count);
+        remove_columns(&mut self.column_validations, at, count);
+        remove_columns(&mut self.column_width_limits, at, count);
+        self.col_outline.remove(at, count);
+        self.wrap_columns = remove_indices(&self.wrap_columns, at, count);
+        self.hidden_cols = remove_indices(&self.hidden_cols, at, count);
+        shift_ranges(&mut self.protected_ranges, false, |start, end| remove_from_span(start, end, at, count));
+        self.frozen_cols -= self.frozen_cols.min(removed.end).saturating_sub(at);
+        if self.selected.col >= removed.end {
+            self.selected.col -= count;
+        } else if self.selected.col >= at {
+            self.selected.col = at.min(GRID_COLS - 1);
+        }
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.filter_menu = None;
+        self.file_state.mark_dirty();
+    }
+
+    /// Insert `count` empty rows before `at`, shifting cells, styles, validations, heights, and
+    /// outline groups down. Rows pushed past the grid edge are dropped.
+    fn insert_rows(&mut self, at: usize, count: usize) {
+        if at >= GRID_ROWS || count == 0 {
+            return;
+        }
+        self.cells.splice(at..at, (0..count).map(|_| vec![String::new(); GRID_COLS]));
+        self.cells.truncate(GRID_ROWS);
+        self.cell_styles.splice(at..at, (0..count).map(|_| vec![CellStyle::default(); GRID_COLS]));
+        self.cell_styles.truncate(GRID_ROWS);
+        self.cell_validations.splice(at..at, (0..count).map(|_| vec![None; GRID_COLS]));
+        self.cell_validations.truncate(GRID_ROWS);
+        self.row_heights.splice(at..at, std::iter::repeat_n(self.default_row_height, count));
+        self.row_heights.truncate(GRID_ROWS);
+        self.row_outline.insert(at, count);
+        self.hidden_rows = insert_indices(&self.hidden_rows, at, count, GRID_ROWS);
+        shift_ranges(&mut self.protected_ranges, true, |start, end| insert_into_span(start, end, at, count, GRID_ROWS));
+        self.header_row = self.header_row
+            .map(|row| if row >= at { row + count } else { row })
+            .filter(|&row| row < GRID_ROWS);
+        if at < self.frozen_rows {
+            self.frozen_rows = (self.frozen_rows + count).min(GRID_ROWS);
+        }
+        self.file_state.mark_dirty();
+    }
+
+    /// Delete `count` rows starting at `at`, shifting cells, styles, validations, heights, and
+    /// outline groups up. Empty rows fill in at the bottom.
+    fn delete_rows(&mut self, at: usize, count: usize) {
+        let count = count.min(GRID_ROWS.saturating_sub(at));
+        if count == 0 {
+            return;
+        }
+        let removed = at..at + count;
+        self.cells.drain(removed.clone());
+        self.cells.extend((0..count).map(|_| vec![String::new(); GRID_COLS]));
+        self.cell_styles.drain(removed.clone());
+        self.cell_styles.extend((0..count).map(|_| vec![CellStyle::default(); GRID_COLS]));
+        self.cell_validations.drain(removed.clone());
+        self.cell_validations.extend((0..count).map(|_| vec![None; GRID_COLS]));
+        self.row_heights.drain(removed.clone());
+        self.row_heights.extend(std::iter::repeat_n(self.default_row_height, count));
+        self.row_outline.remove(at, count);
+        self.hidden_rows = remove_indices(&self.hidden_rows, at, count);
+        shift_ranges(&mut self.protected_ranges, true, |start, end| remove_from_span(start, end, at, count));
+        let shift = |row: usize| if row >= removed.end { Some(row - count) } else if row >= at { None } else { Some(row) };
+        self.header_row = self.header_row.and_then(shift);
+        self.frozen_rows -= self.frozen_rows.min(removed.end).saturating_sub(at);
+        self.selected.row = shift(self.selected.row).unwrap_or(at).min(GRID_ROWS - 1);
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.file_state.mark_dirty();
+    }
+
+    /// Move the rows `rows` so the first lands at `to`, shifting the rows between them to make
+    /// room. Styles, validations, heights, hidden rows, and the header row move along, and
+    /// formulas anywhere are rewritten to follow the cells they refer to.
+    fn move_rows(&mut self, rows: RangeInclusive<usize>, to: usize, cx: &mut Context<Self>) {
+        let count = rows.end() + 1 - rows.start();
+        let to = to.min(GRID_ROWS - count);
+        if self.file_state.is_read_only || *rows.end() >= GRID_ROWS || to == *rows.start() {
+            return;
+        }
+        let order = moved_order(GRID_ROWS, rows, to);
+        let moved_to = inverse_order(&order);
+        for text in self.cells.iter_mut().flatten() {
+            if formula::is_formula(text) {
+                *text = formula::map_references(text, |pos| Some(CellPosition::new(moved_to[pos.row], pos.col)));
+            }
+        }
+        reorder(&mut self.cells, &order);
+        reorder(&mut self.cell_styles, &order);
+        reorder(&mut self.cell_validations, &order);
+        reorder(&mut self.row_heights, &order);
+        self.hidden_rows = self.hidden_rows.iter().map(|&row| moved_to[row]).collect();
+        shift_ranges(&mut self.protected_ranges, true, |start, end| Some(moved_span(start, end, &moved_to)));
+        self.header_row = self.header_row.map(|row| moved_to[row]);
+        self.selected.row = moved_to[self.selected.row];
+        if let Some(anchor) = &mut self.selection_anchor {
+            anchor.row = moved_to[anchor.row];
+        }
+        self.extra_selections.clear();
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// Move the columns `cols` so the first lands at `to`, shifting the columns between them to
+    /// make room. Styles, widths, and per-column settings move along, and formulas anywhere are
+    /// rewritten to follow the cells they refer to.
+    fn move_columns(&mut self, cols: RangeInclusive<usize>, to: usize, cx: &mut Context<Self>) {
+        let count = cols.end() + 1 - cols.start();
+        let to = to.min(GRID_COLS - count);
+        if self.file_state.is_read_only || *cols.end() >= GRID_COLS || to == *cols.start() {
+            return;
+        }
+        let order = moved_order(GRID_COLS, cols, to);
+        let moved_to = inverse_order(&order);
+        for text in self.cells.iter_mut().flatten() {
+            if formula::is_formula(text) {
+                *text = formula::map_references(text, |pos| Some(CellPosition::new(pos.row, moved_to[pos.col])));
+            }
+        }
+        for row in &mut self.cells {
+            reorder(row, &order);
+        }
+        for row in &mut self.cell_styles {
+            reorder(row, &order);
+        }
+        for row in &mut self.cell_validations {
+            reorder(row, &order);
+        }
+        reorder(&mut self.column_widths, &order);
+        renumber_columns(&mut self.filters, &moved_to);
+        renumber_columns(&mut self.column_formats, &moved_to);
+        renumber_columns(&mut self.date_formats, &moved_to);
+        renumber_columns(&mut self.column_alignments, &moved_to);
+        renumber_columns(&mut self.inferred_types, &moved_to);
+        renumber_columns(&mut self.column_types, &moved_to);
+        renumber_columns(&mut self.column_validations, &moved_to);
+        renumber_columns(&mut self.column_width_limits, &moved_to);
+        self.wrap_columns = self.wrap_columns.iter().map(|&col| moved_to[col]).collect();
+        self.hidden_cols = self.hidden_cols.iter().map(|&col| moved_to[col]).collect();
+        shift_ranges(&mut self.protected_ranges, false, |start, end| Some(moved_span(start, end, &moved_to)));
+        self.selected.col = moved_to[self.selected.col];
+        if let Some(anchor) = &mut self.selection_anchor {
+            anchor.col = moved_to[anchor.col];
+        }
+        self.extra_selections.clear();
+        self.filter_menu = None;
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// `ctrl-alt-up` / `ctrl-alt-down` - move the selected rows, or the cursor's row, one row up or down
+    fn move_row_up(&mut self, _: &MoveRowUp, _window: &mut Window, cx: &mut Context<Self>) {
+        let rows = self.selected_rows().unwrap_or(self.selected.row..self.selected.row + 1);
+        if let Some(to) = rows.start.checked_sub(1) {
+            self.move_rows(rows.start..=rows.end - 1, to, cx);
+        }
+    }
+
+    fn move_row_down(&mut self, _: &MoveRowDown, _window: &mut Window, cx: &mut Context<Self>) {
+        let rows = self.selected_rows().unwrap_or(self.selected.row..self.selected.row + 1);
+        self.move_rows(rows.start..=rows.end - 1, rows.start + 1, cx);
+    }
+
+    /// `ctrl-alt-left` / `ctrl-alt-right` - move the selected columns, or the cursor's column, one column
+    /// left or right
+    fn move_column_left(&mut self, _: &MoveColumnLeft, _window: &mut Window, cx: &mut Context<Self>) {
+        let cols = self.selected_columns().unwrap_or(self.selected.col..self.selected.col + 1);
+        if let Some(to) = cols.start.checked_sub(1) {
+            self.move_columns(cols.start..=cols.end - 1, to, cx);
+        }
+    }
+
+    fn move_column_right(&mut self, _: &MoveColumnRight, _window: &mut Window, cx: &mut Context<Self>) {
+        let cols = self.selected_columns().unwrap_or(self.selected.col..self.selected.col + 1);
+        self.move_columns(cols.start..=cols.end - 1, cols.start + 1, cx);
+    }
+
+    /// Split the cursor column's cells on `delimiter` into adjacent columns. Splits the
+    /// selected rows, or every used row below the header when only one cell is selected.
+    /// New columns are inserted to make room unless `overwrite` is set.
+    fn text_to_columns(&mut self, delimiter: &Delimiter, overwrite: bool, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            return;
+        }
+        let col = self.selected.col;
+        let selection = self.selection_range();
+        let rows = if selection.is_single_cell() {
+            self.data_rows().collect::<Vec<_>>()
+        } else {
+            selection.rows().collect()
+        };
+
+        let parts: Vec<(usize, Vec<String>)> = rows
+            .into_iter()
+            .filter(|&row| !self.cells[row][col].is_empty())
+            .map(|row| (row, delimiter.split(&self.cells[row][col])))
+            .collect();
+        let extra = parts.iter().map(|(_, values)| values.len() - 1).max().unwrap_or(0);
+        if extra == 0 {
+            return;
+        }
+        if col + extra >= GRID_COLS {
+            eprintln!("Text to columns: {} value(s) per row don't fit right of column {}", extra + 1, CellPosition::col_to_letter(col));
+        }
+
+        if !overwrite {
+            self.insert_columns(col + 1, extra);
+        }
+        for (row, values) in parts {
+            for (offset, value) in values.into_iter().enumerate().take(GRID_COLS - col) {
+                self.cells[row][col + offset] = value;
+            }
+        }
+        self.grow_to_fit(CellRange::new(
+            CellPosition::new(0, col),
+            CellPosition::new(GRID_ROWS - 1, (col + extra).min(GRID_COLS - 1)),
+        ));
+        self.file_state.mark_dirty();
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    // === Sorting ===
+
+    /// Sort the used rows by a column. Rows move as whole records (including their heights);
+    /// the header row and anything above it stay in place.
+    fn sort_by_column(&mut self, col: usize, descending: bool, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to sort", cx);
+            return;
+        }
+        self.auto_snapshot("before sort");
+        if self.sort_rows(self.data_rows(), col, descending) {
+            self.file_state.mark_dirty();
+            self.cells_changed(cx);
+            self.on_filters_changed(cx);
+        }
+    }
+
+    /// Reorder `rows` by a column, moving whole records. Returns whether anything moved.
+    fn sort_rows(&mut self, rows: RangeInclusive<usize>, col: usize, descending: bool) -> bool {
+        let first = *rows.start();
+        if first >= *rows.end() {
+            return false;
+        }
+
+        let mut order: Vec<usize> = rows.clone().collect();
+        let column_type = self.column_type(col);
+        order.sort_by(|&a, &b| value::compare_as(column_type, &self.cells[a][col], &self.cells[b][col], descending));
+        if order.iter().copied().eq(rows) {
+            return false;
+        }
+
+        let rows: Vec<Vec<String>> = order.iter().map(|&row| self.cells[row].clone()).collect();
+        let styles: Vec<Vec<CellStyle>> = order.iter().map(|&row| self.cell_styles[row].clone()).collect();
+        let validations: Vec<Vec<Option<Validation>>> =
+            order.iter().map(|&row| self.cell_validations[row].clone()).collect();
+        let heights: Vec<f32> = order.iter().map(|&row| self.row_heights[row]).collect();
+        for (offset, (((cells, styles), validations), height)) in
+            rows.into_iter().zip(styles).zip(validations).zip(heights).enumerate()
+        {
+            self.cells[first + offset] = cells;
+            self.cell_styles[first + offset] = styles;
+            self.cell_validations[first + offset] = validations;
+            self.row_heights[first + offset] = height;
+        }
+        true
+    }
+
+    /// Sort by a column named in a command (header label or letters), or the cursor's column
+    fn sort_by_named_column(&mut self, column: Option<&str>, descending: bool, cx: &mut Context<Self>) {
+        let col = match column {
+            Some(name) => match self.find_column(name) {
+                Some(col) => col,
+                None => {
+                    eprintln!("Unknown column: {}", name);
+                    return;
+                }
+            },
+            None => self.selected.col,
+        };
+        self.sort_by_column(col, descending, cx);
+    }
+
+    // === Pivot tables ===
+
+    /// Open a pivot table of the selection, or of the data around the cursor, in a new tab.
+    /// The source's first row holds the labels.
+    fn create_pivot(&mut self, request: &PivotRequest, cx: &mut Context<Self>) {
+        let selection = self.selection_range();
+        let follow_block = selection.is_single_cell();
+        let source = if follow_block { data_block(&self.cells, self.selected) } else { selection };
+        let mut resolve = |names: &[String]| -> Option<Vec<usize>> {
+            names
+                .iter()
+                .map(|name| match self.find_column(name).filter(|&col| source.cols().contains(&col)) {
+                    Some(col) => Some(col),
+                    None => {
+                        let message = format!("No column {} in {}", name, source.to_reference());
+                        self.show_footer_message(message, cx);
+                        None
+                    }
+                })
+                .collect()
+        };
+        let (Some(rows), Some(cols)) = (resolve(&request.rows), resolve(&request.cols)) else {
+            return;
+        };
+        let value = match &request.value {
+            Some(name) => match resolve(std::slice::from_ref(name)) {
+                Some(cols) => Some(cols[0]),
+                None => return,
+            },
+            None => None,
+        };
+        let spec = PivotSpec {
+            source,
+            follow_block,
+            rows,
+            cols,
+            aggregation: request.aggregation,
+            value,
+        };
+        cx.emit(TabEvent::Pivot { source: cx.entity().downgrade(), spec });
+    }
+
+    /// Rebuild this tab's pivot table from the current contents of its source
+    fn refresh_pivot(&mut self, cx: &mut Context<Self>) {
+        let Some((source, mut spec)) = self.pivot.clone() else {
+            self.show_footer_message("Not a pivot table; :pivot rows=... makes one", cx);
+            return;
+        };
+        let Some(source) = source.upgrade() else {
+            self.show_footer_message("The pivot table's source was closed", cx);
+            return;
+        };
+        let table = {
+            let source = source.read(cx);
+            if spec.follow_block {
+                spec.source = data_block(&source.cells, spec.source.start);
+            }
+            let values: Vec<Vec<String>> = (0..GRID_ROWS)
+                .map(|row| (0..GRID_COLS).map(|col| source.value_text(row, col)).collect())
+                .collect();
+            spec.build(&values)
+        };
+
+        let width = table.iter().map(Vec::len).max().unwrap_or(1).min(GRID_COLS);
+        let extent = CellRange::new(CellPosition::new(0, 0), CellPosition::new(table.len().min(GRID_ROWS) - 1, width - 1));
+        for row in self.cells.iter_mut() {
+            row.iter_mut().for_each(String::clear);
+        }
+        for (row, values) in self.cells.iter_mut().zip(table) {
+            for (cell, value) in row.iter_mut().zip(values) {
+                *cell = value;
+            }
+        }
+        self.pivot = Some((source.downgrade(), spec));
+        self.header_row = Some(0);
+        self.infer_column_types();
+        self.record_undo();
+        self.compute_formulas(cx);
+        self.track_cells(cx);
+        self.grow_to_fit(extent);
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Fill this (new, untitled) grid with rows from elsewhere, such as text sent by a service
+    pub fn load_rows(&mut self, rows: Vec<Vec<String>>, cx: &mut Context<Self>) {
+        for (row, values) in self.cells.iter_mut().zip(rows) {
+            for (cell, value) in row.iter_mut().zip(values) {
+                *cell = value;
+            }
+        }
+        self.infer_column_types();
+        self.reset_undo();
+        self.compute_formulas(cx);
+        self.track_cells(cx);
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // === Data sources ===
+
+    /// Bind a data source at the top-left of the selection, replacing any bound there, and fetch it
+    fn bind_source(&mut self, source: DataSource, cx: &mut Context<Self>) {
+        let origin = self.selection_range().start;
+        self.data_sources.retain(|binding| binding.range.start != origin);
+        self.data_sources.push(SourceBinding { source, range: CellRange::new(origin, origin) });
+        self.file_state.mark_dirty();
+        self.fetch_sources(vec![self.data_sources.len() - 1], cx);
+    }
+
+    fn refresh_sources(&mut self, cx: &mut Context<Self>) {
+        if self.data_sources.is_empty() {
+            self.show_footer_message("No data sources; :source url|cmd|sql ... binds one", cx);
+            return;
+        }
+        self.fetch_sources((0..self.data_sources.len()).collect(), cx);
+    }
+
+    /// Unbind the data source whose region holds the cursor; its data stays
+    fn unbind_source(&mut self, cx: &mut Context<Self>) {
+        let cursor = self.selected;
+        let Some(index) = self.data_sources.iter().position(|binding| binding.range.contains(cursor)) else {
+            self.show_footer_message("No data source here", cx);
+            return;
+        };
+        let binding = self.data_sources.remove(index);
+        self.file_state.mark_dirty();
+        self.show_footer_message(format!("Unbound {}", binding.source.describe()), cx);
+    }
+
+    /// Fetch the sources at `indices` in the background, then write each into its region
+    fn fetch_sources(&mut self, indices: Vec<usize>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let sources: Vec<(usize, DataSource)> =
+            indices.into_iter().map(|index| (index, self.data_sources[index].source.clone())).collect();
+        self.show_footer_message(match &sources[..] {
+            [(_, source)] => format!("Fetching {}...", source.describe()),
+            _ => format!("Refreshing {} data sources...", sources.len()),
+        }, cx);
+        cx.spawn(async move |this: WeakEntity<Self>, cx: &mut AsyncApp| {
+            let results = cx
+                .background_executor()
+                .spawn(async move {
+                    sources.into_iter().map(|(index, source)| {
+                        let rows = source.fetch();
+                        (index, source, rows)
+                    }).collect::<Vec<_>>()
+                })
+                .await;
+            let _ = this.update(cx, |grid, cx| grid.apply_fetched_sources(results, cx));
+        })
+        .detach();
+    }
+
+    /// Replace each source's region with what it fetched. Only cell text changes, so styles,
+    /// formats, and sizes stay; rows or columns the data no longer reaches are cleared.
+    fn apply_fetched_sources(&mut self, results: Vec<(usize, DataSource, Result<Rows, String>)>, cx: &mut Context<Self>) {
+        let mut refreshed = 0;
+        let mut errors = Vec::new();
+        for (index, source, rows) in results {
+            // A binding removed or replaced while fetching is left alone
+            let Some(old) = self.data_sources.get(index).filter(|binding| binding.source == source).map(|binding| binding.range) else {
+                continue;
+            };
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(e) => {
+                    eprintln!("Failed to fetch {}: {}", source.describe(), e);
+                    errors.push(format!("{}: {}", source.describe(), e));
+                    continue;
+                }
+            };
+            for row in old.rows() {
+                for col in old.cols() {
+                    self.cells[row][col].clear();
+                }
+            }
+            let origin = old.start;
+            let height = rows.len().clamp(1, GRID_ROWS - origin.row);
+            let width = rows.iter().map(Vec::len).max().unwrap_or(0).clamp(1, GRID_COLS - origin.col);
+            for (row, values) in (origin.row..GRID_ROWS).zip(rows) {
+                for (col, value) in (origin.col..GRID_COLS).zip(values) {
+                    self.cells[row][col] = value;
+                }
+            }
+            let range = CellRange::new(origin, CellPosition::new(origin.row + height - 1, origin.col + width - 1));
+            self.data_sources[index].range = range;
+            self.grow_to_fit(range);
+            refreshed += 1;
+        }
+
+        if refreshed > 0 {
+            self.file_state.mark_dirty();
+            self.cells_changed(cx);
+        }
+        match errors.first() {
+            Some(error) => self.show_footer_message(format!("Failed to fetch {}", error), cx),
+            None if refreshed == 1 => self.show_footer_message("Data source refreshed", cx),
+            None => self.show_footer_message(format!("{} data sources refreshed", refreshed), cx),
+        }
+        cx.notify();
+    }
+
+    /// Name shown on the tab: the file's, or for an unsaved pivot table, its source's
+    fn tab_title(&self, cx: &App) -> String {
+        match &self.pivot {
+            Some((source, _)) if self.file_state.current_path.is_none() => match source.upgrade() {
+                Some(source) => format!("Pivot of {}", source.read(cx).file_state.file_name()),
+                None => "Pivot".to_string(),
+            },
+            _ => self.file_state.file_name(),
+        }
+    }
+
+    // === Charts ===
+
+    fn insert_chart(&mut self, _: &InsertChart, _window: &mut Window, cx: &mut Context<Self>) {
+        self.add_chart(ChartKind::Line, cx);
+    }
+
+    /// Float a chart of the selection, or of the data around the cursor, over the grid
+    fn add_chart(&mut self, kind: ChartKind, cx: &mut Context<Self>) {
+        let selection = self.selection_range();
+        let range = if selection.is_single_cell() { data_block(&self.cells, self.selected) } else { selection };
+        if self.chart_data(range, kind).is_none() {
+            self.show_footer_message(format!("No numbers to chart in {}", range.to_reference()), cx);
+            return;
+        }
+        // Each new chart sits a little below and right of the last
+        let offset = 24.0 * (self.charts.len() % 8) as f32;
+        let origin = point(
+            px(ROW_HEADER_WIDTH + 40.0 + offset),
+            px(self.chrome_height() + COLUMN_HEADER_HEIGHT + 40.0 + offset),
+        );
+        self.charts.push(Chart { range, kind, origin });
+        cx.notify();
+    }
+
+    /// Move the chart whose title bar is held to follow the mouse
+    fn drag_chart(&mut self, event: &MouseMoveEvent, cx: &mut Context<Self>) {
+        if let Some((index, grab)) = self.chart_drag
+            && event.pressed_button == Some(MouseButton::Left)
+            && let Some(chart) = self.charts.get_mut(index)
+        {
+            chart.origin = event.position - grab;
+            cx.notify();
+        }
+    }
+
+    fn chart_data(&self, range: CellRange, kind: ChartKind) -> Option<ChartData> {
+        ChartData::from_range(range, kind, |row, col| self.value_text(row, col))
+    }
+
+    /// Save a chart's plot as a PNG image, to `path` or else where a save dialog says
+    fn export_chart(&mut self, index: usize, path: Option<PathBuf>, cx: &mut Context<Self>) {
+        let Some(chart) = self.charts.get(index).cloned() else {
+            self.show_footer_message("No chart to export; :chart makes one", cx);
+            return;
+        };
+        let Some(data) = self.chart_data(chart.range, chart.kind) else {
+            self.show_footer_message(format!("No numbers to chart in {}", chart.range.to_reference()), cx);
+            return;
+        };
+        let path = path.or_else(|| {
+            rfd::FileDialog::new()
+                .add_filter("PNG", &["png"])
+                .set_file_name("chart.png")
+                .save_file()
+        });
+        let Some(path) = path else {
+            return;
+        };
+        let theme = cx.global::<Theme>();
+        let palette = theme.series_colors();
+        match data.save_png(chart.kind, &path, CHART_EXPORT_SIZE, &palette, theme.surface1, theme.base) {
+            Ok(()) => self.show_footer_message(format!("Saved chart to {}", path.display()), cx),
+            Err(e) => {
+                eprintln!("Failed to export chart: {}", e);
+                self.show_footer_message(format!("Couldn't save {}", path.display()), cx);
+            }
+        }
+    }
+
+    // === Scripts ===
+
+    /// A copy of the grid for a script or plugin command to work on
+    fn script_grid(&self) -> ScriptGrid {
+        ScriptGrid {
+            cells: self.cells.clone(),
+            values: (0..GRID_ROWS).map(|row| (0..GRID_COLS).map(|col| self.value_text(row, col)).collect()).collect(),
+            selection: self.selection_range(),
+            cursor: self.selected,
+            used_bounds: self.used_bounds,
+            changed: BTreeSet::new(),
+            commands: Vec::new(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn run_script(&mut self, name: &str, window: &mut Window, cx: &mut Context<Self>) {
+        match script::run(name, self.script_grid()) {
+            Ok(result) => self.apply_script_result(name, result, window, cx),
+            Err(e) => {
+                eprintln!("Script {} failed: {}", name, e);
+                self.show_footer_message(format!("Script {} failed: {}", name, e), cx);
+            }
+        }
+    }
+
+    /// Take a script's edits (except to protected cells) and run the commands it queued
+    fn apply_script_result(&mut self, name: &str, result: ScriptGrid, window: &mut Window, cx: &mut Context<Self>) {
+        if !result.changed.is_empty() && self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; the script's edits were dropped", cx);
+            return;
+        }
+        for &(row, col) in &result.changed {
+            if !self.is_protected(CellPosition::new(row, col)) {
+                self.cells[row][col] = result.cells[row][col].clone();
+                self.grow_to_fit(CellRange::new(CellPosition::new(row, col), CellPosition::new(row, col)));
+            }
+        }
+        if !result.changed.is_empty() {
+            self.file_state.mark_dirty();
+            self.cells_changed(cx);
+        }
+        for command in &result.commands {
+            let input = format!(":{}", command.trim_start_matches(':'));
+            match VimCommand::parse(&input) {
+                // Scripts don't start other scripts or plugin commands
+                Some(VimCommand::RunScript(_) | VimCommand::Plugin(_)) | None => {
+                    eprintln!("Script {}: can't run {}", name, input)
+                }
+                Some(command) => self.handle_command("", Some(command), window, cx),
+            }
+        }
+        if let Some(message) = result.messages.last() {
+            self.show_footer_message(message.clone(), cx);
+        }
+        cx.notify();
+    }
+
+    /// Run a command a plugin registered
+    fn run_plugin_command(&mut self, id: &str, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(action) = Registry::get().action(id) else {
+            return;
+        };
+        let name = Registry::get().commands().iter().find(|command| command.id == id).map_or(id, |command| command.name);
+        match action {
+            PluginAction::Ex(line) => match VimCommand::parse(&format!(":{}", line.trim_start_matches(':'))) {
+                Some(VimCommand::Plugin(_)) | None => eprintln!("Plugin command {}: can't run :{}", name, line),
+                Some(command) => self.handle_command("", Some(command), window, cx),
+            },
+            PluginAction::Native(run) => {
+                let mut grid = self.script_grid();
+                match run(&mut grid) {
+                    Ok(()) => self.apply_script_result(name, grid, window, cx),
+                    Err(e) => {
+                        eprintln!("Plugin command {} failed: {}", name, e);
+                        self.show_footer_message(format!("{} failed: {}", name, e), cx);
+                    }
+                }
+            }
+        }
+    }
+
+    fn list_scripts(&mut self, cx: &mut Context<Self>) {
+        let names = script::script_names();
+        let message = if names.is_empty() {
+            match script::scripts_dir() {
+                Some(dir) => format!("No scripts in {}", dir.display()),
+                None => "No scripts".to_string(),
+            }
+        } else {
+            format!("Scripts: {}", names.join(", "))
+        };
+        self.show_footer_message(message, cx);
+    }
+
+    // === Filtering ===
+
+    fn clear_filter_state(&mut self) {
+        self.filters.clear();
+        self.filter_expr = None;
+        self.filtered_out = vec![false; GRID_ROWS];
+        self.filter_menu = None;
+    }
+
+    /// Recompute which data rows the filters hide. Edits don't re-filter on their own;
+    /// rows are re-evaluated whenever the filters, header row, or row order change.
+    fn apply_filters(&mut self) {
+        self.filtered_out = vec![false; GRID_ROWS];
+        if !self.is_filtering() {
+            return;
+        }
+        for row in self.data_rows() {
+            let visible = self.filters
+                .iter()
+                .all(|(&col, condition)| condition.matches(&self.cells[row][col]))
+                && self.filter_expr.as_ref().is_none_or(|expr| expr.matches(&self.cells[row], &|col| self.column_type(col)));
+            self.filtered_out[row] = !visible;
+        }
+    }
+
+    fn is_filtering(&self) -> bool {
+        !self.filters.is_empty() || self.filter_expr.is_some()
+    }
+
+    /// (rows passing the filters, total data rows)
+    fn filter_match_count(&self) -> (usize, usize) {
+        let rows = self.data_rows();
+        let total = rows.clone().count();
+        let hidden = rows.filter(|&row| self.filtered_out[row]).count();
+        (total - hidden, total)
+    }
+
+    /// Filter rows by an expression like `C > 100 && A contains "foo"`
+    fn set_filter_expression(&mut self, input: &str, cx: &mut Context<Self>) {
+        match FilterExpr::parse(input, |name| self.find_column(name)) {
+            Ok(expr) => {
+                self.filter_expr = Some(expr);
+                self.on_filters_changed(cx);
+            }
+            Err(e) => eprintln!("Invalid filter: {}", e),
+        }
+    }
+
+    fn on_filters_changed(&mut self, cx: &mut Context<Self>) {
+        self.apply_filters();
+        let row = self.selected.row;
+        if self.is_row_hidden(row) {
+            let above = self.skip_hidden_rows(row, -1, row);
+            self.selected.row = self.skip_hidden_rows(row, 1, above);
+        }
+        self.clamp_scroll_position();
+        self.ensure_visible();
+        cx.notify();
+    }
+
+    /// Set or clear (`None`) the filter on a column
+    fn set_column_filter(&mut self, col: usize, condition: Option<FilterCondition>, cx: &mut Context<Self>) {
+        match condition {
+            Some(condition) => self.filters.insert(col, condition),
+            None => self.filters.remove(&col),
+        };
+        self.on_filters_changed(cx);
+    }
+
+    fn clear_filters(&mut self, cx: &mut Context<Self>) {
+        self.filters.clear();
+        self.filter_expr = None;
+        self.filter_menu = None;
+        self.on_filters_changed(cx);
+    }
+
+    /// A cell's value as text: a formula's result, or else what was typed
+    fn value_text(&self, row: usize, col: usize) -> String {
+        let content = &self.cells[row][col];
+        match self.computed[row][col].as_ref().filter(|_| formula::is_formula(content)) {
+            Some(value) => value.to_string(),
+            None => content.clone(),
+        }
+    }
+
+    /// Profile of a column's data rows, shown when hovering its header and by `:stats`
+    fn column_stats(&self, col: usize) -> ColumnStats {
+        ColumnStats::from_values(self.data_rows().map(|row| self.value_text(row, col)))
+    }
+
+    /// Show the cursor column's statistics in the footer
+    fn show_column_stats(&mut self, cx: &mut Context<Self>) {
+        let col = self.selected.col;
+        let message = format!("{}: {}", CellPosition::col_to_letter(col), self.column_stats(col).lines().join(", "));
+        self.show_footer_message(message, cx);
+    }
+
+    /// Distinct trimmed values in a column's data rows, in sort order ("" for blanks, last)
+    fn filter_values(&self, col: usize) -> Vec<String> {
+        let mut values: Vec<String> = self.data_rows()
+            .map(|row| self.cells[row][col].trim().to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        values.sort_by(|a, b| value::compare_for_sort(a, b, false));
+        values
+    }
+
+    fn is_filter_value_selected(&self, col: usize, value: &str) -> bool {
+        self.filters.get(&col).is_none_or(|condition| condition.matches(value))
+    }
+
+    /// Check or uncheck a value in a column's filter dropdown
+    fn toggle_filter_value(&mut self, col: usize, value: &str, cx: &mut Context<Self>) {
+        let values = self.filter_values(col);
+        let mut selected: BTreeSet<String> = values
+            .iter()
+            .filter(|v| self.is_filter_value_selected(col, v))
+            .cloned()
+            .collect();
+        if !selected.remove(value) {
+            selected.insert(value.to_string());
+        }
+        let condition = (selected.len() < values.len()).then_some(FilterCondition::Values(selected));
+        self.set_column_filter(col, condition, cx);
+    }
+
+    fn toggle_filter_menu(&mut self, col: usize, cx: &mut Context<Self>) {
+        self.filter_menu = if self.filter_menu == Some(col) { None } else { Some(col) };
+        cx.notify();
+    }
+
+    // === Outline groups ===
+
+    fn group_rows(&mut self, start: usize, end: usize, cx: &mut Context<Self>) {
+        if start.max(end) >= GRID_ROWS || !self.row_outline.add(start, end) {
+            eprintln!("Invalid row group: a group needs at least two rows inside the grid");
+            return;
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    fn group_cols(&mut self, start: usize, end: usize, cx: &mut Context<Self>) {
+        if start.max(end) >= GRID_COLS || !self.col_outline.add(start, end) {
+            eprintln!("Invalid column group: a group needs at least two columns inside the grid");
+            return;
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    fn ungroup_rows(&mut self, cx: &mut Context<Self>) {
+        if self.row_outline.remove_at(self.selected.row).is_some() {
+            self.on_outline_changed(cx);
+        }
+    }
+
+    fn ungroup_cols(&mut self, cx: &mut Context<Self>) {
+        if self.col_outline.remove_at(self.selected.col).is_some() {
+            self.on_outline_changed(cx);
+        }
+    }
+
+    fn toggle_row_group(&mut self, row: usize, cx: &mut Context<Self>) {
+        if self.row_outline.toggle_at(row).is_some() {
+            self.on_outline_changed(cx);
+        }
+    }
+
+    fn toggle_col_group(&mut self, col: usize, cx: &mut Context<Self>) {
+        if self.col_outline.toggle_at(col).is_some() {
+            self.on_outline_changed(cx);
+        }
+    }
+
+    /// Set the collapsed state of the row group at the cursor, falling back to the column group
+    fn set_group_collapsed_at_cursor(&mut self, collapsed: bool, cx: &mut Context<Self>) {
+        let changed = self.row_outline.set_collapsed_at(self.selected.row, collapsed).is_some()
+            || self.col_outline.set_collapsed_at(self.selected.col, collapsed).is_some();
+        if changed {
+            self.on_outline_changed(cx);
+        }
+    }
+
+    fn toggle_group(&mut self, _: &ToggleGroup, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.row_outline.group_at(self.selected.row).is_some() {
+            self.toggle_row_group(self.selected.row, cx);
+        } else {
+            self.toggle_col_group(self.selected.col, cx);
+        }
+    }
+
+    fn open_group(&mut self, _: &OpenGroup, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_group_collapsed_at_cursor(false, cx);
+    }
+
+    fn close_group(&mut self, _: &CloseGroup, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_group_collapsed_at_cursor(true, cx);
+    }
+
+    fn expand_all_groups(&mut self, _: &ExpandAllGroups, _window: &mut Window, cx: &mut Context<Self>) {
+        self.row_outline.set_all_collapsed(false);
+        self.col_outline.set_all_collapsed(false);
+        self.on_outline_changed(cx);
+    }
+
+    fn collapse_all_groups(&mut self, _: &CollapseAllGroups, _window: &mut Window, cx: &mut Context<Self>) {
+        self.row_outline.set_all_collapsed(true);
+        self.col_outline.set_all_collapsed(true);
+        self.on_outline_changed(cx);
+    }
+
+    /// Keep the cursor and viewport on visible cells after groups change
+    fn on_outline_changed(&mut self, cx: &mut Context<Self>) {
+        if self.is_row_hidden(self.selected.row)
+            && let Some(group) = self.row_outline.group_at(self.selected.row)
+        {
+            self.selected.row = group.start;
+        }
+        if self.is_col_hidden(self.selected.col)
+            && let Some(group) = self.col_outline.group_at(self.selected.col)
+        {
+            self.selected.col = group.start;
+        }
+        self.clamp_scroll_position();
+        self.ensure_visible();
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // Command palette
+    fn show_command_palette(&mut self, _: &ShowCommandPalette, window: &mut Window, cx: &mut Context<Self>) {
+        // Exit edit mode if active
+        if self.mode == Mode::Edit {
+            self.save_and_exit_edit_mode(window, cx);
+        }
+
+        self.show_command_palette = true;
+        self.command_palette.update(cx, |palette, cx| {
+            palette.reset(cx);
+        });
+
+        let palette_focus = self.command_palette.focus_handle(cx);
+        palette_focus.focus(window, cx);
+        cx.notify();
+    }
+
+    /// Open the palette asking for a passphrase: the pending encrypted file's, or one to
+    /// encrypt this file with
+    fn ask_passphrase(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.show_command_palette(&ShowCommandPalette, window, cx);
+        self.command_palette.update(cx, |palette, cx| palette.start_prompt("encrypt_file", cx));
+    }
+
+    /// Open the palette asking which cell to jump to
+    fn go_to_cell(&mut self, _: &GoToCell, window: &mut Window, cx: &mut Context<Self>) {
+        self.show_command_palette(&ShowCommandPalette, window, cx);
+        self.command_palette.update(cx, |palette, cx| palette.start_prompt("goto", cx));
+    }
+
+    /// Start typing a cell or range into the header's reference label
+    fn edit_name_box(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.mode == Mode::Edit || self.show_command_palette {
+            return;
+        }
+        let reference = self.selection_range().to_reference();
+        self.name_box.update(cx, |input, cx| {
+            input.set_content(reference, cx);
+            input.selected_range = 0..input.content.len();
+        });
+        self.name_box.focus_handle(cx).focus(window, cx);
+        self.editing_name_box = true;
+        cx.notify();
+    }
+
+    /// Jump to the cell or select the range typed into the name box
+    fn confirm_name_box(&mut self, _: &ConfirmNameBox, window: &mut Window, cx: &mut Context<Self>) {
+        let text = self.name_box.read(cx).get_content();
+        match CellRange::from_reference(&text) {
+            Some(range) => {
+                let from = (self.scroll_pos_x(), self.scroll_pos_y());
+                self.select_range(range);
+                self.ensure_visible();
+                self.animate_scroll_from(from, cx);
+            }
+            None => self.show_footer_message(format!("No cell or range named {}", text.trim()), cx),
+        }
+        self.close_name_box(window, cx);
+    }
+
+    fn cancel_name_box(&mut self, _: &CancelNameBox, window: &mut Window, cx: &mut Context<Self>) {
+        self.close_name_box(window, cx);
+    }
+
+    fn close_name_box(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.editing_name_box = false;
+        self.name_box.update(cx, |input, _cx| input.stop_cursor_blink());
+        self.focus_handle.focus(window, cx);
+        cx.notify();
+    }
+
+    /// `@:` - run the last palette command again
+    fn repeat_last_command(&mut self, _: &RepeatLastCommand, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some((cmd_id, vim_cmd)) = self.command_palette.read(cx).last_command() {
+            self.handle_command(cmd_id, vim_cmd, window, cx);
+        }
+    }
+
+    /// `q:` - open the palette on the command history
+    fn show_command_history(&mut self, _: &ShowCommandHistory, window: &mut Window, cx: &mut Context<Self>) {
+        self.show_command_palette(&ShowCommandPalette, window, cx);
+        self.command_palette.update(cx, |palette, cx| palette.show_history(cx));
+    }
+
+    fn hide_command_palette(&mut self, _: &HideCommandPalette, window: &mut Window, cx: &mut Context<Self>) {
+        self.show_command_palette = false;
+        self.pending_count = None;
+        // Dismissing the passphrase prompt gives up on opening the file
+        self.pending_decrypt = None;
+        self.focus_handle.focus(window, cx);
+        cx.notify();
+    }
+
+    fn handle_command(&mut self, cmd_id: &str, vim_cmd: Option<VimCommand>, window: &mut Window, cx: &mut Context<Self>) {
+        // Hide palette first
+        self.show_command_palette = false;
+        self.focus_handle.focus(window, cx);
+        // A count typed before `:`, e.g. the `3` of `3 :insrow`
+        let count = self.pending_count.take();
+
+        // Handle vim commands
+        if let Some(vim_cmd) = vim_cmd {
+            match vim_cmd {
+                VimCommand::Write => self.save_file(&SaveFile, window, cx),
+                VimCommand::WriteTo(path) => {
+                    self.save_to_path(&path, cx);
+                    self.file_state.set_path(path);
+                }
+                VimCommand::WritePipe(command) => self.write_to_command(&command, cx),
+                VimCommand::ForceWrite => self.force_write(&ForceWrite, window, cx),
+                VimCommand::WriteQuit => {
+                    self.save_file(&SaveFile, window, cx);
+                    cx.quit();
+                }
+                VimCommand::Quit => self.close_file(&CloseFile, window, cx),
+                VimCommand::ForceQuit => cx.quit(),
+                VimCommand::Edit(path) => self.load_file(path, false, window, cx),
+                VimCommand::View(path) => self.load_file(path, true, window, cx),
+                VimCommand::Mmap(path) => self.open_viewer(path, cx),
+                VimCommand::Passphrase(passphrase) => match self.pending_decrypt.take() {
+                    Some((path, read_only)) => self.open_encrypted(path, read_only, passphrase, window, cx),
+                    None => {
+                        self.file_state.passphrase = Some(passphrase);
+                        self.file_state.mark_dirty();
+                        self.show_footer_message("Saving encrypts the file with the passphrase", cx);
+                    }
+                },
+                VimCommand::SaveAs(path) => {
+                    self.save_to_path(&path, cx);
+                    self.file_state.set_path(path);
+                }
+                VimCommand::New => self.new_file(&NewFile, window, cx),
+                VimCommand::TabNew(None) => cx.emit(TabEvent::New),
+                VimCommand::TabNew(Some(path)) => cx.emit(TabEvent::Open { path, read_only: false }),
+                VimCommand::NextTab => cx.emit(TabEvent::Next),
+                VimCommand::PreviousTab => cx.emit(TabEvent::Previous),
+                // Auto-fit commands
+                VimCommand::AutoFitAll => self.auto_fit_all(cx),
+                VimCommand::AutoFitColumn => self.auto_fit_column(self.selected.col, cx),
+                VimCommand::AutoFitRow => self.auto_fit_row(self.selected.row, cx),
+                VimCommand::AutoFitWatch => self.toggle_autofit_watch_all(cx),
+                VimCommand::AutoFitColumnWatch => self.toggle_autofit_watch_column(self.selected.col, cx),
+                VimCommand::AutoFitRowWatch => self.toggle_autofit_watch_row(self.selected.row, cx),
+                VimCommand::WidthLimits(limits) => self.set_width_limits(limits, cx),
+                VimCommand::ResetAllSizes => self.reset_all_sizes(cx),
+                VimCommand::Freeze { rows, cols } => self.set_frozen_panes(rows, cols, cx),
+                VimCommand::FreezeAtCursor => self.freeze_panes(&FreezePanes, window, cx),
+                VimCommand::Unfreeze => self.unfreeze_panes(&UnfreezePanes, window, cx),
+                VimCommand::GroupRows(start, end) => self.group_rows(start, end, cx),
+                VimCommand::ToggleAuditPanel => {
+                    self.show_audit = !self.show_audit;
+                    cx.notify();
+                }
+                VimCommand::ExportAudit(path) => self.export_audit(&path, cx),
+                VimCommand::TakeSnapshot(name) => self.take_snapshot(&name, cx),
+                VimCommand::BrowseSnapshots => {
+                    self.show_command_palette(&ShowCommandPalette, window, cx);
+                    self.command_palette.update(cx, |palette, cx| palette.start_prompt("browse_snapshots", cx));
+                }
+                VimCommand::PreviewSnapshot(label) => self.preview_snapshot(&label, cx),
+                VimCommand::RestoreSnapshot(label) => self.restore_snapshot(label.as_deref(), cx),
+                VimCommand::Clear { contents, formats } => self.clear_cells(contents, formats, cx),
+                VimCommand::InsertRows(n) => self.insert_rows_above(n.or(count), cx),
+                VimCommand::InsertCols(n) => self.insert_columns_left(n.or(count), cx),
+                VimCommand::DeleteRows(n) => self.delete_selected_rows(n.or(count), cx),
+                VimCommand::DeleteCols(n) => self.delete_selected_columns(n.or(count), cx),
+                VimCommand::MoveRows { start, end, to } => self.move_rows(start..=end, to, cx),
+                VimCommand::MoveCols { start, end, to } => self.move_columns(start..=end, to, cx),
+                VimCommand::GroupCols(start, end) => self.group_cols(start, end, cx),
+                VimCommand::UngroupRows => self.ungroup_rows(cx),
+                VimCommand::UngroupCols => self.ungroup_cols(cx),
+                VimCommand::ToggleHeaderRow => self.toggle_header_row(cx),
+                VimCommand::SetHeaderRow(row) => self.set_header_row(Some(row), cx),
+                VimCommand::ClearHeaderRow => self.set_header_row(None, cx),
+                VimCommand::Sort { column, descending } => {
+                    self.sort_by_named_column(column.as_deref(), descending, cx)
+                }
+                VimCommand::OpenFilterMenu => self.toggle_filter_menu(self.selected.col, cx),
+                VimCommand::Filter(condition) => self.set_column_filter(self.selected.col, Some(condition), cx),
+                VimCommand::FilterExpression(input) => self.set_filter_expression(&input, cx),
+                VimCommand::ToggleFilterButtons => {
+                    self.show_filter_buttons = !self.show_filter_buttons;
+                    cx.notify();
+                }
+                VimCommand::ClearFilters => self.clear_filters(cx),
+                VimCommand::ColumnStats => self.show_column_stats(cx),
+                VimCommand::ToggleProfile => {
+                    self.show_profile = !self.show_profile;
+                    cx.notify();
+                }
+                VimCommand::Pivot(request) => self.create_pivot(&request, cx),
+                VimCommand::RefreshPivot => self.refresh_pivot(cx),
+                VimCommand::BindSource(source) => self.bind_source(source, cx),
+                VimCommand::RefreshSources => self.refresh_sources(cx),
+                VimCommand::UnbindSource => self.unbind_source(cx),
+                VimCommand::InsertChart(kind) => self.add_chart(kind, cx),
+                VimCommand::ExportChart(path) => self.export_chart(self.charts.len().saturating_sub(1), path, cx),
+                VimCommand::CloseCharts => self.charts.clear(),
+                VimCommand::Tail(on) => self.set_tail(on, cx),
+                VimCommand::Share(port) => self.share(port, cx),
+                VimCommand::Join { address, code } => self.join(&address, &code, cx),
+                VimCommand::Unshare => self.unshare(cx),
+                VimCommand::Generate(generator) => self.fill_generated(generator, cx),
+                VimCommand::RunScript(Some(name)) => self.run_script(&name, window, cx),
+                VimCommand::RunScript(None) => self.list_scripts(cx),
+                VimCommand::Plugin(id) => self.run_plugin_command(id, window, cx),
+                VimCommand::Transpose(target) => self.transpose_selection(target, cx),
+                VimCommand::Goto(position) => self.goto(position, cx),
+                VimCommand::Ranged { range, command } => self.run_range_command(range, command, cx),
+                VimCommand::SetWrap(enabled) => self.set_wrap(enabled, cx),
+                VimCommand::Style(change) => self.apply_style(change, cx),
+                VimCommand::Format { format, column } => self.set_number_format(format, column, cx),
+                VimCommand::DateFormat(format) => self.set_date_format(format, cx),
+                VimCommand::Align(alignment) => self.set_alignment(alignment, cx),
+                VimCommand::SetColumnType(column_type) => self.set_column_type(column_type, cx),
+                VimCommand::ShowColumnType => self.show_column_type(cx),
+                VimCommand::Validate { spec, reject, column } => self.set_validation(&spec, reject, column, cx),
+                VimCommand::ClearValidation { column } => self.apply_validation(None, column, cx),
+                VimCommand::Set(Setting::Calculation(mode)) => self.set_calc_mode(mode, cx),
+                VimCommand::Set(Setting::Refresh(interval)) => self.set_refresh_interval(interval, cx),
+                VimCommand::Set(Setting::Toolbar(show)) => self.set_toolbar(show, cx),
+                VimCommand::Set(Setting::AutoFitMax(width)) => self.set_autofit_max_width(width, cx),
+                VimCommand::Set(Setting::DefaultColWidth(width)) => self.set_default_col_width(width, cx),
+                VimCommand::Set(Setting::DefaultRowHeight(height)) => self.set_default_row_height(height, cx),
+                VimCommand::Set(Setting::EmbedMetadata(embed)) => self.set_embed_metadata(embed, cx),
+                VimCommand::Set(Setting::Protection(enabled)) => self.set_protection(enabled, cx),
+                VimCommand::Set(Setting::RememberGeometry(remember)) => self.set_remember_geometry(remember, cx),
+                VimCommand::Set(Setting::ExpandSelected(expand)) => self.set_expand_selected(expand, cx),
+                VimCommand::Set(Setting::MemoryStats(show)) => self.show_memory_stats = show,
+                VimCommand::Set(Setting::Perf(show)) => {
+                    self.perf = show.then(Rc::default);
+                    cx.notify();
+                }
+                VimCommand::Set(Setting::Gridlines(show)) => self.set_gridlines(show, cx),
+                VimCommand::Set(Setting::Zebra(zebra)) => self.set_zebra_stripes(zebra, cx),
+                VimCommand::Set(Setting::CursorBlink(blink)) => self.set_cursor_blink(blink, cx),
+                VimCommand::Set(Setting::Crosshair(crosshair)) => self.set_crosshair(crosshair, cx),
+                VimCommand::Set(Setting::ScrollSpeed(speed)) => self.scroll_speed = speed,
+                VimCommand::Set(Setting::Quoting(quoting)) => {
+                    self.change_write_options(|options| options.quoting = quoting, cx)
+                }
+                VimCommand::Set(Setting::LineEnding(line_ending)) => {
+                    self.change_write_options(|options| options.line_ending = line_ending, cx)
+                }
+                VimCommand::Set(Setting::FinalNewline(final_newline)) => {
+                    self.change_write_options(|options| options.final_newline = final_newline, cx)
+                }
+                VimCommand::Set(Setting::Encrypt(true)) => self.ask_passphrase(window, cx),
+                VimCommand::Set(Setting::Encrypt(false)) => self.remove_encryption(cx),
+                VimCommand::Set(Setting::Audit(on)) => self.set_audit(on, cx),
+                VimCommand::Set(Setting::AppendOnly(append_only)) => {
+                    self.file_state.append_only = append_only;
+                    self.file_state.mark_dirty();
+                    self.show_footer_message(match append_only {
+                        true => "Saving appends new rows only",
+                        false => "Saving rewrites the whole file",
+                    }, cx);
+                }
+                VimCommand::Set(Setting::PadRows(pad_rows)) => {
+                    self.change_write_options(|options| options.pad_rows = pad_rows, cx)
+                }
+                VimCommand::Set(Setting::Autocomplete(autocomplete)) => self.autocomplete = autocomplete,
+                VimCommand::DiffSaved => self.toggle_diff_saved(cx),
+                VimCommand::DiffOff => self.diff_saved = None,
+                VimCommand::Revert => self.revert_selection(cx),
+                VimCommand::Compare { path, key } => self.compare_with(path, key.as_deref(), cx),
+                VimCommand::NoCompare => self.comparison = None,
+                VimCommand::Protect(range) => self.protect(range, cx),
+                VimCommand::Unprotect(range) => self.unprotect(range, cx),
+                VimCommand::Recalculate => self.recalculate(&Recalculate, window, cx),
+                VimCommand::TextToColumns { delimiter, overwrite } => {
+                    self.text_to_columns(&delimiter, overwrite, cx)
+                }
+            }
+            cx.notify();
+            return;
+        }
+
+        // Handle regular commands
+        match cmd_id {
+            "new_file" => self.new_file(&NewFile, window, cx),
+            "open_file" => self.open_file(&OpenFile, window, cx),
+            "save_file" => self.save_file(&SaveFile, window, cx),
+            "save_file_as" => self.save_file_as(&SaveFileAs, window, cx),
+            "force_write" => self.force_write(&ForceWrite, window, cx),
+            "close_file" => self.close_file(&CloseFile, window, cx),
+            "new_tab" => self.new_tab(&NewTab, window, cx),
+            "next_tab" => self.next_tab(&NextTab, window, cx),
+            "previous_tab" => self.previous_tab(&PreviousTab, window, cx),
+            "quit" => cx.quit(),
+            "toggle_read_only" => self.toggle_read_only(&ToggleReadOnly, window, cx),
+            // Auto-fit commands
+            "autofit_all" => self.auto_fit_all(cx),
+            "autofit_column" => self.auto_fit_column(self.selected.col, cx),
+            "autofit_row" => self.auto_fit_row(self.selected.row, cx),
+            "autofit_watch" => self.toggle_autofit_watch_all(cx),
+            "reset_sizes" => self.reset_all_sizes(cx),
+            "clear_width_limits" => self.set_width_limits(None, cx),
+            "freeze_panes" => self.freeze_panes(&FreezePanes, window, cx),
+            "unfreeze_panes" => self.unfreeze_panes(&UnfreezePanes, window, cx),
+            "toggle_group" => self.toggle_group(&ToggleGroup, window, cx),
+            "expand_all_groups" => self.expand_all_groups(&ExpandAllGroups, window, cx),
+            "collapse_all_groups" => self.collapse_all_groups(&CollapseAllGroups, window, cx),
+            "ungroup_rows" => self.ungroup_rows(cx),
+            "ungroup_cols" => self.ungroup_cols(cx),
+            "toggle_header_row" => self.toggle_header_row(cx),
+            "sort_ascending" => self.sort_by_column(self.selected.col, false, cx),
+            "sort_descending" => self.sort_by_column(self.selected.col, true, cx),
+            "filter_column" => self.toggle_filter_menu(self.selected.col, cx),
+            "insert_rows" => self.insert_rows_above(count, cx),
+            "insert_columns" => self.insert_columns_left(count, cx),
+            "delete_rows" => self.delete_selected_rows(count, cx),
+            "delete_columns" => self.delete_selected_columns(count, cx),
+            "toggle_filter_buttons" => {
+                self.show_filter_buttons = !self.show_filter_buttons;
+                cx.notify();
+            }
+            "clear_filters" => self.clear_filters(cx),
+            "column_stats" => self.show_column_stats(cx),
+            "toggle_profile" => {
+                self.show_profile = !self.show_profile;
+                cx.notify();
+            }
+            "audit_on" => self.set_audit(true, cx),
+            "audit_off" => self.set_audit(false, cx),
+            "toggle_audit_panel" => {
+                self.show_audit = !self.show_audit;
+                cx.notify();
+            }
+            "refresh_pivot" => self.refresh_pivot(cx),
+            "refresh_sources" => self.refresh_sources(cx),
+            "unbind_source" => self.unbind_source(cx),
+            "insert_chart" => self.add_chart(ChartKind::Line, cx),
+            "insert_bar_chart" => self.add_chart(ChartKind::Bar, cx),
+            "insert_scatter_chart" => self.add_chart(ChartKind::Scatter, cx),
+            "export_chart" => self.export_chart(self.charts.len().saturating_sub(1), None, cx),
+            "decrypt_file" => self.remove_encryption(cx),
+            "share" => self.share(None, cx),
+            "unshare" => self.unshare(cx),
+            "tail_file" => self.set_tail(true, cx),
+            "stop_tail" => self.set_tail(false, cx),
+            "close_charts" => {
+                self.charts.clear();
+                cx.notify();
+            }
+            "select_all" => self.select_all(&SelectAllCells, window, cx),
+            "select_data_block" => self.select_data_block(&SelectDataBlock, window, cx),
+            "transpose" => self.transpose_selection(None, cx),
+            "toggle_wrap" => self.set_wrap(!self.wrap_columns.contains(&self.selected.col), cx),
+            "text_to_columns" => self.text_to_columns(&Delimiter::Text(",".to_string()), false, cx),
+            "paste_transposed" => self.paste_transposed(&PasteTransposed, window, cx),
+            "toggle_bold" => self.apply_style(StyleChange::Bold, cx),
+            "toggle_italic" => self.apply_style(StyleChange::Italic, cx),
+            "toggle_underline" => self.apply_style(StyleChange::Underline, cx),
+            "clear_color" => self.apply_style(StyleChange::Color(None), cx),
+            "format_general" => self.format_general(&FormatGeneral, window, cx),
+            "format_decimal" => self.format_decimal(&FormatDecimal, window, cx),
+            "format_thousands" => self.format_thousands(&FormatThousands, window, cx),
+            "format_currency" => self.format_currency(&FormatCurrency, window, cx),
+            "format_percent" => self.format_percent(&FormatPercent, window, cx),
+            "format_scientific" => self.format_scientific(&FormatScientific, window, cx),
+            "date_format_iso" => self.set_date_format(DateFormat::parse("iso"), cx),
+            "date_format_us" => self.set_date_format(DateFormat::parse("us"), cx),
+            "date_format_eu" => self.set_date_format(DateFormat::parse("eu"), cx),
+            "date_format_long" => self.set_date_format(DateFormat::parse("long"), cx),
+            "align_left" => self.set_alignment(Some(Alignment::Left), cx),
+            "align_center" => self.set_alignment(Some(Alignment::Center), cx),
+            "align_right" => self.set_alignment(Some(Alignment::Right), cx),
+            "align_auto" => self.set_alignment(None, cx),
+            "type_integer" => self.set_column_type(Some(ColumnType::Integer), cx),
+            "type_float" => self.set_column_type(Some(ColumnType::Float), cx),
+            "type_date" => self.set_column_type(Some(ColumnType::Date), cx),
+            "type_boolean" => self.set_column_type(Some(ColumnType::Boolean), cx),
+            "type_text" => self.set_column_type(Some(ColumnType::Text), cx),
+            "type_auto" => self.set_column_type(None, cx),
+            "show_column_type" => self.show_column_type(cx),
+            "clear_validation" => self.apply_validation(None, false, cx),
+            "recalculate" => self.recalculate(&Recalculate, window, cx),
+            "calc_manual" => self.set_calc_mode(CalcMode::Manual, cx),
+            "calc_auto" => self.set_calc_mode(CalcMode::Automatic, cx),
+            "refresh_every_second" => self.set_refresh_interval(Some(Duration::from_secs(1)), cx),
+            "refresh_off" => self.set_refresh_interval(None, cx),
+            "diff_saved" => self.toggle_diff_saved(cx),
+            "close_comparison" => self.comparison = None,
+            "protect_selection" => self.protect(None, cx),
+            "unprotect_selection" => self.unprotect(None, cx),
+            "protection_on" => self.set_protection(true, cx),
+            "protection_off" => self.set_protection(false, cx),
+            "embed_metadata" => self.set_embed_metadata(true, cx),
+            "sidecar_metadata" => self.set_embed_metadata(false, cx),
+            "remember_geometry" => self.set_remember_geometry(true, cx),
+            "expand_selected_on" => self.set_expand_selected(true, cx),
+            "show_gridlines" => self.set_gridlines(true, cx),
+            "autocomplete_on" => self.autocomplete = true,
+            "autocomplete_off" => self.autocomplete = false,
+            "zebra_on" => self.set_zebra_stripes(true, cx),
+            "cursor_steady" => self.set_cursor_blink(CursorBlink::Steady, cx),
+            "cursor_fade" => self.set_cursor_blink(CursorBlink::Fade, cx),
+            "zebra_off" => self.set_zebra_stripes(false, cx),
+            "crosshair_on" => self.set_crosshair(Crosshair::Both, cx),
+            "crosshair_off" => self.set_crosshair(Crosshair::Off, cx),
+            "hide_gridlines" => self.set_gridlines(false, cx),
+            "expand_selected_off" => self.set_expand_selected(false, cx),
+            "forget_geometry" => self.set_remember_geometry(false, cx),
+            "show_toolbar" => self.set_toolbar(true, cx),
+            "hide_toolbar" => self.set_toolbar(false, cx),
+            id if id.starts_with("plugin.") => self.run_plugin_command(id, window, cx),
+            _ => {}
+        }
+        cx.notify();
+    }
+
+    fn ensure_visible(&mut self) {
+        self.finish_scroll_animation();
+        // Cells in frozen panes are always visible along that axis
+        if self.selected.row < self.frozen_rows {
+            // Nothing to do vertically
+        } else if self.selected.row < self.scroll_row
+            || (self.selected.row == self.scroll_row && self.scroll_offset_y > 0.0)
+        {
+            self.scroll_row = self.selected.row;
+            self.scroll_offset_y = 0.0;
+        } else {
+            // Check if cursor row is partially clipped at the bottom
+            let last_full_row = self.last_fully_visible_row();
+            if self.selected.row > last_full_row {
+                // Scroll down so cursor row is fully visible at the bottom
+                self.scroll_to_show_row_at_bottom(self.selected.row);
+            }
+        }
+
+        // Horizontal: cursor left of viewport or partially hidden at left
+        if self.selected.col < self.frozen_cols {
+            // Nothing to do horizontally
+        } else if self.selected.col < self.scroll_col
+            || (self.selected.col == self.scroll_col && self.scroll_offset_x > 0.0)
+        {
+            self.scroll_col = self.selected.col;
+            self.scroll_offset_x = 0.0;
+        } else {
+            // Check if cursor col is partially clipped at the right
+            let last_full_col = self.last_fully_visible_col();
+            if self.selected.col > last_full_col {
+                // Scroll right so cursor col is fully visible at the right
+                self.scroll_to_show_col_at_right(self.selected.col);
+            }
+        }
+    }
+
+    /// Find the last row index that is fully visible in the viewport
+    fn last_fully_visible_row(&self) -> usize {
+        let grid_height = self.scroll_area_height();
+        let mut total = 0.0;
+        for (i, row) in (self.scroll_row..GRID_ROWS).enumerate() {
+            let h = self.row_height(row);
+            let visible_h = if i == 0 { h - self.scroll_offset_y } else { h };
+            total += visible_h;
+            if total > grid_height {
+                // This row is partially clipped; the previous row is the last fully visible
+                return if row > self.scroll_row { row - 1 } else { self.scroll_row };
+            }
+        }
+        (GRID_ROWS - 1).min(self.scroll_row + self.visible_rows - 1)
+    }
+
+    /// Find the last column index that is fully visible in the viewport
+    fn last_fully_visible_col(&self) -> usize {
+        let grid_width = self.scroll_area_width();
+        let mut total = 0.0;
+        for (i, col) in (self.scroll_col..GRID_COLS).enumerate() {
+            let w = self.col_width(col);
+            let visible_w = if i == 0 { w - self.scroll_offset_x } else { w };
+            total += visible_w;
+            if total > grid_width {
+                return if col > self.scroll_col { col - 1 } else { self.scroll_col };
+            }
+        }
+        (GRID_COLS - 1).min(self.scroll_col + self.visible_cols - 1)
+    }
+
+    /// Scroll viewport by just enough pixels to fully reveal `target_row` at the bottom
+    fn scroll_to_show_row_at_bottom(&mut self, target_row: usize) {
+        // Compute how far the bottom edge of target_row extends past the viewport
+        let mut total = 0.0;
+        for (i, row) in (self.scroll_row..=target_row).enumerate() {
+            let h = self.row_height(row);
+            let visible_h = if i == 0 { h - self.scroll_offset_y } else { h };
+            total += visible_h;
+        }
+        let overflow = total - self.scroll_area_height();
+        if overflow > 0.0 {
+            self.apply_smooth_scroll(0.0, overflow);
+        }
+    }
+
+    /// Scroll viewport by just enough pixels to fully reveal `target_col` at the right
+    fn scroll_to_show_col_at_right(&mut self, target_col: usize) {
+        let mut total = 0.0;
+        for (i, col) in (self.scroll_col..=target_col).enumerate() {
+            let w = self.col_width(col);
+            let visible_w = if i == 0 { w - self.scroll_offset_x } else { w };
+            total += visible_w;
+        }
+        let overflow = total - self.scroll_area_width();
+        if overflow > 0.0 {
+            self.apply_smooth_scroll(overflow, 0.0);
+        }
+    }
+
+    // === Header selection ===
+
+    /// Select whole rows from `anchor` to `cursor`, with the cursor at the start of its row
+    fn select_rows(&mut self, cursor: usize, anchor: usize) {
+        self.extra_selections.clear();
+        self.selection_anchor = Some(CellPosition::new(anchor, GRID_COLS - 1));
+        self.selected = CellPosition::new(cursor, 0);
+    }
+
+    /// Select whole columns from `anchor` to `cursor`, with the cursor at the top of its column
+    fn select_cols(&mut self, cursor: usize, anchor: usize) {
+        self.extra_selections.clear();
+        self.selection_anchor = Some(CellPosition::new(GRID_ROWS - 1, anchor));
+        self.selected = CellPosition::new(0, cursor);
+    }
+
+    /// Rows of the selection when it spans whole rows
+    fn selected_rows(&self) -> Option<Range<usize>> {
+        let selection = self.selection_range();
+        (selection.start.col == 0 && selection.end.col == GRID_COLS - 1).then(|| selection.rows())
+    }
+
+    /// Columns of the selection when it spans whole columns
+    fn selected_columns(&self) -> Option<Range<usize>> {
+        let selection = self.selection_range();
+        (selection.start.row == 0 && selection.end.row == GRID_ROWS - 1).then(|| selection.cols())
+    }
+
+    /// Click on a column header away from its resize edge: select the column, or with shift
+    /// extend the selection to it
+    fn on_column_header_click(&mut self, col: usize, event: &MouseDownEvent, window: &mut Window, cx: &mut Context<Self>) {
+        let x = f32::from(event.position.x) - ROW_HEADER_WIDTH;
+        if self.column_resize_target(x).is_some() {
+            return;
+        }
+        if self.mode == Mode::Edit {
+            self.save_and_exit_edit_mode(window, cx);
+        }
+        // Grabbing a selected column's header drags the selected columns to a new position
+        if !event.modifiers.shift && self.selected_columns().is_some_and(|cols| cols.contains(&col)) {
+            self.header_drag = Some(HeaderDrag { kind: SelectionDrag::Columns, grabbed: col, over: col });
+            cx.notify();
+            return;
+        }
+        let anchor = if !event.modifiers.shift {
+            col
+        } else if self.selected_columns().is_some() {
+            self.selection_anchor.map_or(col, |anchor| anchor.col)
+        } else {
+            self.selected.col
+        };
+        self.select_cols(col, anchor);
+        self.selection_drag = Some(SelectionDrag::Columns);
+        cx.notify();
+    }
+
+    /// Click on a row header away from its resize edge: select the row, or with shift
+    /// extend the selection to it
+    fn on_row_header_click(&mut self, row: usize, event: &MouseDownEvent, window: &mut Window, cx: &mut Context<Self>) {
+        let y = f32::from(event.position.y) - COLUMN_HEADER_HEIGHT - self.chrome_height();
+        if self.row_resize_target(y).is_some() {
+            return;
+        }
+        if self.mode == Mode::Edit {
+            self.save_and_exit_edit_mode(window, cx);
+        }
+        if !event.modifiers.shift && self.selected_rows().is_some_and(|rows| rows.contains(&row)) {
+            self.header_drag = Some(HeaderDrag { kind: SelectionDrag::Rows, grabbed: row, over: row });
+            cx.notify();
+            return;
+        }
+        let anchor = if !event.modifiers.shift {
+            row
+        } else if self.selected_rows().is_some() {
+            self.selection_anchor.map_or(row, |anchor| anchor.row)
+        } else {
+            self.selected.row
+        };
+        self.select_rows(row, anchor);
+        self.selection_drag = Some(SelectionDrag::Rows);
+        cx.notify();
+    }
+
+    /// Dragging from a header click across other headers extends the row or column selection,
+    /// or with selected headers grabbed, picks where they'll be dropped
+    fn on_selection_drag(&mut self, drag: SelectionDrag, index: usize, cx: &mut Context<Self>) {
+        if let Some(header_drag) = self.header_drag.as_mut().filter(|header_drag| header_drag.kind == drag) {
+            if header_drag.over != index {
+                header_drag.over = index;
+                cx.notify();
+            }
+            return;
+        }
+        let Some(anchor) = self.selection_anchor.filter(|_| self.selection_drag == Some(drag)) else {
+            return;
+        };
+        match drag {
+            SelectionDrag::Rows if index != self.selected.row => self.select_rows(index, anchor.row),
+            SelectionDrag::Columns if index != self.selected.col => self.select_cols(index, anchor.col),
+            _ => return,
+        }
+        cx.notify();
+    }
+
+    /// Release grabbed headers: move the selected rows or columns so the grabbed one lands where
+    /// it was dropped. Released without moving, it selects just the grabbed row or column.
+    fn drop_header_drag(&mut self, cx: &mut Context<Self>) {
+        let Some(drag) = self.header_drag.take() else {
+            return;
+        };
+        match drag.kind {
+            SelectionDrag::Rows if drag.over == drag.grabbed => self.select_rows(drag.grabbed, drag.grabbed),
+            SelectionDrag::Rows => {
+                if let Some(rows) = self.selected_rows() {
+                    let to = (rows.start + drag.over).saturating_sub(drag.grabbed);
+                    self.move_rows(rows.start..=rows.end - 1, to, cx);
+                }
+            }
+            _ if drag.over == drag.grabbed => self.select_cols(drag.grabbed, drag.grabbed),
+            _ => {
+                if let Some(cols) = self.selected_columns() {
+                    let to = (cols.start + drag.over).saturating_sub(drag.grabbed);
+                    self.move_columns(cols.start..=cols.end - 1, to, cx);
+                }
+            }
+        }
+        cx.notify();
+    }
+
+    /// Delete the rows the selection spans, or with a count, that many from its top
+    fn delete_selected_rows(&mut self, count: Option<usize>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let rows = self.selection_range().rows();
+        self.auto_snapshot("before delete");
+        self.delete_rows(rows.start, count.unwrap_or(rows.len()));
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// Delete the columns the selection spans, or with a count, that many from its left
+    fn delete_selected_columns(&mut self, count: Option<usize>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let cols = self.selection_range().cols();
+        self.auto_snapshot("before delete");
+        self.delete_columns(cols.start, count.unwrap_or(cols.len()));
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// Insert empty rows above the selection: `count` of them, or as many as it spans
+    fn insert_rows_above(&mut self, count: Option<usize>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let rows = self.selection_range().rows();
+        self.insert_rows(rows.start, count.unwrap_or(rows.len()));
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    /// Insert empty columns left of the selection: `count` of them, or as many as it spans
+    fn insert_columns_left(&mut self, count: Option<usize>, cx: &mut Context<Self>) {
+        if self.file_state.is_read_only {
+            self.show_footer_message("File is read-only; toggle read-only off to edit", cx);
+            return;
+        }
+        let cols = self.selection_range().cols();
+        self.insert_columns(cols.start, count.unwrap_or(cols.len()));
+        self.cells_changed(cx);
+        self.on_filters_changed(cx);
+    }
+
+    // === Context menu ===
+
+    /// Open the right-click menu, first selecting what was clicked unless it's already selected
+    fn open_context_menu(&mut self, target: MenuTarget, row: usize, col: usize, position: Point<Pixels>, cx: &mut Context<Self>) {
+        if self.mode == Mode::Edit {
+            return;
+        }
+        match target {
+            MenuTarget::Cell => {
+                if !self.selection_ranges().any(|range| range.contains(CellPosition::new(row, col))) {
+                    self.selected = CellPosition::new(row, col);
+                    self.selection_anchor = None;
+                    self.extra_selections.clear();
+                }
+            }
+            MenuTarget::Rows => {
+                if !self.selected_rows().is_some_and(|rows| rows.contains(&row)) {
+                    self.select_rows(row, row);
+                }
+            }
+            MenuTarget::Columns => {
+                if !self.selected_columns().is_some_and(|cols| cols.contains(&col)) {
+                    self.select_cols(col, col);
+                }
+            }
+        }
+        self.filter_menu = None;
+        self.context_menu = Some(ContextMenu { target, position });
+        cx.notify();
+    }
+
+    fn run_context_action(&mut self, action: ContextAction, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(ContextMenu { target, .. }) = self.context_menu.take() else {
+            return;
+        };
+        if action.edits() && self.file_state.is_read_only {
+            cx.notify();
+            return;
+        }
+        let range = self.selection_range();
+        let (rows, cols) = (range.rows(), range.cols());
+        match action {
+            ContextAction::Cut => self.cut_selection(&Cut, window, cx),
+            ContextAction::Copy => self.copy_selection(&Copy, window, cx),
+            ContextAction::Paste => self.paste_at_cursor(&Paste, window, cx),
+            ContextAction::ClearContents => self.clear_contents(&ClearContents, window, cx),
+            ContextAction::ClearFormats => self.clear_formats(&ClearFormats, window, cx),
+            ContextAction::ClearAll => self.clear_all(&ClearAll, window, cx),
+            ContextAction::InsertBefore | ContextAction::InsertAfter => {
+                let after = action == ContextAction::InsertAfter;
+                match target {
+                    MenuTarget::Columns => {
+                        self.insert_columns(if after { cols.end } else { cols.start }, cols.len());
+                    }
+                    _ => self.insert_rows(if after { rows.end } else { rows.start }, rows.len()),
+                }
+                self.cells_changed(cx);
+                self.on_filters_changed(cx);
+            }
+            ContextAction::DeleteRows => self.delete_selected_rows(None, cx),
+            ContextAction::DeleteColumns => self.delete_selected_columns(None, cx),
+            ContextAction::AutoFit => match target {
+                MenuTarget::Columns => cols.for_each(|col| self.auto_fit_column(col, cx)),
+                _ => rows.for_each(|row| self.auto_fit_row(row, cx)),
+            },
+            ContextAction::ResetSize => {
+                match target {
+                    MenuTarget::Columns => {
+                        for col in cols.clone() {
+                            self.column_widths[col] = self.limit_width(col, self.default_col_width);
+                        }
+                        cols.for_each(|col| self.fit_rows_to_wrapped_column(col));
+                    }
+                    _ => self.row_heights[rows].fill(self.default_row_height),
+                }
+                self.file_state.mark_dirty();
+                cx.notify();
+            }
+            ContextAction::Hide => self.hide_selection(target, cx),
+            ContextAction::Unhide => self.unhide_selection(target, cx),
+            ContextAction::SortAscending => self.sort_by_column(self.selected.col, false, cx),
+            ContextAction::SortDescending => self.sort_by_column(self.selected.col, true, cx),
+            ContextAction::Filter => self.toggle_filter_menu(self.selected.col, cx),
+            ContextAction::Bold => self.apply_style(StyleChange::Bold, cx),
+            ContextAction::Italic => self.apply_style(StyleChange::Italic, cx),
+            ContextAction::Underline => self.apply_style(StyleChange::Underline, cx),
+            ContextAction::FormatGeneral => self.set_number_format(None, target == MenuTarget::Columns, cx),
+            ContextAction::FormatCurrency => {
+                self.set_number_format(NumberFormat::parse("$#,##0.00"), target == MenuTarget::Columns, cx);
+            }
+            ContextAction::FormatPercent => {
+                self.set_number_format(NumberFormat::parse("0%"), target == MenuTarget::Columns, cx);
+            }
+        }
+    }
+
+    // === Hidden rows/columns ===
+
+    fn is_row_hidden(&self, row: usize) -> bool {
+        self.row_outline.is_hidden(row) || self.filtered_out[row] || self.hidden_rows.contains(&row)
+    }
+
+    fn is_col_hidden(&self, col: usize) -> bool {
+        self.col_outline.is_hidden(col) || self.hidden_cols.contains(&col)
+    }
+
+    /// Hide the selected rows or columns, moving the cursor off them
+    fn hide_selection(&mut self, target: MenuTarget, cx: &mut Context<Self>) {
+        let range = self.selection_range();
+        match target {
+            MenuTarget::Columns => self.hidden_cols.extend(range.cols()),
+            _ => self.hidden_rows.extend(range.rows()),
+        }
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.on_hidden_changed(cx);
+    }
+
+    /// Show hidden rows or columns within the selection and any hidden runs next to it
+    fn unhide_selection(&mut self, target: MenuTarget, cx: &mut Context<Self>) {
+        let range = self.selection_range();
+        let (hidden, indices) = match target {
+            MenuTarget::Columns => (&mut self.hidden_cols, range.cols()),
+            _ => (&mut self.hidden_rows, range.rows()),
+        };
+        let mut start = indices.start;
+        while start > 0 && hidden.contains(&(start - 1)) {
+            start -= 1;
+        }
+        let mut end = indices.end;
+        while hidden.contains(&end) {
+            end += 1;
+        }
+        hidden.retain(|index| !(start..end).contains(index));
+        self.on_hidden_changed(cx);
+    }
+
+    fn on_hidden_changed(&mut self, cx: &mut Context<Self>) {
+        let CellPosition { row, col } = self.selected;
+        if self.is_row_hidden(row) {
+            let above = self.skip_hidden_rows(row, -1, row);
+            self.selected.row = self.skip_hidden_rows(row, 1, above);
+        }
+        if self.is_col_hidden(col) {
+            let left = self.skip_hidden_cols(col, -1, col);
+            self.selected.col = self.skip_hidden_cols(col, 1, left);
+        }
+        self.clamp_scroll_position();
+        self.ensure_visible();
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Displayed height of a row (zero when hidden)
+    fn row_height(&self, row: usize) -> f32 {
+        if self.is_row_hidden(row) { 0.0 } else { self.row_heights[row] }
+    }
+
+    /// Displayed width of a column (zero when hidden)
+    fn col_width(&self, col: usize) -> f32 {
+        if self.is_col_hidden(col) { 0.0 } else { self.column_widths[col] }
+    }
+
+    /// Total displayed height of a range of rows
+    fn rows_height(&self, rows: Range<usize>) -> f32 {
+        rows.map(|row| self.row_height(row)).sum()
+    }
+
+    /// Total displayed width of a range of columns
+    fn cols_width(&self, cols: Range<usize>) -> f32 {
+        cols.map(|col| self.col_width(col)).sum()
+    }
+
+    /// Step from `row` in `direction` until reaching a visible row; stays put if there is none
+    fn skip_hidden_rows(&self, row: usize, direction: isize, fallback: usize) -> usize {
+        let mut row = row as isize;
+        while row >= 0 && (row as usize) < GRID_ROWS && self.is_row_hidden(row as usize) {
+            if direction == 0 {
+                return fallback;
+            }
+            row += direction;
+        }
+        if row < 0 || row as usize >= GRID_ROWS { fallback } else { row as usize }
+    }
+
+    /// Step from `col` in `direction` until reaching a visible column; stays put if there is none
+    fn skip_hidden_cols(&self, col: usize, direction: isize, fallback: usize) -> usize {
+        let mut col = col as isize;
+        while col >= 0 && (col as usize) < GRID_COLS && self.is_col_hidden(col as usize) {
+            if direction == 0 {
+                return fallback;
+            }
+            col += direction;
+        }
+        if col < 0 || col as usize >= GRID_COLS { fallback } else { col as usize }
+    }
+
+    /// Total height of the frozen rows
+    fn frozen_height(&self) -> f32 {
+        self.rows_height(0..self.frozen_rows)
+    }
+
+    /// Total width of the frozen columns
+    fn frozen_width(&self) -> f32 {
+        self.cols_width(0..self.frozen_cols)
+    }
+
+    /// The header row, when it has scrolled above the viewport and is pinned under the frozen panes
+    fn pinned_header_row(&self) -> Option<usize> {
+        self.header_row
+            .filter(|&row| row >= self.frozen_rows && row < self.scroll_row && !self.is_row_hidden(row))
+    }
+
+    /// Height of everything above the scrolling rows: frozen rows plus a pinned header row
+    fn top_pane_height(&self) -> f32 {
+        self.frozen_height() + self.pinned_header_row().map_or(0.0, |row| self.row_height(row))
+    }
+
+    /// Height of the scrolling part of the grid, below any frozen or pinned rows
+    fn scroll_area_height(&self) -> f32 {
+        (self.grid_height - self.top_pane_height()).max(0.0)
+    }
+
+    /// Width of the scrolling part of the grid, right of any frozen columns
+    fn scroll_area_width(&self) -> f32 {
+        (self.grid_width - self.frozen_width()).max(0.0)
+    }
+
+    /// Calculate number of visible rows from scroll position that fit in given height
+    fn calculate_visible_rows(&self, available_height: f32) -> usize {
+        let mut total_height = 0.0;
+        let mut count = 0;
+        for row in self.scroll_row..GRID_ROWS {
+            let row_h = self.row_height(row);
+            // First row is partially hidden by scroll_offset_y
+            let visible_h = if count == 0 { row_h - self.scroll_offset_y } else { row_h };
+            total_height += visible_h;
+            count += 1;
+            if total_height >= available_height {
+                break;
+            }
+        }
+        count.max(1)
+    }
+
+    /// Calculate number of visible columns from scroll position that fit in given width
+    fn calculate_visible_cols(&self, available_width: f32) -> usize {
+        let mut total_width = 0.0;
+        let mut count = 0;
+        for col in self.scroll_col..GRID_COLS {
+            let col_w = self.col_width(col);
+            // First column is partially hidden by scroll_offset_x
+            let visible_w = if count == 0 { col_w - self.scroll_offset_x } else { col_w };
+            total_width += visible_w;
+            count += 1;
+            if total_width >= available_width {
+                break;
+            }
+        }
+        count.max(1)
+    }
+
+    // === Resize handle detection helpers ===
+
+    /// Get the X position where a column ends (relative to grid area, after row header)
+    fn column_end_x(&self, col: usize) -> f32 {
+        if col < self.frozen_cols {
+            return self.cols_width(0..col + 1);
+        }
+        let sum = self.cols_width(self.scroll_col..col + 1);
+        self.frozen_width() + sum - self.scroll_offset_x
+    }
+
+    /// Get the Y position where a row ends (relative to grid area, after column header)
+    fn row_end_y(&self, row: usize) -> f32 {
+        if row < self.frozen_rows {
+            return self.rows_height(0..row + 1);
+        }
+        if self.pinned_header_row() == Some(row) {
+            return self.top_pane_height();
+        }
+        let sum = self.rows_height(self.scroll_row..row + 1);
+        self.top_pane_height() + sum - self.scroll_offset_y
+    }
+
+    /// Find if x position is near a column resize border, returns the column index whose right edge is near
+    fn column_resize_target(&self, x: f32) -> Option<usize> {
+        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
+        let frozen_width = self.frozen_width();
+        for col in (0..self.frozen_cols).chain(self.scroll_col..end_col) {
+            let col_end = self.column_end_x(col);
+            // Scrolled columns tucked under the frozen panes can't be grabbed
+            if col >= self.frozen_cols && col_end < frozen_width {
+                continue;
+            }
+            if (x - col_end).abs() <= RESIZE_HANDLE_WIDTH {
+                return Some(col);
+            }
+        }
+        None
+    }
+
+    /// Find if y position is near a row resize border, returns the row index whose bottom edge is near
+    fn row_resize_target(&self, y: f32) -> Option<usize> {
+        let end_row = (self.scroll_row + self.visible_rows).min(GRID_ROWS);
+        let top_pane_height = self.top_pane_height();
+        let pinned = self.pinned_header_row();
+        for row in (0..self.frozen_rows).chain(pinned).chain(self.scroll_row..end_row) {
+            let row_end = self.row_end_y(row);
+            if row >= self.frozen_rows && Some(row) != pinned && row_end < top_pane_height {
+                continue;
+            }
+            if (y - row_end).abs() <= RESIZE_HANDLE_WIDTH {
+                return Some(row);
+            }
+        }
+        None
+    }
+
+    // === Resize operations ===
+
+    /// Start a column resize operation
+    fn start_column_resize(&mut self, col: usize, mouse_x: f32, _cx: &mut Context<Self>) {
+        self.resize_state = Some(ResizeState {
+            target: ResizeTarget::Column(col),
+            start_mouse_pos: mouse_x,
+            original_size: self.column_widths[col],
+        });
+    }
+
+    /// Start a row resize operation
+    fn start_row_resize(&mut self, row: usize, mouse_y: f32, _cx: &mut Context<Self>) {
+        self.resize_state = Some(ResizeState {
+            target: ResizeTarget::Row(row),
+            start_mouse_pos: mouse_y,
+            original_size: self.row_heights[row],
+        });
+    }
+
+    /// Update size during resize drag
+    fn update_resize(&mut self, current_pos: f32, cx: &mut Context<Self>) {
+        if let Some(state) = &self.resize_state {
+            let delta = current_pos - state.start_mouse_pos;
+            let new_size = (state.original_size + delta).max(MIN_CELL_WIDTH);
+
+            match state.target {
+                ResizeTarget::Column(col) => {
+                    self.column_widths[col] = self.limit_width(col, new_size.max(MIN_CELL_WIDTH));
+                }
+                ResizeTarget::Row(row) => {
+                    self.row_heights[row] = new_size.max(MIN_CELL_HEIGHT);
+                }
+            }
+            cx.notify();
+        }
+    }
+
+    /// End resize operation
+    fn end_resize(&mut self, cx: &mut Context<Self>) {
+        // Resizing a wrapped column changes its line counts; refit its rows
+        if let Some(ResizeState { target: ResizeTarget::Column(col), .. }) = self.resize_state {
+            self.fit_rows_to_wrapped_column(col);
+        }
+        self.resize_state = None;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Handle column header mouse down - start resize or double-click auto-fit
+    fn on_column_header_mouse_down(&mut self, event: &MouseDownEvent, header_x: f32, cx: &mut Context<Self>) {
+        // x position relative to column header area (after row header)
+        let x = f32::from(event.position.x) - ROW_HEADER_WIDTH - header_x;
+
+        if let Some(col) = self.column_resize_target(x) {
+            if event.click_count == 2 {
+                // Double-click: auto-fit column
+                self.auto_fit_column(col, cx);
+            } else {
+                // Single click: start resize
+                self.start_column_resize(col, f32::from(event.position.x), cx);
+            }
+        }
+    }
+
+    /// Handle row header mouse down - start resize or double-click auto-fit
+    fn on_row_header_mouse_down(&mut self, event: &MouseDownEvent, header_y: f32, cx: &mut Context<Self>) {
+        // y position relative to row area (after column header)
+        let y = f32::from(event.position.y) - COLUMN_HEADER_HEIGHT - self.chrome_height() - header_y;
+
+        if let Some(row) = self.row_resize_target(y) {
+            if event.click_count == 2 {
+                // Double-click: auto-fit row
+                self.auto_fit_row(row, cx);
+            } else {
+                // Single click: start resize
+                self.start_row_resize(row, f32::from(event.position.y), cx);
+            }
+        }
+    }
+
+    // === Auto-fit methods (implemented in Phase 5) ===
+
+    /// Auto-fit a column width to its content
+    fn auto_fit_column(&mut self, col: usize, cx: &mut Context<Self>) {
+        // Find the maximum content width in this column
+        let mut max_width = self.default_col_width;
+        for row in 0..GRID_ROWS {
+            let content = &self.cells[row][col];
+            if !content.is_empty() {
+                // Estimate width: approximately 8 pixels per narrow character + padding
+                let longest_line = content.lines().map(wrap::text_width).max().unwrap_or(0);
+                let estimated_width = longest_line as f32 * wrap::CHAR_WIDTH + 16.0;
+                max_width = max_width.max(estimated_width);
+            }
+        }
+        self.column_widths[col] = self.autofit_width(col, max_width.max(self.default_col_width));
+        self.fit_rows_to_wrapped_column(col);
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Auto-fit a row height to its content, as wrapped
+    fn auto_fit_row(&mut self, row: usize, cx: &mut Context<Self>) {
+        self.row_heights[row] = self.fitted_row_height(row);
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Auto-fit all columns and rows
+    fn auto_fit_all(&mut self, cx: &mut Context<Self>) {
+        for col in 0..GRID_COLS {
+            let mut max_width = self.default_col_width;
+            for row in 0..GRID_ROWS {
+                let content = &self.cells[row][col];
+                if !content.is_empty() {
+                    let longest_line = content.lines().map(wrap::text_width).max().unwrap_or(0);
+                    let estimated_width = longest_line as f32 * wrap::CHAR_WIDTH + 16.0;
+                    max_width = max_width.max(estimated_width);
+                }
+            }
+            self.column_widths[col] = self.autofit_width(col, max_width.max(self.default_col_width));
+        }
+        for row in 0..GRID_ROWS {
+            self.row_heights[row] = self.fitted_row_height(row);
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Reset all column widths and row heights to defaults
+    fn reset_all_sizes(&mut self, cx: &mut Context<Self>) {
+        self.column_widths = (0..GRID_COLS).map(|col| self.limit_width(col, self.default_col_width)).collect();
+        self.row_heights = vec![self.default_row_height; GRID_ROWS];
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Choose whether this file's layout metadata is embedded in it or kept in a sidecar file
+    /// Change how the file is written; takes effect on the next save
+    fn remove_encryption(&mut self, cx: &mut Context<Self>) {
+        if self.file_state.passphrase.take().is_some() {
+            self.file_state.mark_dirty();
+            self.show_footer_message("Saving writes the file unencrypted", cx);
+        }
+    }
+
+    fn change_write_options(&mut self, change: impl FnOnce(&mut WriteOptions), cx: &mut Context<Self>) {
+        change(&mut self.file_state.write_options);
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    fn set_embed_metadata(&mut self, embed: bool, cx: &mut Context<Self>) {
+        self.embed_metadata = embed;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Change the file's default column width; columns still at the old default follow it
+    fn set_default_col_width(&mut self, width: f32, cx: &mut Context<Self>) {
+        let width = width.max(MIN_CELL_WIDTH);
+        for col in 0..GRID_COLS {
+            if self.column_widths[col] == self.default_col_width {
+                self.column_widths[col] = self.limit_width(col, width);
+                self.fit_rows_to_wrapped_column(col);
+            }
+        }
+        self.default_col_width = width;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Change the file's default row height; rows still at the old default follow it
+    fn set_default_row_height(&mut self, height: f32, cx: &mut Context<Self>) {
+        let height = height.max(MIN_CELL_HEIGHT);
+        for row_height in &mut self.row_heights {
+            if *row_height == self.default_row_height {
+                *row_height = height;
+            }
+        }
+        self.default_row_height = height;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // === Width limits ===
+
+    /// Keep a width within the column's limits
+    fn limit_width(&self, col: usize, width: f32) -> f32 {
+        self.column_width_limits.get(&col).map_or(width, |limits| limits.clamp(width))
+    }
+
+    /// Width auto-fit gives a column: capped by `autofitmax`, then kept within the column's limits
+    fn autofit_width(&self, col: usize, width: f32) -> f32 {
+        let width = self.autofit_max_width.map_or(width, |max| width.min(max.max(self.default_col_width)));
+        self.limit_width(col, width)
+    }
+
+    /// Set or remove width limits on the selected columns, bringing their widths within them
+    fn set_width_limits(&mut self, limits: Option<WidthLimits>, cx: &mut Context<Self>) {
+        for col in self.selected_cols() {
+            match limits {
+                Some(limits) => {
+                    self.column_width_limits.insert(col, limits);
+                    self.column_widths[col] = limits.clamp(self.column_widths[col]);
+                    self.fit_rows_to_wrapped_column(col);
+                }
+                None => {
+                    self.column_width_limits.remove(&col);
+                }
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Cap how wide auto-fit makes columns; existing widths are left alone
+    fn set_autofit_max_width(&mut self, width: Option<f32>, cx: &mut Context<Self>) {
+        self.autofit_max_width = width;
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    // === Watch mode methods ===
+
+    /// Toggle auto-fit watch mode for all cells
+    fn toggle_autofit_watch_all(&mut self, cx: &mut Context<Self>) {
+        self.autofit_watch = match &self.autofit_watch {
+            AutoFitWatch::All => AutoFitWatch::None,
+            _ => AutoFitWatch::All,
+        };
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Toggle auto-fit watch for a specific column
+    fn toggle_autofit_watch_column(&mut self, col: usize, cx: &mut Context<Self>) {
+        match &mut self.autofit_watch {
+            AutoFitWatch::Columns(cols) => {
+                if cols.contains(&col) {
+                    cols.remove(&col);
+                    if cols.is_empty() {
+                        self.autofit_watch = AutoFitWatch::None;
+                    }
+                } else {
+                    cols.insert(col);
+                }
+            }
+            AutoFitWatch::None => {
+                let mut cols = HashSet::new();
+                cols.insert(col);
+                self.autofit_watch = AutoFitWatch::Columns(cols);
+            }
+            _ => {
+                // If All or Rows mode, switch to just this column
+                let mut cols = HashSet::new();
+                cols.insert(col);
+                self.autofit_watch = AutoFitWatch::Columns(cols);
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Toggle auto-fit watch for a specific row
+    fn toggle_autofit_watch_row(&mut self, row: usize, cx: &mut Context<Self>) {
+        match &mut self.autofit_watch {
+            AutoFitWatch::Rows(rows) => {
+                if rows.contains(&row) {
+                    rows.remove(&row);
+                    if rows.is_empty() {
+                        self.autofit_watch = AutoFitWatch::None;
+                    }
+                } else {
+                    rows.insert(row);
+                }
+            }
+            AutoFitWatch::None => {
+                let mut rows = HashSet::new();
+                rows.insert(row);
+                self.autofit_watch = AutoFitWatch::Rows(rows);
+            }
+            _ => {
+                // If All or Columns mode, switch to just this row
+                let mut rows = HashSet::new();
+                rows.insert(row);
+                self.autofit_watch = AutoFitWatch::Rows(rows);
+            }
+        }
+        self.file_state.mark_dirty();
+        cx.notify();
+    }
+
+    /// Check if auto-fit should be applied for a cell, and apply it
+    fn check_autofit_watch(&mut self, row: usize, col: usize, cx: &mut Context<Self>) {
+        match &self.autofit_watch {
+            AutoFitWatch::None => {}
+            AutoFitWatch::All => {
+                self.auto_fit_column(col, cx);
+                self.auto_fit_row(row, cx);
+            }
+            AutoFitWatch::Columns(cols) => {
+                if cols.contains(&col) {
+                    self.auto_fit_column(col, cx);
+                }
+            }
+            AutoFitWatch::Rows(rows) => {
+                if rows.contains(&row) {
+                    self.auto_fit_row(row, cx);
+                }
+            }
+        }
+    }
+
+    // === Scroll wheel / trackpad ===
+
+    fn handle_scroll_wheel(&mut self, event: &ScrollWheelEvent, _window: &mut Window, cx: &mut Context<Self>) {
+        // The viewer's list scrolls itself
+        if self.viewer.is_some() {
+            return;
+        }
+        self.cancel_scroll_animation();
+        // A vertical wheel scrolls sideways with shift held, for mice without a horizontal wheel
+        let sideways = |x: f32, y: f32| if event.modifiers.shift && x == 0.0 { (y, 0.0) } else { (x, y) };
+        match event.delta {
+            ScrollDelta::Lines(delta) => {
+                // Mouse wheel: jump by whole cells
+                self.scroll_offset_x = 0.0;
+                self.scroll_offset_y = 0.0;
+
+                let (x, y) = sideways(delta.x, delta.y);
+                let speed = self.scroll_speed as isize;
+                let row_delta = -y.round() as isize * speed;
+                let col_delta = -x.round() as isize * speed;
+
+                self.scroll_row = (self.scroll_row as isize + row_delta)
+                    .max(self.frozen_rows as isize)
+                    .min((GRID_ROWS - 1) as isize) as usize;
+                self.scroll_col = (self.scroll_col as isize + col_delta)
+                    .max(self.frozen_cols as isize)
+                    .min((GRID_COLS - 1) as isize) as usize;
+            }
+            ScrollDelta::Pixels(delta) => {
+                // Trackpad: smooth pixel scrolling
+                let (x, y) = sideways(f32::from(delta.x), f32::from(delta.y));
+                self.apply_smooth_scroll(-x, -y);
+            }
+        }
+
+        if self.keep_cursor_in_view {
+            self.clamp_cursor_to_viewport();
+        }
+
+        cx.notify();
+    }
+
+    fn apply_smooth_scroll(&mut self, dx: f32, dy: f32) {
+        // Accumulate vertical offset
+        self.scroll_offset_y += dy;
+
+        // Carry over to next/previous rows
+        while self.scroll_offset_y >= self.row_height(self.scroll_row)
+            && self.scroll_row < GRID_ROWS - 1
+        {
+            self.scroll_offset_y -= self.row_height(self.scroll_row);
+            self.scroll_row += 1;
+        }
+        while self.scroll_offset_y < 0.0 && self.scroll_row > self.frozen_rows {
+            self.scroll_row -= 1;
+            self.scroll_offset_y += self.row_height(self.scroll_row);
+        }
+
+        // Accumulate horizontal offset
+        self.scroll_offset_x += dx;
+
+        // Carry over to next/previous columns
+        while self.scroll_offset_x >= self.col_width(self.scroll_col)
+            && self.scroll_col < GRID_COLS - 1
+        {
+            self.scroll_offset_x -= self.col_width(self.scroll_col);
+            self.scroll_col += 1;
+        }
+        while self.scroll_offset_x < 0.0 && self.scroll_col > self.frozen_cols {
+            self.scroll_col -= 1;
+            self.scroll_offset_x += self.col_width(self.scroll_col);
+        }
+
+        self.clamp_scroll_position();
+    }
+
+    fn clamp_scroll_position(&mut self) {
+        // Clamp at top/left edges (the first scrollable row/column sits just past the frozen panes)
+        if self.scroll_row < self.frozen_rows {
+            self.scroll_row = self.frozen_rows;
+            self.scroll_offset_y = 0.0;
+        }
+        if self.scroll_col < self.frozen_cols {
+            self.scroll_col = self.frozen_cols;
+            self.scroll_offset_x = 0.0;
+        }
+        if self.scroll_row == self.frozen_rows && self.scroll_offset_y < 0.0 {
+            self.scroll_offset_y = 0.0;
+        }
+        if self.scroll_col == self.frozen_cols && self.scroll_offset_x < 0.0 {
+            self.scroll_offset_x = 0.0;
+        }
+        // Clamp at bottom/right edges
+        if self.scroll_row >= GRID_ROWS - 1 {
+            self.scroll_row = GRID_ROWS - 1;
+            if self.scroll_offset_y > 0.0 {
+                self.scroll_offset_y = 0.0;
+            }
+        }
+        if self.scroll_col >= GRID_COLS - 1 {
+            self.scroll_col = GRID_COLS - 1;
+            if self.scroll_offset_x > 0.0 {
+                self.scroll_offset_x = 0.0;
+            }
+        }
+    }
+
+    /// Move the cursor into the fully visible viewport (used when keep_cursor_in_view is enabled)
+    fn clamp_cursor_to_viewport(&mut self) {
+        // First fully visible row: if pixel offset hides part of scroll_row, skip it
+        let first_full_row = if self.scroll_offset_y > 0.0 {
+            (self.scroll_row + 1).min(GRID_ROWS - 1)
+        } else {
+            self.scroll_row
+        };
+        let last_full_row = self.last_fully_visible_row();
+
+        if self.selected.row < self.frozen_rows {
+            // Cursor in frozen rows is always visible
+        } else if self.selected.row < first_full_row {
+            self.selected.row = first_full_row;
+        } else if self.selected.row > last_full_row {
+            self.selected.row = last_full_row;
+        }
+
+        let first_full_col = if self.scroll_offset_x > 0.0 {
+            (self.scroll_col + 1).min(GRID_COLS - 1)
+        } else {
+            self.scroll_col
+        };
+        let last_full_col = self.last_fully_visible_col();
+
+        if self.selected.col < self.frozen_cols {
+            // Cursor in frozen columns is always visible
+        } else if self.selected.col < first_full_col {
+            self.selected.col = first_full_col;
+        } else if self.selected.col > last_full_col {
+            self.selected.col = last_full_col;
+        }
+    }
+
+    // === Scrollbars ===
+
+    /// Pixel distance from the first scrollable row to the top of the viewport
+    fn scroll_pos_y(&self) -> f32 {
+        self.rows_height(self.frozen_rows..self.scroll_row) + self.scroll_offset_y
+    }
+
+    /// Pixel distance from the first scrollable column to the left of the viewport
+    fn scroll_pos_x(&self) -> f32 {
+        self.cols_width(self.frozen_cols..self.scroll_col) + self.scroll_offset_x
+    }
+
+    /// Scroll so the viewport starts `y` pixels below the first scrollable row
+    fn scroll_to_pos_y(&mut self, y: f32) {
+        let mut remaining = y.max(0.0);
+        let mut row = self.frozen_rows;
+        while row < GRID_ROWS - 1 && remaining >= self.row_height(row) {
+            remaining -= self.row_height(row);
+            row += 1;
+        }
+        self.scroll_row = row;
+        self.scroll_offset_y = remaining;
+        self.clamp_scroll_position();
+    }
+
+    /// Scroll so the viewport starts `x` pixels right of the first scrollable column
+    fn scroll_to_pos_x(&mut self, x: f32) {
+        let mut remaining = x.max(0.0);
+        let mut col = self.frozen_cols;
+        while col < GRID_COLS - 1 && remaining >= self.col_width(col) {
+            remaining -= self.col_width(col);
+            col += 1;
+        }
+        self.scroll_col = col;
+        self.scroll_offset_x = remaining;
+        self.clamp_scroll_position();
+    }
+
+    /// Compute track and thumb geometry for a scrollbar.
+    /// The scrollable extent is the used data range past any frozen panes, grown to include
+    /// the current viewport.
+    fn scrollbar_metrics(&self, axis: ScrollbarAxis) -> ScrollbarMetrics {
+        let (used_row, used_col) = self.used_bounds;
+        let (len, frozen, used_end, view_end, track_len, view_len, scroll_pos) = match axis {
+            ScrollbarAxis::Vertical => (
+                GRID_ROWS,
+                self.frozen_rows,
+                used_row + 1,
+                self.scroll_row + self.visible_rows,
+                self.grid_height,
+                self.scroll_area_height(),
+                self.scroll_pos_y(),
+            ),
+            ScrollbarAxis::Horizontal => (
+                GRID_COLS,
+                self.frozen_cols,
+                used_col + 1,
+                self.scroll_col + self.visible_cols,
+                self.grid_width,
+                self.scroll_area_width(),
+                self.scroll_pos_x(),
+            ),
+        };
+        let extent = used_end.max(view_end).clamp(frozen, len);
+        let scrollable = match axis {
+            ScrollbarAxis::Vertical => self.rows_height(frozen..extent),
+            ScrollbarAxis::Horizontal => self.cols_width(frozen..extent),
+        };
+        let content_len = scrollable
+            .max(scroll_pos + view_len)
+            .max(1.0);
+        let track_len = track_len.max(0.0);
+        let thumb_len = (track_len * view_len / content_len)
+            .max(MIN_SCROLLBAR_THUMB)
+            .min(track_len);
+        let thumb_start = if content_len > view_len {
+            (scroll_pos / (content_len - view_len)) * (track_len - thumb_len)
+        } else {
+            0.0
+        };
+
+        ScrollbarMetrics {
+            track_len,
+            view_len,
+            thumb_start: thumb_start.clamp(0.0, (track_len - thumb_len).max(0.0)),
+            thumb_len,
+            content_len,
+        }
+    }
+
+    /// Handle mouse down on a scrollbar track: start a thumb drag, or page toward the click
+    fn on_scrollbar_mouse_down(&mut self, axis: ScrollbarAxis, event: &MouseDownEvent, cx: &mut Context<Self>) {
+        let metrics = self.scrollbar_metrics(axis);
+        let (mouse_pos, track_start, scroll_pos, page) = match axis {
+            ScrollbarAxis::Vertical => (
+                f32::from(event.position.y),
+                self.chrome_height() + COLUMN_HEADER_HEIGHT,
+                self.scroll_pos_y(),
+                metrics.view_len,
+            ),
+            ScrollbarAxis::Horizontal => (
+                f32::from(event.position.x),
+                ROW_HEADER_WIDTH,
+                self.scroll_pos_x(),
+                metrics.view_len,
+            ),
+        };
+        let pos = mouse_pos - track_start;
+
+        if pos >= metrics.thumb_start && pos <= metrics.thumb_start + metrics.thumb_len {
+            let free_track = (metrics.track_len - metrics.thumb_len).max(1.0);
+            let free_content = (metrics.content_len - metrics.view_len).max(0.0);
+            self.scrollbar_drag = Some(ScrollbarDragState {
+                axis,
+                start_mouse_pos: mouse_pos,
+                start_scroll_pos: scroll_pos,
+                scale: free_content / free_track,
+            });
+        } else {
+            // Click on the track: jump one page toward the click
+            let delta = if pos < metrics.thumb_start { -page } else { page };
+            match axis {
+                ScrollbarAxis::Vertical => self.apply_smooth_scroll(0.0, delta),
+                ScrollbarAxis::Horizontal => self.apply_smooth_scroll(delta, 0.0),
+            }
+            if self.keep_cursor_in_view {
+                self.clamp_cursor_to_viewport();
+            }
+        }
+        cx.notify();
+    }
+
+    /// Update scroll position during a scrollbar thumb drag
+    fn update_scrollbar_drag(&mut self, position: Point<Pixels>, cx: &mut Context<Self>) {
+        if let Some(drag) = self.scrollbar_drag {
+            match drag.axis {
+                ScrollbarAxis::Vertical => {
+                    let delta = f32::from(position.y) - drag.start_mouse_pos;
+                    self.scroll_to_pos_y(drag.start_scroll_pos + delta * drag.scale);
+                }
+                ScrollbarAxis::Horizontal => {
+                    let delta = f32::from(position.x) - drag.start_mouse_pos;
+                    self.scroll_to_pos_x(drag.start_scroll_pos + delta * drag.scale);
+                }
+            }
+            if self.keep_cursor_in_view {
+                self.clamp_cursor_to_viewport();
+            }
+            cx.notify();
+        }
+    }
+
+    /// End scrollbar thumb drag
+    fn end_scrollbar_drag(&mut self, cx: &mut Context<Self>) {
+        self.scrollbar_drag = None;
+        cx.notify();
+    }
+
+    fn on_cell_click(&mut self, row: usize, col: usize, modifiers: Modifiers, window: &mut Window, cx: &mut Context<Self>) {
+        let extend = modifiers.shift;
+        // Clicking another cell while a formula expects a reference inserts that cell's
+        // reference (shift-click extends the last one into a range); focus stays in the editor
+        if self.mode == Mode::Edit && self.active_input.read(cx).is_pointing() {
+            let clicked = CellPosition::new(row, col);
+            let anchor = match self.current_pointer(cx) {
+                Some((anchor, _)) if extend => anchor,
+                _ => clicked,
+            };
+            window.prevent_default();
+            self.point_at(anchor, clicked, window, cx);
+            return;
+        }
+
+        // If clicking on a different cell while in edit mode, save and exit first
+        if self.mode == Mode::Edit && (row != self.selected.row || col != self.selected.col) {
+            self.save_and_exit_edit_mode(window, cx);
+        }
+
+        self.selection_drag = Some(SelectionDrag::Cells);
+        self.move_drag = None;
+        if modifiers.secondary() {
+            // Cmd-click keeps the selection and starts another range at the clicked cell
+            self.extra_selections.push(self.selection_range());
+            self.selection_anchor = None;
+        } else if extend {
+            // Shift-click extends the selection from the current cursor
+            self.selection_anchor.get_or_insert(self.selected);
+        } else if !self.visual_mode {
+            self.selection_anchor = None;
+            self.extra_selections.clear();
+        }
+        self.selected = CellPosition::new(row, col);
+        self.ensure_visible();
+        cx.notify();
+    }
+
+    fn on_cell_double_click(&mut self, row: usize, col: usize, window: &mut Window, cx: &mut Context<Self>) {
+        self.selected = CellPosition::new(row, col);
+        self.selection_anchor = None;
+        self.extra_selections.clear();
+        self.visual_mode = false;
+        self.ensure_visible();
+
+        // Enter edit mode on double click
+        if !self.can_edit_selected(cx) {
+            cx.notify();
+            return;
+        }
+        self.mode = Mode::Edit;
+        let content = self.cells[row][col].clone();
+        let values = self.autocomplete_values(self.selected);
+        self.active_input.update(cx, |input, cx| {
+            input.set_content(content, cx);
+            input.autocomplete_values = values;
+        });
+        let focus_handle = self.active_input.focus_handle(cx);
+        focus_handle.focus(window, cx);
+        cx.notify();
+    }
+
+    fn render_header(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let cell_ref = self.selection_range().to_reference();
+
+        div()
+            .flex()
+            .flex_row()
+            .w_full()
+            .h(px(HEADER_HEIGHT))
+            .bg(theme.mantle)
+            .border_b_1()
+            .border_color(theme.surface0)
+            .items_center()
+            .px(px(8.))
+            .gap(px(8.))
+            .child(
+                // Cell reference label, which can be typed into to jump (the name box)
+                div()
+                    .id("name-box")
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .min_w(px(60.))
+                    .px(px(6.))
+                    .h(px(24.))
+                    .bg(theme.surface0)
+                    .rounded(px(4.))
+                    .text_size(px(14.))
+                    .text_color(theme.subtext1)
+                    .when(self.editing_name_box, |d| d.w(px(100.)).border_1().border_color(theme.accent))
+                    .when(!self.editing_name_box, |d| {
+                        d.cursor_text().on_click(cx.listener(|grid, _, window, cx| grid.edit_name_box(window, cx)))
+                    })
+                    .map(|d| if self.editing_name_box { d.child(self.name_box.clone()) } else { d.child(cell_ref) })
+            )
+            .child(
+                // Formula bar / content display
+                div()
+                    .flex_1()
+                    .h(px(24.))
+                    .bg(theme.surface0)
+                    .rounded(px(4.))
+                    .overflow_hidden()
+                    .px(px(8.))
+                    .items_center()
+                    .text_size(px(14.))
+                    .child({
+                        let content = if self.mode == Mode::Edit {
+                            // Show input content in edit mode
+                            self.active_input.read(cx).get_content()
+                        } else {
+                            // Show cell content in normal mode
+                            self.cells[self.selected.row][self.selected.col].clone()
+                        };
+                        // Formulas are syntax highlighted, as in the cell editor
+                        let highlights: Vec<(Range<usize>, HighlightStyle)> = formula::highlight(&content)
+                            .spans
+                            .into_iter()
+                            .map(|(range, kind)| {
+                                let style = HighlightStyle { color: Some(theme.formula_color(kind).into()), ..Default::default() };
+                                (range, style)
+                            })
+                            .collect();
+                        StyledText::new(content).with_highlights(highlights)
+                    })
+            )
+    }
+
+    /// Optional row of buttons for common actions, for those who'd rather not use the keyboard
+    fn render_toolbar(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let style = self.cell_styles[self.selected.row][self.selected.col];
+
+        let button = |id: &'static str, label: &'static str, active: bool, run: ToolbarHandler| {
+            let entity = entity.clone();
+            div()
+                .id(id)
+                .h(px(24.))
+                .min_w(px(24.))
+                .px(px(6.))
+                .flex()
+                .items_center()
+                .justify_center()
+                .rounded(px(4.))
+                .cursor_pointer()
+                .when(active, |d| d.bg(theme.surface1))
+                .hover(|d| d.bg(theme.surface0))
+                .child(label)
+                .on_click(move |_event, window, app| {
+                    entity.update(app, |grid, cx| run(grid, window, cx));
+                })
+        };
+        let separator = || div().w(px(1.)).h(px(18.)).mx(px(4.)).bg(theme.surface0);
+
+        div()
+            .flex()
+            .flex_row()
+            .w_full()
+            .h(px(TOOLBAR_HEIGHT))
+            .bg(theme.mantle)
+            .border_b_1()
+            .border_color(theme.surface0)
+            .items_center()
+            .px(px(8.))
+            .gap(px(2.))
+            .text_size(px(13.))
+            .text_color(theme.subtext1)
+            .child(button("toolbar-save", "Save", false, |grid, window, cx| grid.save_file(&SaveFile, window, cx)))
+            .child(separator())
+            .child(button("toolbar-undo", "Undo", false, |_, window, cx| window.dispatch_action(Box::new(crate::menu::Undo), cx)))
+            .child(button("toolbar-redo", "Redo", false, |_, window, cx| window.dispatch_action(Box::new(crate::menu::Redo), cx)))
+            .child(separator())
+            .child(button("toolbar-bold", "B", style.bold, |grid, _, cx| grid.apply_style(StyleChange::Bold, cx)))
+            .child(button("toolbar-italic", "I", style.italic, |grid, _, cx| grid.apply_style(StyleChange::Italic, cx)))
+            .child(button("toolbar-underline", "U", style.underline, |grid, _, cx| grid.apply_style(StyleChange::Underline, cx)))
+            .child(separator())
+            .child(button("toolbar-general", "123", false, |grid, _, cx| grid.set_number_format(None, false, cx)))
+            .child(button("toolbar-decimal", ".00", false, |grid, window, cx| grid.format_decimal(&FormatDecimal, window, cx)))
+            .child(button("toolbar-currency", "$", false, |grid, window, cx| grid.format_currency(&FormatCurrency, window, cx)))
+            .child(button("toolbar-percent", "%", false, |grid, window, cx| grid.format_percent(&FormatPercent, window, cx)))
+            .child(separator())
+            .child(button("toolbar-sort-asc", "Sort ↑", false, |grid, _, cx| grid.sort_by_column(grid.selected.col, false, cx)))
+            .child(button("toolbar-sort-desc", "Sort ↓", false, |grid, _, cx| grid.sort_by_column(grid.selected.col, true, cx)))
+            .child(button("toolbar-filter", "Filter", self.filters.contains_key(&self.selected.col), |grid, _, cx| {
+                grid.toggle_filter_menu(grid.selected.col, cx)
+            }))
+    }
+
+    fn render_column_headers(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
+        let offset_x = self.scroll_offset_x;
+        let frozen_cols = self.frozen_cols;
+
+        div()
+            .id("column-headers")
+            .relative()
+            .flex()
+            .flex_row()
+            .h(px(COLUMN_HEADER_HEIGHT))
+            .bg(theme.mantle)
+            .border_b_1()
+            .border_color(theme.surface0)
+            .on_mouse_down(MouseButton::Left, {
+                let entity = entity.clone();
+                move |event, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        grid.on_column_header_mouse_down(event, 0.0, cx);
+                    });
+                }
+            })
+            .on_mouse_move({
+                let entity = entity.clone();
+                move |event, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        if grid.resize_state.is_some() {
+                            grid.update_resize(f32::from(event.position.x), cx);
+                        }
+                    });
+                }
+            })
+            .on_mouse_up(MouseButton::Left, {
+                let entity = entity.clone();
+                move |_event, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        grid.selection_drag = None;
+                        if grid.resize_state.is_some() {
+                            grid.end_resize(cx);
+                        }
+                    });
+                }
+            })
+            .child(
+                // Empty corner cell
+                div()
+                    .w(px(ROW_HEADER_WIDTH))
+                    .h_full()
+                    .flex_none()
+                    .border_r_1()
+                    .border_color(theme.surface0)
+            )
+            .children(
+                (0..frozen_cols)
+                    .filter(|&col| !self.is_col_hidden(col))
+                    .map(|col| self.render_column_header_cell(col, theme, &entity))
+            )
+            .child(
+                // Clipped container for column headers with horizontal scroll offset
+                div()
+                    .flex_1()
+                    .h_full()
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .flex()
+                            .flex_row()
+                            .h_full()
+                            .ml(px(-offset_x))
+                            .children(
+                                (self.scroll_col..end_col)
+                                    .filter(|&col| !self.is_col_hidden(col))
+                                    .map(|col| self.render_column_header_cell(col, theme, &entity))
+                            )
+                    )
+            )
+            .when(frozen_cols > 0, |d| {
+                d.child(self.render_frozen_divider(ScrollbarAxis::Horizontal, theme))
+            })
+    }
+
+    fn render_column_header_cell(&self, col: usize, theme: &Theme, entity: &Entity<Self>) -> impl IntoElement {
+        let col_letter = CellPosition::new(0, col).to_reference();
+        let col_letter: String = col_letter.chars().take_while(|c| c.is_alphabetic()).collect();
+        let is_selected = col == self.selected.col
+            || self.selected_columns().is_some_and(|cols| cols.contains(&col));
+        let is_drop_target = self.header_drag
+            .is_some_and(|drag| drag.kind == SelectionDrag::Columns && drag.over == col && drag.over != drag.grabbed);
+        let group_header = self.col_outline.header_at(col).copied();
+        let in_group = self.col_outline.group_at(col).is_some();
+        let is_filtered = self.filters.contains_key(&col);
+        let show_filter_button = self.show_filter_buttons || is_filtered;
+
+        div()
+            .id(ElementId::Name(format!("col-header-{}", col).into()))
+            .relative()
+            .w(px(self.column_widths[col]))
+            .h_full()
+            .flex_none()
+            .flex()
+            .items_center()
+            .justify_center()
+            .border_r_1()
+            .border_color(theme.surface0)
+            .text_size(px(12.))
+            .text_color(if is_selected { theme.accent } else { theme.subtext0 })
+            .font_weight(if is_selected { FontWeight::BOLD } else { FontWeight::NORMAL })
+            .when(is_drop_target, |d| d.bg(theme.surface1))
+            // Outline bar along the top of grouped columns
+            .when(in_group, |d| d.border_t_2())
+            .child(col_letter)
+            // A quick profile of the column's data on hover
+            .tooltip({
+                let entity = entity.clone();
+                move |_window, cx| {
+                    let text = entity.read(cx).column_stats(col).lines().join("\n");
+                    cx.new(|_| CellTooltip { text: text.into() }).into()
+                }
+            })
+            .on_mouse_down(MouseButton::Left, {
+                let entity = entity.clone();
+                move |event, window, app| {
+                    entity.update(app, |grid, cx| grid.on_column_header_click(col, event, window, cx));
+                }
+            })
+            .on_mouse_move({
+                let entity = entity.clone();
+                move |event, _window, app| {
+                    if event.pressed_button == Some(MouseButton::Left) {
+                        entity.update(app, |grid, cx| grid.on_selection_drag(SelectionDrag::Columns, col, cx));
+                    }
+                }
+            })
+            .on_mouse_down(MouseButton::Right, {
+                let entity = entity.clone();
+                move |event, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        grid.open_context_menu(MenuTarget::Columns, 0, col, event.position, cx);
+                    });
+                }
+            })
+            .when_some(group_header, |d, group| {
+                let entity = entity.clone();
+                d.child(
+                    self.render_group_toggle(group.collapsed, theme)
+                        .id(ElementId::Name(format!("col-group-toggle-{}", col).into()))
+                        .left(px(2.))
+                        .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                            app.stop_propagation();
+                            entity.update(app, |grid, cx| grid.toggle_col_group(col, cx));
+                        })
+                )
+            })
+            .when(show_filter_button, |d| {
+                let entity = entity.clone();
+                d.child(
+                    div()
+                        .id(ElementId::Name(format!("col-filter-button-{}", col).into()))
+                        .absolute()
+                        .top(px(4.))
+                        .right(px(6.))
+                        .size(px(14.))
+                        .flex()
+                        .items_center()
+                        .justify_center()
+                        .rounded(px(3.))
+                        .text_size(px(10.))
+                        .text_color(if is_filtered { theme.base } else { theme.subtext0 })
+                        .when(is_filtered, |d| d.bg(theme.accent))
+                        .cursor_pointer()
+                        .child("▾")
+                        .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                            app.stop_propagation();
+                            entity.update(app, |grid, cx| grid.toggle_filter_menu(col, cx));
+                        })
+                )
+            })
+    }
+
+    /// Dropdown listing a column's distinct values as checkboxes, plus quick conditions
+    fn render_filter_menu(&self, col: usize, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let left = (ROW_HEADER_WIDTH + self.column_end_x(col) - self.col_width(col)).max(ROW_HEADER_WIDTH);
+        let condition = self.filters.get(&col);
+
+        let menu_item = |id: String, label: String| {
+            div()
+                .id(ElementId::Name(id.into()))
+                .px(px(8.))
+                .py(px(3.))
+                .rounded(px(3.))
+                .cursor_pointer()
+                .hover(|d| d.bg(theme.surface0))
+                .child(label)
+        };
+        let condition_item = |id: &str, label: &str, condition: Option<FilterCondition>| {
+            let entity = entity.clone();
+            menu_item(format!("filter-{}-{}", id, col), label.to_string())
+                .on_click(move |_event, _window, app| {
+                    entity.update(app, |grid, cx| grid.set_column_filter(col, condition.clone(), cx));
+                })
+        };
+
+        div()
+            .absolute()
+            .top(px(self.chrome_height() + COLUMN_HEADER_HEIGHT))
+            .left(px(left))
+            .w(px(220.))
+            .flex()
+            .flex_col()
+            .p(px(4.))
+            .gap(px(2.))
+            .bg(theme.mantle)
+            .border_1()
+            .border_color(theme.overlay0)
+            .rounded(px(6.))
+            .shadow_lg()
+            .text_size(px(12.))
+            .text_color(theme.text)
+            .on_mouse_down_out({
+                let entity = entity.clone();
+                let headers_top = self.chrome_height();
+                move |event: &MouseDownEvent, _window, app| {
+                    // Clicks on the column headers are left to the filter buttons, which toggle the menu
+                    let y = f32::from(event.position.y);
+                    if (headers_top..headers_top + COLUMN_HEADER_HEIGHT).contains(&y) {
+                        return;
+                    }
+                    entity.update(app, |grid, cx| {
+                        grid.filter_menu = None;
+                        cx.notify();
+                    });
+                }
+            })
+            .child(
+                div()
+                    .px(px(8.))
+                    .py(px(3.))
+                    .text_color(theme.subtext0)
+                    .child(match condition {
+                        Some(condition) => format!("Filter: {}", condition.describe()),
+                        None => format!("Filter column {}", CellPosition::col_to_letter(col)),
+                    })
+            )
+            .child(condition_item("clear", "Clear Filter", None))
+            .child(condition_item("empty", "Only Empty", Some(FilterCondition::Empty)))
+            .child(condition_item("nonempty", "Only Non-empty", Some(FilterCondition::NonEmpty)))
+            .child(div().h(px(1.)).my(px(2.)).bg(theme.surface0))
+            .child(
+                div()
+                    .id(ElementId::Name(format!("filter-values-{}", col).into()))
+                    .flex()
+                    .flex_col()
+                    .max_h(px(240.))
+                    .overflow_y_scroll()
+                    .children(self.filter_values(col).into_iter().enumerate().map(|(i, value)| {
+                        let checked = self.is_filter_value_selected(col, &value);
+                        let label = if value.is_empty() { "(Blanks)".to_string() } else { value.clone() };
+                        let entity = entity.clone();
+                        menu_item(format!("filter-value-{}-{}", col, i), format!("{} {}", if checked { "☑" } else { "☐" }, label))
+                            .on_click(move |_event, _window, app| {
+                                entity.update(app, |grid, cx| grid.toggle_filter_value(col, &value, cx));
+                            })
+                    }))
+            )
+    }
+
+    /// Right-click menu at the pointer, kept inside the window
+    fn render_context_menu(&self, menu: ContextMenu, window: &Window, cx: &mut Context<Self>) -> impl IntoElement {
+        const WIDTH: f32 = 200.0;
+        const ITEM_HEIGHT: f32 = 22.0;
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let read_only = self.file_state.is_read_only;
+        let items = menu.target.items();
+        let viewport = window.viewport_size();
+        let height = items.len() as f32 * ITEM_HEIGHT + 8.0;
+        let left = f32::from(menu.position.x).min(f32::from(viewport.width) - WIDTH).max(0.0);
+        let top = f32::from(menu.position.y).min(f32::from(viewport.height) - height).max(0.0);
+
+        div()
+            .absolute()
+            .top(px(top))
+            .left(px(left))
+            .w(px(WIDTH))
+            .flex()
+            .flex_col()
+            .p(px(4.))
+            .bg(theme.mantle)
+            .border_1()
+            .border_color(theme.overlay0)
+            .rounded(px(6.))
+            .shadow_lg()
+            .text_size(px(12.))
+            .text_color(theme.text)
+            .on_mouse_down_out({
+                let entity = entity.clone();
+                move |event: &MouseDownEvent, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        // The right-click that opened a menu elsewhere has already replaced this one
+                        if grid.context_menu.is_some_and(|open| open.position == event.position) {
+                            return;
+                        }
+                        grid.context_menu = None;
+                        cx.notify();
+                    });
+                }
+            })
+            .children(items.iter().enumerate().map(|(i, item)| match *item {
+                None => div().h(px(1.)).my(px(3.)).bg(theme.surface0).into_any_element(),
+                Some(action) => {
+                    let enabled = !(read_only && action.edits());
+                    let entity = entity.clone();
+                    div()
+                        .id(ElementId::Name(format!("context-menu-{}", i).into()))
+                        .px(px(8.))
+                        .py(px(3.))
+                        .rounded(px(3.))
+                        .child(action.label(menu.target))
+                        .when(!enabled, |d| d.text_color(theme.overlay0))
+                        .when(enabled, |d| {
+                            d.cursor_pointer()
+                                .hover(|d| d.bg(theme.surface0))
+                                .on_click(move |_event, window, app| {
+                                    entity.update(app, |grid, cx| grid.run_context_action(action, window, cx));
+                                })
+                        })
+                        .into_any_element()
+                }
+            }))
+    }
+
+    /// The +/- button shown in the header of an outline group
+    fn render_group_toggle(&self, collapsed: bool, theme: &Theme) -> Div {
+        div()
+            .absolute()
+            .top(px(4.))
+            .size(px(14.))
+            .flex()
+            .items_center()
+            .justify_center()
+            .rounded(px(3.))
+            .border_1()
+            .border_color(theme.overlay0)
+            .bg(theme.surface0)
+            .text_size(px(11.))
+            .text_color(theme.subtext1)
+            .cursor_pointer()
+            .child(if collapsed { "+" } else { "−" })
+    }
+
+    /// Line marking the edge of the frozen panes. `ScrollbarAxis::Vertical` draws the
+    /// horizontal line below frozen rows, `Horizontal` the vertical line right of frozen columns.
+    fn render_frozen_divider(&self, axis: ScrollbarAxis, theme: &Theme) -> impl IntoElement {
+        div()
+            .absolute()
+            .bg(theme.overlay0)
+            .map(|d| match axis {
+                ScrollbarAxis::Vertical => d
+                    .left_0()
+                    .w_full()
+                    .top(px(self.top_pane_height() - 1.))
+                    .h(px(2.)),
+                ScrollbarAxis::Horizontal => d
+                    .top_0()
+                    .h_full()
+                    .left(px(ROW_HEADER_WIDTH + self.frozen_width() - 1.))
+                    .w(px(2.)),
+            })
+    }
+
+    fn render_grid(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let entity = cx.entity().clone();
+        let cx: &Context<Self> = cx;
+        let theme = cx.global::<Theme>();
+        let end_row = (self.scroll_row + self.visible_rows).min(GRID_ROWS);
+        let offset_y = self.scroll_offset_y;
+        let frozen_rows = self.frozen_rows;
+        let frozen_cols = self.frozen_cols;
+
+        div()
+            .id("grid-area")
+            .relative()
+            .flex()
+            .flex_col()
+            .flex_1()
+            .overflow_hidden()
+            .on_mouse_move({
+                let entity = entity.clone();
+                move |event, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        if grid.resize_state.is_some() {
+                            match grid.resize_state.as_ref().unwrap().target {
+                                ResizeTarget::Column(_) => {
+                                    grid.update_resize(f32::from(event.position.x), cx);
+                                }
+                                ResizeTarget::Row(_) => {
+                                    grid.update_resize(f32::from(event.position.y), cx);
+                                }
+                            }
+                        }
+                    });
+                }
+            })
+            .on_mouse_up(MouseButton::Left, {
+                let entity = entity.clone();
+                move |event: &MouseUpEvent, _window, app| {
+                    entity.update(app, |grid, cx| {
+                        grid.selection_drag = None;
+                        if let Some(drag) = grid.move_drag.take() {
+                            let target = grid.move_drag_target(drag);
+                            grid.move_block(grid.selection_range(), target.start, event.modifiers.alt, cx);
+                        }
+                        if grid.resize_state.is_some() {
+                            grid.end_resize(cx);
+                        }
+                    });
+                }
+            })
+            // Frozen rows never scroll vertically
+            .children(
+                (0..frozen_rows)
+                    .filter(|&row| !self.is_row_hidden(row))
+                    .map(|row| self.render_grid_row(row, cx))
+            )
+            // Header row stays visible once scrolled past
+            .when_some(self.pinned_header_row(), |d, row| d.child(self.render_grid_row(row, cx)))
+            .child(
+                // Clipped container for scrolling rows with vertical scroll offset
+                div()
+                    .flex_1()
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .mt(px(-offset_y))
+                            .children(
+                                (self.scroll_row..end_row)
+                                    .filter(|&row| !self.is_row_hidden(row))
+                                    .map(|row| self.render_grid_row(row, cx))
+                            )
+                    )
+            )
+            .when(frozen_rows > 0 || self.pinned_header_row().is_some(), |d| {
+                d.child(self.render_frozen_divider(ScrollbarAxis::Vertical, theme))
+            })
+            .when(frozen_cols > 0, |d| {
+                d.child(self.render_frozen_divider(ScrollbarAxis::Horizontal, theme))
+            })
+    }
+
+    /// The viewer's rows; only those scrolled into view are parsed and built each frame
+    fn render_viewer(&self, viewer: &Viewer, cx: &Context<Self>) -> impl IntoElement {
+        let file = viewer.file.clone();
+        let cursor = viewer.row;
+        let col_width = self.default_col_width;
+        // Wide enough for the longest row number
+        let header_width = ROW_HEADER_WIDTH.max(group_thousands(file.row_count()).len() as f32 * 8.0 + 12.0);
+        let theme = cx.global::<Theme>();
+
+        div()
+            .flex()
+            .flex_col()
+            .flex_1()
+            .overflow_hidden()
+            .bg(theme.base)
+            .child(
+                uniform_list("viewer-rows", file.row_count(), move |range, _window, cx| {
+                    let theme = cx.global::<Theme>();
+                    range
+                        .map(|row| {
+                            let is_cursor = row == cursor;
+                            div()
+                                .flex()
+                                .flex_row()
+                                .h(px(DEFAULT_CELL_HEIGHT))
+                                .text_size(px(13.))
+                                .when(is_cursor, |d| d.bg(theme.surface0))
+                                .child(
+                                    div()
+                                        .w(px(header_width))
+                                        .h_full()
+                                        .flex_none()
+                                        .flex()
+                                        .items_center()
+                                        .justify_center()
+                                        .bg(theme.mantle)
+                                        .border_r_1()
+                                        .border_b_1()
+                                        .border_color(theme.surface0)
+                                        .text_size(px(12.))
+                                        .text_color(if is_cursor { theme.accent } else { theme.subtext0 })
+                                        .font_weight(if is_cursor { FontWeight::BOLD } else { FontWeight::NORMAL })
+                                        .child(group_thousands(row + 1)),
+                                )
+                                .children(file.row(row).into_iter().map(|field| {
+                                    div()
+                                        .w(px(col_width))
+                                        .h_full()
+                                        .flex_none()
+                                        .flex()
+                                        .items_center()
+                                        .px(px(4.))
+                                        .overflow_hidden()
+                                        .whitespace_nowrap()
+                                        .border_r_1()
+                                        .border_b_1()
+                                        .border_color(theme.surface0)
+                                        .child(field.replace('\n', " ⏎ "))
+                                }))
+                        })
+                        .collect()
+                })
+                .flex_1()
+                .track_scroll(viewer.scroll.clone())
+                .with_horizontal_sizing_behavior(ListHorizontalSizingBehavior::Unconstrained),
+            )
+    }
+
+    /// Render one grid row: row header, frozen cells, then horizontally scrolled cells
+    fn render_grid_row(&self, row: usize, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let is_row_selected = row == self.selected.row
+            || self.selected_rows().is_some_and(|rows| rows.contains(&row));
+        let is_drop_target = self.header_drag
+            .is_some_and(|drag| drag.kind == SelectionDrag::Rows && drag.over == row && drag.over != drag.grabbed);
+        let row_height = self.display_row_height(row, cx);
+        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
+        let offset_x = self.scroll_offset_x;
+        let group_header = self.row_outline.header_at(row).copied();
+        let in_group = self.row_outline.group_at(row).is_some();
+        // Differences from the saved file while comparing, else from git HEAD
+        let row_diff = match &self.git_baseline {
+            _ if self.diff_saved.is_some() => self.row_saved_diff(row),
+            Some(git) => git.rows[row],
+            None => None,
+        };
+
+        div()
+            .flex()
+            .flex_row()
+            .flex_none()
+            .h(px(row_height))
+            .child(
+                // Row header with resize handling
+                div()
+                    .id(ElementId::Name(format!("row-header-{}", row).into()))
+                    .relative()
+                    .w(px(ROW_HEADER_WIDTH))
+                    .h_full()
+                    .flex_none()
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .bg(theme.mantle)
+                    .border_r_1()
+                    .border_b_1()
+                    .border_color(theme.surface0)
+                    .text_size(px(12.))
+                    .text_color(if is_row_selected { theme.accent } else { theme.subtext0 })
+                    .font_weight(if is_row_selected { FontWeight::BOLD } else { FontWeight::NORMAL })
+                    .when(is_drop_target, |d| d.bg(theme.surface1))
+                    // Outline bar along the left of grouped rows
+                    .when(in_group, |d| d.border_l_2())
+                    .on_mouse_down(MouseButton::Left, {
+                        let entity = entity.clone();
+                        move |event, _window, app| {
+                            entity.update(app, |grid, cx| {
+                                grid.on_row_header_mouse_down(event, 0.0, cx);
+                            });
+                        }
+                    })
+                    .on_mouse_down(MouseButton::Left, {
+                        let entity = entity.clone();
+                        move |event, window, app| {
+                            entity.update(app, |grid, cx| grid.on_row_header_click(row, event, window, cx));
+                        }
+                    })
+                    .on_mouse_move({
+                        let entity = entity.clone();
+                        move |event, _window, app| {
+                            if event.pressed_button == Some(MouseButton::Left) {
+                                entity.update(app, |grid, cx| grid.on_selection_drag(SelectionDrag::Rows, row, cx));
+                            }
+                        }
+                    })
+                    .on_mouse_down(MouseButton::Right, {
+                        let entity = entity.clone();
+                        move |event, _window, app| {
+                            entity.update(app, |grid, cx| {
+                                grid.open_context_menu(MenuTarget::Rows, row, 0, event.position, cx);
+                            });
+                        }
+                    })
+                    .child(format!("{}", row + 1))
+                    // Change marker along the right edge of rows that differ from the saved file
+                    .when_some(row_diff, |d, diff| {
+                        d.child(div().absolute().top_0().right_0().w(px(3.)).h_full().bg(Self::diff_color(diff, theme)))
+                    })
+                    .when_some(group_header, |d, group| {
+                        d.child(
+                            self.render_group_toggle(group.collapsed, theme)
+                                .id(ElementId::Name(format!("row-group-toggle-{}", row).into()))
+                                .left(px(4.))
+                                .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                                    app.stop_propagation();
+                                    entity.update(app, |grid, cx| grid.toggle_row_group(row, cx));
+                                })
+                        )
+                    })
+            )
+            .children(
+                (0..self.frozen_cols)
+                    .filter(|&col| !self.is_col_hidden(col))
+                    .map(|col| self.render_cell(row, col, cx))
+            )
+            .child(
+                // Clipped container for cells with horizontal scroll offset
+                div()
+                    .flex_1()
+                    .h_full()
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .flex()
+                            .flex_row()
+                            .h_full()
+                            .ml(px(-offset_x))
+                            .children(
+                                (self.scroll_col..end_col)
+                                    .filter(|&col| !self.is_col_hidden(col))
+                                    .map(|col| self.render_cell(row, col, cx))
+                            )
+                    )
+            )
+    }
+
+    /// The compared file beside the grid: its rows paired with the grid's visible rows, under the
+    /// same columns and scroll position, and its name along the bottom
+    fn render_compare_panel(&self, comparison: &Comparison, width: f32, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let end_row = (self.scroll_row + self.visible_rows).min(GRID_ROWS);
+        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
+        let offset_x = self.scroll_offset_x;
+        let offset_y = self.scroll_offset_y;
+        let key_label = comparison.key.map_or("by row".to_string(), |col| format!("by {}", CellPosition::col_to_letter(col)));
+        let header_cell = |col: usize| {
+            div()
+                .w(px(self.column_widths[col]))
+                .h_full()
+                .flex_none()
+                .flex()
+                .items_center()
+                .justify_center()
+                .border_r_1()
+                .border_color(theme.surface0)
+                .child(CellPosition::col_to_letter(col))
+        };
+
+        div()
+            .id("compare-panel")
+            .flex()
+            .flex_col()
+            .flex_none()
+            .w(px(width))
+            .h_full()
+            .overflow_hidden()
+            .border_l_2()
+            .border_color(theme.surface1)
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_none()
+                    .h(px(COLUMN_HEADER_HEIGHT))
+                    .bg(theme.mantle)
+                    .border_b_1()
+                    .border_color(theme.surface0)
+                    .text_size(px(12.))
+                    .text_color(theme.subtext0)
+                    .child(div().w(px(ROW_HEADER_WIDTH)).h_full().flex_none().border_r_1().border_color(theme.surface0))
+                    .children((0..self.frozen_cols).filter(|&col| !self.is_col_hidden(col)).map(header_cell))
+                    .child(
+                        div().flex_1().h_full().overflow_hidden().child(
+                            div()
+                                .flex()
+                                .flex_row()
+                                .h_full()
+                                .ml(px(-offset_x))
+                                .children((self.scroll_col..end_col).filter(|&col| !self.is_col_hidden(col)).map(header_cell)),
+                        ),
+                    ),
+            )
+            .child(
+                div()
+                    .flex()
+                    .flex_col()
+                    .flex_1()
+                    .overflow_hidden()
+                    .children(
+                        (0..self.frozen_rows)
+                            .filter(|&row| !self.is_row_hidden(row))
+                            .map(|row| self.render_compare_row(comparison, row, cx)),
+                    )
+                    .when_some(self.pinned_header_row(), |d, row| d.child(self.render_compare_row(comparison, row, cx)))
+                    .child(
+                        div().flex_1().overflow_hidden().child(
+                            div()
+                                .flex()
+                                .flex_col()
+                                .mt(px(-offset_y))
+                                .children(
+                                    (self.scroll_row..end_row)
+                                        .filter(|&row| !self.is_row_hidden(row))
+                                        .map(|row| self.render_compare_row(comparison, row, cx)),
+                                ),
+                        ),
+                    ),
+            )
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_none()
+                    .items_center()
+                    .h(px(SCROLLBAR_SIZE))
+                    .px(px(8.))
+                    .bg(theme.mantle)
+                    .border_t_1()
+                    .border_color(theme.surface0)
+                    .text_size(px(11.))
+                    .text_color(theme.subtext0)
+                    .whitespace_nowrap()
+                    .overflow_hidden()
+                    .child(format!("{} ({})", comparison.file_name(), key_label)),
+            )
+    }
+
+    /// Side panel with a first look at each used column: its type, empty and distinct counts,
+    /// range of numbers, and a histogram of them (or its most frequent values)
+    fn render_profile_panel(&self, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let (_, last_col) = self.used_bounds;
+
+        let column = |col: usize| {
+            let stats = self.column_stats(col);
+            let label = self.header_row.map(|row| self.cells[row][col].trim()).unwrap_or_default();
+            let column_type = self.column_type(col).map_or("mixed", ColumnType::name);
+            // Bars are drawn relative to the tallest
+            let counts = stats.histogram.iter().chain(stats.top_values.iter().map(|(_, count)| count));
+            let bar_scale = counts.copied().max().unwrap_or(0).max(1) as f32;
+            div()
+                .flex()
+                .flex_col()
+                .gap(px(4.))
+                .p(px(8.))
+                .border_b_1()
+                .border_color(theme.surface0)
+                .child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .gap(px(6.))
+                        .child(div().font_weight(FontWeight::BOLD).text_color(theme.accent).child(CellPosition::col_to_letter(col)))
+                        .child(div().overflow_hidden().whitespace_nowrap().child(label.to_string())),
+                )
+                .child(
+                    div()
+                        .text_color(theme.subtext0)
+                        .child(format!("{} · {} empty · {} distinct", column_type, stats.empty, stats.distinct)),
+                )
+                .when_some(stats.numbers, |d, (min, max, _)| {
+                    let range = format!("{} to {}", formula::Value::Number(min), formula::Value::Number(max));
+                    d.child(div().text_color(theme.subtext0).child(range))
+                })
+                .when(!stats.histogram.is_empty(), |d| {
+                    d.child(
+                        div()
+                            .flex()
+                            .flex_row()
+                            .items_end()
+                            .gap(px(1.))
+                            .h(px(24.))
+                            .children(stats.histogram.iter().map(|&count| {
+                                div().flex_1().h(px(24. * count as f32 / bar_scale)).bg(theme.accent)
+                            })),
+                    )
+                })
+                .when(stats.histogram.is_empty(), |d| {
+                    d.children(stats.top_values.iter().map(|(value, count)| {
+                        div()
+                            .relative()
+                            .h(px(16.))
+                            .child(
+                                div()
+                                    .absolute()
+                                    .top_0()
+                                    .left_0()
+                                    .h_full()
+                                    .w(relative(*count as f32 / bar_scale))
+                                    .bg(Rgba { a: 0.25, ..theme.accent }),
+                            )
+                            .child(
+                                div()
+                                    .relative()
+                                    .flex()
+                                    .flex_row()
+                                    .justify_between()
+                                    .px(px(4.))
+                                    .whitespace_nowrap()
+                                    .overflow_hidden()
+                                    .child(value.clone())
+                                    .child(count.to_string()),
+                            )
+                    }))
+                })
+        };
+
+        div()
+            .id("profile-panel")
+            .flex()
+            .flex_col()
+            .flex_none()
+            .w(px(PROFILE_PANEL_WIDTH))
+            .h_full()
+            .overflow_y_scroll()
+            .border_l_2()
+            .border_color(theme.surface1)
+            .bg(theme.mantle)
+            .text_size(px(12.))
+            .children((0..=last_col).filter(|&col| !self.is_col_hidden(col)).map(column))
+    }
+
+    /// Side panel listing recorded cell changes, newest first
+    fn render_audit_panel(&self, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entries = self.audit.as_ref().map_or(&[][..], |audit| audit.entries.as_slice());
+        let entry = |entry: &AuditEntry| {
+            div()
+                .flex()
+                .flex_col()
+                .px(px(8.))
+                .py(px(4.))
+                .border_b_1()
+                .border_color(theme.surface0)
+                .child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .justify_between()
+                        .child(div().font_weight(FontWeight::BOLD).text_color(theme.accent).child(entry.cell.to_reference()))
+                        .child(div().text_color(theme.subtext0).child(entry.time.clone())),
+                )
+                .child(
+                    div()
+                        .whitespace_nowrap()
+                        .overflow_hidden()
+                        .child(format!("{} → {}", entry.old, entry.new)),
+                )
+        };
+
+        div()
+            .id("audit-panel")
+            .flex()
+            .flex_col()
+            .flex_none()
+            .w(px(AUDIT_PANEL_WIDTH))
+            .h_full()
+            .overflow_y_scroll()
+            .border_l_2()
+            .border_color(theme.surface1)
+            .bg(theme.mantle)
+            .text_size(px(12.))
+            .when(self.audit.is_none(), |d| {
+                d.child(div().p(px(8.)).text_color(theme.subtext0).child("Not recording; :set audit to start"))
+            })
+            .children(entries.iter().rev().take(AUDIT_PANEL_ENTRIES).map(entry))
+    }
+
+    /// A chart floating over the grid: a title bar to drag it by, with buttons to change its
+    /// kind, export it, or close it, above a legend and the plot
+    fn render_chart(&self, index: usize, chart: &Chart, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let data = self.chart_data(chart.range, chart.kind);
+        let palette = theme.series_colors();
+        let grid_color = theme.surface0;
+
+        let button = |id: &str, label: &'static str, on_press: fn(&mut Self, usize, &mut Context<Self>)| {
+            let entity = entity.clone();
+            div()
+                .id(ElementId::Name(format!("chart-{}-{}", index, id).into()))
+                .px(px(6.))
+                .rounded(px(3.))
+                .cursor_pointer()
+                .hover(|d| d.bg(theme.surface0))
+                .child(label)
+                .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                    // Not a drag of the title bar
+                    app.stop_propagation();
+                    entity.update(app, |grid, cx| {
+                        on_press(grid, index, cx);
+                        cx.notify();
+                    });
+                })
+        };
+        let kind_button = |kind: ChartKind| {
+            let entity = entity.clone();
+            div()
+                .id(ElementId::Name(format!("chart-{}-{}", index, kind.name()).into()))
+                .px(px(6.))
+                .rounded(px(3.))
+                .cursor_pointer()
+                .when(kind == chart.kind, |d| d.bg(theme.surface1))
+                .hover(|d| d.bg(theme.surface0))
+                .child(kind.name())
+                .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                    app.stop_propagation();
+                    entity.update(app, |grid, cx| {
+                        if let Some(chart) = grid.charts.get_mut(index) {
+                            chart.kind = kind;
+                        }
+                        cx.notify();
+                    });
+                })
+        };
+
+        let title_bar = div()
+            .id(ElementId::Name(format!("chart-title-{}", index).into()))
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap(px(2.))
+            .h(px(26.))
+            .px(px(6.))
+            .border_b_1()
+            .border_color(theme.surface0)
+            .on_mouse_down(MouseButton::Left, {
+                let entity = entity.clone();
+                let origin = chart.origin;
+                move |event, _window, app| {
+                    entity.update(app, |grid, _cx| grid.chart_drag = Some((index, event.position - origin)));
+                }
+            })
+            .child(
+                div()
+                    .flex_1()
+                    .overflow_hidden()
+                    .whitespace_nowrap()
+                    .text_color(theme.subtext0)
+                    .child(chart.range.to_reference()),
+            )
+            .children(ChartKind::ALL.map(kind_button))
+            .child(button("export", "PNG", |grid, index, cx| grid.export_chart(index, None, cx)))
+            .child(button("close", "×", |grid, index, _cx| {
+                grid.charts.remove(index);
+                grid.chart_drag = None;
+            }));
+
+        let body = match data {
+            None => div()
+                .flex()
+                .flex_1()
+                .items_center()
+                .justify_center()
+                .text_color(theme.overlay1)
+                .child(format!("No numbers in {}", chart.range.to_reference())),
+            Some(data) => {
+                let (min, max) = data.y_bounds(chart.kind);
+                // Scatter charts run from the smallest x to the largest; others, first point to last
+                let (first, last) = match chart.kind {
+                    ChartKind::Scatter => {
+                        let xs = data.categories.iter().filter_map(|category| value::parse_number(category));
+                        let (x_min, x_max) = xs.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), n| (min.min(n), max.max(n)));
+                        (formula::Value::Number(x_min).to_string(), formula::Value::Number(x_max).to_string())
+                    }
+                    _ => (
+                        data.categories.first().cloned().unwrap_or_default(),
+                        data.categories.last().cloned().unwrap_or_default(),
+                    ),
+                };
+                let legend = data.series.iter().enumerate().map(|(i, series)| {
+                    div()
+                        .flex()
+                        .flex_row()
+                        .items_center()
+                        .gap(px(4.))
+                        .child(div().size(px(8.)).rounded(px(2.)).bg(palette[i % palette.len()]))
+                        .child(series.name.clone())
+                });
+                let kind = chart.kind;
+                div()
+                    .flex()
+                    .flex_col()
+                    .flex_1()
+                    .gap(px(4.))
+                    .p(px(8.))
+                    .text_size(px(11.))
+                    .child(div().flex().flex_row().flex_wrap().gap(px(10.)).children(legend))
+                    .child(
+                        div()
+                            .flex()
+                            .flex_row()
+                            .flex_1()
+                            .gap(px(6.))
+                            .child(
+                                div()
+                                    .flex()
+                                    .flex_col()
+                                    .justify_between()
+                                    .w(px(44.))
+                                    .text_right()
+                                    .text_color(theme.overlay1)
+                                    .child(formula::Value::Number(max).to_string())
+                                    .child(formula::Value::Number(min).to_string()),
+                            )
+                            .child(
+                                canvas(|_, _, _| {}, move |bounds, _, window, _| {
+                                    data.paint(kind, bounds, &palette, grid_color, window)
+                                })
+                                .flex_1()
+                                .h_full(),
+                            ),
+                    )
+                    .child(
+                        div()
+                            .flex()
+                            .flex_row()
+                            .justify_between()
+                            .ml(px(50.))
+                            .text_color(theme.overlay1)
+                            .whitespace_nowrap()
+                            .child(first)
+                            .child(last),
+                    )
+            }
+        };
+
+        div()
+            .id(ElementId::Name(format!("chart-{}", index).into()))
+            .absolute()
+            .left(chart.origin.x)
+            .top(chart.origin.y)
+            .w(px(CHART_WIDTH))
+            .h(px(CHART_HEIGHT))
+            .flex()
+            .flex_col()
+            .bg(theme.mantle)
+            .border_1()
+            .border_color(theme.overlay0)
+            .rounded(px(6.))
+            .shadow_lg()
+            .overflow_hidden()
+            .text_size(px(12.))
+            .text_color(theme.text)
+            // Clicks land on the chart, not the cells under it, so it handles its own dragging
+            .occlude()
+            .on_mouse_move(cx.listener(|grid, event: &MouseMoveEvent, _window, cx| grid.drag_chart(event, cx)))
+            .on_mouse_up(MouseButton::Left, cx.listener(|grid, _event: &MouseUpEvent, _window, _cx| {
+                grid.chart_drag = None;
+            }))
+            .child(title_bar)
+            .child(body)
+    }
+
+    /// One row of the compare panel: the compared file's row paired with grid row `row`, if any
+    fn render_compare_row(&self, comparison: &Comparison, row: usize, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let other = comparison.aligned_row(&self.cells, row);
+        let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
+        let cell = |col: usize| {
+            let text = other
+                .and_then(|other| comparison.cells[other][col].lines().next())
+                .unwrap_or_default()
+                .to_string();
+            let diff = comparison.cell_diff(&self.cells, row, col);
+            div()
+                .relative()
+                .w(px(self.column_widths[col]))
+                .h_full()
+                .flex_none()
+                .flex()
+                .items_center()
+                .px(px(4.))
+                .border_r_1()
+                .border_b_1()
+                .border_color(theme.surface0)
+                .text_size(px(14.))
+                .whitespace_nowrap()
+                .overflow_hidden()
+                .child(text)
+                .when_some(diff, |d, diff| {
+                    let color = Self::diff_color(diff, theme);
+                    d.child(div().absolute().top_0().left_0().size_full().bg(Rgba { a: 0.15, ..color }))
+                })
+        };
+
+        div()
+            .flex()
+            .flex_row()
+            .flex_none()
+            .h(px(self.display_row_height(row, cx)))
+            .child(
+                // The compared file's row number, or a dash where no row pairs up
+                div()
+                    .w(px(ROW_HEADER_WIDTH))
+                    .h_full()
+                    .flex_none()
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .bg(theme.mantle)
+                    .border_r_1()
+                    .border_b_1()
+                    .border_color(theme.surface0)
+                    .text_size(px(12.))
+                    .text_color(theme.subtext0)
+                    .child(other.map_or("–".to_string(), |other| (other + 1).to_string())),
+            )
+            .children((0..self.frozen_cols).filter(|&col| !self.is_col_hidden(col)).map(cell))
+            .child(
+                div().flex_1().h_full().overflow_hidden().child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .h_full()
+                        .ml(px(-self.scroll_offset_x))
+                        .children((self.scroll_col..end_col).filter(|&col| !self.is_col_hidden(col)).map(cell)),
+                ),
+            )
+    }
+
+    fn render_cell(&self, row: usize, col: usize, cx: &Context<Self>) -> AnyElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let is_selected = row == self.selected.row && col == self.selected.col;
+        let is_remote_cursor = !is_selected
+            && self.collab.as_ref().and_then(|session| session.remote_cursor) == Some(CellPosition::new(row, col));
+        let in_selection = self.is_in_selection(CellPosition::new(row, col));
+        // Edges of the selection that can be dragged to move it (not for discontiguous selections)
+        let selection = self.selection_range();
+        let movable = self.mode == Mode::Normal && self.extra_selections.is_empty() && selection.contains(CellPosition::new(row, col));
+        let move_edges = [
+            movable && row == selection.start.row,
+            movable && row == selection.end.row,
+            movable && col == selection.start.col,
+            movable && col == selection.end.col,
+        ];
+        let drop_target = self.move_drag
+            .map(|drag| self.move_drag_target(drag))
+            .filter(|target| target.contains(CellPosition::new(row, col)));
+        let style = self.cell_styles[row][col];
+        let is_invalid = self.is_cell_invalid(row, col);
+        let col_width = self.column_widths[col];
+        let row_height = self.display_row_height(row, cx);
+        let is_header = self.header_row == Some(row);
+        let read_only = self.file_state.is_read_only;
+        let is_protected = self.is_protected(CellPosition::new(row, col));
+        let saved_diff = self.saved_diff(row, col).or_else(|| self.compared_diff(row, col));
+        let reference = self
+            .edit_references
+            .iter()
+            .position(|range| range.contains(CellPosition::new(row, col)));
+
+        if is_selected && self.mode == Mode::Edit {
+            // Render the active input for selected cell in edit mode
+            div()
+                .id(ElementId::Name(format!("cell-edit-{}-{}", row, col).into()))
+                .w(px(col_width))
+                .h(px(row_height))
+                .flex_none()
+                .border_2()
+                .border_color(theme.accent)
+                .overflow_hidden()
+                .child(self.active_input.clone())
+                .into_any_element()
+        } else {
+            // Render static cell with multiline and word-wrap support
+            let lines = self.cell_lines(row, col);
+            // Clipped text shows in full in a tooltip on hover
+            let full_text = lines_overflow(&lines, col_width, row_height).then(|| SharedString::from(self.display_text(row, col)));
+            let has_newlines = lines.len() > 1;
+            let background = if is_selected {
+                theme.surface0
+            } else if in_selection {
+                theme.surface1
+            } else if is_header || read_only || self.is_striped(row) {
+                theme.mantle
+            } else {
+                theme.base
+            };
+            let in_crosshair = !is_selected && self.crosshair.highlights(self.selected, row, col);
+            let single_line = lines.first().cloned().unwrap_or_default();
+            let alignment = self.cell_alignment(row, col);
+            let sparkline = match self.computed.get(row).and_then(|cols| cols.get(col)) {
+                Some(Some(formula::Value::Sparkline(sparkline))) => Some(sparkline.clone()),
+                _ => None,
+            };
+            let sparkline_color = style.color.map(rgb).unwrap_or(theme.accent);
+            let negative_color = theme.red;
+            div()
+                .id(ElementId::Name(format!("cell-{}-{}", row, col).into()))
+                .w(px(col_width))
+                .h(px(row_height))
+                .flex_none()
+                .flex()
+                .flex_col()
+                .when(!has_newlines, |d| d.justify_center())
+                .when(has_newlines, |d| d.pt(px(2.)))
+                .map(|d| match alignment {
+                    Alignment::Left => d.items_start().text_left(),
+                    Alignment::Center => d.items_center().text_center(),
+                    Alignment::Right => d.items_end().text_right(),
+                })
+                .px(px(4.))
+                .border_r_1()
+                .border_b_1()
+                // Without gridlines the borders keep their space but blend into the cell
+                .border_color(if is_selected {
+                    theme.accent
+                } else if is_remote_cursor {
+                    // A collaborator's cursor, in a second accent
+                    theme.peach
+                } else if self.show_gridlines {
+                    theme.surface0
+                } else {
+                    background
+                })
+                .when(is_selected || is_remote_cursor, |d| d.border_2())
+                .bg(background)
+                .text_size(px(14.))
+                // Read-only files draw their cells dimmed
+                .when(read_only, |d| d.text_color(theme.overlay2))
+                .when(is_header || style.bold, |d| d.font_weight(FontWeight::BOLD))
+                .when(style.italic, |d| d.italic())
+                .when(style.underline, |d| d.underline())
+                .when_some(style.color, |d, color| d.text_color(rgb(color)))
+                .overflow_hidden()
+                .when(in_crosshair, |d| {
+                    d.relative().child(div().absolute().top_0().left_0().size_full().bg(Rgba { a: 0.08, ..theme.accent }))
+                })
+                // An ellipsis at the right edge marks clipped text
+                .when(full_text.is_some(), |d| {
+                    d.relative().child(
+                        div()
+                            .absolute()
+                            .right_0()
+                            .bottom_0()
+                            .h(px(row_height.min(DEFAULT_CELL_HEIGHT)))
+                            .flex()
+                            .items_center()
+                            .px(px(2.))
+                            .bg(background)
+                            .text_color(theme.overlay1)
+                            .child("…"),
+                    )
+                })
+                .when_some(full_text, |d, text| {
+                    d.tooltip(move |_window, cx| cx.new(|_| CellTooltip { text: text.clone() }).into())
+                })
+                .on_mouse_down(MouseButton::Left, {
+                    let entity = entity.clone();
+                    move |event, window, app| {
+                        if event.click_count == 2 {
+                            entity.update(app, |this, cx| {
+                                this.on_cell_double_click(row, col, window, cx);
+                            });
+                        } else {
+                            entity.update(app, |this, cx| {
+                                this.on_cell_click(row, col, event.modifiers, window, cx);
+                            });
+                        }
+                    }
+                })
+                .on_mouse_down(MouseButton::Right, {
+                    let entity = entity.clone();
+                    move |event, _window, app| {
+                        entity.update(app, |this, cx| {
+                            this.open_context_menu(MenuTarget::Cell, row, col, event.position, cx);
+                        });
+                    }
+                })
+                .on_mouse_move({
+                    let entity = entity.clone();
+                    move |event, window, app| {
+                        if event.pressed_button == Some(MouseButton::Left) {
+                            entity.update(app, |this, cx| this.on_cell_drag(row, col, window, cx));
+                        }
+                    }
+                })
+                .when(!has_newlines, |d| d.whitespace_nowrap().child(single_line))
+                // Sparklines draw over the cell, inset from its edges
+                .when_some(sparkline, |d, sparkline| {
+                    d.relative().child(
+                        canvas(|_, _, _| {}, move |bounds, _, window, _| {
+                            sparkline.paint(bounds, sparkline_color, negative_color, window)
+                        })
+                        .absolute()
+                        .top(px(4.))
+                        .bottom(px(4.))
+                        .left(px(4.))
+                        .right(px(4.)),
+                    )
+                })
+                .when(has_newlines, |d| {
+                    d.children(lines.into_iter().map(|line| {
+                        div()
+                            .w_full()
+                            .whitespace_nowrap()
+                            .line_height(px(wrap::LINE_HEIGHT))
+                            .child(line)
+                    }))
+                })
+                // Outline cells the formula being edited refers to, in the reference's color
+                .when_some(reference, |d, index| {
+                    let range = self.edit_references[index];
+                    let color = theme.reference_color(index);
+                    d.relative().child(
+                        div()
+                            .absolute()
+                            .top_0()
+                            .left_0()
+                            .size_full()
+                            .bg(Rgba { a: 0.12, ..color })
+                            .border_color(color)
+                            .when(row == range.start.row, |d| d.border_t_2())
+                            .when(row == range.end.row, |d| d.border_b_2())
+                            .when(col == range.start.col, |d| d.border_l_2())
+                            .when(col == range.end.col, |d| d.border_r_2()),
+                    )
+                })
+                // Outline where a dragged selection would land
+                .when_some(drop_target, |d, target| {
+                    d.relative().child(
+                        div()
+                            .absolute()
+                            .top_0()
+                            .left_0()
+                            .size_full()
+                            .border_color(theme.accent)
+                            .when(row == target.start.row, |d| d.border_t_2())
+                            .when(row == target.end.row, |d| d.border_b_2())
+                            .when(col == target.start.col, |d| d.border_l_2())
+                            .when(col == target.end.col, |d| d.border_r_2()),
+                    )
+                })
+                // Grab handles along the selection's border start a move (alt to copy)
+                .when(move_edges.contains(&true), |d| {
+                    d.relative().children(move_edges.into_iter().enumerate().filter(|&(_, edge)| edge).map(|(side, _)| {
+                        let entity = entity.clone();
+                        div()
+                            .id(ElementId::Name(format!("move-handle-{}-{}-{}", row, col, side).into()))
+                            .absolute()
+                            .cursor_move()
+                            .map(|d| match side {
+                                0 => d.top_0().left_0().w_full().h(px(3.)),
+                                1 => d.bottom_0().left_0().w_full().h(px(3.)),
+                                2 => d.top_0().left_0().h_full().w(px(3.)),
+                                _ => d.top_0().right_0().h_full().w(px(3.)),
+                            })
+                            .on_mouse_down(MouseButton::Left, move |_event, _window, app| {
+                                app.stop_propagation();
+                                entity.update(app, |grid, cx| {
+                                    let pos = CellPosition::new(row, col);
+                                    grid.move_drag = Some(MoveDrag { grabbed: pos, over: pos });
+                                    cx.notify();
+                                });
+                            })
+                    }))
+                })
+                // Corner marker for values that fail the cell's validation
+                .when(is_invalid, |d| {
+                    d.relative().child(div().absolute().top_0().right_0().size(px(6.)).bg(theme.red))
+                })
+                .when(is_protected, |d| {
+                    d.relative().child(div().absolute().top_0().left_0().size(px(4.)).bg(theme.overlay0))
+                })
+                // Tint cells that differ from the saved or compared file
+                .when_some(saved_diff, |d, diff| {
+                    let color = Self::diff_color(diff, theme);
+                    d.relative().child(div().absolute().top_0().left_0().size_full().bg(Rgba { a: 0.15, ..color }))
+                })
+                .into_any_element()
+        }
+    }
+
+    /// The cursor cell drawn over its neighbors, big enough for its whole content, when
+    /// `expandselected` is on and the content doesn't fit in the cell
+    fn render_expanded_cell(&self, cx: &App) -> Option<impl IntoElement> {
+        let CellPosition { row, col } = self.selected;
+        let row_visible = row < self.frozen_rows
+            || self.pinned_header_row() == Some(row)
+            || (self.scroll_row..self.scroll_row + self.visible_rows).contains(&row);
+        let col_visible = col < self.frozen_cols || (self.scroll_col..self.scroll_col + self.visible_cols).contains(&col);
+        if !self.expand_selected
+            || self.mode != Mode::Normal
+            || !row_visible
+            || !col_visible
+            || self.is_row_hidden(row)
+            || self.is_col_hidden(col)
+            || !self.is_truncated(row, col)
+        {
+            return None;
+        }
+        let theme = cx.global::<Theme>();
+        let left = (ROW_HEADER_WIDTH + self.column_end_x(col) - self.col_width(col)).max(ROW_HEADER_WIDTH);
+        let top = self.chrome_height() + COLUMN_HEADER_HEIGHT + self.row_end_y(row) - self.row_height(row);
+        // Grow right to the edge of the grid, wrapping whatever is still too long
+        let max_width = (ROW_HEADER_WIDTH + self.grid_width - left).max(self.column_widths[col]);
+        let lines = wrap::wrap_text(&self.display_text(row, col), wrap::chars_per_line(max_width));
+        let longest = lines.iter().map(|line| wrap::text_width(line)).max().unwrap_or(0);
+        let width = (longest as f32 * wrap::CHAR_WIDTH + 12.0).clamp(self.column_widths[col], max_width);
+        let height = (lines.len() as f32 * wrap::LINE_HEIGHT + 8.0).max(self.row_heights[row]);
+
+        Some(
+            div()
+                .absolute()
+                .top(px(top))
+                .left(px(left))
+                .w(px(width))
+                .min_h(px(height))
+                .flex()
+                .flex_col()
+                .justify_center()
+                .px(px(4.))
+                .bg(theme.surface0)
+                .border_2()
+                .border_color(theme.accent)
+                .shadow_lg()
+                .text_size(px(14.))
+                .children(lines.into_iter().map(|line| {
+                    div().whitespace_nowrap().line_height(px(wrap::LINE_HEIGHT)).child(line)
+                })),
+        )
+    }
+
+    /// Popup list anchored under the cell being edited
+    fn render_edit_popup(&self, id: &'static str, cx: &App) -> Stateful<Div> {
+        let theme = cx.global::<Theme>();
+        let CellPosition { row, col } = self.selected;
+        let left = (ROW_HEADER_WIDTH + self.column_end_x(col) - self.col_width(col)).max(ROW_HEADER_WIDTH);
+        let top = self.chrome_height() + COLUMN_HEADER_HEIGHT + self.row_end_y(row) - self.row_height(row)
+            + self.display_row_height(row, cx);
+
+        div()
+            .id(id)
+            .absolute()
+            .top(px(top))
+            .left(px(left))
+            .min_w(px(self.column_widths[col].max(120.)))
+            .max_h(px(240.))
+            .overflow_y_scroll()
+            .flex()
+            .flex_col()
+            .p(px(4.))
+            .gap(px(2.))
+            .bg(theme.mantle)
+            .border_1()
+            .border_color(theme.overlay0)
+            .rounded(px(6.))
+            .shadow_lg()
+            .text_size(px(12.))
+            .text_color(theme.text)
+    }
+
+    /// Dropdown of allowed values under a list-validated cell being edited
+    fn render_validation_menu(&self, choices: Vec<String>, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+        let current = self.active_input.read(cx).get_content();
+
+        self.render_edit_popup("validation-menu", cx)
+            .children(choices.into_iter().enumerate().map(|(i, value)| {
+                let entity = entity.clone();
+                let is_current = value == current.trim();
+                div()
+                    .id(ElementId::Name(format!("validation-value-{}", i).into()))
+                    .px(px(8.))
+                    .py(px(3.))
+                    .rounded(px(3.))
+                    .cursor_pointer()
+                    .when(is_current, |d| d.bg(theme.surface0))
+                    .hover(|d| d.bg(theme.surface0))
+                    .child(value.clone())
+                    .on_mouse_down(MouseButton::Left, move |_event, window, app| {
+                        app.stop_propagation();
+                        entity.update(app, |grid, cx| grid.choose_validation_value(value.clone(), window, cx));
+                    })
+            }))
+    }
+
+    /// Function names completing the formula being typed; up/down move the highlight and
+    /// tab (or a click) accepts
+    fn render_completion_menu(&self, completions: Vec<&'static str>, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let input = self.active_input.clone();
+        let highlighted = self.active_input.read(cx).completion_index;
+
+        self.render_edit_popup("completion-menu", cx)
+            .font_family("Berkeley Mono")
+            .children(completions.into_iter().enumerate().map(|(i, name)| {
+                let input = input.clone();
+                div()
+                    .id(ElementId::Name(format!("completion-{}", i).into()))
+                    .px(px(8.))
+                    .py(px(3.))
+                    .rounded(px(3.))
+                    .cursor_pointer()
+                    .when(i == highlighted, |d| d.bg(theme.surface0).text_color(theme.accent))
+                    .hover(|d| d.bg(theme.surface0))
+                    .child(name)
+                    .on_mouse_down(MouseButton::Left, move |_event, window, app| {
+                        app.stop_propagation();
+                        input.update(app, |input, cx| input.complete(name, window, cx));
+                    })
+            }))
+    }
+
+    fn render_scrollbar_thumb(&self, axis: ScrollbarAxis, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let metrics = self.scrollbar_metrics(axis);
+        let is_dragging = self.scrollbar_drag.is_some_and(|drag| drag.axis == axis);
+        let thickness = SCROLLBAR_SIZE - 4.0;
+
+        div()
+            .absolute()
+            .rounded(px(thickness / 2.0))
+            .bg(if is_dragging { theme.overlay0 } else { theme.surface2 })
+            .map(|d| match axis {
+                ScrollbarAxis::Vertical => d
+                    .left(px(2.))
+                    .w(px(thickness))
+                    .top(px(metrics.thumb_start))
+                    .h(px(metrics.thumb_len)),
+                ScrollbarAxis::Horizontal => d
+                    .top(px(2.))
+                    .h(px(thickness))
+                    .left(px(metrics.thumb_start))
+                    .w(px(metrics.thumb_len)),
+            })
+    }
+
+    fn render_vertical_scrollbar(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+
+        div()
+            .id("vertical-scrollbar")
+            .relative()
+            .w(px(SCROLLBAR_SIZE))
+            .h_full()
+            .flex_none()
+            .bg(theme.mantle)
+            .border_l_1()
+            .border_color(theme.surface0)
+            .on_mouse_down(MouseButton::Left, move |event, _window, app| {
+                entity.update(app, |grid, cx| {
+                    grid.on_scrollbar_mouse_down(ScrollbarAxis::Vertical, event, cx);
+                });
+            })
+            .child(self.render_scrollbar_thumb(ScrollbarAxis::Vertical, cx))
+    }
+
+    fn render_horizontal_scrollbar(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let entity = cx.entity().clone();
+
+        div()
             .flex()
             .flex_row()
-            .h(px(COLUMN_HEADER_HEIGHT))
+            .w_full()
+            .h(px(SCROLLBAR_SIZE))
+            .flex_none()
             .bg(theme.mantle)
-            .border_b_1()
+            .border_t_1()
             .border_color(theme.surface0)
-            .on_mouse_down(MouseButton::Left, {
-                let entity = entity.clone();
-                move |event, _window, app| {
-                    entity.update(app, |grid, cx| {
-                        grid.on_column_header_mouse_down(event, 0.0, cx);
-                    });
-                }
-            })
-            .on_mouse_move({
-                let entity = entity.clone();
-                move |event, _window, app| {
-                    entity.update(app, |grid, cx| {
-                        if grid.resize_state.is_some() {
-                            grid.update_resize(f32::from(event.position.x), cx);
-                        }
-                    });
-                }
-            })
-            .on_mouse_up(MouseButton::Left, {
-                let entity = entity.clone();
-                move |_event, _window, app| {
-                    entity.update(app, |grid, cx| {
-                        if grid.resize_state.is_some() {
-                            grid.end_resize(cx);
-                        }
-                    });
-                }
-            })
             .child(
-                // Empty corner cell
+                // Spacer under the row headers
                 div()
                     .w(px(ROW_HEADER_WIDTH))
                     .h_full()
@@ -1168,229 +8052,163 @@ impl SpreadsheetGrid {
                     .border_color(theme.surface0)
             )
             .child(
-                // Clipped container for column headers with horizontal scroll offset
                 div()
+                    .id("horizontal-scrollbar")
+                    .relative()
                     .flex_1()
                     .h_full()
-                    .overflow_hidden()
-                    .child(
-                        div()
-                            .flex()
-                            .flex_row()
-                            .h_full()
-                            .ml(px(-offset_x))
-                            .children(
-                                (self.scroll_col..end_col).map(move |col| {
-                                    let col_letter = CellPosition::new(0, col).to_reference();
-                                    let col_letter: String = col_letter.chars().take_while(|c| c.is_alphabetic()).collect();
-                                    let is_selected = col == selected_col;
-                                    let col_width = column_widths[col];
-
-                                    div()
-                                        .w(px(col_width))
-                                        .h_full()
-                                        .flex_none()
-                                        .flex()
-                                        .items_center()
-                                        .justify_center()
-                                        .border_r_1()
-                                        .border_color(theme.surface0)
-                                        .text_size(px(12.))
-                                        .text_color(if is_selected { theme.accent } else { theme.subtext0 })
-                                        .font_weight(if is_selected { FontWeight::BOLD } else { FontWeight::NORMAL })
-                                        .child(col_letter)
-                                })
-                            )
-                    )
+                    .on_mouse_down(MouseButton::Left, move |event, _window, app| {
+                        entity.update(app, |grid, cx| {
+                            grid.on_scrollbar_mouse_down(ScrollbarAxis::Horizontal, event, cx);
+                        });
+                    })
+                    .child(self.render_scrollbar_thumb(ScrollbarAxis::Horizontal, cx))
+            )
+            .child(
+                // Corner between the two scrollbars
+                div()
+                    .w(px(SCROLLBAR_SIZE))
+                    .h_full()
+                    .flex_none()
             )
     }
 
-    fn render_grid(&self, cx: &mut Context<Self>) -> impl IntoElement {
-        let theme = cx.global::<Theme>();
-        let entity = cx.entity().clone();
+    /// Cells drawn in the visible rows and columns, and how many of those have text
+    fn drawn_cell_counts(&self) -> (usize, usize) {
         let end_row = (self.scroll_row + self.visible_rows).min(GRID_ROWS);
         let end_col = (self.scroll_col + self.visible_cols).min(GRID_COLS);
-        let column_widths = self.column_widths.clone();
-        let row_heights = self.row_heights.clone();
-        let cells = self.cells.clone();
-        let selected = self.selected;
-        let mode = self.mode;
-        let active_input = self.active_input.clone();
-        let scroll_col = self.scroll_col;
-        let offset_x = self.scroll_offset_x;
-        let offset_y = self.scroll_offset_y;
+        let rows = (0..self.frozen_rows).chain(self.scroll_row.max(self.frozen_rows)..end_row).filter(|&row| !self.is_row_hidden(row));
+        let cols: Vec<usize> = (0..self.frozen_cols)
+            .chain(self.scroll_col.max(self.frozen_cols)..end_col)
+            .filter(|col| !self.hidden_cols.contains(col))
+            .collect();
+        rows.fold((0, 0), |(drawn, shaped), row| {
+            let with_text = cols.iter().filter(|&&col| !self.display_text(row, col).is_empty()).count();
+            (drawn + cols.len(), shaped + with_text)
+        })
+    }
 
+    /// Frame timings over the top-right of the grid (`:set perf`)
+    fn render_perf_overlay(&self, perf: Rc<RefCell<PerfStats>>, cx: &Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+        let (drawn, shaped) = self.drawn_cell_counts();
+        let lines = {
+            let mut stats = perf.borrow_mut();
+            stats.cells_drawn = drawn;
+            stats.cells_shaped = shaped;
+            let (mean, worst) = stats.recent();
+            vec![
+                format!("frame   {}", perf::millis(stats.frame_time)),
+                format!("  avg   {}  worst {}", perf::millis(mean), perf::millis(worst)),
+                format!("layout  {}", perf::millis(stats.layout_time)),
+                format!("formula {}", perf::millis(stats.formula_time)),
+                format!("cells   {} drawn, {} shaped", stats.cells_drawn, stats.cells_shaped),
+                // Cell text and wrapping are recomputed every frame
+                "cache   none".to_string(),
+            ]
+        };
+        let top = self.chrome_height() + COLUMN_HEADER_HEIGHT + 8.0;
         div()
-            .id("grid-area")
-            .flex()
-            .flex_col()
-            .flex_1()
-            .overflow_hidden()
-            .on_mouse_move({
-                let entity = entity.clone();
-                move |event, _window, app| {
-                    entity.update(app, |grid, cx| {
-                        if grid.resize_state.is_some() {
-                            match grid.resize_state.as_ref().unwrap().target {
-                                ResizeTarget::Column(_) => {
-                                    grid.update_resize(f32::from(event.position.x), cx);
-                                }
-                                ResizeTarget::Row(_) => {
-                                    grid.update_resize(f32::from(event.position.y), cx);
-                                }
-                            }
-                        }
-                    });
-                }
-            })
-            .on_mouse_up(MouseButton::Left, {
-                let entity = entity.clone();
-                move |_event, _window, app| {
-                    entity.update(app, |grid, cx| {
-                        if grid.resize_state.is_some() {
-                            grid.end_resize(cx);
-                        }
-                    });
-                }
-            })
+            .absolute()
+            .top(px(top))
+            .right(px(SCROLLBAR_SIZE + 8.0))
+            .p(px(8.))
+            .rounded(px(4.))
+            .bg(Rgba { a: 0.9, ..theme.crust })
+            .border_1()
+            .border_color(theme.surface1)
+            .text_size(px(11.))
+            .font_family("monospace")
+            .text_color(theme.text)
+            .children(lines.into_iter().map(|line| div().child(line)))
             .child(
-                // Inner container with vertical scroll offset
-                div()
-                    .flex()
-                    .flex_col()
-                    .mt(px(-offset_y))
-                    .children(
-                        (self.scroll_row..end_row).map(move |row| {
-                            let is_row_selected = row == selected.row;
-                            let row_height = row_heights[row];
-                            let column_widths = column_widths.clone();
-                            let cells = cells.clone();
-                            let entity = entity.clone();
-                            let active_input = active_input.clone();
-
-                            div()
-                                .flex()
-                                .flex_row()
-                                .h(px(row_height))
-                                .child({
-                                    // Row header with resize handling
-                                    let entity = entity.clone();
-                                    div()
-                                        .id(ElementId::Name(format!("row-header-{}", row).into()))
-                                        .w(px(ROW_HEADER_WIDTH))
-                                        .h_full()
-                                        .flex_none()
-                                        .flex()
-                                        .items_center()
-                                        .justify_center()
-                                        .bg(theme.mantle)
-                                        .border_r_1()
-                                        .border_b_1()
-                                        .border_color(theme.surface0)
-                                        .text_size(px(12.))
-                                        .text_color(if is_row_selected { theme.accent } else { theme.subtext0 })
-                                        .font_weight(if is_row_selected { FontWeight::BOLD } else { FontWeight::NORMAL })
-                                        .on_mouse_down(MouseButton::Left, {
-                                            move |event, _window, app| {
-                                                entity.update(app, |grid, cx| {
-                                                    grid.on_row_header_mouse_down(event, 0.0, cx);
-                                                });
-                                            }
-                                        })
-                                        .child(format!("{}", row + 1))
-                                })
-                                .child(
-                                    // Clipped container for cells with horizontal scroll offset
-                                    div()
-                                        .flex_1()
-                                        .h_full()
-                                        .overflow_hidden()
-                                        .child(
-                                            div()
-                                                .flex()
-                                                .flex_row()
-                                                .h_full()
-                                                .ml(px(-offset_x))
-                                                .children(
-                                                    (scroll_col..end_col).map(move |col| {
-                                                        let is_selected = row == selected.row && col == selected.col;
-                                                        let content = cells[row][col].clone();
-                                                        let col_width = column_widths[col];
-                                                        let entity = entity.clone();
-
-                                                        if is_selected && mode == Mode::Edit {
-                                                            // Render the active input for selected cell in edit mode
-                                                            div()
-                                                                .id(ElementId::Name(format!("cell-edit-{}-{}", row, col).into()))
-                                                                .w(px(col_width))
-                                                                .h(px(row_height))
-                                                                .flex_none()
-                                                                .border_2()
-                                                                .border_color(theme.accent)
-                                                                .overflow_hidden()
-                                                                .child(active_input.clone())
-                                                        } else {
-                                                            // Render static cell with multiline support
-                                                            let has_newlines = content.contains('\n');
-                                                            div()
-                                                                .id(ElementId::Name(format!("cell-{}-{}", row, col).into()))
-                                                                .w(px(col_width))
-                                                                .h(px(row_height))
-                                                                .flex_none()
-                                                                .flex()
-                                                                .flex_col()
-                                                                .when(!has_newlines, |d| d.items_center().justify_center())
-                                                                .when(has_newlines, |d| d.items_start().pt(px(2.)))
-                                                                .px(px(4.))
-                                                                .border_r_1()
-                                                                .border_b_1()
-                                                                .border_color(if is_selected { theme.accent } else { theme.surface0 })
-                                                                .when(is_selected, |d| d.border_2())
-                                                                .bg(if is_selected { theme.surface0 } else { theme.base })
-                                                                .text_size(px(14.))
-                                                                .overflow_hidden()
-                                                                .on_mouse_down(MouseButton::Left, {
-                                                                    move |event, window, app| {
-                                                                        if event.click_count == 2 {
-                                                                            entity.update(app, |this, cx| {
-                                                                                this.on_cell_double_click(row, col, window, cx);
-                                                                            });
-                                                                        } else {
-                                                                            entity.update(app, |this, cx| {
-                                                                                this.on_cell_click(row, col, window, cx);
-                                                                            });
-                                                                        }
-                                                                    }
-                                                                })
-                                                                .when(!has_newlines, |d| d.child(content.clone()))
-                                                                .when(has_newlines, |d| {
-                                                                    d.children(content.lines().map(|line| {
-                                                                        div()
-                                                                            .w_full()
-                                                                            .line_height(px(18.))
-                                                                            .child(line.to_string())
-                                                                    }))
-                                                                })
-                                                        }
-                                                    })
-                                                )
-                                        )
-                                )
-                        })
-                    )
+                canvas(
+                    {
+                        let perf = perf.clone();
+                        move |_, _, _| perf.borrow_mut().laid_out()
+                    },
+                    move |_, _, _, _| perf.borrow_mut().painted(),
+                )
+                .size_0(),
             )
     }
 
     fn render_footer(&self, cx: &mut Context<Self>) -> impl IntoElement {
         let theme = cx.global::<Theme>();
         let mode_text = match self.mode {
+            _ if self.viewer.is_some() => "-- VIEWER --",
+            Mode::Normal if self.visual_mode => "-- VISUAL --",
             Mode::Normal => "-- NORMAL --",
             Mode::Edit => "-- EDIT --",
         };
 
+        // The cursor cell's full value, when it's too big for the cell
+        let CellPosition { row, col } = self.selected;
+        let selected_text = (self.mode == Mode::Normal && self.is_truncated(row, col))
+            .then(|| self.display_text(row, col).replace('\n', " ⏎ "));
         let file_name = self.file_state.file_name();
         let dirty_indicator = if self.file_state.is_dirty { "[+] " } else { "" };
-        let read_only_indicator = if self.file_state.is_read_only { "[RO] " } else { "" };
+        let read_only_indicator = if self.file_state.is_read_only { "🔒 Read-only " } else { "" };
+        let encrypted_indicator = if self.file_state.passphrase.is_some() { "[ENCRYPTED] " } else { "" };
+        let calc_indicator = match self.calc_mode {
+            CalcMode::Manual if self.results_stale() => "[CALC: stale, F9] ",
+            CalcMode::Manual => "[CALC: manual] ",
+            CalcMode::Automatic => "",
+        };
+        let diff_indicator = match &self.diff_saved {
+            Some(_) => {
+                let changed = (0..GRID_ROWS)
+                    .flat_map(|row| (0..GRID_COLS).map(move |col| (row, col)))
+                    .filter(|&(row, col)| self.saved_diff(row, col).is_some())
+                    .count();
+                format!("[DIFF {} cells] ", changed)
+            }
+            None => String::new(),
+        };
+        let compare_indicator = match &self.comparison {
+            Some(comparison) => {
+                let (differing, unpaired) = comparison.summary(&self.cells);
+                format!("[COMPARE {} cells, {} rows only in {}] ", differing, unpaired, comparison.file_name())
+            }
+            None => String::new(),
+        };
+        // Where the cursor is within the used extent, e.g. `R42 C7 of 3,120 × 24`
+        let (last_row, last_col) = self.used_bounds;
+        let (rows, cols) = match self.cells[0][0].is_empty() && self.used_bounds == (0, 0) {
+            true => (0, 0),
+            false => (last_row + 1, last_col + 1),
+        };
+        let position_indicator = match &self.viewer {
+            // Rows are counted as indexing finds them
+            Some(viewer) => {
+                let file = &viewer.file;
+                let progress = match file.is_indexed() {
+                    true => String::new(),
+                    false => format!(", indexing {}%", file.indexed_len() * 100 / file.size().max(1)),
+                };
+                format!("R{} of {}{} ", group_thousands(viewer.row + 1), group_thousands(file.row_count()), progress)
+            }
+            None => format!(
+                "R{} C{} of {} × {} ",
+                group_thousands(row + 1),
+                group_thousands(col + 1),
+                group_thousands(rows),
+                group_thousands(cols)
+            ),
+        };
+        let memory_indicator = match self.show_memory_stats {
+            true => {
+                let bytes: usize = self.cells.iter().flatten().map(|cell| cell.capacity() + size_of::<String>()).sum();
+                format!("[MEM cells ~{}] ", format_bytes(bytes))
+            }
+            false => String::new(),
+        };
+        let filter_indicator = if self.is_filtering() {
+            let (matching, total) = self.filter_match_count();
+            format!("[FILTER {}/{} rows] ", matching, total)
+        } else {
+            String::new()
+        };
 
         div()
             .flex()
@@ -1407,8 +8225,23 @@ impl SpreadsheetGrid {
             .text_color(theme.subtext0)
             .child(
                 div()
-                    .font_weight(FontWeight::BOLD)
-                    .child(mode_text)
+                    .flex()
+                    .flex_row()
+                    .gap(px(12.))
+                    .child(
+                        div()
+                            .font_weight(FontWeight::BOLD)
+                            .child(mode_text)
+                    )
+                    .when_some(self.pending_count, |d, count| {
+                        d.child(div().text_color(theme.text).child(count.to_string()))
+                    })
+                    .when_some(self.footer_message.clone(), |d, message| {
+                        d.child(div().text_color(theme.peach).child(message))
+                    })
+                    .when_some(selected_text, |d, text| {
+                        d.child(div().max_w(px(480.)).overflow_hidden().whitespace_nowrap().text_color(theme.text).child(text))
+                    })
             )
             .child(
                 div()
@@ -1417,7 +8250,30 @@ impl SpreadsheetGrid {
                     .gap(px(8.))
                     .child(
                         div()
-                            .when(self.file_state.is_read_only, |d| d.text_color(theme.overlay1))
+                            .text_color(if self.results_stale() { theme.peach } else { theme.subtext0 })
+                            .child(calc_indicator)
+                    )
+                    .child(
+                        div()
+                            .text_color(theme.yellow)
+                            .child(diff_indicator)
+                    )
+                    .child(
+                        div()
+                            .text_color(theme.yellow)
+                            .child(compare_indicator)
+                    )
+                    .child(
+                        div()
+                            .text_color(theme.accent)
+                            .child(filter_indicator)
+                    )
+                    .child(memory_indicator)
+                    .child(position_indicator)
+                    .child(encrypted_indicator)
+                    .child(
+                        div()
+                            .when(self.file_state.is_read_only, |d| d.text_color(theme.peach))
                             .child(read_only_indicator)
                     )
                     .child(
@@ -1433,36 +8289,74 @@ impl SpreadsheetGrid {
 impl Render for SpreadsheetGrid {
     fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         // Calculate visible rows and columns based on window size
+        if let Some(perf) = &self.perf {
+            perf.borrow_mut().start_frame();
+        }
         let content_bounds = window.viewport_size();
-        self.grid_height = f32::from(content_bounds.height) - HEADER_HEIGHT - COLUMN_HEADER_HEIGHT - FOOTER_HEIGHT;
-        self.grid_width = f32::from(content_bounds.width) - ROW_HEADER_WIDTH;
+        self.grid_height = f32::from(content_bounds.height)
+            - self.chrome_height()
+            - COLUMN_HEADER_HEIGHT
+            - SCROLLBAR_SIZE
+            - FOOTER_HEIGHT;
+        // Comparing splits the width between the grid and the compared file
+        let compare_width = if self.comparison.is_some() { (f32::from(content_bounds.width) / 2.0).floor() } else { 0.0 };
+        let profile_width = if self.show_profile { PROFILE_PANEL_WIDTH } else { 0.0 };
+        let audit_width = if self.show_audit { AUDIT_PANEL_WIDTH } else { 0.0 };
+        self.grid_width = f32::from(content_bounds.width)
+            - compare_width
+            - profile_width
+            - audit_width
+            - ROW_HEADER_WIDTH
+            - SCROLLBAR_SIZE;
 
         // Calculate visible rows by summing row heights from scroll position
-        self.visible_rows = self.calculate_visible_rows(self.grid_height);
-        self.visible_cols = self.calculate_visible_cols(self.grid_width);
+        self.visible_rows = self.calculate_visible_rows(self.scroll_area_height());
+        self.visible_cols = self.calculate_visible_cols(self.scroll_area_width());
 
-        // Ensure selection is still visible after resize
-        self.ensure_visible();
+        // Ensure selection is still visible after resize (a scroll animation gets there itself)
+        if self.scroll_animation.is_none() {
+            self.ensure_visible();
+            self.keep_edit_cell_visible(cx);
+        }
+
+        self.edit_references = if self.mode == Mode::Edit {
+            formula::highlight(&self.active_input.read(cx).content).references
+        } else {
+            Vec::new()
+        };
+
+        // Clicking away from the name box puts it back
+        if self.editing_name_box && !self.name_box.focus_handle(cx).is_focused(window) {
+            self.editing_name_box = false;
+        }
 
         let key_context = if self.show_command_palette {
             "CommandPalette"
+        } else if self.editing_name_box {
+            "NameBox"
         } else if self.mode == Mode::Edit {
             "EditMode"
+        } else if self.visual_mode {
+            "NormalMode VisualMode"
         } else {
             "NormalMode"
         };
 
         // Set up command handler for the palette
         let entity = cx.entity().clone();
+        let provider_entity = entity.clone();
         self.command_palette.update(cx, |palette, _cx| {
             palette.set_command_handler(move |cmd_id, vim_cmd, window, app| {
                 entity.update(app, |grid, cx| {
                     grid.handle_command(cmd_id, vim_cmd, window, cx);
                 });
             });
+            palette.set_choices_provider(move |cmd_id, app| provider_entity.read(app).prompt_choices(cmd_id));
         });
 
         let show_palette = self.show_command_palette;
+        let validation_choices = if self.mode == Mode::Edit { self.validation_choices(cx) } else { Vec::new() };
+        let completions = if self.mode == Mode::Edit { self.active_input.read(cx).completions() } else { Vec::new() };
 
         div()
             .id("spreadsheet-root")
@@ -1472,18 +8366,84 @@ impl Render for SpreadsheetGrid {
             .key_context(key_context)
             .track_focus(&self.focus_handle)
             .on_scroll_wheel(cx.listener(Self::handle_scroll_wheel))
+            .on_key_down(cx.listener(Self::handle_key_down))
+            // Scrollbar drags track the mouse anywhere in the window
+            .on_mouse_move(cx.listener(|grid, event: &MouseMoveEvent, _window, cx| {
+                if grid.scrollbar_drag.is_some() {
+                    grid.update_scrollbar_drag(event.position, cx);
+                }
+                grid.drag_chart(event, cx);
+            }))
+            .on_mouse_up(MouseButton::Left, cx.listener(|grid, _event: &MouseUpEvent, _window, cx| {
+                if grid.scrollbar_drag.is_some() {
+                    grid.end_scrollbar_drag(cx);
+                }
+                grid.drop_header_drag(cx);
+                grid.chart_drag = None;
+            }))
             // Normal mode actions
             .on_action(cx.listener(Self::move_up))
             .on_action(cx.listener(Self::move_down))
             .on_action(cx.listener(Self::move_left))
             .on_action(cx.listener(Self::move_right))
+            .on_action(cx.listener(Self::pick_from_list))
+            .on_action(cx.listener(Self::move_row_up))
+            .on_action(cx.listener(Self::move_row_down))
+            .on_action(cx.listener(Self::move_column_left))
+            .on_action(cx.listener(Self::move_column_right))
+            .on_action(cx.listener(Self::clear_contents))
+            .on_action(cx.listener(Self::clear_formats))
+            .on_action(cx.listener(Self::clear_all))
+            .on_action(cx.listener(Self::increment))
+            .on_action(cx.listener(Self::decrement))
+            .on_action(cx.listener(Self::increment_sequence))
+            .on_action(cx.listener(Self::decrement_sequence))
+            .on_action(cx.listener(Self::go_to_first_row))
+            .on_action(cx.listener(Self::go_to_last_row))
+            .on_action(cx.listener(Self::go_to_first_cell))
+            .on_action(cx.listener(Self::go_to_last_cell))
+            .on_action(cx.listener(Self::page_up))
+            .on_action(cx.listener(Self::page_down))
             .on_action(cx.listener(Self::enter_edit_mode))
+            .on_action(cx.listener(Self::extend_up))
+            .on_action(cx.listener(Self::extend_down))
+            .on_action(cx.listener(Self::extend_left))
+            .on_action(cx.listener(Self::extend_right))
+            .on_action(cx.listener(Self::toggle_visual_mode))
+            .on_action(cx.listener(Self::select_all))
+            .on_action(cx.listener(Self::select_data_block))
+            .on_action(cx.listener(Self::revert_to_saved))
+            .on_action(cx.listener(Self::clear_selection))
+            // Clipboard
+            .on_action(cx.listener(Self::copy_selection))
+            .on_action(cx.listener(Self::cut_selection))
+            .on_action(cx.listener(Self::paste_at_cursor))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::paste_transposed))
+            .on_action(cx.listener(Self::toggle_bold))
+            .on_action(cx.listener(Self::toggle_italic))
+            .on_action(cx.listener(Self::toggle_underline))
+            .on_action(cx.listener(Self::format_general))
+            .on_action(cx.listener(Self::format_decimal))
+            .on_action(cx.listener(Self::format_thousands))
+            .on_action(cx.listener(Self::format_currency))
+            .on_action(cx.listener(Self::format_percent))
+            .on_action(cx.listener(Self::format_scientific))
             // Edit mode actions
             .on_action(cx.listener(Self::exit_edit_mode))
             .on_action(cx.listener(Self::exit_and_move_up))
             .on_action(cx.listener(Self::exit_and_move_down))
             .on_action(cx.listener(Self::exit_and_move_left))
             .on_action(cx.listener(Self::exit_and_move_right))
+            .on_action(cx.listener(Self::point_up))
+            .on_action(cx.listener(Self::point_down))
+            .on_action(cx.listener(Self::point_left))
+            .on_action(cx.listener(Self::point_right))
+            .on_action(cx.listener(Self::point_extend_up))
+            .on_action(cx.listener(Self::point_extend_down))
+            .on_action(cx.listener(Self::point_extend_left))
+            .on_action(cx.listener(Self::point_extend_right))
             // File actions
             .on_action(cx.listener(Self::new_file))
             .on_action(cx.listener(Self::open_file))
@@ -1491,16 +8451,79 @@ impl Render for SpreadsheetGrid {
             .on_action(cx.listener(Self::save_file_as))
             .on_action(cx.listener(Self::force_write))
             .on_action(cx.listener(Self::close_file))
+            .on_action(cx.listener(Self::new_tab))
+            .on_action(cx.listener(Self::next_tab))
+            .on_action(cx.listener(Self::previous_tab))
             .on_action(cx.listener(Self::force_quit))
             .on_action(cx.listener(Self::toggle_read_only))
             .on_action(cx.listener(Self::toggle_keep_cursor_in_view))
+            .on_action(cx.listener(Self::toggle_toolbar))
+            .on_action(cx.listener(Self::toggle_gridlines))
+            .on_action(cx.listener(Self::recalculate))
+            .on_action(cx.listener(Self::insert_chart))
+            .on_action(cx.listener(Self::freeze_panes))
+            .on_action(cx.listener(Self::unfreeze_panes))
+            // Outline group actions
+            .on_action(cx.listener(Self::toggle_group))
+            .on_action(cx.listener(Self::open_group))
+            .on_action(cx.listener(Self::close_group))
+            .on_action(cx.listener(Self::expand_all_groups))
+            .on_action(cx.listener(Self::collapse_all_groups))
             // Command palette actions
             .on_action(cx.listener(Self::show_command_palette))
+            .on_action(cx.listener(Self::go_to_cell))
+            .on_action(cx.listener(Self::confirm_name_box))
+            .on_action(cx.listener(Self::cancel_name_box))
+            .on_action(cx.listener(Self::repeat_last_command))
+            .on_action(cx.listener(Self::show_command_history))
             .on_action(cx.listener(Self::hide_command_palette))
             .child(self.render_header(cx))
-            .child(self.render_column_headers(cx))
-            .child(self.render_grid(cx))
+            .when(self.show_toolbar, |d| d.child(self.render_toolbar(cx)))
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_1()
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .flex_1()
+                            .overflow_hidden()
+                            .map(|d| match &self.viewer {
+                                Some(viewer) => d.child(self.render_viewer(viewer, cx)),
+                                None => d
+                                    .child(self.render_column_headers(cx))
+                                    .child(
+                                        div()
+                                            .flex()
+                                            .flex_row()
+                                            .flex_1()
+                                            .overflow_hidden()
+                                            .child(self.render_grid(cx))
+                                            .child(self.render_vertical_scrollbar(cx))
+                                    )
+                                    .child(self.render_horizontal_scrollbar(cx)),
+                            })
+                    )
+                    .when_some(self.comparison.as_ref(), |d, comparison| {
+                        d.child(self.render_compare_panel(comparison, compare_width, cx))
+                    })
+                    .when(self.show_profile, |d| d.child(self.render_profile_panel(cx)))
+                    .when(self.show_audit, |d| d.child(self.render_audit_panel(cx)))
+            )
             .child(self.render_footer(cx))
+            .children(self.charts.iter().enumerate().map(|(index, chart)| self.render_chart(index, chart, cx)))
+            // Filter dropdown
+            .when_some(self.filter_menu, |d, col| d.child(self.render_filter_menu(col, cx)))
+            .when_some(self.context_menu, |d, menu| d.child(self.render_context_menu(menu, window, cx)))
+            // Allowed values while editing a list-validated cell
+            .children(self.render_expanded_cell(cx))
+            .when(!completions.is_empty(), |d| d.child(self.render_completion_menu(completions, cx)))
+            .when(!validation_choices.is_empty(), |d| {
+                d.child(self.render_validation_menu(validation_choices, cx))
+            })
             // Command palette overlay
             .when(show_palette, |d| {
                 d.child(
@@ -1531,6 +8554,34 @@ impl Render for SpreadsheetGrid {
                         )
                 )
             })
+            // Last, so its canvas paints after everything else
+            .when_some(self.perf.clone(), |d, perf| d.child(self.render_perf_overlay(perf, cx)))
+    }
+}
+
+impl EventEmitter<TabEvent> for SpreadsheetGrid {}
+
+/// Tooltip with the full text of a cell that's too small to show it
+struct CellTooltip {
+    text: SharedString,
+}
+
+impl Render for CellTooltip {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>();
+
+        div()
+            .max_w(px(400.))
+            .px(px(8.))
+            .py(px(4.))
+            .bg(theme.surface0)
+            .border_1()
+            .border_color(theme.surface1)
+            .rounded(px(4.))
+            .text_size(px(12.))
+            .text_color(theme.text)
+            .font_family("Berkeley Mono")
+            .children(self.text.split('\n').map(|line| div().child(line.to_string())))
     }
 }
 
@@ -1539,3 +8590,216 @@ impl Focusable for SpreadsheetGrid {
         self.focus_handle.clone()
     }
 }
+
+/// What a toolbar button does when clicked
+type ToolbarHandler = fn(&mut SpreadsheetGrid, &mut Window, &mut Context<SpreadsheetGrid>);
+
+/// Whether lines of cell text overflow a cell of the given size, by the same estimate auto-fit uses
+fn lines_overflow(lines: &[String], width: f32, height: f32) -> bool {
+    let too_wide = lines.iter().any(|line| wrap::text_width(line) > wrap::chars_per_line(width));
+    let too_tall = lines.len() > 1 && lines.len() as f32 * wrap::LINE_HEIGHT + 4.0 > height;
+    too_wide || too_tall
+}
+
+/// The smallest range around `pos` with no filled cells touching it from outside, edges or
+/// corners (the contiguous table the cell belongs to)
+fn data_block(cells: &[Vec<String>], pos: CellPosition) -> CellRange {
+    let filled = |row: usize, cols: RangeInclusive<usize>| cols.into_iter().any(|col| !cells[row][col].is_empty());
+    let (mut top, mut bottom, mut left, mut right) = (pos.row, pos.row, pos.col, pos.col);
+    loop {
+        let cols = left.saturating_sub(1)..=(right + 1).min(GRID_COLS - 1);
+        let rows = top.saturating_sub(1)..=(bottom + 1).min(GRID_ROWS - 1);
+        let grow_up = top > 0 && filled(top - 1, cols.clone());
+        let grow_down = bottom + 1 < GRID_ROWS && filled(bottom + 1, cols);
+        let grow_left = left > 0 && rows.clone().any(|row| !cells[row][left - 1].is_empty());
+        let grow_right = right + 1 < GRID_COLS && rows.into_iter().any(|row| !cells[row][right + 1].is_empty());
+        if !(grow_up || grow_down || grow_left || grow_right) {
+            break;
+        }
+        top -= grow_up as usize;
+        bottom += grow_down as usize;
+        left -= grow_left as usize;
+        right += grow_right as usize;
+    }
+    CellRange::new(CellPosition::new(top, left), CellPosition::new(bottom, right))
+}
+
+/// Swap the rows and columns of a (possibly ragged) block of values
+fn transpose_block(block: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    let width = block.iter().map(Vec::len).max().unwrap_or(0);
+    (0..width)
+        .map(|col| block.iter().map(|row| row.get(col).cloned().unwrap_or_default()).collect())
+        .collect()
+}
+
+/// Move a row's items `count` places right (negative: left), padding with `empty` and
+/// keeping the row's length
+fn shift_cells<T: Clone>(row: &mut Vec<T>, count: isize, empty: T) {
+    let len = row.len();
+    let n = count.unsigned_abs().min(len);
+    if count >= 0 {
+        row.splice(..0, std::iter::repeat_n(empty, n));
+        row.truncate(len);
+    } else {
+        row.drain(..n);
+        row.extend(std::iter::repeat_n(empty, n));
+    }
+}
+
+/// Indices of a row or column set in ascending order, for saving
+fn sorted_indices(set: &HashSet<usize>) -> Vec<usize> {
+    let mut indices: Vec<usize> = set.iter().copied().collect();
+    indices.sort_unstable();
+    indices
+}
+
+/// A count with thousands separators, e.g. `3,120`
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// A size in bytes as B, KB, MB, or GB
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+/// Renumber row or column indices for `count` inserted at `at`, dropping any pushed to `len` or past
+fn insert_indices(set: &HashSet<usize>, at: usize, count: usize, len: usize) -> HashSet<usize> {
+    set.iter()
+        .map(|&index| if index >= at { index + count } else { index })
+        .filter(|&index| index < len)
+        .collect()
+}
+
+/// Move an inclusive span of rows or columns for `count` inserted at `at`; a span the insertion
+/// falls inside grows. Spans pushed off the grid are dropped, and ones pushed past `len` trimmed.
+fn insert_into_span(start: usize, end: usize, at: usize, count: usize, len: usize) -> Option<(usize, usize)> {
+    let start = if start >= at { start + count } else { start };
+    let end = if end >= at { end + count } else { end };
+    (start < len).then(|| (start, end.min(len - 1)))
+}
+
+/// Move an inclusive span of rows or columns for `count` removed at `at`, shrinking it by the
+/// removed ones. Spans removed entirely are dropped.
+fn remove_from_span(start: usize, end: usize, at: usize, count: usize) -> Option<(usize, usize)> {
+    let removed_end = at + count;
+    let start = if start >= removed_end { start - count } else { start.min(at) };
+    let end = if end >= removed_end {
+        end - count
+    } else if end >= at {
+        at.checked_sub(1)?
+    } else {
+        end
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Apply a span change to the rows (or columns) of each range, dropping ranges it removes
+fn shift_ranges(ranges: &mut Vec<CellRange>, rows: bool, shift: impl Fn(usize, usize) -> Option<(usize, usize)>) {
+    ranges.retain_mut(|range| {
+        let (start, end) = if rows {
+            (&mut range.start.row, &mut range.end.row)
+        } else {
+            (&mut range.start.col, &mut range.end.col)
+        };
+        match shift(*start, *end) {
+            Some((new_start, new_end)) => {
+                (*start, *end) = (new_start, new_end);
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+/// Whether two ranges share any cell
+fn ranges_overlap(a: CellRange, b: CellRange) -> bool {
+    a.start.row <= b.end.row && b.start.row <= a.end.row && a.start.col <= b.end.col && b.start.col <= a.end.col
+}
+
+/// Renumber row or column indices for `count` removed at `at`, dropping the removed ones
+fn remove_indices(set: &HashSet<usize>, at: usize, count: usize) -> HashSet<usize> {
+    set.iter()
+        .filter(|&&index| index < at || index >= at + count)
+        .map(|&index| if index >= at { index - count } else { index })
+        .collect()
+}
+
+/// Move column-keyed settings right by `count` for columns at or after `at`,
+/// dropping any pushed past the grid edge
+fn shift_columns<T>(map: &mut BTreeMap<usize, T>, at: usize, count: usize) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .map(|(col, value)| (if col >= at { col + count } else { col }, value))
+        .filter(|&(col, _)| col < GRID_COLS)
+        .collect();
+}
+
+/// For `len` rows or columns with `span` moved to start at `to`, which old index ends up at
+/// each position
+fn moved_order(len: usize, span: RangeInclusive<usize>, to: usize) -> Vec<usize> {
+    let start = *span.start();
+    let moved = to..to + span.clone().count();
+    let mut rest = (0..len).filter(|index| !span.contains(index));
+    (0..len)
+        .map(|index| if moved.contains(&index) { start + index - to } else { rest.next().unwrap_or(index) })
+        .collect()
+}
+
+/// Where each old index ends up, given which old index lands at each position
+fn inverse_order(order: &[usize]) -> Vec<usize> {
+    let mut moved_to = vec![0; order.len()];
+    for (index, &old) in order.iter().enumerate() {
+        moved_to[old] = index;
+    }
+    moved_to
+}
+
+/// Rearrange items so position `i` holds what was at `order[i]`
+fn reorder<T: Default>(items: &mut Vec<T>, order: &[usize]) {
+    let mut old = std::mem::take(items);
+    *items = order.iter().map(|&index| std::mem::take(&mut old[index])).collect();
+}
+
+/// Follow an inclusive span of rows or columns through a move when it moved whole, otherwise
+/// leave it where it was
+fn moved_span(start: usize, end: usize, moved_to: &[usize]) -> (usize, usize) {
+    let (new_start, new_end) = (moved_to[start], moved_to[end]);
+    if new_end >= new_start && new_end - new_start == end - start {
+        (new_start, new_end)
+    } else {
+        (start, end)
+    }
+}
+
+/// Renumber column-keyed settings through a move of columns
+fn renumber_columns<T>(map: &mut BTreeMap<usize, T>, moved_to: &[usize]) {
+    *map = std::mem::take(map).into_iter().map(|(col, value)| (moved_to[col], value)).collect();
+}
+
+/// Drop column-keyed settings for `count` columns removed at `at`, moving later ones left
+fn remove_columns<T>(map: &mut BTreeMap<usize, T>, at: usize, count: usize) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter(|&(col, _)| col < at || col >= at + count)
+        .map(|(col, value)| (if col >= at { col - count } else { col }, value))
+        .collect();
+}
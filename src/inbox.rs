@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::command_palette::expand_home;
+use crate::state::CellPosition;
+
+/// Something another app asked to open: text sent with the "Open in zsheets" service, or a
+/// `zsheets://open?path=/path/file.csv&cell=B7` link
+pub enum Request {
+    /// Only services send text, and they're macOS-only
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    Text(String),
+    Link { path: PathBuf, cell: Option<CellPosition> },
+}
+
+/// Requests arrive from platform callbacks with no app context, so they're sent down a channel
+/// the app waits on. Ones sent before the window is up wait in the channel.
+struct Inbox {
+    sender: UnboundedSender<Request>,
+    receiver: Mutex<Option<UnboundedReceiver<Request>>>,
+}
+
+static INBOX: LazyLock<Inbox> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::unbounded();
+    Inbox { sender, receiver: Mutex::new(Some(receiver)) }
+});
+
+pub fn push(request: Request) {
+    // Only fails once the app has stopped listening, when there's nothing left to open it in
+    let _ = INBOX.sender.unbounded_send(request);
+}
+
+/// The end requests come out of, for whoever asks first
+pub fn receiver() -> Option<UnboundedReceiver<Request>> {
+    INBOX.receiver.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Rows of selected text: tab-separated when its first line has tabs, else CSV
+pub fn parse_table(text: &str) -> Vec<Vec<String>> {
+    let first_line = text.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains('\t') { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes());
+    reader
+        .records()
+        .flatten()
+        .map(|record| record.iter().map(str::to_string).collect())
+        .collect()
+}
+
+/// Queue the links the app was asked to open, skipping ones it doesn't understand
+pub fn open_urls(urls: Vec<String>) {
+    for url in urls {
+        match parse_url(&url) {
+            Ok(request) => push(request),
+            Err(e) => eprintln!("Ignoring {}: {}", url, e),
+        }
+    }
+}
+
+/// `zsheets://open?path=<path>&cell=<ref>`: `path` is required and percent-encoded, `cell` optional
+fn parse_url(url: &str) -> Result<Request, String> {
+    let rest = url.strip_prefix("zsheets://").ok_or("not a zsheets:// link")?;
+    let (action, query) = rest.split_once('?').unwrap_or((rest, ""));
+    if action.trim_end_matches('/') != "open" {
+        return Err(format!("unknown action {}", action));
+    }
+    let mut path = None;
+    let mut cell = None;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+        match key {
+            "path" => path = Some(expand_home(&value)),
+            "cell" => cell = Some(CellPosition::from_reference(&value).ok_or_else(|| format!("not a cell: {}", value))?),
+            _ => {}
+        }
+    }
+    Ok(Request::Link { path: path.ok_or("no path")?, cell })
+}
+
+/// Decode `%XX` escapes, and `+` as a space as in form-encoded queries
+fn percent_decode(text: &str) -> Result<String, String> {
+    let source = text.as_bytes();
+    let mut bytes = Vec::with_capacity(source.len());
+    let mut i = 0;
+    while i < source.len() {
+        match source[i] {
+            b'%' => {
+                let byte = text
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("bad % escape in {}", text))?;
+                bytes.push(byte);
+                i += 3;
+                continue;
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
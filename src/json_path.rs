@@ -0,0 +1,78 @@
+use serde_json::Value;
+
+/// One step into a JSON document
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    /// Every element of an array, or every value of an object
+    Wildcard,
+}
+
+/// A JSONPath-like expression: `$.data.items[*]`, `results[0].rows`, `$['odd key'].*`.
+/// The leading `$` is optional.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let mut rest = input.strip_prefix('$').unwrap_or(input);
+        let mut steps = Vec::new();
+        // A bare first key, as in `data.items`
+        if !rest.is_empty() && !rest.starts_with(['.', '[']) {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            steps.push(Step::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        while !rest.is_empty() {
+            if rest.starts_with("..") {
+                return Err("Recursive descent (..) isn't supported".to_string());
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                steps.push(match &after[..end] {
+                    "" => return Err(format!("Missing key in {}", input)),
+                    "*" => Step::Wildcard,
+                    key => Step::Key(key.to_string()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| format!("Unclosed [ in {}", input))?;
+                let inner = after[..end].trim();
+                steps.push(match inner {
+                    "*" => Step::Wildcard,
+                    _ if inner.len() >= 2 && (inner.starts_with('\'') || inner.starts_with('"')) && inner.ends_with(&inner[..1]) => {
+                        Step::Key(inner[1..inner.len() - 1].to_string())
+                    }
+                    _ => Step::Index(inner.parse().map_err(|_| format!("Not an index: [{}]", inner))?),
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(format!("Unexpected {} in {}", rest, input));
+            }
+        }
+        Ok(Self { steps })
+    }
+
+    /// Every value the path reaches in `root`, in document order
+    pub fn select<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        let mut matches = vec![root];
+        for step in &self.steps {
+            matches = matches
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (step, value) {
+                        (Step::Key(key), Value::Object(object)) => object.get(key).into_iter().collect(),
+                        (Step::Index(index), Value::Array(items)) => items.get(*index).into_iter().collect(),
+                        (Step::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Step::Wildcard, Value::Object(object)) => object.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        matches
+    }
+}
@@ -1,13 +1,52 @@
 mod assets;
+mod audit;
 mod cell;
+mod chart;
+mod cli;
+mod collab;
 mod command_palette;
+mod compare;
+mod config;
+mod context_menu;
+mod crypt;
+mod date;
+mod delimiter;
+mod diff;
 mod file_io;
 mod file_state;
+mod filter;
+mod formula;
+mod generate;
 mod grid;
+mod inbox;
+mod json_path;
 mod menu;
 mod metadata;
+mod number_format;
+mod outline;
+mod parquet;
+mod perf;
+mod pipe;
+mod pivot;
+mod plugin;
+mod script;
+#[cfg(target_os = "macos")]
+mod services;
+mod sizing;
+mod snapshot;
+mod source;
+mod sparkline;
 mod state;
+mod stats;
+mod style;
 mod theme;
+mod undo;
+mod validation;
+mod value;
+mod viewer;
+mod window_state;
+mod wrap;
+mod xlsx;
 
 use gpui::*;
 
@@ -16,14 +55,37 @@ use cell::*;
 use command_palette::*;
 use grid::*;
 use theme::Theme;
+use window_state::WindowState;
 
 fn main() {
-    Application::new()
-        .with_assets(Assets)
+    // Subcommands such as `zsheets convert` and `zsheets eval` run without a window and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+    let exit_output = match cli::ExitOutput::from_args(&args) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("zsheets: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let app = Application::new().with_assets(Assets);
+    // `zsheets://` links, registered in Info.plist
+    app.on_open_urls(inbox::open_urls);
+    app
         .run(|cx| {
             // Initialize theme
             Theme::init(cx);
 
+            if let Some(output) = exit_output {
+                cx.set_global(output);
+            }
+
+            // Load plugins before anything lists commands or opens files
+            plugin::Registry::get();
+
             // Set up menu bar
             menu::setup_menu(cx);
 
@@ -38,10 +100,73 @@ fn main() {
                 KeyBinding::new("j", MoveDown, Some("NormalMode")),
                 KeyBinding::new("h", MoveLeft, Some("NormalMode")),
                 KeyBinding::new("l", MoveRight, Some("NormalMode")),
+                KeyBinding::new("alt-down", PickFromList, Some("NormalMode")),
+                KeyBinding::new("ctrl-alt-up", MoveRowUp, Some("NormalMode")),
+                KeyBinding::new("ctrl-alt-down", MoveRowDown, Some("NormalMode")),
+                KeyBinding::new("ctrl-alt-left", MoveColumnLeft, Some("NormalMode")),
+                KeyBinding::new("ctrl-alt-right", MoveColumnRight, Some("NormalMode")),
+                KeyBinding::new("g g", GoToFirstRow, Some("NormalMode")),
+                KeyBinding::new("shift-g", GoToLastRow, Some("NormalMode")),
+                KeyBinding::new("ctrl-home", GoToFirstCell, Some("NormalMode")),
+                KeyBinding::new("ctrl-end", GoToLastCell, Some("NormalMode")),
+                KeyBinding::new("pageup", PageUp, Some("NormalMode")),
+                KeyBinding::new("pagedown", PageDown, Some("NormalMode")),
+                KeyBinding::new("ctrl-b", PageUp, Some("NormalMode")),
+                KeyBinding::new("ctrl-f", PageDown, Some("NormalMode")),
                 KeyBinding::new("i", EnterEditMode, Some("NormalMode")),
+                KeyBinding::new("ctrl-a", Increment, Some("NormalMode")),
+                KeyBinding::new("ctrl-x", Decrement, Some("NormalMode")),
+                KeyBinding::new("g ctrl-a", IncrementSequence, Some("VisualMode")),
+                KeyBinding::new("g ctrl-x", DecrementSequence, Some("VisualMode")),
+
+                // Range selection
+                KeyBinding::new("shift-up", ExtendUp, Some("NormalMode")),
+                KeyBinding::new("shift-down", ExtendDown, Some("NormalMode")),
+                KeyBinding::new("shift-left", ExtendLeft, Some("NormalMode")),
+                KeyBinding::new("shift-right", ExtendRight, Some("NormalMode")),
+                KeyBinding::new("v", ToggleVisualMode, Some("NormalMode")),
+                KeyBinding::new("escape", ClearSelection, Some("NormalMode")),
+                KeyBinding::new("cmd-a", SelectAllCells, Some("NormalMode")),
+                KeyBinding::new("i p", SelectDataBlock, Some("VisualMode")), // vip
+                KeyBinding::new("d o", RevertToSaved, Some("NormalMode")), // do (diff obtain)
+                KeyBinding::new("delete", ClearContents, Some("NormalMode")),
+                KeyBinding::new("backspace", ClearContents, Some("NormalMode")),
+                KeyBinding::new("x", ClearContents, Some("NormalMode")),
+
+                // Grid clipboard
+                KeyBinding::new("cmd-c", menu::Copy, Some("NormalMode")),
+                KeyBinding::new("cmd-x", menu::Cut, Some("NormalMode")),
+                KeyBinding::new("cmd-v", menu::Paste, Some("NormalMode")),
+                KeyBinding::new("cmd-z", menu::Undo, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-z", menu::Redo, Some("NormalMode")),
+                KeyBinding::new("u", menu::Undo, Some("NormalMode")),
+                KeyBinding::new("ctrl-r", menu::Redo, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-v", PasteTransposed, Some("NormalMode")),
+                KeyBinding::new("cmd-b", ToggleBold, Some("NormalMode")),
+                KeyBinding::new("cmd-i", ToggleItalic, Some("NormalMode")),
+                KeyBinding::new("cmd-u", ToggleUnderline, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-4", FormatCurrency, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-5", FormatPercent, Some("NormalMode")),
+                KeyBinding::new("f9", Recalculate, Some("NormalMode")),
+
+                // Outline groups (vim fold-style)
+                KeyBinding::new("z a", ToggleGroup, Some("NormalMode")),
+                KeyBinding::new("z o", OpenGroup, Some("NormalMode")),
+                KeyBinding::new("z c", CloseGroup, Some("NormalMode")),
+                KeyBinding::new("z shift-r", ExpandAllGroups, Some("NormalMode")),
+                KeyBinding::new("z shift-m", CollapseAllGroups, Some("NormalMode")),
 
                 // Edit mode
                 KeyBinding::new("escape", ExitEditMode, Some("EditMode")),
+                // Point at cells to insert references while typing a formula
+                KeyBinding::new("up", PointUp, Some("EditMode")),
+                KeyBinding::new("down", PointDown, Some("EditMode")),
+                KeyBinding::new("left", PointLeft, Some("EditMode")),
+                KeyBinding::new("right", PointRight, Some("EditMode")),
+                KeyBinding::new("shift-up", PointExtendUp, Some("EditMode")),
+                KeyBinding::new("shift-down", PointExtendDown, Some("EditMode")),
+                KeyBinding::new("shift-left", PointExtendLeft, Some("EditMode")),
+                KeyBinding::new("shift-right", PointExtendRight, Some("EditMode")),
                 KeyBinding::new("backspace", Backspace, Some("CellInput")),
                 KeyBinding::new("delete", Delete, Some("CellInput")),
 
@@ -61,18 +186,31 @@ fn main() {
                 KeyBinding::new("alt-shift-right", SelectWordRight, Some("CellInput")),
                 KeyBinding::new("cmd-backspace", DeleteToStart, Some("CellInput")),
                 KeyBinding::new("alt-backspace", DeleteWordBackward, Some("CellInput")),
+                KeyBinding::new("alt-enter", InsertNewline, Some("CellInput")),
+                KeyBinding::new("ctrl-enter", InsertNewline, Some("CellInput")),
+                KeyBinding::new("down", CompletionNext, Some("CellInput")),
+                KeyBinding::new("up", CompletionPrevious, Some("CellInput")),
+                KeyBinding::new("tab", AcceptCompletion, Some("CellInput")),
                 KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, Some("CellInput")),
                 KeyBinding::new("cmd-v", Paste, Some("CellInput")),
                 KeyBinding::new("cmd-c", Copy, Some("CellInput")),
                 KeyBinding::new("cmd-x", Cut, Some("CellInput")),
+                KeyBinding::new("cmd-z", menu::Undo, Some("CellInput")),
+                KeyBinding::new("cmd-shift-z", menu::Redo, Some("CellInput")),
 
                 // Command palette
                 KeyBinding::new("cmd-k", ShowCommandPalette, Some("NormalMode")),
                 KeyBinding::new("shift-;", ShowCommandPalette, Some("NormalMode")), // : key
+                KeyBinding::new("ctrl-g", GoToCell, Some("NormalMode")),
+                KeyBinding::new("enter", ConfirmNameBox, Some("NameBox")),
+                KeyBinding::new("escape", CancelNameBox, Some("NameBox")),
+                KeyBinding::new("shift-2 shift-;", RepeatLastCommand, Some("NormalMode")), // @:
+                KeyBinding::new("q shift-;", ShowCommandHistory, Some("NormalMode")), // q:
                 KeyBinding::new("escape", HideCommandPalette, Some("CommandPalette")),
                 KeyBinding::new("up", SelectPrevious, Some("CommandPalette")),
                 KeyBinding::new("down", SelectNext, Some("CommandPalette")),
                 KeyBinding::new("enter", Confirm, Some("CommandPalette")),
+                KeyBinding::new("tab", AcceptPathCompletion, Some("CommandPalette")),
 
                 // File operations
                 KeyBinding::new("cmd-n", NewFile, Some("NormalMode")),
@@ -80,6 +218,11 @@ fn main() {
                 KeyBinding::new("cmd-s", SaveFile, Some("NormalMode")),
                 KeyBinding::new("cmd-shift-s", SaveFileAs, Some("NormalMode")),
                 KeyBinding::new("cmd-w", CloseFile, Some("NormalMode")),
+                KeyBinding::new("cmd-t", NewTab, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-]", NextTab, Some("NormalMode")),
+                KeyBinding::new("cmd-shift-[", PreviousTab, Some("NormalMode")),
+                KeyBinding::new("ctrl-tab", NextTab, Some("NormalMode")),
+                KeyBinding::new("ctrl-shift-tab", PreviousTab, Some("NormalMode")),
 
                 // Global
                 KeyBinding::new("cmd-q", Quit, None),
@@ -91,12 +234,12 @@ fn main() {
             });
 
             // Create the main window
+            // Reopen where the window was left on these displays, else centered
+            let window_bounds = WindowState::load().bounds_for(cx).unwrap_or_else(|| {
+                WindowBounds::Windowed(Bounds::centered(None, size(px(1000.), px(700.)), cx))
+            });
             let window_options = WindowOptions {
-                window_bounds: Some(WindowBounds::Windowed(Bounds::centered(
-                    None,
-                    size(px(1000.), px(700.)),
-                    cx,
-                ))),
+                window_bounds: Some(window_bounds),
                 titlebar: Some(TitlebarOptions {
                     title: Some("zsheets".into()),
                     appears_transparent: false,
@@ -106,9 +249,12 @@ fn main() {
                 ..Default::default()
             };
 
-            cx.open_window(window_options, |_window, cx| {
-                cx.new(|cx| SpreadsheetApp::new(cx))
+            cx.open_window(window_options, |window, cx| {
+                cx.new(|cx| SpreadsheetApp::new(window, cx))
             })
             .unwrap();
+
+            #[cfg(target_os = "macos")]
+            services::register();
         });
 }
@@ -1,17 +1,25 @@
 use gpui::*;
 
 use crate::grid::{
-    CloseFile, ForceWrite, NewFile, OpenFile, Quit, SaveFile, SaveFileAs,
-    ToggleKeepCursorInView, ToggleReadOnly,
+    ClearAll, ClearContents, ClearFormats, CloseFile, ForceWrite, FormatCurrency, FormatDecimal, FormatGeneral, FormatPercent,
+    FormatScientific, FormatThousands, FreezePanes, InsertChart, NewFile, NewTab, NextTab,
+    OpenFile, PasteTransposed, PreviousTab, Quit, SaveFile, SaveFileAs, ToggleBold,
+    ToggleGridlines, ToggleItalic, ToggleKeepCursorInView, ToggleReadOnly, ToggleToolbar,
+    ToggleUnderline, UnfreezePanes,
 };
 
 /// Set up the application menu bar (initial call with defaults)
 pub fn setup_menu(cx: &mut App) {
-    setup_menu_with_state(cx, false);
+    setup_menu_with_state(cx, false, false, true);
 }
 
 /// Set up the application menu bar with current state for checked items
-pub fn setup_menu_with_state(cx: &mut App, keep_cursor_in_view: bool) {
+pub fn setup_menu_with_state(
+    cx: &mut App,
+    keep_cursor_in_view: bool,
+    show_toolbar: bool,
+    show_gridlines: bool,
+) {
     cx.set_menus(vec![
         Menu {
             name: "zsheets".into(),
@@ -25,6 +33,7 @@ pub fn setup_menu_with_state(cx: &mut App, keep_cursor_in_view: bool) {
             name: "File".into(),
             items: vec![
                 MenuItem::action("New", NewFile),
+                MenuItem::action("New Tab", NewTab),
                 MenuItem::separator(),
                 MenuItem::action("Open...", OpenFile),
                 MenuItem::separator(),
@@ -33,6 +42,9 @@ pub fn setup_menu_with_state(cx: &mut App, keep_cursor_in_view: bool) {
                 MenuItem::action("Force Write", ForceWrite),
                 MenuItem::separator(),
                 MenuItem::action("Close", CloseFile),
+                MenuItem::separator(),
+                MenuItem::action("Next Tab", NextTab),
+                MenuItem::action("Previous Tab", PreviousTab),
             ],
         },
         Menu {
@@ -44,6 +56,30 @@ pub fn setup_menu_with_state(cx: &mut App, keep_cursor_in_view: bool) {
                 MenuItem::action("Cut", Cut),
                 MenuItem::action("Copy", Copy),
                 MenuItem::action("Paste", Paste),
+                MenuItem::action("Paste Transposed", PasteTransposed),
+                MenuItem::separator(),
+                MenuItem::action("Clear Contents", ClearContents),
+                MenuItem::action("Clear Formats", ClearFormats),
+                MenuItem::action("Clear All", ClearAll),
+            ],
+        },
+        Menu {
+            name: "Insert".into(),
+            items: vec![MenuItem::action("Chart", InsertChart)],
+        },
+        Menu {
+            name: "Format".into(),
+            items: vec![
+                MenuItem::action("Bold", ToggleBold),
+                MenuItem::action("Italic", ToggleItalic),
+                MenuItem::action("Underline", ToggleUnderline),
+                MenuItem::separator(),
+                MenuItem::action("General", FormatGeneral),
+                MenuItem::action("Number (0.00)", FormatDecimal),
+                MenuItem::action("Thousands (#,##0)", FormatThousands),
+                MenuItem::action("Currency ($#,##0.00)", FormatCurrency),
+                MenuItem::action("Percent (0%)", FormatPercent),
+                MenuItem::action("Scientific (0.00E+00)", FormatScientific),
             ],
         },
         Menu {
@@ -53,6 +89,13 @@ pub fn setup_menu_with_state(cx: &mut App, keep_cursor_in_view: bool) {
                 MenuItem::separator(),
                 MenuItem::action("Keep Cursor in View", ToggleKeepCursorInView)
                     .checked(keep_cursor_in_view),
+                MenuItem::action("Show Toolbar", ToggleToolbar)
+                    .checked(show_toolbar),
+                MenuItem::action("Show Gridlines", ToggleGridlines)
+                    .checked(show_gridlines),
+                MenuItem::separator(),
+                MenuItem::action("Freeze Panes at Cursor", FreezePanes),
+                MenuItem::action("Unfreeze Panes", UnfreezePanes),
             ],
         },
     ]);
@@ -1,21 +1,75 @@
+use std::collections::BTreeMap;
 use std::io;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
-use crate::state::{GRID_COLS, GRID_ROWS};
-use crate::grid::{DEFAULT_CELL_WIDTH, DEFAULT_CELL_HEIGHT};
+use crate::config::{app_support_dir, Config, MetadataStore};
+use crate::file_io::{self, WriteOptions};
+use crate::state::{CellRange, GRID_COLS, GRID_ROWS};
+use crate::grid::{AutoFitWatch, DEFAULT_CELL_WIDTH, DEFAULT_CELL_HEIGHT, MIN_CELL_HEIGHT, MIN_CELL_WIDTH};
+use crate::date::DateFormat;
+use crate::number_format::NumberFormat;
+use crate::outline::OutlineGroup;
+use crate::sizing::WidthLimits;
+use crate::source::{DataSource, SourceBinding};
+use crate::style::{Alignment, CellStyle, StyledCell};
+use crate::validation::{ValidatedCell, Validation};
+use crate::value::ColumnType;
 
 /// Metadata for spreadsheet dimensions and settings
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct SpreadsheetMetadata {
     pub column_widths: Option<Vec<f32>>,
     pub row_heights: Option<Vec<f32>>,
+    pub column_width_limits: Option<BTreeMap<usize, WidthLimits>>,
+    pub autofit_max_width: Option<f32>,
+    pub default_column_width: Option<f32>,
+    pub default_row_height: Option<f32>,
+    pub row_groups: Option<Vec<OutlineGroup>>,
+    pub col_groups: Option<Vec<OutlineGroup>>,
+    pub wrap_columns: Option<Vec<usize>>,
+    pub hidden_rows: Option<Vec<usize>>,
+    pub hidden_cols: Option<Vec<usize>>,
+    pub cell_styles: Option<Vec<StyledCell>>,
+    pub column_formats: Option<BTreeMap<usize, NumberFormat>>,
+    pub date_formats: Option<BTreeMap<usize, DateFormat>>,
+    pub column_alignments: Option<BTreeMap<usize, Alignment>>,
+    /// Column types set with `:type`, which win over the ones inferred on load
+    pub column_types: Option<BTreeMap<usize, ColumnType>>,
+    pub validations: Option<Vec<ValidatedCell>>,
+    pub column_validations: Option<BTreeMap<usize, Validation>>,
+    pub autofit_watch: Option<AutoFitWatch>,
+    pub frozen_rows: Option<usize>,
+    pub frozen_cols: Option<usize>,
+    pub header_row: Option<usize>,
+    pub read_only: Option<bool>,
+    /// Protected ranges as references, e.g. `A1:C1`
+    pub protected_ranges: Option<Vec<String>>,
+    pub protection: Option<bool>,
+    pub gridlines: Option<bool>,
+    pub data_sources: Option<Vec<SavedSource>>,
+    /// CSV style set with `:set quote=`, `eol=`, `finalnewline=`, or `padrows=`
+    pub write_options: Option<WriteOptions>,
+    /// Whether saving only appends new rows to the file
+    pub append_only: Option<bool>,
+    /// Whether cell changes are recorded to the file's audit log
+    pub audit: Option<bool>,
+    /// Whether the metadata lives in the CSV file's first line rather than a sidecar file
+    #[serde(skip)]
+    pub embedded: bool,
 }
 
 impl SpreadsheetMetadata {
-    /// Get the metadata file path for a given CSV file
+    /// Get the metadata file path for a given CSV file: beside it, or in the app support
+    /// directory when the config keeps metadata centrally
     pub fn metadata_path(csv_path: &Path) -> std::path::PathBuf {
+        if Config::get().metadata_store == MetadataStore::Central
+            && let Some(dir) = app_support_dir()
+        {
+            let csv_path = std::fs::canonicalize(csv_path).unwrap_or_else(|_| csv_path.to_path_buf());
+            return dir.join("metadata").join(format!("{:016x}.zsheets", path_hash(&csv_path)));
+        }
         let mut path = csv_path.to_path_buf();
         let file_name = path.file_name()
             .and_then(|n| n.to_str())
@@ -24,8 +78,19 @@ impl SpreadsheetMetadata {
         path
     }
 
-    /// Load metadata from a CSV file's companion metadata file
+    /// Load metadata embedded in a CSV file, else from its companion metadata file
     pub fn load(csv_path: &Path) -> io::Result<Self> {
+        Self::load_for_content(csv_path, &file_io::read_text(csv_path)?)
+    }
+
+    /// Load metadata for a CSV file whose text has already been read, e.g. once decrypted
+    pub fn load_for_content(csv_path: &Path, content: &str) -> io::Result<Self> {
+        if let (Some(line), _) = file_io::split_embedded_metadata(content) {
+            let mut metadata: Self = serde_json::from_str(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            metadata.embedded = true;
+            return Ok(metadata);
+        }
         let meta_path = Self::metadata_path(csv_path);
         if !meta_path.exists() {
             return Ok(Self::default());
@@ -35,25 +100,231 @@ impl SpreadsheetMetadata {
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
-    /// Save metadata to a CSV file's companion metadata file
+    /// Save metadata where it was loaded from: the CSV file's first line, leaving its data
+    /// untouched, or the companion metadata file
     pub fn save(&self, csv_path: &Path) -> io::Result<()> {
+        if self.embedded {
+            let content = file_io::read_text(csv_path)?;
+            let (_, data) = file_io::split_embedded_metadata(&content);
+            let line = self.embedded_line()?;
+            let content = format!("{}{}\n{}", file_io::EMBEDDED_METADATA_PREFIX, line, data);
+            return file_io::write_file(csv_path, content.as_bytes());
+        }
         let meta_path = Self::metadata_path(csv_path);
         let content = serde_json::to_string_pretty(self)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = meta_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
         std::fs::write(&meta_path, content)
     }
 
+    /// The metadata as a single line, for embedding in the CSV file
+    pub fn embedded_line(&self) -> io::Result<String> {
+        serde_json::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Delete a CSV file's companion metadata file, if it has one
+    pub fn remove_sidecar(csv_path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(Self::metadata_path(csv_path)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Get the file's default column width, or the global default
+    pub fn get_default_column_width(&self) -> f32 {
+        self.default_column_width
+            .filter(|width| width.is_finite() && *width >= MIN_CELL_WIDTH)
+            .unwrap_or(DEFAULT_CELL_WIDTH)
+    }
+
+    /// Get the file's default row height, or the global default
+    pub fn get_default_row_height(&self) -> f32 {
+        self.default_row_height
+            .filter(|height| height.is_finite() && *height >= MIN_CELL_HEIGHT)
+            .unwrap_or(DEFAULT_CELL_HEIGHT)
+    }
+
     /// Get column widths, filling with defaults if needed
     pub fn get_column_widths(&self) -> Vec<f32> {
         let mut widths = self.column_widths.clone().unwrap_or_default();
-        widths.resize(GRID_COLS, DEFAULT_CELL_WIDTH);
+        widths.resize(GRID_COLS, self.get_default_column_width());
         widths
     }
 
     /// Get row heights, filling with defaults if needed
     pub fn get_row_heights(&self) -> Vec<f32> {
         let mut heights = self.row_heights.clone().unwrap_or_default();
-        heights.resize(GRID_ROWS, DEFAULT_CELL_HEIGHT);
+        heights.resize(GRID_ROWS, self.get_default_row_height());
         heights
     }
+
+    /// Get per-column width limits, dropping any outside the grid
+    pub fn get_column_width_limits(&self) -> BTreeMap<usize, WidthLimits> {
+        let mut limits = self.column_width_limits.clone().unwrap_or_default();
+        limits.retain(|&col, _| col < GRID_COLS);
+        limits
+    }
+
+    /// Get the auto-fit width cap, ignoring one that isn't a usable width
+    pub fn get_autofit_max_width(&self) -> Option<f32> {
+        self.autofit_max_width.filter(|width| width.is_finite() && *width > 0.0)
+    }
+
+    /// Get row outline groups, dropping any that fall outside the grid
+    pub fn get_row_groups(&self) -> Vec<OutlineGroup> {
+        let mut groups = self.row_groups.clone().unwrap_or_default();
+        groups.retain(|g| g.end < GRID_ROWS);
+        groups
+    }
+
+    /// Get column outline groups, dropping any that fall outside the grid
+    pub fn get_col_groups(&self) -> Vec<OutlineGroup> {
+        let mut groups = self.col_groups.clone().unwrap_or_default();
+        groups.retain(|g| g.end < GRID_COLS);
+        groups
+    }
+
+    /// Get word-wrapped columns, dropping any outside the grid
+    pub fn get_wrap_columns(&self) -> Vec<usize> {
+        let mut cols = self.wrap_columns.clone().unwrap_or_default();
+        cols.retain(|&col| col < GRID_COLS);
+        cols
+    }
+
+    /// Get hidden rows, dropping any outside the grid
+    pub fn get_hidden_rows(&self) -> Vec<usize> {
+        let mut rows = self.hidden_rows.clone().unwrap_or_default();
+        rows.retain(|&row| row < GRID_ROWS);
+        rows
+    }
+
+    /// Get hidden columns, dropping any outside the grid
+    pub fn get_hidden_cols(&self) -> Vec<usize> {
+        let mut cols = self.hidden_cols.clone().unwrap_or_default();
+        cols.retain(|&col| col < GRID_COLS);
+        cols
+    }
+
+    /// Get the per-cell style table, dropping styles for cells outside the grid
+    pub fn get_cell_styles(&self) -> Vec<Vec<CellStyle>> {
+        let mut styles = vec![vec![CellStyle::default(); GRID_COLS]; GRID_ROWS];
+        for styled in self.cell_styles.iter().flatten() {
+            if styled.row < GRID_ROWS && styled.col < GRID_COLS {
+                styles[styled.row][styled.col] = styled.style;
+            }
+        }
+        styles
+    }
+
+    /// Get per-column number formats, dropping any outside the grid
+    pub fn get_column_formats(&self) -> BTreeMap<usize, NumberFormat> {
+        let mut formats = self.column_formats.clone().unwrap_or_default();
+        formats.retain(|&col, _| col < GRID_COLS);
+        formats
+    }
+
+    /// Get per-column date display formats, dropping any outside the grid
+    pub fn get_date_formats(&self) -> BTreeMap<usize, DateFormat> {
+        let mut formats = self.date_formats.clone().unwrap_or_default();
+        formats.retain(|&col, _| col < GRID_COLS);
+        formats
+    }
+
+    /// Get per-column alignment overrides, dropping any outside the grid
+    pub fn get_column_alignments(&self) -> BTreeMap<usize, Alignment> {
+        let mut alignments = self.column_alignments.clone().unwrap_or_default();
+        alignments.retain(|&col, _| col < GRID_COLS);
+        alignments
+    }
+
+    /// Get per-column type overrides, dropping any outside the grid
+    pub fn get_column_types(&self) -> BTreeMap<usize, ColumnType> {
+        let mut types = self.column_types.clone().unwrap_or_default();
+        types.retain(|&col, _| col < GRID_COLS);
+        types
+    }
+
+    /// Get the per-cell validation table, dropping rules for cells outside the grid
+    pub fn get_validations(&self) -> Vec<Vec<Option<Validation>>> {
+        let mut validations = vec![vec![None; GRID_COLS]; GRID_ROWS];
+        for validated in self.validations.iter().flatten() {
+            if validated.row < GRID_ROWS && validated.col < GRID_COLS {
+                validations[validated.row][validated.col] = Some(validated.validation.clone());
+            }
+        }
+        validations
+    }
+
+    /// Get per-column validations, dropping any outside the grid
+    pub fn get_column_validations(&self) -> BTreeMap<usize, Validation> {
+        let mut validations = self.column_validations.clone().unwrap_or_default();
+        validations.retain(|&col, _| col < GRID_COLS);
+        validations
+    }
+
+    /// Get the auto-fit watch mode, dropping rows and columns outside the grid
+    pub fn get_autofit_watch(&self) -> AutoFitWatch {
+        match self.autofit_watch.clone().unwrap_or_default() {
+            AutoFitWatch::Columns(mut cols) => {
+                cols.retain(|&col| col < GRID_COLS);
+                if cols.is_empty() { AutoFitWatch::None } else { AutoFitWatch::Columns(cols) }
+            }
+            AutoFitWatch::Rows(mut rows) => {
+                rows.retain(|&row| row < GRID_ROWS);
+                if rows.is_empty() { AutoFitWatch::None } else { AutoFitWatch::Rows(rows) }
+            }
+            watch => watch,
+        }
+    }
+
+    /// Get the frozen row and column counts, limited to the grid
+    pub fn get_frozen(&self) -> (usize, usize) {
+        (
+            self.frozen_rows.unwrap_or(0).min(GRID_ROWS - 1),
+            self.frozen_cols.unwrap_or(0).min(GRID_COLS - 1),
+        )
+    }
+
+    /// Get protected ranges, dropping any that don't parse or fall outside the grid
+    pub fn get_protected_ranges(&self) -> Vec<CellRange> {
+        self.protected_ranges
+            .iter()
+            .flatten()
+            .filter_map(|reference| CellRange::from_reference(reference))
+            .collect()
+    }
+
+    /// Get data source bindings, dropping any whose range doesn't parse
+    pub fn get_data_sources(&self) -> Vec<SourceBinding> {
+        self.data_sources
+            .iter()
+            .flatten()
+            .filter_map(|saved| {
+                let range = CellRange::from_reference(&saved.range)?;
+                Some(SourceBinding { source: saved.source.clone(), range })
+            })
+            .collect()
+    }
+
+    /// Get the header row, dropping one outside the grid
+    pub fn get_header_row(&self) -> Option<usize> {
+        self.header_row.filter(|&row| row < GRID_ROWS)
+    }
+}
+
+/// A data source binding, its range as a reference, e.g. `A1:D20`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedSource {
+    pub range: String,
+    #[serde(flatten)]
+    pub source: DataSource,
+}
+
+/// FNV-1a hash of a path, stable across runs and builds, naming its central metadata file
+pub fn path_hash(path: &Path) -> u64 {
+    path.as_os_str().as_encoded_bytes().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
 }
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+/// Currency symbols accepted as a format prefix, e.g. `$#,##0.00`
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥'];
+
+/// How numeric cell values are displayed. The stored cell text is never changed;
+/// formats only apply at render time, and only to cells that parse as numbers.
+/// Stored in metadata as its spreadsheet-style pattern (`0.00`, `#,##0`, `$#,##0.00`, `0%`, `0.00E+00`).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct NumberFormat {
+    kind: FormatKind,
+    decimals: usize,
+    thousands: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FormatKind {
+    Number,
+    Currency(char),
+    Percent,
+    Scientific,
+}
+
+impl NumberFormat {
+    /// Parse a format pattern like `0.00`, `#,##0`, `$#,##0.00`, `0.0%`, or `0.00E+00`
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let mut core = pattern.trim();
+        let mut kind = FormatKind::Number;
+
+        if let Some(symbol) = core.chars().next().filter(|c| CURRENCY_SYMBOLS.contains(c)) {
+            kind = FormatKind::Currency(symbol);
+            core = &core[symbol.len_utf8()..];
+        } else if let Some(rest) = core.strip_suffix('%') {
+            kind = FormatKind::Percent;
+            core = rest;
+        } else if let Some(pos) = core.to_uppercase().find("E+") {
+            kind = FormatKind::Scientific;
+            core = &core[..pos];
+        }
+
+        if core.is_empty() || !core.chars().all(|c| "0#,.".contains(c)) {
+            return None;
+        }
+        let (int_part, frac_part) = core.split_once('.').unwrap_or((core, ""));
+        if frac_part.contains(['.', ',']) || !int_part.contains(['0', '#']) {
+            return None;
+        }
+
+        Some(Self {
+            kind,
+            decimals: frac_part.len(),
+            thousands: int_part.contains(',') || matches!(kind, FormatKind::Currency(_)),
+        })
+    }
+
+    /// The pattern this format was parsed from, normalized
+    pub fn pattern(self) -> String {
+        let mut pattern = if self.thousands && self.kind != FormatKind::Scientific {
+            "#,##0".to_string()
+        } else {
+            "0".to_string()
+        };
+        if self.decimals > 0 {
+            pattern.push('.');
+            pattern.push_str(&"0".repeat(self.decimals));
+        }
+        match self.kind {
+            FormatKind::Number => pattern,
+            FormatKind::Currency(symbol) => format!("{}{}", symbol, pattern),
+            FormatKind::Percent => format!("{}%", pattern),
+            FormatKind::Scientific => format!("{}E+00", pattern),
+        }
+    }
+
+    /// Display a number in this format
+    pub fn format(self, value: f64) -> String {
+        match self.kind {
+            FormatKind::Number => fixed(value, self.decimals, self.thousands),
+            FormatKind::Currency(symbol) => {
+                let text = fixed(value, self.decimals, self.thousands);
+                match text.strip_prefix('-') {
+                    Some(abs) => format!("-{}{}", symbol, abs),
+                    None => format!("{}{}", symbol, text),
+                }
+            }
+            FormatKind::Percent => format!("{}%", fixed(value * 100.0, self.decimals, self.thousands)),
+            FormatKind::Scientific => {
+                // Rust renders `1.23e4`; spreadsheets show `1.23E+04`
+                let text = format!("{:.*e}", self.decimals, value);
+                let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+                let exponent: i32 = exponent.parse().unwrap_or(0);
+                let sign = if exponent < 0 { '-' } else { '+' };
+                format!("{}E{}{:02}", mantissa, sign, exponent.abs())
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for NumberFormat {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Self::parse(&pattern).ok_or_else(|| format!("Invalid number format: {}", pattern))
+    }
+}
+
+impl From<NumberFormat> for String {
+    fn from(format: NumberFormat) -> Self {
+        format.pattern()
+    }
+}
+
+/// Fixed-point text with optional thousands separators; never shows `-0`
+fn fixed(value: f64, decimals: usize, thousands: bool) -> String {
+    let text = format!("{:.*}", decimals, value.abs());
+    let (int_part, frac_part) = text.split_once('.').unwrap_or((&text, ""));
+
+    let mut result = String::new();
+    if value < 0.0 && text.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        result.push('-');
+    }
+    for (i, digit) in int_part.chars().enumerate() {
+        if thousands && i > 0 && (int_part.len() - i) % 3 == 0 {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    if !frac_part.is_empty() {
+        result.push('.');
+        result.push_str(frac_part);
+    }
+    result
+}
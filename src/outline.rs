@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+/// A collapsible group of contiguous rows or columns (inclusive range).
+/// The first index acts as the group's header and stays visible when collapsed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutlineGroup {
+    pub start: usize,
+    pub end: usize,
+    pub collapsed: bool,
+}
+
+impl OutlineGroup {
+    pub fn contains(&self, index: usize) -> bool {
+        index >= self.start && index <= self.end
+    }
+
+    /// Whether this group hides `index` (everything but the header when collapsed)
+    pub fn hides(&self, index: usize) -> bool {
+        self.collapsed && index > self.start && index <= self.end
+    }
+}
+
+/// Non-overlapping outline groups along one axis, kept sorted by start index
+#[derive(Clone, Debug, Default)]
+pub struct Outline {
+    groups: Vec<OutlineGroup>,
+}
+
+impl Outline {
+    pub fn from_groups(mut groups: Vec<OutlineGroup>) -> Self {
+        groups.retain(|g| g.end > g.start);
+        groups.sort_by_key(|g| g.start);
+        let mut outline = Self::default();
+        for group in groups {
+            outline.insert_group(group);
+        }
+        outline
+    }
+
+    pub fn groups(&self) -> &[OutlineGroup] {
+        &self.groups
+    }
+
+    /// Group `start..=end`, merging with any groups it overlaps. Returns false for
+    /// single-item ranges, which have nothing to collapse.
+    pub fn add(&mut self, start: usize, end: usize) -> bool {
+        let (start, end) = (start.min(end), start.max(end));
+        if start == end {
+            return false;
+        }
+        self.insert_group(OutlineGroup {
+            start,
+            end,
+            collapsed: false,
+        });
+        true
+    }
+
+    fn insert_group(&mut self, mut group: OutlineGroup) {
+        self.groups.retain(|g| {
+            let overlaps = g.start <= group.end && group.start <= g.end;
+            if overlaps {
+                group.start = group.start.min(g.start);
+                group.end = group.end.max(g.end);
+                group.collapsed |= g.collapsed;
+            }
+            !overlaps
+        });
+        let pos = self.groups.partition_point(|g| g.start < group.start);
+        self.groups.insert(pos, group);
+    }
+
+    /// Remove the group containing `index`, returning it if there was one
+    pub fn remove_at(&mut self, index: usize) -> Option<OutlineGroup> {
+        let pos = self.groups.iter().position(|g| g.contains(index))?;
+        Some(self.groups.remove(pos))
+    }
+
+    pub fn group_at(&self, index: usize) -> Option<&OutlineGroup> {
+        self.groups.iter().find(|g| g.contains(index))
+    }
+
+    /// The group whose header is at `index`
+    pub fn header_at(&self, index: usize) -> Option<&OutlineGroup> {
+        self.groups.iter().find(|g| g.start == index)
+    }
+
+    /// Set the collapsed state of the group containing `index`, returning the group
+    pub fn set_collapsed_at(&mut self, index: usize, collapsed: bool) -> Option<OutlineGroup> {
+        let group = self.groups.iter_mut().find(|g| g.contains(index))?;
+        group.collapsed = collapsed;
+        Some(*group)
+    }
+
+    /// Toggle the group containing `index`, returning the updated group
+    pub fn toggle_at(&mut self, index: usize) -> Option<OutlineGroup> {
+        let collapsed = !self.group_at(index)?.collapsed;
+        self.set_collapsed_at(index, collapsed)
+    }
+
+    pub fn set_all_collapsed(&mut self, collapsed: bool) {
+        for group in &mut self.groups {
+            group.collapsed = collapsed;
+        }
+    }
+
+    /// Shift groups for `count` items inserted at `index`: groups after it move along,
+    /// and a group containing it grows to include the new items
+    pub fn insert(&mut self, index: usize, count: usize) {
+        for group in &mut self.groups {
+            if group.start >= index {
+                group.start += count;
+                group.end += count;
+            } else if group.end >= index {
+                group.end += count;
+            }
+        }
+    }
+
+    /// Shift groups for `count` items removed at `index`: groups after them move back, and
+    /// groups overlapping them shrink, disappearing once a single item is left
+    pub fn remove(&mut self, index: usize, count: usize) {
+        let end = index + count;
+        self.groups.retain_mut(|group| {
+            let overlap = (group.end + 1).min(end).saturating_sub(group.start.max(index));
+            let size = group.end + 1 - group.start - overlap;
+            if group.start >= end {
+                group.start -= count;
+            } else if group.start >= index {
+                group.start = index;
+            }
+            if size > 1 {
+                group.end = group.start + size - 1;
+            }
+            size > 1
+        });
+    }
+
+    pub fn is_hidden(&self, index: usize) -> bool {
+        self.groups.iter().any(|g| g.hides(index))
+    }
+}
@@ -0,0 +1,990 @@
+// Parquet for `zsheets convert`: flat tables of optional columns, one per spreadsheet column,
+// named by the first row. Written uncompressed in one row group; read back from files that
+// use the common encodings (plain and dictionary) and codecs (Snappy, gzip, zstd).
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::date::DateTime;
+use crate::state::CellPosition;
+use crate::value::{self, ColumnType};
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Physical types
+const BOOLEAN: i64 = 0;
+const INT32: i64 = 1;
+const INT64: i64 = 2;
+const INT96: i64 = 3;
+const FLOAT: i64 = 4;
+const DOUBLE: i64 = 5;
+const BYTE_ARRAY: i64 = 6;
+const FIXED_LEN_BYTE_ARRAY: i64 = 7;
+
+// Encodings
+const PLAIN: i64 = 0;
+const PLAIN_DICTIONARY: i64 = 2;
+const RLE: i64 = 3;
+const RLE_DICTIONARY: i64 = 8;
+
+// Page types
+const DATA_PAGE: i64 = 0;
+const DICTIONARY_PAGE: i64 = 2;
+const DATA_PAGE_V2: i64 = 3;
+
+// Thrift compact protocol field types
+const T_BOOL_TRUE: u8 = 1;
+const T_BOOL_FALSE: u8 = 2;
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+/// Write rows as a Parquet table. The first row names the columns, and each column is stored
+/// as its type says (or as inferred, when it has none) if every value fits, else as text.
+pub fn write(path: &Path, rows: &[Vec<String>], column_types: &BTreeMap<usize, ColumnType>) -> io::Result<()> {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let (header, records) = rows.split_first().map_or((&[][..], &[][..]), |(header, records)| (header.as_slice(), records));
+    let names = column_names(header, width);
+
+    let mut file = MAGIC.to_vec();
+    let mut chunks = Vec::new();
+    for (col, name) in names.iter().enumerate() {
+        let values: Vec<&str> = records.iter().map(|row| row.get(col).map_or("", String::as_str)).collect();
+        let storage = Storage::of(&values, column_types.get(&col).copied());
+        let page = data_page(&values, storage);
+        let offset = file.len();
+        file.extend_from_slice(&page);
+        chunks.push((name.as_str(), storage, offset, page.len()));
+    }
+    let footer = file_metadata(&chunks, records.len());
+    file.extend_from_slice(&footer);
+    file.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+    file.extend_from_slice(MAGIC);
+    std::fs::write(path, file)
+}
+
+/// The header row as column names: blank ones are named by their column letter, and repeats
+/// get a number
+fn column_names(header: &[String], width: usize) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(width);
+    for col in 0..width {
+        let name = header.get(col).map_or("", |name| name.trim());
+        let name = if name.is_empty() { CellPosition::col_to_letter(col) } else { name.to_string() };
+        let mut unique = name.clone();
+        let mut n = 1;
+        while names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        names.push(unique);
+    }
+    names
+}
+
+/// How a written column's values are stored
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Storage {
+    Boolean,
+    Integer,
+    Float,
+    /// Days since 1970 in an INT32
+    Date,
+    Text,
+}
+
+impl Storage {
+    fn of(values: &[&str], column_type: Option<ColumnType>) -> Self {
+        let filled: Vec<&str> = values.iter().map(|value| value.trim()).filter(|value| !value.is_empty()).collect();
+        // Numbers with leading zeros are codes, unless the column says it holds numbers
+        let number = |value: &str| match column_type {
+            Some(_) => value.parse::<f64>().ok().filter(|n| n.is_finite()),
+            None => value::exported_number(value),
+        };
+        let fits = |fits: &dyn Fn(&str) -> bool| filled.iter().all(|value| fits(value));
+        match column_type.or_else(|| ColumnType::infer(filled.iter().copied())) {
+            Some(ColumnType::Integer) if fits(&|value| value.parse::<i64>().is_ok() && number(value).is_some()) => Self::Integer,
+            Some(ColumnType::Integer | ColumnType::Float) if fits(&|value| number(value).is_some()) => Self::Float,
+            Some(ColumnType::Boolean) if fits(&|value| boolean(value).is_some()) => Self::Boolean,
+            Some(ColumnType::Date) if fits(&|value| date_days(value).is_some()) => Self::Date,
+            _ => Self::Text,
+        }
+    }
+
+    fn physical_type(self) -> i64 {
+        match self {
+            Self::Boolean => BOOLEAN,
+            Self::Integer => INT64,
+            Self::Float => DOUBLE,
+            Self::Date => INT32,
+            Self::Text => BYTE_ARRAY,
+        }
+    }
+}
+
+fn boolean(value: &str) -> Option<bool> {
+    match value.trim() {
+        text if text.eq_ignore_ascii_case("true") => Some(true),
+        text if text.eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// Days since 1970 of a date without a time of day
+fn date_days(value: &str) -> Option<i32> {
+    let date = DateTime::parse(value).filter(|date| date.has_date() && *date == date.date())?;
+    i32::try_from(date.to_serial() as i64 - 25569).ok()
+}
+
+/// A column's one page with its header: definition levels marking the empty cells, then the
+/// filled cells' values
+fn data_page(values: &[&str], storage: Storage) -> Vec<u8> {
+    let levels: Vec<bool> = values.iter().map(|value| !value.trim().is_empty()).collect();
+    let mut runs = Vec::new();
+    for run in levels.chunk_by(|a, b| a == b) {
+        push_varint(&mut runs, (run.len() as u64) << 1);
+        runs.push(run[0] as u8);
+    }
+    let mut body = (runs.len() as u32).to_le_bytes().to_vec();
+    body.extend_from_slice(&runs);
+
+    let filled = values.iter().map(|value| value.trim()).filter(|value| !value.is_empty());
+    match storage {
+        Storage::Boolean => {
+            let flags: Vec<bool> = filled.map(|value| boolean(value).unwrap_or(false)).collect();
+            for byte in flags.chunks(8) {
+                body.push(byte.iter().enumerate().fold(0, |bits, (i, &flag)| bits | (flag as u8) << i));
+            }
+        }
+        Storage::Integer => filled.for_each(|value| body.extend_from_slice(&value.parse::<i64>().unwrap_or(0).to_le_bytes())),
+        Storage::Float => filled.for_each(|value| body.extend_from_slice(&value.parse::<f64>().unwrap_or(0.0).to_le_bytes())),
+        Storage::Date => filled.for_each(|value| body.extend_from_slice(&date_days(value).unwrap_or(0).to_le_bytes())),
+        // Text keeps its surrounding spaces
+        Storage::Text => {
+            for value in values.iter().filter(|value| !value.trim().is_empty()) {
+                body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                body.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+
+    let mut header = ThriftWriter::new();
+    header.i32(1, DATA_PAGE as i32);
+    header.i32(2, body.len() as i32);
+    header.i32(3, body.len() as i32);
+    header.struct_begin(5);
+    header.i32(1, values.len() as i32);
+    header.i32(2, PLAIN as i32);
+    header.i32(3, RLE as i32);
+    header.i32(4, RLE as i32);
+    header.struct_end();
+    let mut page = header.finish();
+    page.extend_from_slice(&body);
+    page
+}
+
+/// The footer: the schema, and where each column's page is
+fn file_metadata(chunks: &[(&str, Storage, usize, usize)], num_rows: usize) -> Vec<u8> {
+    let mut metadata = ThriftWriter::new();
+    metadata.i32(1, 1);
+    metadata.list_begin(2, T_STRUCT, chunks.len() + 1);
+    metadata.element_begin();
+    metadata.binary(4, b"schema");
+    metadata.i32(5, chunks.len() as i32);
+    metadata.struct_end();
+    for &(name, storage, ..) in chunks {
+        metadata.element_begin();
+        metadata.i32(1, storage.physical_type() as i32);
+        // Optional, so empty cells are nulls
+        metadata.i32(3, 1);
+        metadata.binary(4, name.as_bytes());
+        // The converted type for older readers, and the logical type for newer ones
+        let annotation = match storage {
+            Storage::Text => Some((0, 1)),
+            Storage::Date => Some((6, 6)),
+            _ => None,
+        };
+        if let Some((converted, logical)) = annotation {
+            metadata.i32(6, converted);
+            metadata.struct_begin(10);
+            metadata.struct_begin(logical);
+            metadata.struct_end();
+            metadata.struct_end();
+        }
+        metadata.struct_end();
+    }
+    metadata.i64(3, num_rows as i64);
+    metadata.list_begin(4, T_STRUCT, 1);
+    metadata.element_begin();
+    metadata.list_begin(1, T_STRUCT, chunks.len());
+    for &(name, storage, offset, length) in chunks {
+        metadata.element_begin();
+        metadata.i64(2, offset as i64);
+        metadata.struct_begin(3);
+        metadata.i32(1, storage.physical_type() as i32);
+        metadata.list_begin(2, T_I32, 2);
+        push_varint(&mut metadata.bytes, zigzag(PLAIN));
+        push_varint(&mut metadata.bytes, zigzag(RLE));
+        metadata.list_begin(3, T_BINARY, 1);
+        push_varint(&mut metadata.bytes, name.len() as u64);
+        metadata.bytes.extend_from_slice(name.as_bytes());
+        metadata.i32(4, 0);
+        metadata.i64(5, num_rows as i64);
+        metadata.i64(6, length as i64);
+        metadata.i64(7, length as i64);
+        metadata.i64(9, offset as i64);
+        metadata.struct_end();
+        metadata.struct_end();
+    }
+    metadata.i64(2, chunks.iter().map(|chunk| chunk.3 as i64).sum());
+    metadata.i64(3, num_rows as i64);
+    metadata.struct_end();
+    metadata.binary(6, b"zsheets");
+    metadata.finish()
+}
+
+/// Read a Parquet table: a header row of its column names, then its rows
+pub fn read(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    let file = std::fs::read(path)?;
+    let footer_end = file.len().checked_sub(8).filter(|_| file.starts_with(MAGIC) && file.ends_with(MAGIC));
+    let footer_end = footer_end.ok_or_else(|| invalid("Not a Parquet file"))?;
+    let footer_length = u32::from_le_bytes(file[footer_end..footer_end + 4].try_into().unwrap()) as usize;
+    let footer_start = footer_end.checked_sub(footer_length).filter(|&start| start >= MAGIC.len());
+    let footer_start = footer_start.ok_or_else(|| invalid("Bad Parquet footer"))?;
+    let metadata = ThriftReader::new(&file[footer_start..footer_end]).read_struct()?;
+
+    let schema = list(&metadata, 2);
+    let columns = schema
+        .iter()
+        .skip(1)
+        .map(|element| Column::from_schema(as_struct(element)?))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut rows = vec![columns.iter().map(|column| column.name.clone()).collect::<Vec<_>>()];
+    for row_group in list(&metadata, 4) {
+        let row_group = as_struct(row_group)?;
+        let chunks = list(row_group, 1);
+        if chunks.len() != columns.len() {
+            return Err(invalid("A Parquet row group doesn't match the schema"));
+        }
+        let values = chunks
+            .iter()
+            .zip(&columns)
+            .map(|(chunk, column)| read_chunk(&file, as_struct(chunk)?, column))
+            .collect::<io::Result<Vec<_>>>()?;
+        let num_rows = values.iter().map(Vec::len).max().unwrap_or(0);
+        let mut cursors: Vec<_> = values.into_iter().map(Vec::into_iter).collect();
+        for _ in 0..num_rows {
+            rows.push(cursors.iter_mut().map(|cursor| cursor.next().unwrap_or_default()).collect());
+        }
+    }
+    Ok(rows)
+}
+
+/// A column of the schema, and how its values read as cell text
+struct Column {
+    name: String,
+    physical_type: i64,
+    type_length: usize,
+    optional: bool,
+    kind: Kind,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Plain,
+    Unsigned,
+    /// Days since 1970
+    Date,
+    /// Time of day, in units per second
+    Time(i64),
+    /// Time since 1970, in units per second
+    Timestamp(i64),
+    /// Digits after the decimal point
+    Decimal(u32),
+}
+
+impl Column {
+    fn from_schema(element: &Fields) -> io::Result<Self> {
+        let name = binary(element, 4).map(|name| String::from_utf8_lossy(name).into_owned()).unwrap_or_default();
+        if int(element, 5).is_some_and(|children| children > 0) {
+            return Err(unsupported(format!("Nested Parquet columns ({}) aren't supported", name)));
+        }
+        let optional = match int(element, 3).unwrap_or(0) {
+            0 => false,
+            1 => true,
+            _ => return Err(unsupported(format!("Repeated Parquet columns ({}) aren't supported", name))),
+        };
+        let scale = int(element, 7).unwrap_or(0) as u32;
+        let kind = match structure(element, 10).and_then(|logical| logical.iter().next()) {
+            Some((&5, decimal)) => Kind::Decimal(as_struct(decimal).ok().and_then(|decimal| int(decimal, 1)).unwrap_or(0) as u32),
+            Some((&6, _)) => Kind::Date,
+            Some((&7, time)) => Kind::Time(time_unit(time)),
+            Some((&8, timestamp)) => Kind::Timestamp(time_unit(timestamp)),
+            Some((&10, integer)) => match as_struct(integer).ok().and_then(|integer| fields_bool(integer, 2)) {
+                Some(false) => Kind::Unsigned,
+                _ => Kind::Plain,
+            },
+            Some(_) => Kind::Plain,
+            None => match int(element, 6) {
+                Some(5) => Kind::Decimal(scale),
+                Some(6) => Kind::Date,
+                Some(7) => Kind::Time(1_000),
+                Some(8) => Kind::Time(1_000_000),
+                Some(9) => Kind::Timestamp(1_000),
+                Some(10) => Kind::Timestamp(1_000_000),
+                Some(11..=14) => Kind::Unsigned,
+                _ => Kind::Plain,
+            },
+        };
+        Ok(Self {
+            name,
+            physical_type: int(element, 1).ok_or_else(|| invalid("A Parquet column has no type"))?,
+            type_length: int(element, 2).unwrap_or(0) as usize,
+            optional,
+            kind,
+        })
+    }
+
+    fn int_text(&self, n: i64) -> String {
+        match self.kind {
+            Kind::Unsigned if self.physical_type == INT32 => (n as u32).to_string(),
+            Kind::Unsigned => (n as u64).to_string(),
+            Kind::Date => DateTime::from_parts(1970, 1, 1 + n).to_string(),
+            Kind::Time(per_second) => time_text(n.div_euclid(per_second)),
+            Kind::Timestamp(per_second) => timestamp_text(n.div_euclid(per_second)),
+            Kind::Decimal(scale) => decimal_text(n as i128, scale),
+            Kind::Plain => n.to_string(),
+        }
+    }
+
+    fn bytes_text(&self, bytes: &[u8]) -> String {
+        match self.kind {
+            // Big-endian two's complement
+            Kind::Decimal(scale) if !bytes.is_empty() && bytes.len() <= 16 => {
+                let fill = if bytes[0] & 0x80 != 0 { 0xff } else { 0 };
+                let mut buffer = [fill; 16];
+                buffer[16 - bytes.len()..].copy_from_slice(bytes);
+                decimal_text(i128::from_be_bytes(buffer), scale)
+            }
+            _ => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    /// `count` values stored one after another
+    fn plain(&self, data: &[u8], count: usize) -> io::Result<Vec<String>> {
+        let fixed = |size: usize| {
+            let data = data.get(..count.saturating_mul(size)).ok_or_else(|| invalid("A Parquet page ends early"))?;
+            Ok::<_, io::Error>(data.chunks_exact(size))
+        };
+        Ok(match self.physical_type {
+            BOOLEAN => {
+                if data.len() * 8 < count {
+                    return Err(invalid("A Parquet page ends early"));
+                }
+                (0..count).map(|i| bool_text(data[i / 8] >> (i % 8) & 1 == 1)).collect()
+            }
+            INT32 => fixed(4)?.map(|bytes| self.int_text(i32::from_le_bytes(bytes.try_into().unwrap()) as i64)).collect(),
+            INT64 => fixed(8)?.map(|bytes| self.int_text(i64::from_le_bytes(bytes.try_into().unwrap()))).collect(),
+            // Nanoseconds into the day, then the Julian day
+            INT96 => fixed(12)?
+                .map(|bytes| {
+                    let nanos = i64::from_le_bytes(bytes[..8].try_into().unwrap());
+                    let julian_day = i32::from_le_bytes(bytes[8..].try_into().unwrap()) as i64;
+                    timestamp_text((julian_day - 2_440_588) * 86_400 + nanos.div_euclid(1_000_000_000))
+                })
+                .collect(),
+            FLOAT => fixed(4)?.map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()).to_string()).collect(),
+            DOUBLE => fixed(8)?.map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()).to_string()).collect(),
+            BYTE_ARRAY => {
+                let mut reader = ThriftReader::new(data);
+                let mut values = Vec::with_capacity(count.min(data.len() / 4));
+                for _ in 0..count {
+                    let length = u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as usize;
+                    values.push(self.bytes_text(reader.take(length)?));
+                }
+                values
+            }
+            FIXED_LEN_BYTE_ARRAY if self.type_length > 0 => fixed(self.type_length)?.map(|bytes| self.bytes_text(bytes)).collect(),
+            other => return Err(unsupported(format!("Parquet type {} isn't supported", other))),
+        })
+    }
+}
+
+fn bool_text(flag: bool) -> String {
+    if flag { "TRUE" } else { "FALSE" }.to_string()
+}
+
+/// Units per second of a `TimeUnit`
+fn time_unit(annotation: &Thrift) -> i64 {
+    let unit = as_struct(annotation).ok().and_then(|annotation| structure(annotation, 2));
+    match unit.and_then(|unit| unit.keys().next().copied()) {
+        Some(1) => 1_000,
+        Some(2) => 1_000_000,
+        _ => 1_000_000_000,
+    }
+}
+
+fn time_text(seconds: i64) -> String {
+    let seconds = seconds.rem_euclid(86_400);
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Seconds since 1970 as an ISO date, with the time unless it's midnight
+fn timestamp_text(seconds: i64) -> String {
+    let date = DateTime::from_parts(1970, 1, 1 + seconds.div_euclid(86_400));
+    match seconds.rem_euclid(86_400) {
+        0 => date.to_string(),
+        time => format!("{} {}", date, time_text(time)),
+    }
+}
+
+fn decimal_text(unscaled: i128, scale: u32) -> String {
+    if scale == 0 {
+        return unscaled.to_string();
+    }
+    let digits = format!("{:0>width$}", unscaled.unsigned_abs(), width = scale as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale as usize);
+    format!("{}{}.{}", if unscaled < 0 { "-" } else { "" }, whole, fraction)
+}
+
+/// A column chunk's values, one per row, empty for nulls
+fn read_chunk(file: &[u8], chunk: &Fields, column: &Column) -> io::Result<Vec<String>> {
+    let metadata = structure(chunk, 3).ok_or_else(|| unsupported("Parquet columns in other files aren't supported".to_string()))?;
+    let codec = int(metadata, 4).unwrap_or(0);
+    let num_values = int(metadata, 5).unwrap_or(0) as usize;
+    let data_start = int(metadata, 9).ok_or_else(|| invalid("A Parquet column has no data"))?;
+    let start = match int(metadata, 11) {
+        Some(offset) if offset > 0 && offset < data_start => offset,
+        _ => data_start,
+    } as usize;
+    let length = int(metadata, 7).unwrap_or(0) as usize;
+    let bytes = file.get(start..start.saturating_add(length)).ok_or_else(|| invalid("A Parquet column runs past the file"))?;
+
+    let mut reader = ThriftReader::new(bytes);
+    let mut dictionary = Vec::new();
+    let mut values = Vec::with_capacity(num_values.min(bytes.len() * 8));
+    while values.len() < num_values && reader.position < bytes.len() {
+        let header = reader.read_struct()?;
+        let uncompressed_size = int(&header, 2).unwrap_or(0) as usize;
+        let page = reader.take(int(&header, 3).unwrap_or(0) as usize)?;
+        match int(&header, 1) {
+            Some(DICTIONARY_PAGE) => {
+                let count = structure(&header, 7).and_then(|page_header| int(page_header, 1)).unwrap_or(0) as usize;
+                dictionary = column.plain(&decompress(page, codec, uncompressed_size)?, count)?;
+            }
+            Some(DATA_PAGE) => {
+                let page_header = structure(&header, 5).ok_or_else(|| invalid("A Parquet data page has no header"))?;
+                let count = int(page_header, 1).unwrap_or(0) as usize;
+                let encoding = int(page_header, 2).unwrap_or(PLAIN);
+                let data = decompress(page, codec, uncompressed_size)?;
+                let (levels, data) = match column.optional {
+                    true => {
+                        let length = data.get(..4).map_or(0, |length| u32::from_le_bytes(length.try_into().unwrap()) as usize);
+                        let levels = data.get(4..4 + length).ok_or_else(|| invalid("A Parquet page ends early"))?;
+                        (rle_hybrid(levels, 1, count)?, &data[4 + length..])
+                    }
+                    false => (vec![1; count], &data[..]),
+                };
+                push_values(&mut values, &levels, data, encoding, &dictionary, column)?;
+            }
+            Some(DATA_PAGE_V2) => {
+                let page_header = structure(&header, 8).ok_or_else(|| invalid("A Parquet data page has no header"))?;
+                let count = int(page_header, 1).unwrap_or(0) as usize;
+                let encoding = int(page_header, 4).unwrap_or(PLAIN);
+                let definition_length = int(page_header, 5).unwrap_or(0) as usize;
+                let repetition_length = int(page_header, 6).unwrap_or(0) as usize;
+                let levels_end = repetition_length + definition_length;
+                let levels = page.get(repetition_length..levels_end).ok_or_else(|| invalid("A Parquet page ends early"))?;
+                let levels = match column.optional {
+                    true => rle_hybrid(levels, 1, count)?,
+                    false => vec![1; count],
+                };
+                // Levels are never compressed, and the values may not be either
+                let data = match fields_bool(page_header, 7).unwrap_or(true) {
+                    true => decompress(&page[levels_end..], codec, uncompressed_size.saturating_sub(levels_end))?,
+                    false => page[levels_end..].to_vec(),
+                };
+                push_values(&mut values, &levels, &data, encoding, &dictionary, column)?;
+            }
+            // Index pages hold nothing to show
+            _ => {}
+        }
+    }
+    values.resize(num_values, String::new());
+    Ok(values)
+}
+
+/// A data page's values, placed by its definition levels: 1 for a value, 0 for a null
+fn push_values(
+    values: &mut Vec<String>,
+    levels: &[u32],
+    data: &[u8],
+    encoding: i64,
+    dictionary: &[String],
+    column: &Column,
+) -> io::Result<()> {
+    let present = levels.iter().filter(|&&level| level == 1).count();
+    let decoded = match encoding {
+        PLAIN => column.plain(data, present)?,
+        PLAIN_DICTIONARY | RLE_DICTIONARY => {
+            let (&width, indices) = data.split_first().unwrap_or((&0, &[]));
+            rle_hybrid(indices, width as u32, present)?
+                .into_iter()
+                .map(|index| dictionary.get(index as usize).cloned().ok_or_else(|| invalid("A Parquet dictionary index is out of range")))
+                .collect::<io::Result<Vec<_>>>()?
+        }
+        RLE if column.physical_type == BOOLEAN => {
+            let flags = data.get(4..).unwrap_or_default();
+            rle_hybrid(flags, 1, present)?.into_iter().map(|flag| bool_text(flag == 1)).collect()
+        }
+        other => return Err(unsupported(format!("Parquet encoding {} isn't supported", other))),
+    };
+    let mut decoded = decoded.into_iter();
+    values.extend(levels.iter().map(|&level| if level == 1 { decoded.next().unwrap_or_default() } else { String::new() }));
+    Ok(())
+}
+
+/// Parquet's run-length and bit-packed hybrid: runs of one repeated value, and groups of
+/// eight values packed `width` bits apiece, least significant first
+fn rle_hybrid(data: &[u8], width: u32, count: usize) -> io::Result<Vec<u32>> {
+    if width > 32 {
+        return Err(invalid("A Parquet bit width is over 32"));
+    }
+    let mut reader = ThriftReader::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        let header = reader.varint()?;
+        let wanted = count - values.len();
+        if header & 1 == 1 {
+            let groups = (header >> 1) as usize;
+            let packed = reader.take(groups.saturating_mul(width as usize))?;
+            let available = (groups * 8).min(wanted);
+            values.extend((0..available).map(|i| {
+                let mut value = 0u64;
+                for bit in 0..width as usize {
+                    let position = i * width as usize + bit;
+                    value |= ((packed[position / 8] >> (position % 8) & 1) as u64) << bit;
+                }
+                value as u32
+            }));
+        } else {
+            let run = ((header >> 1) as usize).min(wanted);
+            let bytes = reader.take(width.div_ceil(8) as usize)?;
+            let value = bytes.iter().rev().fold(0u32, |value, &byte| value << 8 | byte as u32);
+            values.extend(std::iter::repeat_n(value, run));
+        }
+    }
+    Ok(values)
+}
+
+fn decompress(data: &[u8], codec: i64, size: usize) -> io::Result<Vec<u8>> {
+    match codec {
+        0 => Ok(data.to_vec()),
+        1 => snappy(data),
+        2 => {
+            let mut output = Vec::with_capacity(size);
+            flate2::read::MultiGzDecoder::new(data).read_to_end(&mut output)?;
+            Ok(output)
+        }
+        6 => zstd::decode_all(data),
+        other => Err(unsupported(format!("Parquet compression codec {} isn't supported", other))),
+    }
+}
+
+/// Decompress a raw Snappy block: its length, then literals and copies of earlier output
+fn snappy(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = ThriftReader::new(data);
+    let length = reader.varint()? as usize;
+    let mut output: Vec<u8> = Vec::with_capacity(length.min(data.len().saturating_mul(32)));
+    while reader.position < data.len() {
+        let tag = reader.byte()?;
+        let (copy_length, offset) = match tag & 3 {
+            0 => {
+                let mut literal_length = (tag >> 2) as usize;
+                if literal_length >= 60 {
+                    let bytes = reader.take(literal_length - 59)?;
+                    literal_length = bytes.iter().rev().fold(0, |length, &byte| length << 8 | byte as usize);
+                }
+                output.extend_from_slice(reader.take(literal_length + 1)?);
+                continue;
+            }
+            1 => (((tag >> 2) & 7) as usize + 4, ((tag as usize >> 5) << 8) | reader.byte()? as usize),
+            2 => ((tag >> 2) as usize + 1, u16::from_le_bytes(reader.take(2)?.try_into().unwrap()) as usize),
+            _ => ((tag >> 2) as usize + 1, u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as usize),
+        };
+        if offset == 0 || offset > output.len() {
+            return Err(invalid("Bad Snappy data in a Parquet page"));
+        }
+        // Copies may overlap what they produce
+        for _ in 0..copy_length {
+            output.push(output[output.len() - offset]);
+        }
+    }
+    match output.len() == length {
+        true => Ok(output),
+        false => Err(invalid("Bad Snappy data in a Parquet page")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unsupported(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+/// Writes Thrift's compact protocol, which Parquet's headers and footer are in
+struct ThriftWriter {
+    bytes: Vec<u8>,
+    /// The last field id written in each struct being written, innermost last
+    last_ids: Vec<i16>,
+}
+
+impl ThriftWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), last_ids: vec![0] }
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_ids.last_mut().expect("writing outside a struct");
+        match id - *last {
+            delta @ 1..=15 => self.bytes.push((delta as u8) << 4 | kind),
+            _ => {
+                self.bytes.push(kind);
+                push_varint(&mut self.bytes, zigzag(id as i64));
+            }
+        }
+        *last = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, T_I32);
+        push_varint(&mut self.bytes, zigzag(value as i64));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, T_I64);
+        push_varint(&mut self.bytes, zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, T_BINARY);
+        push_varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn struct_begin(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.element_begin();
+    }
+
+    /// A struct as an element of a list
+    fn element_begin(&mut self) {
+        self.last_ids.push(0);
+    }
+
+    fn struct_end(&mut self) {
+        self.bytes.push(0);
+        self.last_ids.pop();
+    }
+
+    /// A list's header; its elements follow
+    fn list_begin(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, T_LIST);
+        match len {
+            0..15 => self.bytes.push((len as u8) << 4 | kind),
+            _ => {
+                self.bytes.push(0xf0 | kind);
+                push_varint(&mut self.bytes, len as u64);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.bytes.push(0);
+        self.bytes
+    }
+}
+
+/// A value read from Thrift's compact protocol. Parquet's structs are looked up by field id.
+enum Thrift {
+    Bool(bool),
+    Int(i64),
+    Double,
+    Binary(Vec<u8>),
+    List(Vec<Thrift>),
+    Struct(Fields),
+}
+
+type Fields = BTreeMap<i16, Thrift>;
+
+fn int(fields: &Fields, id: i16) -> Option<i64> {
+    match fields.get(&id)? {
+        Thrift::Int(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn fields_bool(fields: &Fields, id: i16) -> Option<bool> {
+    match fields.get(&id)? {
+        Thrift::Bool(flag) => Some(*flag),
+        _ => None,
+    }
+}
+
+fn binary(fields: &Fields, id: i16) -> Option<&[u8]> {
+    match fields.get(&id)? {
+        Thrift::Binary(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+fn list(fields: &Fields, id: i16) -> &[Thrift] {
+    match fields.get(&id) {
+        Some(Thrift::List(items)) => items,
+        _ => &[],
+    }
+}
+
+fn structure(fields: &Fields, id: i16) -> Option<&Fields> {
+    fields.get(&id).and_then(|value| as_struct(value).ok())
+}
+
+fn as_struct(value: &Thrift) -> io::Result<&Fields> {
+    match value {
+        Thrift::Struct(fields) => Ok(fields),
+        _ => Err(invalid("Bad Parquet metadata")),
+    }
+}
+
+/// Reads Thrift's compact protocol, and the varints and byte runs of Parquet's pages
+struct ThriftReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ThriftReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid("Unexpected end of Parquet data"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(invalid("Bad varint in Parquet data"))
+    }
+
+    fn zigzag(&mut self) -> io::Result<i64> {
+        let n = self.varint()?;
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn read_struct(&mut self) -> io::Result<Fields> {
+        let mut fields = Fields::new();
+        let mut last_id = 0i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(fields);
+            }
+            let kind = header & 0x0f;
+            let id = match header >> 4 {
+                0 => self.zigzag()? as i16,
+                delta => last_id + delta as i16,
+            };
+            last_id = id;
+            let value = match kind {
+                T_BOOL_TRUE => Thrift::Bool(true),
+                T_BOOL_FALSE => Thrift::Bool(false),
+                kind => self.read_value(kind)?,
+            };
+            fields.insert(id, value);
+        }
+    }
+
+    fn read_value(&mut self, kind: u8) -> io::Result<Thrift> {
+        Ok(match kind {
+            // Booleans in lists take a byte each
+            T_BOOL_TRUE | T_BOOL_FALSE => Thrift::Bool(self.byte()? == 1),
+            3 => Thrift::Int(self.byte()? as i8 as i64),
+            4..=6 => Thrift::Int(self.zigzag()?),
+            7 => {
+                self.take(8)?;
+                Thrift::Double
+            }
+            T_BINARY => {
+                let length = self.varint()? as usize;
+                Thrift::Binary(self.take(length)?.to_vec())
+            }
+            // Lists and sets
+            9 | 10 => {
+                let header = self.byte()?;
+                let length = match header >> 4 {
+                    15 => self.varint()? as usize,
+                    length => length as usize,
+                };
+                let items = (0..length.min(self.bytes.len())).map(|_| self.read_value(header & 0x0f));
+                Thrift::List(items.collect::<io::Result<_>>()?)
+            }
+            // Maps, as a list of key and value pairs
+            11 => {
+                let length = self.varint()? as usize;
+                let mut pairs = Vec::new();
+                if length > 0 {
+                    let kinds = self.byte()?;
+                    for _ in 0..length.min(self.bytes.len()) {
+                        let key = self.read_value(kinds >> 4)?;
+                        let value = self.read_value(kinds & 0x0f)?;
+                        pairs.push(Thrift::List(vec![key, value]));
+                    }
+                }
+                Thrift::List(pairs)
+            }
+            T_STRUCT => Thrift::Struct(self.read_struct()?),
+            _ => return Err(invalid("Bad Parquet metadata")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn written_tables_read_back() {
+        let table = rows(&[
+            &["id", "zip", "price", "paid", "due", "note"],
+            &["1", "02134", "9.5", "true", "2024-01-31", " spaced "],
+            &["2", "", "", "FALSE", "", "x"],
+            &["3", "10001", "12", "", "2024-02-29", ""],
+        ]);
+        let path = std::env::temp_dir().join(format!("zsheets-table-{}.parquet", std::process::id()));
+        write(&path, &table, &BTreeMap::new()).unwrap();
+        let read_back = read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            read_back.unwrap(),
+            rows(&[
+                &["id", "zip", "price", "paid", "due", "note"],
+                &["1", "02134", "9.5", "TRUE", "2024-01-31", " spaced "],
+                &["2", "", "", "FALSE", "", "x"],
+                &["3", "10001", "12", "", "2024-02-29", ""],
+            ])
+        );
+    }
+
+    #[test]
+    fn columns_are_stored_by_type() {
+        assert_eq!(Storage::of(&["1", "", "-20"], None), Storage::Integer);
+        assert_eq!(Storage::of(&["1", "2.5"], None), Storage::Float);
+        assert_eq!(Storage::of(&["02134", "10001"], None), Storage::Text);
+        assert_eq!(Storage::of(&["02134", "10001"], Some(ColumnType::Integer)), Storage::Integer);
+        assert_eq!(Storage::of(&["1", "2"], Some(ColumnType::Text)), Storage::Text);
+        assert_eq!(Storage::of(&["2024-01-31", "2024-01-31 10:00"], Some(ColumnType::Date)), Storage::Text);
+        assert_eq!(column_names(&["a".into(), "".into(), "a".into()], 4), ["a", "B", "a_2", "D"]);
+    }
+
+    #[test]
+    fn snappy_copies_repeat_earlier_output() {
+        let compressed = [12, 2 << 2, b'a', b'b', b'c', (5 << 2) | 1, 3];
+        assert_eq!(snappy(&compressed).unwrap(), b"abcabcabcabc");
+        assert!(snappy(&[4, (1 << 2) | 1, 9]).is_err());
+    }
+
+    /// Snappy with just a literal, the simplest stream there is
+    fn snappy_literal(data: &[u8]) -> Vec<u8> {
+        let mut compressed = vec![data.len() as u8, ((data.len() - 1) as u8) << 2];
+        compressed.extend_from_slice(data);
+        compressed
+    }
+
+    /// A page compressed with Snappy, after a header with its type, sizes, and type's own header
+    fn snappy_page(file: &mut Vec<u8>, page_type: i64, header_id: i16, header: &[(i16, i64)], data: &[u8]) {
+        let compressed = snappy_literal(data);
+        let mut writer = ThriftWriter::new();
+        writer.i32(1, page_type as i32);
+        writer.i32(2, data.len() as i32);
+        writer.i32(3, compressed.len() as i32);
+        writer.struct_begin(header_id);
+        for &(id, value) in header {
+            writer.i32(id, value as i32);
+        }
+        writer.struct_end();
+        file.extend_from_slice(&writer.finish());
+        file.extend_from_slice(&compressed);
+    }
+
+    #[test]
+    fn dictionary_pages_read_through_snappy() {
+        let mut file = MAGIC.to_vec();
+        let dictionary_offset = file.len();
+        let dictionary = [&3u32.to_le_bytes()[..], b"red", &4u32.to_le_bytes(), b"blue"].concat();
+        snappy_page(&mut file, DICTIONARY_PAGE, 7, &[(1, 2), (2, PLAIN)], &dictionary);
+        // Levels 1, 0, 1, 1 bit-packed, then indices 1, 0, 0 one bit wide
+        let data_offset = file.len();
+        let data = [2, 0, 0, 0, 3, 0b1101, 1, 3, 0b001];
+        snappy_page(&mut file, DATA_PAGE, 5, &[(1, 4), (2, RLE_DICTIONARY)], &data);
+
+        let mut chunk = ThriftWriter::new();
+        chunk.struct_begin(3);
+        chunk.i32(4, 1);
+        chunk.i64(5, 4);
+        chunk.i64(7, (file.len() - dictionary_offset) as i64);
+        chunk.i64(9, data_offset as i64);
+        chunk.i64(11, dictionary_offset as i64);
+        chunk.struct_end();
+        let chunk = ThriftReader::new(&chunk.finish()).read_struct().unwrap();
+        let column = Column { name: "color".into(), physical_type: BYTE_ARRAY, type_length: 0, optional: true, kind: Kind::Plain };
+        assert_eq!(read_chunk(&file, &chunk, &column).unwrap(), ["blue", "", "red", "red"]);
+    }
+
+    #[test]
+    fn annotated_values_read_as_text() {
+        let column = |kind| Column { name: String::new(), physical_type: INT64, type_length: 0, optional: true, kind };
+        assert_eq!(column(Kind::Date).int_text(19753), "2024-01-31");
+        assert_eq!(column(Kind::Timestamp(1_000)).int_text(1_706_700_600_000), "2024-01-31 11:30:00");
+        assert_eq!(column(Kind::Timestamp(1_000_000)).int_text(1_706_659_200_000_000), "2024-01-31");
+        assert_eq!(column(Kind::Decimal(2)).int_text(-1205), "-12.05");
+        assert_eq!(column(Kind::Decimal(3)).int_text(5), "0.005");
+        assert_eq!(column(Kind::Decimal(2)).bytes_text(&[0xff, 0x38]), "-2.00");
+        assert_eq!(column(Kind::Unsigned).int_text(-1), "18446744073709551615");
+    }
+}
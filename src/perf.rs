@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Frames averaged for the overlay's mean and worst times
+const HISTORY_LEN: usize = 60;
+
+/// Timings and counts shown by the `:set perf` overlay
+#[derive(Debug, Default)]
+pub struct PerfStats {
+    /// When the frame being drawn started rendering
+    frame_start: Option<Instant>,
+    /// Last frame: building the element tree, through layout
+    pub layout_time: Duration,
+    /// Last frame: from the start of render to the end of paint
+    pub frame_time: Duration,
+    /// Last evaluation of every formula
+    pub formula_time: Duration,
+    /// Cells drawn last frame, and how many of them had text to shape
+    pub cells_drawn: usize,
+    pub cells_shaped: usize,
+    history: VecDeque<Duration>,
+}
+
+impl PerfStats {
+    pub fn start_frame(&mut self) {
+        self.frame_start = Some(Instant::now());
+    }
+
+    /// Called once layout is done and the frame begins to paint
+    pub fn laid_out(&mut self) {
+        if let Some(start) = self.frame_start {
+            self.layout_time = start.elapsed();
+        }
+    }
+
+    /// Called after everything else has painted
+    pub fn painted(&mut self) {
+        let Some(start) = self.frame_start.take() else {
+            return;
+        };
+        self.frame_time = start.elapsed();
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.frame_time);
+    }
+
+    /// Mean and worst frame time over recent frames
+    pub fn recent(&self) -> (Duration, Duration) {
+        let total: Duration = self.history.iter().sum();
+        let mean = total.checked_div(self.history.len() as u32).unwrap_or_default();
+        (mean, self.history.iter().max().copied().unwrap_or_default())
+    }
+}
+
+/// A duration in milliseconds for display, e.g. `4.21 ms`
+pub fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,60 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Run `command` through the shell with `rows` as CSV on its stdin, and read its stdout back
+/// as CSV rows. A failing command's error is the first line of its stderr.
+pub fn filter(command: &str, rows: &[Vec<String>]) -> Result<Vec<Vec<String>>, String> {
+    let stdout = run(command, rows)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(stdout.as_slice());
+    reader
+        .records()
+        .map(|record| record.map(|record| record.iter().map(str::to_string).collect()).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Run `command` through the shell with `rows` as CSV on its stdin, returning what it printed
+pub fn write(command: &str, rows: &[Vec<String>]) -> Result<String, String> {
+    run(command, rows).map(|stdout| String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// `rows` as CSV, the way they're sent to commands and stdout
+pub fn to_csv(rows: &[Vec<String>]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).flexible(true).from_writer(Vec::new());
+    for row in rows {
+        writer.write_record(row).map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+fn run(command: &str, rows: &[Vec<String>]) -> Result<Vec<u8>, String> {
+    let input = to_csv(rows)?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    // Written from another thread so a command that answers before reading everything can't
+    // leave both sides waiting on full pipes
+    let mut stdin = child.stdin.take().ok_or("No stdin")?;
+    let writer = std::thread::spawn(move || {
+        // A command that ignores its input closes the pipe early; that's not an error
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => line.trim().to_string(),
+            None => format!("exited with {}", output.status),
+        });
+    }
+    Ok(output.stdout)
+}
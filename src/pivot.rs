@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::formula::Value;
+use crate::state::CellRange;
+use crate::value;
+
+/// How a pivot combines the values falling in each of its cells
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    Sum,
+    Count,
+    Average,
+}
+
+impl Aggregation {
+    fn title(self, value: Option<&str>) -> String {
+        let name = match self {
+            Self::Sum => "Sum",
+            Self::Count => "Count",
+            Self::Average => "Average",
+        };
+        match value {
+            Some(value) => format!("{} of {}", name, value),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// `:pivot` arguments as typed, before the grid resolves the column names
+#[derive(Clone, Debug, PartialEq)]
+pub struct PivotRequest {
+    pub rows: Vec<String>,
+    pub cols: Vec<String>,
+    pub aggregation: Aggregation,
+    /// Column aggregated; counting without one counts records
+    pub value: Option<String>,
+}
+
+impl PivotRequest {
+    /// Parse `rows=Region,Rep cols=Quarter sum=Amount`, with `count=` or `avg=` in place of
+    /// `sum=` (or neither, to count records). Columns are header labels or letters, and a
+    /// label may contain spaces.
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for word in input.split_whitespace() {
+            match word.split_once('=') {
+                Some((key, rest)) if matches!(key, "rows" | "cols" | "sum" | "count" | "avg") => {
+                    fields.push((key.to_string(), rest.to_string()));
+                }
+                _ => {
+                    let (_, field) = fields.last_mut()?;
+                    field.push(' ');
+                    field.push_str(word);
+                }
+            }
+        }
+
+        let columns = |list: &str| -> Vec<String> {
+            list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect()
+        };
+        let mut request = Self { rows: Vec::new(), cols: Vec::new(), aggregation: Aggregation::Count, value: None };
+        for (key, field) in fields {
+            let field = field.trim();
+            match key.as_str() {
+                "rows" => request.rows = columns(field),
+                "cols" => request.cols = columns(field),
+                _ => {
+                    request.aggregation = match key.as_str() {
+                        "sum" => Aggregation::Sum,
+                        "avg" => Aggregation::Average,
+                        _ => Aggregation::Count,
+                    };
+                    request.value = (!field.is_empty()).then(|| field.to_string());
+                }
+            }
+        }
+        let needs_value = request.aggregation != Aggregation::Count;
+        (!request.rows.is_empty() && (request.value.is_some() || !needs_value)).then_some(request)
+    }
+}
+
+/// A pivot table's layout over a source range whose first row holds the labels
+#[derive(Clone, Debug, PartialEq)]
+pub struct PivotSpec {
+    pub source: CellRange,
+    /// Re-find the block of data around the source's top-left corner on refresh, so rows
+    /// added since count; off when the source was an explicit selection
+    pub follow_block: bool,
+    pub rows: Vec<usize>,
+    pub cols: Vec<usize>,
+    pub aggregation: Aggregation,
+    pub value: Option<usize>,
+}
+
+/// The values of one pivot cell, or of a total
+#[derive(Clone, Copy, Debug, Default)]
+struct Tally {
+    count: usize,
+    numbers: usize,
+    sum: f64,
+}
+
+impl Tally {
+    fn add(&mut self, text: Option<&str>) {
+        let Some(text) = text else {
+            self.count += 1;
+            return;
+        };
+        if !text.is_empty() {
+            self.count += 1;
+        }
+        if let Some(n) = value::parse_number(text) {
+            self.numbers += 1;
+            self.sum += n;
+        }
+    }
+
+    fn result(self, aggregation: Aggregation) -> String {
+        match aggregation {
+            Aggregation::Count => self.count.to_string(),
+            _ if self.numbers == 0 => String::new(),
+            Aggregation::Sum => Value::Number(self.sum).to_string(),
+            Aggregation::Average => Value::Number(self.sum / self.numbers as f64).to_string(),
+        }
+    }
+}
+
+impl PivotSpec {
+    /// Lay out the pivot table from the grid's values (formulas given as their results): a
+    /// label row, one row per distinct row key, one column per distinct column key, and totals
+    pub fn build(&self, cells: &[Vec<String>]) -> Vec<Vec<String>> {
+        let label = |col: usize| cells[self.source.start.row][col].trim().to_string();
+        let key = |row: usize, cols: &[usize]| -> Vec<String> {
+            cols.iter().map(|&col| cells[row][col].trim().to_string()).collect()
+        };
+        let records: Vec<usize> = (self.source.start.row + 1..=self.source.end.row)
+            .filter(|&row| self.source.cols().any(|col| !cells[row][col].trim().is_empty()))
+            .collect();
+
+        let mut row_keys: Vec<Vec<String>> = records.iter().map(|&row| key(row, &self.rows)).collect();
+        row_keys.sort_by(|a, b| compare_keys(a, b));
+        row_keys.dedup();
+        let mut col_keys: Vec<Vec<String>> = records.iter().map(|&row| key(row, &self.cols)).collect();
+        col_keys.sort_by(|a, b| compare_keys(a, b));
+        col_keys.dedup();
+
+        let row_index: HashMap<&Vec<String>, usize> = row_keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+        let col_index: HashMap<&Vec<String>, usize> = col_keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+        let mut tallies = vec![vec![Tally::default(); col_keys.len()]; row_keys.len()];
+        let mut row_totals = vec![Tally::default(); row_keys.len()];
+        let mut col_totals = vec![Tally::default(); col_keys.len()];
+        let mut total = Tally::default();
+        for &row in &records {
+            let text = self.value.map(|col| cells[row][col].trim());
+            let r = row_index[&key(row, &self.rows)];
+            let c = col_index[&key(row, &self.cols)];
+            for tally in [&mut tallies[r][c], &mut row_totals[r], &mut col_totals[c], &mut total] {
+                tally.add(text);
+            }
+        }
+
+        let title = self.aggregation.title(self.value.map(label).as_deref());
+        let mut header: Vec<String> = self.rows.iter().map(|&col| label(col)).collect();
+        if self.cols.is_empty() {
+            header.push(title);
+        } else {
+            header.extend(col_keys.iter().map(|key| key.join(" / ")));
+            header.push(format!("Total {}", title));
+        }
+
+        let show_total_col = !self.cols.is_empty();
+        let mut table = vec![header];
+        for (r, key) in row_keys.iter().enumerate() {
+            let mut line = key.clone();
+            line.extend(tallies[r].iter().map(|tally| tally.result(self.aggregation)));
+            if show_total_col {
+                line.push(row_totals[r].result(self.aggregation));
+            }
+            table.push(line);
+        }
+        let mut totals = vec![String::new(); self.rows.len()];
+        totals[0] = "Total".to_string();
+        totals.extend(col_totals.iter().map(|tally| tally.result(self.aggregation)));
+        if show_total_col {
+            totals.push(total.result(self.aggregation));
+        }
+        table.push(totals);
+        table
+    }
+}
+
+/// Order keys part by part, the way sorting orders cells
+fn compare_keys(a: &[String], b: &[String]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| value::compare_for_sort(a, b, false))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
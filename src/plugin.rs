@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::command_palette::Command;
+use crate::config::config_dir;
+use crate::file_io;
+use crate::script::ScriptGrid;
+
+/// What a plugin command does when it's run
+#[derive(Clone, Debug)]
+pub enum PluginAction {
+    /// An ex command line, e.g. `script tidy` or `sort! B`
+    Ex(String),
+    /// Edit the grid the way a script does: its changes and queued commands are applied after.
+    /// Only plugins built into the binary can register these.
+    #[allow(dead_code)]
+    Native(fn(&mut ScriptGrid) -> Result<(), String>),
+}
+
+/// How a file of another format becomes rows
+#[derive(Clone, Debug)]
+pub enum Importer {
+    #[allow(dead_code)]
+    Native(fn(&Path) -> io::Result<Vec<Vec<String>>>),
+    /// A program and its arguments, run with the file's path added last, that prints CSV
+    Program(Vec<String>),
+}
+
+impl Importer {
+    fn import(&self, path: &Path) -> io::Result<Vec<Vec<String>>> {
+        match self {
+            Self::Native(import) => import(path),
+            Self::Program(argv) => {
+                let (program, args) = argv.split_first().ok_or_else(|| io::Error::other("Importer has no program"))?;
+                let output = std::process::Command::new(program).args(args).arg(path).output()?;
+                if !output.status.success() {
+                    return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+                }
+                file_io::parse_records(&String::from_utf8_lossy(&output.stdout))
+            }
+        }
+    }
+}
+
+/// Something that adds commands, aliases, or importers when the app starts
+pub trait Plugin: Sync {
+    fn register(&self, registry: &mut Registry);
+}
+
+/// Plugins built into the binary; a plugin crate is enabled by adding it here
+const PLUGINS: &[&dyn Plugin] = &[];
+
+/// Everything plugins registered: from `PLUGINS`, then from `plugins.json` in the config directory
+#[derive(Debug, Default)]
+pub struct Registry {
+    commands: Vec<Command>,
+    actions: HashMap<&'static str, PluginAction>,
+    /// Command ids by the name that runs them as `:<name>`
+    vim_names: HashMap<String, &'static str>,
+    aliases: HashMap<String, String>,
+    /// Lowercase file extensions, without the dot, and what reads them
+    importers: Vec<(Vec<String>, Importer)>,
+}
+
+impl Registry {
+    /// The registry, built on first use
+    pub fn get() -> &'static Registry {
+        static REGISTRY: OnceLock<Registry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let mut registry = Registry::default();
+            for plugin in PLUGINS {
+                plugin.register(&mut registry);
+            }
+            registry.load_manifest();
+            registry
+        })
+    }
+
+    /// Add a palette command, also run by `:<vim_name>` when one is given
+    pub fn register_command(&mut self, name: &str, vim_name: Option<&str>, action: PluginAction) {
+        // Commands are registered once at startup and kept for the life of the app
+        let id: &'static str = format!("plugin.{}", self.commands.len()).leak();
+        let mut command = Command::new(id, name.to_string().leak());
+        if let Some(vim_name) = vim_name {
+            command = command.with_vim(format!(":{}", vim_name).leak());
+            self.vim_names.insert(vim_name.to_string(), id);
+        }
+        self.commands.push(command);
+        self.actions.insert(id, action);
+    }
+
+    /// Make `:<alias> args` run `:<expansion> args`; built-in commands can't be replaced
+    pub fn register_alias(&mut self, alias: &str, expansion: &str) {
+        self.aliases.insert(alias.to_string(), expansion.trim_start_matches(':').to_string());
+    }
+
+    /// Open files with these extensions through `importer` instead of as CSV
+    pub fn register_importer(&mut self, extensions: &[&str], importer: Importer) {
+        let extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_lowercase()).collect();
+        self.importers.push((extensions, importer));
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn action(&self, id: &str) -> Option<&PluginAction> {
+        self.actions.get(id)
+    }
+
+    /// The id of the plugin command run by `:<name>`
+    pub fn vim_command(&self, name: &str) -> Option<&'static str> {
+        self.vim_names.get(name).copied()
+    }
+
+    pub fn expand_alias(&self, alias: &str) -> Option<&str> {
+        self.aliases.get(alias).map(String::as_str)
+    }
+
+    /// Extensions some importer reads
+    pub fn import_extensions(&self) -> Vec<&str> {
+        self.importers.iter().flat_map(|(extensions, _)| extensions.iter().map(String::as_str)).collect()
+    }
+
+    /// Read a file through the importer registered for its extension, if there is one
+    pub fn import(&self, path: &Path) -> Option<io::Result<Vec<Vec<String>>>> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        let (_, importer) = self.importers.iter().find(|(extensions, _)| extensions.contains(&extension))?;
+        Some(importer.import(path))
+    }
+
+    fn load_manifest(&mut self) {
+        let Some(path) = config_dir().map(|dir| dir.join("plugins.json")) else {
+            return;
+        };
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return;
+        };
+        let manifest: Manifest = match serde_json::from_str(&content) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                return;
+            }
+        };
+        for command in manifest.commands {
+            self.register_command(&command.name, command.vim.as_deref(), PluginAction::Ex(command.run));
+        }
+        for (alias, expansion) in manifest.aliases {
+            self.register_alias(&alias, &expansion);
+        }
+        for importer in manifest.importers {
+            let extensions: Vec<&str> = importer.extensions.iter().map(String::as_str).collect();
+            self.register_importer(&extensions, Importer::Program(importer.command));
+        }
+    }
+}
+
+/// `plugins.json`, e.g.
+/// `{"commands": [{"name": "Tidy Up", "vim": "tidy", "run": "script tidy"}],
+///   "aliases": {"ss": "sort!"}, "importers": [{"extensions": ["xlsx"], "command": ["xlsx2csv"]}]}`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Manifest {
+    commands: Vec<ManifestCommand>,
+    aliases: HashMap<String, String>,
+    importers: Vec<ManifestImporter>,
+}
+
+#[derive(Deserialize)]
+struct ManifestCommand {
+    name: String,
+    vim: Option<String>,
+    /// Ex command line the command runs
+    run: String,
+}
+
+#[derive(Deserialize)]
+struct ManifestImporter {
+    extensions: Vec<String>,
+    /// Program and arguments; the file's path is added last
+    command: Vec<String>,
+}
@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FLOAT, INT};
+
+use crate::config::config_dir;
+use crate::formula::Value;
+use crate::state::{CellPosition, CellRange, GRID_COLS, GRID_ROWS};
+use crate::value;
+
+/// Operations a script may run before it's stopped, so a runaway loop can't hang the app
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+/// Where macro scripts live: `scripts/` in the config directory
+pub fn scripts_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("scripts"))
+}
+
+/// Names of the `.rhai` scripts in the scripts directory, sorted
+pub fn script_names() -> Vec<String> {
+    let Some(entries) = scripts_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// The grid as a script sees it, and what the script asked for
+#[derive(Clone, Debug)]
+pub struct ScriptGrid {
+    /// Cell contents as typed; the script's edits land here
+    pub cells: Vec<Vec<String>>,
+    /// Cell values as shown, formulas given as their results
+    pub values: Vec<Vec<String>>,
+    pub selection: CellRange,
+    pub cursor: CellPosition,
+    /// Last used row and column
+    pub used_bounds: (usize, usize),
+    /// Cells the script set, as (row, col)
+    pub changed: BTreeSet<(usize, usize)>,
+    /// Ex commands to run once the script finishes, e.g. `sort! B`
+    pub commands: Vec<String>,
+    /// Text from `message` and `print`, the last shown in the footer
+    pub messages: Vec<String>,
+}
+
+impl ScriptGrid {
+    fn get(&self, pos: CellPosition) -> Dynamic {
+        let text = self.values[pos.row][pos.col].trim();
+        match value::parse_number(text) {
+            Some(n) if n.fract() == 0.0 && n.abs() < 1e15 => Dynamic::from(n as INT),
+            Some(n) => Dynamic::from(n as FLOAT),
+            None => Dynamic::from(text.to_string()),
+        }
+    }
+
+    fn set(&mut self, pos: CellPosition, value: Dynamic) {
+        let text = if value.is_unit() {
+            String::new()
+        } else if let Ok(n) = value.as_float() {
+            Value::Number(n).to_string()
+        } else {
+            value.to_string()
+        };
+        self.values[pos.row][pos.col] = text.clone();
+        self.cells[pos.row][pos.col] = text;
+        self.changed.insert((pos.row, pos.col));
+    }
+}
+
+/// Run `<name>.rhai` from the scripts directory against `grid`, returning the grid as the
+/// script left it. Scripts get:
+///
+/// - `get("B2")` / `get(row, col)` - a cell's value (rows and columns count from 1)
+/// - `set("B2", value)` / `set(row, col, value)` - change a cell; text starting `=` is a formula
+/// - `range("A1:C9")` - values as an array of rows
+/// - `selection()`, `cursor()` - references like `"A1:C9"` and `"B2"`
+/// - `last_row()`, `last_col()` - extent of the used cells
+/// - `command("sort! B")` - an ex command to run after the script
+/// - `message(text)` - a note for the footer, as does `print`
+pub fn run(name: &str, grid: ScriptGrid) -> Result<ScriptGrid, String> {
+    let dir = scripts_dir().ok_or("No home directory")?;
+    let path = dir.join(format!("{}.rhai", name));
+    let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let grid = Rc::new(RefCell::new(grid));
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    {
+        let grid = grid.clone();
+        engine.on_print(move |text| grid.borrow_mut().messages.push(text.to_string()));
+    }
+    let g = grid.clone();
+    engine.register_fn("get", move |reference: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(g.borrow().get(position(reference)?))
+    });
+    let g = grid.clone();
+    engine.register_fn("get", move |row: INT, col: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(g.borrow().get(position_at(row, col)?))
+    });
+    let g = grid.clone();
+    engine.register_fn("set", move |reference: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        g.borrow_mut().set(position(reference)?, value);
+        Ok(())
+    });
+    let g = grid.clone();
+    engine.register_fn("set", move |row: INT, col: INT, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        g.borrow_mut().set(position_at(row, col)?, value);
+        Ok(())
+    });
+    let g = grid.clone();
+    engine.register_fn("range", move |reference: &str| -> Result<Array, Box<EvalAltResult>> {
+        let range = CellRange::from_reference(reference).ok_or_else(|| format!("Not a range: {}", reference))?;
+        let grid = g.borrow();
+        Ok(range
+            .rows()
+            .map(|row| Dynamic::from(range.cols().map(|col| grid.get(CellPosition::new(row, col))).collect::<Array>()))
+            .collect())
+    });
+    let g = grid.clone();
+    engine.register_fn("selection", move || g.borrow().selection.to_reference());
+    let g = grid.clone();
+    engine.register_fn("cursor", move || g.borrow().cursor.to_reference());
+    let g = grid.clone();
+    engine.register_fn("last_row", move || g.borrow().used_bounds.0 as INT + 1);
+    let g = grid.clone();
+    engine.register_fn("last_col", move || g.borrow().used_bounds.1 as INT + 1);
+    let g = grid.clone();
+    engine.register_fn("command", move |command: &str| g.borrow_mut().commands.push(command.to_string()));
+    let g = grid.clone();
+    engine.register_fn("message", move |text: &str| g.borrow_mut().messages.push(text.to_string()));
+
+    engine.run(&source).map_err(|e| e.to_string())?;
+    let grid = grid.borrow().clone();
+    Ok(grid)
+}
+
+fn position(reference: &str) -> Result<CellPosition, Box<EvalAltResult>> {
+    CellPosition::from_reference(reference).ok_or_else(|| format!("Not a cell: {}", reference).into())
+}
+
+fn position_at(row: INT, col: INT) -> Result<CellPosition, Box<EvalAltResult>> {
+    match (usize::try_from(row - 1), usize::try_from(col - 1)) {
+        (Ok(row), Ok(col)) if row < GRID_ROWS && col < GRID_COLS => Ok(CellPosition::new(row, col)),
+        _ => Err(format!("No cell at row {}, column {}", row, col).into()),
+    }
+}
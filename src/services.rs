@@ -0,0 +1,83 @@
+// macOS Services: "Open in zsheets" loads selected CSV text from any app into a new tab, and
+// "Copy as Table" puts selected CSV text on the clipboard as a table other apps paste as one.
+// Both are listed under `NSServices` in Info.plist.
+
+use objc2::rc::Retained;
+use objc2::runtime::{AnyObject, NSObject};
+use objc2::{define_class, msg_send, MainThreadMarker, MainThreadOnly};
+use objc2_app_kit::{NSApplication, NSPasteboard, NSPasteboardTypeHTML, NSPasteboardTypeString};
+use objc2_foundation::NSString;
+
+use crate::inbox::{self, parse_table, Request};
+
+define_class!(
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "ZsheetsServiceProvider"]
+    struct ServiceProvider;
+
+    impl ServiceProvider {
+        #[unsafe(method(openInZsheets:userData:error:))]
+        fn open_in_zsheets(&self, pasteboard: &NSPasteboard, _user_data: Option<&NSString>, _error: *mut *mut NSString) {
+            if let Some(text) = pasteboard_text(pasteboard) {
+                inbox::push(Request::Text(text));
+            }
+        }
+
+        #[unsafe(method(copyAsTable:userData:error:))]
+        fn copy_as_table(&self, pasteboard: &NSPasteboard, _user_data: Option<&NSString>, _error: *mut *mut NSString) {
+            let Some(text) = pasteboard_text(pasteboard) else {
+                return;
+            };
+            let rows = parse_table(&text);
+            let general = NSPasteboard::generalPasteboard();
+            general.clearContents();
+            // Rich text editors paste the HTML as a table; plain ones get tab-separated columns
+            unsafe {
+                general.setString_forType(&NSString::from_str(&to_html(&rows)), NSPasteboardTypeHTML);
+                general.setString_forType(&NSString::from_str(&to_tsv(&rows)), NSPasteboardTypeString);
+            }
+        }
+    }
+);
+
+/// Make the app the provider for its Services. Must be called on the main thread.
+pub fn register() {
+    let Some(mtm) = MainThreadMarker::new() else {
+        return;
+    };
+    let provider: Retained<ServiceProvider> = unsafe { msg_send![ServiceProvider::alloc(mtm), init] };
+    let object: &AnyObject = &provider;
+    unsafe { NSApplication::sharedApplication(mtm).setServicesProvider(Some(object)) };
+    // The app doesn't retain its provider, and it's needed for as long as the app runs
+    std::mem::forget(provider);
+}
+
+fn pasteboard_text(pasteboard: &NSPasteboard) -> Option<String> {
+    let text = unsafe { pasteboard.stringForType(NSPasteboardTypeString) }?;
+    Some(text.to_string())
+}
+
+fn to_tsv(rows: &[Vec<String>]) -> String {
+    rows.iter()
+        .map(|row| {
+            let fields: Vec<String> = row.iter().map(|field| field.replace(['\t', '\n', '\r'], " ")).collect();
+            fields.join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn to_html(rows: &[Vec<String>]) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let mut html = String::from("<table>");
+    for row in rows {
+        html.push_str("<tr>");
+        for field in row {
+            html.push_str(&format!("<td>{}</td>", escape(field)));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// Bounds on a column's width, kept when resizing and auto-fitting
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct WidthLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f32>,
+}
+
+impl WidthLimits {
+    /// Parse `min=<px>` and/or `max=<px>`, e.g. `min=40 max=300`
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut limits = Self::default();
+        for part in input.split_whitespace() {
+            let (name, value) = part.split_once('=')?;
+            let value = parse_width(value)?;
+            match name {
+                "min" => limits.min = Some(value),
+                "max" => limits.max = Some(value),
+                _ => return None,
+            }
+        }
+        let ordered = match (limits.min, limits.max) {
+            (Some(min), Some(max)) => min <= max,
+            (None, None) => false,
+            _ => true,
+        };
+        ordered.then_some(limits)
+    }
+
+    pub fn clamp(self, width: f32) -> f32 {
+        let width = self.max.map_or(width, |max| width.min(max));
+        self.min.map_or(width, |min| width.max(min))
+    }
+}
+
+/// A width in pixels: positive and finite
+pub fn parse_width(input: &str) -> Option<f32> {
+    let width: f32 = input.trim().trim_end_matches("px").parse().ok()?;
+    (width.is_finite() && width > 0.0).then_some(width)
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::config::app_support_dir;
+use crate::file_io;
+use crate::metadata::{path_hash, SpreadsheetMetadata};
+use crate::state::{GRID_COLS, GRID_ROWS};
+
+/// Automatic snapshots kept per file; older ones are deleted as new ones are taken
+const MAX_AUTOMATIC: usize = 20;
+
+/// A full copy of a sheet, kept in the app's history directory to look back at or restore
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    /// Cells up to the last used row, each row up to its last used cell
+    pub cells: Vec<Vec<String>>,
+    pub metadata: SpreadsheetMetadata,
+}
+
+impl Snapshot {
+    pub fn new(name: &str, cells: &[Vec<String>], metadata: SpreadsheetMetadata) -> Self {
+        let (max_row, _) = file_io::find_used_bounds(cells);
+        let cells = cells[..=max_row]
+            .iter()
+            .map(|row| {
+                let width = row.iter().rposition(|cell| !cell.is_empty()).map_or(0, |col| col + 1);
+                row[..width].to_vec()
+            })
+            .collect();
+        Self { name: name.to_string(), cells, metadata }
+    }
+
+    /// The cells padded back out to the grid
+    pub fn grid_cells(&self) -> Vec<Vec<String>> {
+        let mut cells = vec![vec![String::new(); GRID_COLS]; GRID_ROWS];
+        for (row, values) in cells.iter_mut().zip(&self.cells) {
+            for (cell, value) in row.iter_mut().zip(values) {
+                cell.clone_from(value);
+            }
+        }
+        cells
+    }
+}
+
+/// A snapshot on disk, described by its file name: `<millis>-<named|auto>-<name>.json`
+#[derive(Clone, Debug)]
+pub struct SnapshotEntry {
+    pub path: PathBuf,
+    pub name: String,
+    /// Milliseconds since the Unix epoch
+    pub taken: i64,
+    pub automatic: bool,
+}
+
+impl SnapshotEntry {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let stem = path.file_name()?.to_str()?.strip_suffix(".json")?;
+        let mut parts = stem.splitn(3, '-');
+        let taken = parts.next()?.parse().ok()?;
+        let automatic = match parts.next()? {
+            "auto" => true,
+            "named" => false,
+            _ => return None,
+        };
+        let name = parts.next()?.to_string();
+        Some(Self { path, name, taken, automatic })
+    }
+
+    /// How the snapshot is listed: when it was taken (local time) and its name
+    pub fn label(&self) -> String {
+        let taken = match Local.timestamp_millis_opt(self.taken).single() {
+            Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => self.taken.to_string(),
+        };
+        let kind = if self.automatic { " (auto)" } else { "" };
+        format!("{} {}{}", taken, self.name, kind)
+    }
+
+    pub fn load(&self) -> io::Result<Snapshot> {
+        let json = std::fs::read_to_string(&self.path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Where a file's snapshots are kept: `snapshots/<hash of its path>` in the app support
+/// directory. Untitled sheets share `snapshots/untitled`.
+fn snapshot_dir(file: Option<&Path>) -> Option<PathBuf> {
+    let dir = app_support_dir()?.join("snapshots");
+    Some(match file {
+        Some(path) => {
+            let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            dir.join(format!("{:016x}", path_hash(&path)))
+        }
+        None => dir.join("untitled"),
+    })
+}
+
+/// Store a snapshot of `file`'s sheet. Taking an automatic one drops the oldest automatic
+/// ones past the limit; named snapshots are kept until deleted.
+pub fn take(file: Option<&Path>, snapshot: &Snapshot, automatic: bool) -> io::Result<()> {
+    let dir = snapshot_dir(file)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No app support directory"))?;
+    std::fs::create_dir_all(&dir)?;
+    // Path separators can't go in a file name; the snapshot itself keeps the name as given
+    let name: String = snapshot.name
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') { '_' } else { c })
+        .collect();
+    let kind = if automatic { "auto" } else { "named" };
+    let path = dir.join(format!("{}-{}-{}.json", Local::now().timestamp_millis(), kind, name));
+    let json = serde_json::to_string(snapshot).map_err(io::Error::other)?;
+    std::fs::write(path, json)?;
+    if automatic {
+        for old in list(file).into_iter().filter(|entry| entry.automatic).skip(MAX_AUTOMATIC) {
+            let _ = std::fs::remove_file(old.path);
+        }
+    }
+    Ok(())
+}
+
+/// A file's snapshots, newest first
+pub fn list(file: Option<&Path>) -> Vec<SnapshotEntry> {
+    let Some(entries) = snapshot_dir(file).and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<SnapshotEntry> = entries
+        .flatten()
+        .filter_map(|entry| SnapshotEntry::from_path(entry.path()))
+        .collect();
+    snapshots.sort_by_key(|entry| std::cmp::Reverse(entry.taken));
+    snapshots
+}
+
+/// The snapshot listed as `label`, else the newest with that name
+pub fn find(file: Option<&Path>, label: &str) -> Option<SnapshotEntry> {
+    let snapshots = list(file);
+    let label = label.trim().trim_matches('"');
+    snapshots
+        .iter()
+        .find(|entry| entry.label() == label)
+        .or_else(|| snapshots.iter().find(|entry| entry.name == label))
+        .cloned()
+}
@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command_palette::expand_home;
+use crate::config::Config;
+use crate::file_io::json_rows;
+use crate::json_path::JsonPath;
+use crate::pipe;
+use crate::state::CellRange;
+
+/// Fetched data, row by row
+pub type Rows = Vec<Vec<String>>;
+
+/// Where a bound region's data comes from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DataSource {
+    /// CSV, TSV, or a JSON array fetched over HTTP
+    Url { url: String },
+    /// JSON fetched over HTTP, its rows picked out by a JSONPath-like expression
+    Json { url: String, path: Option<String> },
+    /// A shell command that prints CSV
+    Command { command: String },
+    /// A query against a SQLite database, led by a row of column names
+    Sql { database: PathBuf, query: String },
+}
+
+/// A region of the grid filled from a data source; `range` is where the last fetch wrote
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceBinding {
+    pub source: DataSource,
+    pub range: CellRange,
+}
+
+impl DataSource {
+    /// Parse `url <url>`, `json <url> [path]`, `cmd <command>`, or `sql <database> <query>`
+    pub fn parse(input: &str) -> Option<Self> {
+        let (kind, rest) = input.trim().split_once(' ')?;
+        let rest = rest.trim();
+        match kind {
+            "url" if !rest.contains(char::is_whitespace) => Some(Self::Url { url: rest.to_string() }),
+            "json" => {
+                let (url, path) = rest.split_once(' ').unwrap_or((rest, ""));
+                let path = path.trim();
+                if url.is_empty() || (!path.is_empty() && JsonPath::parse(path).is_err()) {
+                    return None;
+                }
+                Some(Self::Json { url: url.to_string(), path: (!path.is_empty()).then(|| path.to_string()) })
+            }
+            "cmd" | "command" | "sh" if !rest.is_empty() => Some(Self::Command { command: rest.to_string() }),
+            "sql" => {
+                let (database, query) = rest.split_once(' ')?;
+                let query = query.trim();
+                (!query.is_empty()).then(|| Self::Sql { database: expand_home(database), query: query.to_string() })
+            }
+            _ => None,
+        }
+    }
+
+    /// Short description for messages, e.g. `sql sales.db`
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Url { url } | Self::Json { url, .. } => url.clone(),
+            Self::Command { command } => format!("!{}", command),
+            Self::Sql { database, .. } => format!("sql {}", database.display()),
+        }
+    }
+
+    /// Fetch the source's rows. Blocks, so run it off the main thread.
+    pub fn fetch(&self) -> Result<Rows, String> {
+        match self {
+            Self::Url { url } => parse_document(&http_get(url)?),
+            Self::Json { url, path } => {
+                let document: serde_json::Value = serde_json::from_str(&http_get(url)?).map_err(|e| e.to_string())?;
+                let matches = match path {
+                    Some(path) => JsonPath::parse(path)?.select(&document),
+                    None => vec![&document],
+                };
+                // A path reaching one array lists its items; otherwise each match is a row
+                let items: Vec<serde_json::Value> = match &matches[..] {
+                    [serde_json::Value::Array(items)] => items.clone(),
+                    _ => matches.into_iter().cloned().collect(),
+                };
+                Ok(json_rows(&items))
+            }
+            Self::Command { command } => pipe::filter(command, &[]),
+            Self::Sql { database, query } => query_sqlite(database, query).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// GET a URL as text, sending the headers the config file gives for its host
+fn http_get(url: &str) -> Result<String, String> {
+    let mut request = ureq::get(url);
+    let host = url.parse::<ureq::http::Uri>().ok().and_then(|uri| uri.host().map(str::to_string));
+    if let Some(headers) = host.and_then(|host| Config::get().http_headers.get(&host)) {
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+    }
+    let mut response = request.call().map_err(|e| e.to_string())?;
+    response.body_mut().read_to_string().map_err(|e| e.to_string())
+}
+
+/// Rows from a fetched document: a JSON array of arrays or of objects (keys become a header
+/// row), else CSV, or TSV when the first line has tabs but no commas
+fn parse_document(body: &str) -> Result<Rows, String> {
+    if body.trim_start().starts_with('[') {
+        let items: Vec<serde_json::Value> = serde_json::from_str(body).map_err(|e| e.to_string())?;
+        return Ok(json_rows(&items));
+    }
+    let first_line = body.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains('\t') && !first_line.contains(',') { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(body.as_bytes());
+    reader
+        .records()
+        .map(|record| record.map(|record| record.iter().map(str::to_string).collect()).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn query_sqlite(database: &std::path::Path, query: &str) -> rusqlite::Result<Rows> {
+    use rusqlite::types::ValueRef;
+
+    let connection = rusqlite::Connection::open_with_flags(database, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(query)?;
+    let columns = statement.column_count();
+    let mut rows = vec![statement.column_names().into_iter().map(str::to_string).collect()];
+    let mut results = statement.query([])?;
+    while let Some(row) = results.next()? {
+        let mut cells = Vec::with_capacity(columns);
+        for col in 0..columns {
+            cells.push(match row.get_ref(col)? {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(n) => n.to_string(),
+                ValueRef::Real(n) => n.to_string(),
+                ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+            });
+        }
+        rows.push(cells);
+    }
+    Ok(rows)
+}
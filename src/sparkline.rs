@@ -0,0 +1,76 @@
+use gpui::{Bounds, Pixels, PathBuilder, Rgba, Window, fill, point, px, size};
+
+/// How a sparkline draws its values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SparklineKind {
+    #[default]
+    Line,
+    Bar,
+}
+
+impl SparklineKind {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "line" => Some(Self::Line),
+            "bar" | "column" => Some(Self::Bar),
+            _ => None,
+        }
+    }
+}
+
+/// A miniature chart drawn inside a cell, the result of `=SPARKLINE(range, [kind])`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sparkline {
+    pub kind: SparklineKind,
+    pub values: Vec<f64>,
+}
+
+impl Sparkline {
+    /// Draw the chart to fill `bounds`, scaled between its smallest and largest values;
+    /// bars rise from zero, and those below it take `negative`
+    pub fn paint(&self, bounds: Bounds<Pixels>, color: Rgba, negative: Rgba, window: &mut Window) {
+        if self.values.is_empty() {
+            return;
+        }
+        let (mut min, mut max) = self.values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &n| (min.min(n), max.max(n)));
+        if self.kind == SparklineKind::Bar {
+            min = min.min(0.0);
+            max = max.max(0.0);
+        }
+        let (left, top) = (f32::from(bounds.origin.x), f32::from(bounds.origin.y));
+        let (width, height) = (f32::from(bounds.size.width), f32::from(bounds.size.height));
+        // A flat series draws along the middle
+        let y = |n: f64| match max - min {
+            range if range > 0.0 => top + height * (1.0 - ((n - min) / range) as f32),
+            _ => top + height / 2.0,
+        };
+
+        match self.kind {
+            SparklineKind::Line => {
+                let step = width / (self.values.len().max(2) - 1) as f32;
+                let mut path = PathBuilder::stroke(px(1.5));
+                for (i, &n) in self.values.iter().enumerate() {
+                    let at = point(px(left + step * i as f32), px(y(n)));
+                    if i == 0 { path.move_to(at) } else { path.line_to(at) }
+                }
+                if self.values.len() == 1 {
+                    path.line_to(point(px(left + width), px(y(self.values[0]))));
+                }
+                if let Ok(path) = path.build() {
+                    window.paint_path(path, color);
+                }
+            }
+            SparklineKind::Bar => {
+                let slot = width / self.values.len() as f32;
+                let gap = (slot * 0.2).min(2.0);
+                let zero = y(0.0);
+                for (i, &n) in self.values.iter().enumerate() {
+                    let (bar_top, bar_bottom) = if n < 0.0 { (zero, y(n)) } else { (y(n), zero) };
+                    let origin = point(px(left + slot * i as f32 + gap / 2.0), px(bar_top));
+                    let bar = Bounds::new(origin, size(px(slot - gap), px((bar_bottom - bar_top).max(1.0))));
+                    window.paint_quad(fill(bar, if n < 0.0 { negative } else { color }));
+                }
+            }
+        }
+    }
+}
@@ -18,7 +18,29 @@ impl CellPosition {
         format!("{}{}", col_letter, self.row + 1)
     }
 
-    fn col_to_letter(col: usize) -> String {
+    /// Parse column letters (A, B, ..., AA) into a column index
+    pub fn letter_to_col(letters: &str) -> Option<usize> {
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let mut col = 0usize;
+        for c in letters.chars() {
+            let digit = (c.to_ascii_uppercase() as u8 - b'A') as usize + 1;
+            col = col.checked_mul(26)?.checked_add(digit)?;
+        }
+        Some(col - 1)
+    }
+
+    /// Parse an Excel-style reference (A1, b5) into a position inside the grid
+    pub fn from_reference(reference: &str) -> Option<Self> {
+        let reference = reference.trim();
+        let split = reference.find(|c: char| c.is_ascii_digit())?;
+        let col = Self::letter_to_col(&reference[..split])?;
+        let row = reference[split..].parse::<usize>().ok()?.checked_sub(1)?;
+        (row < GRID_ROWS && col < GRID_COLS).then_some(Self { row, col })
+    }
+
+    pub fn col_to_letter(col: usize) -> String {
         let mut result = String::new();
         let mut n = col;
         loop {
@@ -32,6 +54,56 @@ impl CellPosition {
     }
 }
 
+/// A rectangular block of cells, normalized so `start` is the top-left corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: CellPosition,
+    pub end: CellPosition,
+}
+
+impl CellRange {
+    /// The range spanning two opposite corners, in any order
+    pub fn new(a: CellPosition, b: CellPosition) -> Self {
+        Self {
+            start: CellPosition::new(a.row.min(b.row), a.col.min(b.col)),
+            end: CellPosition::new(a.row.max(b.row), a.col.max(b.col)),
+        }
+    }
+
+    pub fn rows(&self) -> std::ops::Range<usize> {
+        self.start.row..self.end.row + 1
+    }
+
+    pub fn cols(&self) -> std::ops::Range<usize> {
+        self.start.col..self.end.col + 1
+    }
+
+    pub fn contains(&self, pos: CellPosition) -> bool {
+        self.rows().contains(&pos.row) && self.cols().contains(&pos.col)
+    }
+
+    /// Parse an Excel-style range (A1:C3) or single cell (B2)
+    pub fn from_reference(reference: &str) -> Option<Self> {
+        match reference.split_once(':') {
+            Some((a, b)) => Some(Self::new(CellPosition::from_reference(a)?, CellPosition::from_reference(b)?)),
+            None => CellPosition::from_reference(reference).map(|pos| Self::new(pos, pos)),
+        }
+    }
+
+    pub fn is_single_cell(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Excel-style reference (A1:C3, or A1 for a single cell)
+    pub fn to_reference(self) -> String {
+        if self.is_single_cell() {
+            self.start.to_reference()
+        } else {
+            format!("{}:{}", self.start.to_reference(), self.end.to_reference())
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Mode {
     Normal,
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::formula::Value;
+use crate::value;
+
+/// Bars in a numeric column's histogram
+const HISTOGRAM_BINS: usize = 10;
+/// Most frequent values kept for a column that isn't numeric
+const TOP_VALUES: usize = 3;
+
+/// A quick profile of a column's data rows
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnStats {
+    /// Cells with anything in them
+    pub count: usize,
+    /// Different values among the filled cells, ignoring surrounding whitespace
+    pub distinct: usize,
+    pub empty: usize,
+    /// Smallest, largest, and mean of the cells holding numbers, if any do
+    pub numbers: Option<(f64, f64, f64)>,
+    /// How many numbers fall in each of equal slices of min..=max
+    pub histogram: Vec<usize>,
+    /// The most frequent values and how often they appear, most frequent first
+    pub top_values: Vec<(String, usize)>,
+}
+
+impl ColumnStats {
+    /// Profile the values of a column's cells, formulas given as their results
+    pub fn from_values(values: impl IntoIterator<Item = String>) -> Self {
+        let mut stats = Self::default();
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        let mut numbers = Vec::new();
+        for text in values {
+            let text = text.trim();
+            if text.is_empty() {
+                stats.empty += 1;
+                continue;
+            }
+            stats.count += 1;
+            numbers.extend(value::parse_number(text));
+            *frequencies.entry(text.to_string()).or_default() += 1;
+        }
+        stats.distinct = frequencies.len();
+
+        if !numbers.is_empty() {
+            let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+            stats.numbers = Some((min, max, mean));
+            stats.histogram = vec![0; HISTOGRAM_BINS];
+            for n in numbers {
+                let slice = if max > min { ((n - min) / (max - min) * HISTOGRAM_BINS as f64) as usize } else { 0 };
+                stats.histogram[slice.min(HISTOGRAM_BINS - 1)] += 1;
+            }
+        }
+
+        let mut top_values: Vec<(String, usize)> = frequencies.into_iter().collect();
+        top_values.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| value::compare_for_sort(a, b, false)));
+        top_values.truncate(TOP_VALUES);
+        stats.top_values = top_values;
+        stats
+    }
+
+    /// One `label: value` line per figure, numbers written as formulas show them
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Count: {}", self.count),
+            format!("Distinct: {}", self.distinct),
+            format!("Empty: {}", self.empty),
+        ];
+        if let Some((min, max, mean)) = self.numbers {
+            lines.push(format!("Min: {}", Value::Number(min)));
+            lines.push(format!("Max: {}", Value::Number(max)));
+            lines.push(format!("Mean: {}", Value::Number(mean)));
+        }
+        lines
+    }
+}
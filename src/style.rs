@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+use crate::number_format::NumberFormat;
+
+/// Text styling applied to a single cell
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellStyle {
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub underline: bool,
+    /// Text color as 0xRRGGBB; the theme's text color when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    /// Display format for numeric values; overrides the column's format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_format: Option<NumberFormat>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl CellStyle {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Horizontal alignment of cell text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+impl Alignment {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "left" | "l" => Some(Self::Left),
+            "center" | "centre" | "c" => Some(Self::Center),
+            "right" | "r" => Some(Self::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A styled cell as stored in the metadata sidecar (unstyled cells are omitted)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct StyledCell {
+    pub row: usize,
+    pub col: usize,
+    #[serde(flatten)]
+    pub style: CellStyle,
+}
+
+/// A formatting change applied to every cell in the selection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StyleChange {
+    /// Toggle bold: turned off if every selected cell is bold, otherwise turned on
+    Bold,
+    Italic,
+    Underline,
+    /// Set or (with `None`) clear the text color
+    Color(Option<u32>),
+    /// Set or (with `None`) clear the number format
+    NumberFormat(Option<NumberFormat>),
+}
+
+impl StyleChange {
+    /// Whether `style` already has this change's attribute set (used to decide toggles)
+    pub fn is_set(self, style: &CellStyle) -> bool {
+        match self {
+            Self::Bold => style.bold,
+            Self::Italic => style.italic,
+            Self::Underline => style.underline,
+            Self::Color(color) => style.color == color,
+            Self::NumberFormat(format) => style.number_format == format,
+        }
+    }
+
+    /// Apply the change, turning a toggled attribute on or off
+    pub fn apply(self, style: &mut CellStyle, on: bool) {
+        match self {
+            Self::Bold => style.bold = on,
+            Self::Italic => style.italic = on,
+            Self::Underline => style.underline = on,
+            Self::Color(color) => style.color = color,
+            Self::NumberFormat(format) => style.number_format = format,
+        }
+    }
+}
+
+/// Color names accepted by `:color`
+pub const COLOR_NAMES: &[&str] = &[
+    "red", "orange", "yellow", "green", "teal", "blue", "purple", "pink", "gray", "white", "black",
+];
+
+/// Parse a color for `:color`: a name like `red`, or hex `#f00` / `#ff0000`
+pub fn parse_color(input: &str) -> Option<u32> {
+    let input = input.trim().to_lowercase();
+    let named = match input.as_str() {
+        "red" => Some(0xf38ba8),
+        "orange" => Some(0xfab387),
+        "yellow" => Some(0xf9e2af),
+        "green" => Some(0xa6e3a1),
+        "teal" => Some(0x94e2d5),
+        "blue" => Some(0x89b4fa),
+        "purple" => Some(0xcba6f7),
+        "pink" => Some(0xf5c2e7),
+        "gray" | "grey" => Some(0x9399b2),
+        "white" => Some(0xffffff),
+        "black" => Some(0x000000),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+
+    let hex = input.strip_prefix('#').unwrap_or(&input);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        // Expand shorthand: #f80 -> #ff8800
+        3 => u32::from_str_radix(&hex.chars().flat_map(|c| [c, c]).collect::<String>(), 16).ok(),
+        _ => None,
+    }
+}
@@ -1,5 +1,8 @@
 use gpui::*;
 
+use crate::config::Config;
+use crate::formula::SpanKind;
+
 #[cfg(target_os = "macos")]
 use objc2::rc::Retained;
 #[cfg(target_os = "macos")]
@@ -22,6 +25,14 @@ pub struct Theme {
     pub crust: Rgba,
     pub crust_light: Rgba,
     pub accent: Rgba,
+    pub red: Rgba,
+    pub peach: Rgba,
+    pub yellow: Rgba,
+    pub green: Rgba,
+    pub teal: Rgba,
+    pub blue: Rgba,
+    pub mauve: Rgba,
+    pub pink: Rgba,
 }
 
 impl Global for Theme {}
@@ -47,11 +58,94 @@ fn get_system_accent_color() -> Rgba {
     gpui::blue().into()
 }
 
-#[cfg(not(target_os = "macos"))]
+/// Get the accent color from GNOME (`accent-color`, 47+) or KDE (`AccentColor` in kdeglobals)
+#[cfg(target_os = "linux")]
+fn get_system_accent_color() -> Rgba {
+    gnome_accent_color()
+        .or_else(kde_accent_color)
+        .unwrap_or_else(|| gpui::blue().into())
+}
+
+#[cfg(target_os = "linux")]
+fn gnome_accent_color() -> Option<Rgba> {
+    let output = std::process::Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "accent-color"])
+        .output()
+        .ok()?;
+    // libadwaita's named accents
+    let hex = match String::from_utf8_lossy(&output.stdout).trim().trim_matches('\'') {
+        "blue" => 0x3584e4,
+        "teal" => 0x2190a4,
+        "green" => 0x3a944a,
+        "yellow" => 0xc88800,
+        "orange" => 0xed5b00,
+        "red" => 0xe62d42,
+        "pink" => 0xd56199,
+        "purple" => 0x9141ac,
+        "slate" => 0x6f8396,
+        _ => return None,
+    };
+    Some(rgb(hex))
+}
+
+#[cfg(target_os = "linux")]
+fn kde_accent_color() -> Option<Rgba> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| std::path::PathBuf::from(home).join(".config")))?;
+    let content = std::fs::read_to_string(config.join("kdeglobals")).ok()?;
+    // `AccentColor=r,g,b` under [General]
+    let value = content.lines().find_map(|line| line.trim().strip_prefix("AccentColor="))?;
+    let channels: Vec<u32> = value.split(',').map(|c| c.trim().parse().ok()).collect::<Option<_>>()?;
+    match channels[..] {
+        [r, g, b, ..] if r < 256 && g < 256 && b < 256 => Some(rgb((r << 16) | (g << 8) | b)),
+        _ => None,
+    }
+}
+
+/// Get the accent color Windows uses for window chrome (`AccentColor` under DWM, as ABGR)
+#[cfg(target_os = "windows")]
+fn get_system_accent_color() -> Rgba {
+    let accent = std::process::Command::new("reg")
+        .args(["query", r"HKCU\Software\Microsoft\Windows\DWM", "/v", "AccentColor"])
+        .output()
+        .ok()
+        .and_then(|output| {
+            let text = String::from_utf8_lossy(&output.stdout).into_owned();
+            let hex = text.split_whitespace().find_map(|word| word.strip_prefix("0x"))?;
+            u32::from_str_radix(hex, 16).ok()
+        });
+    match accent {
+        Some(abgr) => rgb(((abgr & 0xff) << 16) | (abgr & 0xff00) | ((abgr >> 16) & 0xff)),
+        None => gpui::blue().into(),
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
 fn get_system_accent_color() -> Rgba {
     gpui::blue().into()
 }
 
+/// Parse a `#rrggbb` (or `rrggbb`) color
+fn parse_hex_color(input: &str) -> Option<Rgba> {
+    let hex = input.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(rgb)
+}
+
+/// The accent from the config file if it sets one, else the system's
+fn accent_color() -> Rgba {
+    let Some(configured) = Config::get().accent_color.as_deref() else {
+        return get_system_accent_color();
+    };
+    parse_hex_color(configured).unwrap_or_else(|| {
+        eprintln!("Warning: Invalid accent_color {:?}, expected #rrggbb", configured);
+        get_system_accent_color()
+    })
+}
+
 impl Theme {
     pub fn init(app: &mut App) {
         let theme = Theme::get_dark();
@@ -71,6 +165,14 @@ impl Theme {
     // Base	#1e1e2e	rgb(30, 30, 46)	hsl(240, 21%, 15%)
     // Mantle	#181825	rgb(24, 24, 37)	hsl(240, 21%, 12%)
     // Crust	#11111b	rgb(17, 17, 27)	hsl(240, 23%, 9%)
+    // Red	#f38ba8	rgb(243, 139, 168)	hsl(343, 81%, 75%)
+    // Peach	#fab387	rgb(250, 179, 135)	hsl(23, 92%, 75%)
+    // Yellow	#f9e2af	rgb(249, 226, 175)	hsl(41, 86%, 83%)
+    // Green	#a6e3a1	rgb(166, 227, 161)	hsl(115, 54%, 76%)
+    // Teal	#94e2d5	rgb(148, 226, 213)	hsl(170, 57%, 73%)
+    // Blue	#89b4fa	rgb(137, 180, 250)	hsl(217, 92%, 76%)
+    // Mauve	#cba6f7	rgb(203, 166, 247)	hsl(267, 84%, 81%)
+    // Pink	#f5c2e7	rgb(245, 194, 231)	hsl(316, 72%, 86%)
     pub fn get_dark() -> Theme {
         Theme {
             text: rgb(0xcdd6f4),
@@ -87,7 +189,37 @@ impl Theme {
             mantle: rgb(0x181825),
             crust: rgb(0x11111b),
             crust_light: rgba(0x6c708666),
-            accent: get_system_accent_color(),
+            accent: accent_color(),
+            red: rgb(0xf38ba8),
+            peach: rgb(0xfab387),
+            yellow: rgb(0xf9e2af),
+            green: rgb(0xa6e3a1),
+            teal: rgb(0x94e2d5),
+            blue: rgb(0x89b4fa),
+            mauve: rgb(0xcba6f7),
+            pink: rgb(0xf5c2e7),
+        }
+    }
+
+    /// Color for the nth distinct reference in a formula, shared by the editor text and
+    /// the highlighted cells
+    pub fn reference_color(&self, index: usize) -> Rgba {
+        let colors = self.series_colors();
+        colors[index % colors.len()]
+    }
+
+    /// Distinct colors taken in turn, for formula references and chart series
+    pub fn series_colors(&self) -> [Rgba; 7] {
+        [self.blue, self.red, self.mauve, self.green, self.peach, self.pink, self.teal]
+    }
+
+    /// Text color for a highlighted part of a formula
+    pub fn formula_color(&self, kind: SpanKind) -> Rgba {
+        match kind {
+            SpanKind::Function => self.yellow,
+            SpanKind::Reference(index) => self.reference_color(index),
+            SpanKind::Text => self.green,
+            SpanKind::Number => self.peach,
         }
     }
 }
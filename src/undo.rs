@@ -0,0 +1,161 @@
+/// Edits to the cells that undo remembers
+const UNDO_LIMIT: usize = 100;
+
+/// The cells one edit changed, as (row, col, before, after)
+type Change = Vec<(usize, usize, String, String)>;
+
+/// Undo and redo for edits made here. Each edit keeps only the cells it changed, so undoing it
+/// leaves alone cells a collaborator or the tailed file changed since.
+pub struct History {
+    /// Edits, latest last
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+    /// The cells as of the last edit, to compare the next one against
+    base: Vec<Vec<String>>,
+}
+
+impl History {
+    pub fn new(cells: &[Vec<String>]) -> Self {
+        Self { undo: Vec::new(), redo: Vec::new(), base: cells.to_vec() }
+    }
+
+    /// Remember the cells that changed since the last edit, if any
+    pub fn record(&mut self, cells: &[Vec<String>]) {
+        let change = self.catch_up(cells);
+        if change.is_empty() {
+            return;
+        }
+        self.undo.push(change);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Take in changes that came from elsewhere. Undo and redo no longer touch the cells they
+    /// changed, so the other side's values stay.
+    pub fn changed_elsewhere(&mut self, cells: &[Vec<String>]) {
+        let change = self.catch_up(cells);
+        if change.is_empty() {
+            return;
+        }
+        for stack in [&mut self.undo, &mut self.redo] {
+            for edit in stack.iter_mut() {
+                edit.retain(|(row, col, ..)| !change.iter().any(|(r, c, ..)| r == row && c == col));
+            }
+            stack.retain(|edit| !edit.is_empty());
+        }
+    }
+
+    /// Forget edit history, for cells that were loaded rather than edited
+    pub fn reset(&mut self, cells: &[Vec<String>]) {
+        self.undo.clear();
+        self.redo.clear();
+        self.base = cells.to_vec();
+    }
+
+    /// Put back the cells the last edit changed. Returns false if there's nothing to undo.
+    pub fn undo(&mut self, cells: &mut [Vec<String>]) -> bool {
+        self.changed_elsewhere(cells);
+        let Some(change) = self.undo.pop() else {
+            return false;
+        };
+        for (row, col, before, _) in &change {
+            cells[*row][*col].clone_from(before);
+        }
+        self.base = cells.to_vec();
+        self.redo.push(change);
+        true
+    }
+
+    /// Make the last undone edit again. Returns false if there's nothing to redo.
+    pub fn redo(&mut self, cells: &mut [Vec<String>]) -> bool {
+        self.changed_elsewhere(cells);
+        let Some(change) = self.redo.pop() else {
+            return false;
+        };
+        for (row, col, _, after) in &change {
+            cells[*row][*col].clone_from(after);
+        }
+        self.base = cells.to_vec();
+        self.undo.push(change);
+        true
+    }
+
+    /// The cells that differ from the base, which becomes `cells`
+    fn catch_up(&mut self, cells: &[Vec<String>]) -> Change {
+        let mut change = Vec::new();
+        for (row, (before, after)) in self.base.iter_mut().zip(cells).enumerate() {
+            for (col, (before, after)) in before.iter_mut().zip(after).enumerate() {
+                if before != after {
+                    change.push((row, col, std::mem::replace(before, after.clone()), after.clone()));
+                }
+            }
+        }
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> Vec<Vec<String>> {
+        vec![vec![String::new(); 3]; 3]
+    }
+
+    #[test]
+    fn undo_and_redo_local_edits() {
+        let mut cells = sheet();
+        let mut history = History::new(&cells);
+        cells[0][0] = "a".to_string();
+        history.record(&cells);
+        cells[0][0] = "b".to_string();
+        cells[1][1] = "c".to_string();
+        history.record(&cells);
+
+        assert!(history.undo(&mut cells));
+        assert_eq!((cells[0][0].as_str(), cells[1][1].as_str()), ("a", ""));
+        assert!(history.undo(&mut cells));
+        assert_eq!(cells, sheet());
+        assert!(!history.undo(&mut cells));
+        assert!(history.redo(&mut cells));
+        assert!(history.redo(&mut cells));
+        assert_eq!((cells[0][0].as_str(), cells[1][1].as_str()), ("b", "c"));
+        assert!(!history.redo(&mut cells));
+    }
+
+    #[test]
+    fn undo_keeps_remote_edits() {
+        let mut cells = sheet();
+        let mut history = History::new(&cells);
+        cells[0][0] = "mine".to_string();
+        cells[0][1] = "mine too".to_string();
+        history.record(&cells);
+        // A collaborator writes over one of those cells and another elsewhere
+        cells[0][1] = "theirs".to_string();
+        cells[2][2] = "theirs".to_string();
+        history.changed_elsewhere(&cells);
+
+        assert!(history.undo(&mut cells));
+        assert_eq!(cells[0][0], "");
+        assert_eq!(cells[0][1], "theirs");
+        assert_eq!(cells[2][2], "theirs");
+        assert!(history.redo(&mut cells));
+        assert_eq!(cells[0][0], "mine");
+        assert_eq!(cells[0][1], "theirs");
+    }
+
+    #[test]
+    fn edits_overwritten_elsewhere_are_forgotten() {
+        let mut cells = sheet();
+        let mut history = History::new(&cells);
+        cells[1][0] = "mine".to_string();
+        history.record(&cells);
+        cells[1][0] = "theirs".to_string();
+        history.changed_elsewhere(&cells);
+
+        assert!(!history.undo(&mut cells));
+        assert_eq!(cells[1][0], "theirs");
+    }
+}
@@ -0,0 +1,139 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::value;
+
+/// A rule cell values must follow. Invalid cells are flagged in the grid; with `reject`,
+/// invalid input is also refused when leaving edit mode.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Validation {
+    pub rule: ValidationRule,
+    #[serde(default)]
+    pub reject: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ValidationRule {
+    /// One of a fixed set of values, offered as a dropdown while editing
+    List { values: Vec<String> },
+    /// A number within the (inclusive) bounds
+    Range { min: Option<f64>, max: Option<f64> },
+    /// Text the whole of which matches a regular expression
+    Pattern {
+        #[serde(serialize_with = "serialize_regex", deserialize_with = "deserialize_regex")]
+        regex: Regex,
+    },
+}
+
+impl Validation {
+    /// Parse the argument of `:validate`: `list a,b,c`, `range 1 10` (`*` for an open
+    /// bound), or `regex <pattern>`
+    pub fn parse(input: &str, reject: bool) -> Result<Self, String> {
+        let input = input.trim();
+        let (kind, rest) = input.split_once(' ').unwrap_or((input, ""));
+        let rest = rest.trim();
+
+        let rule = match kind {
+            "list" => {
+                let values: Vec<String> = rest
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+                    .collect();
+                if values.is_empty() {
+                    return Err("Usage: :validate list <value>,<value>,...".to_string());
+                }
+                ValidationRule::List { values }
+            }
+            "range" => {
+                let bound = |text: Option<&str>| match text {
+                    Some("*") | None => Ok(None),
+                    Some(text) => value::parse_number(text)
+                        .map(Some)
+                        .ok_or_else(|| format!("Not a number: {}", text)),
+                };
+                let mut bounds = rest.split_whitespace();
+                let (min, max) = (bound(bounds.next())?, bound(bounds.next())?);
+                if min.is_none() && max.is_none() {
+                    return Err("Usage: :validate range <min> <max>".to_string());
+                }
+                ValidationRule::Range { min, max }
+            }
+            "regex" => {
+                let pattern = rest.strip_prefix('/').and_then(|p| p.strip_suffix('/')).unwrap_or(rest);
+                if pattern.is_empty() {
+                    return Err("Usage: :validate regex <pattern>".to_string());
+                }
+                let regex = Regex::new(&format!("^(?:{})$", pattern))
+                    .map_err(|e| format!("Invalid pattern: {}", e))?;
+                ValidationRule::Pattern { regex }
+            }
+            _ => return Err(format!("Unknown validation: {} (expected list, range, or regex)", kind)),
+        };
+        Ok(Self { rule, reject })
+    }
+
+    /// Whether a value passes. Empty cells always pass.
+    pub fn is_valid(&self, text: &str) -> bool {
+        let text = text.trim();
+        if text.is_empty() {
+            return true;
+        }
+        match &self.rule {
+            ValidationRule::List { values } => values.iter().any(|value| value == text),
+            ValidationRule::Range { min, max } => value::parse_number(text).is_some_and(|n| {
+                min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max)
+            }),
+            ValidationRule::Pattern { regex } => regex.is_match(text),
+        }
+    }
+
+    /// Short description used in messages
+    pub fn describe(&self) -> String {
+        match &self.rule {
+            ValidationRule::List { values } => format!("one of {}", values.join(", ")),
+            ValidationRule::Range { min, max } => match (min, max) {
+                (Some(min), Some(max)) => format!("a number from {} to {}", min, max),
+                (Some(min), None) => format!("a number of at least {}", min),
+                (None, Some(max)) => format!("a number of at most {}", max),
+                (None, None) => "a number".to_string(),
+            },
+            ValidationRule::Pattern { regex } => {
+                let pattern = regex.as_str();
+                let pattern = pattern
+                    .strip_prefix("^(?:")
+                    .and_then(|pattern| pattern.strip_suffix(")$"))
+                    .unwrap_or(pattern);
+                format!("text matching /{}/", pattern)
+            }
+        }
+    }
+
+    /// Allowed values for a list rule
+    pub fn list_values(&self) -> Option<&[String]> {
+        match &self.rule {
+            ValidationRule::List { values } => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Regexes are stored as their (anchored) pattern text
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// A cell's own validation as stored in the metadata sidecar
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ValidatedCell {
+    pub row: usize,
+    pub col: usize,
+    #[serde(flatten)]
+    pub validation: Validation,
+}
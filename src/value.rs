@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::date::DateTime;
+
+/// The kind of value a cell holds, as used for alignment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Empty,
+    Number,
+    Date,
+    Text,
+}
+
+/// Classify cell text, reading numbers as `parse_number` does
+pub fn classify(text: &str) -> ValueKind {
+    let text = text.trim();
+    if text.is_empty() {
+        return ValueKind::Empty;
+    }
+    if parse_number(text).is_some() {
+        ValueKind::Number
+    } else if DateTime::parse(text).is_some() {
+        ValueKind::Date
+    } else {
+        ValueKind::Text
+    }
+}
+
+/// What a column holds, inferred from its values when the file opens or set with `:type`.
+/// It decides how the column's cells align, sort, and compare in filters.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Date,
+    Boolean,
+    Text,
+}
+
+impl ColumnType {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "integer" | "int" => Some(Self::Integer),
+            "float" | "number" | "decimal" => Some(Self::Float),
+            "date" => Some(Self::Date),
+            "boolean" | "bool" => Some(Self::Boolean),
+            "text" | "string" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Date => "date",
+            Self::Boolean => "boolean",
+            Self::Text => "text",
+        }
+    }
+
+    /// The type every filled value shares, integers widening to floats; `None` when the
+    /// values are mixed or there are none
+    pub fn infer<'a>(values: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        values
+            .into_iter()
+            .filter(|text| !text.trim().is_empty())
+            .map(Self::of)
+            .try_fold(None, |inferred, kind| match (inferred, kind) {
+                (None, kind) => Some(Some(kind)),
+                (Some(a), b) if a == b => Some(Some(a)),
+                (Some(Self::Integer | Self::Float), Self::Integer | Self::Float) => Some(Some(Self::Float)),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// The type of one value
+    fn of(text: &str) -> Self {
+        let text = text.trim();
+        if text.parse::<i64>().is_ok() {
+            Self::Integer
+        } else if classify(text) == ValueKind::Number {
+            Self::Float
+        } else if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+            Self::Boolean
+        } else if DateTime::parse(text).is_some() {
+            Self::Date
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Parse cell text as a number, ignoring surrounding whitespace. Numbers may carry a currency
+/// symbol, thousands separators, or a trailing percent sign (`$1,234.50`, `-€20`, `12%` is 0.12).
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text),
+    };
+    let unsigned = unsigned.trim_start_matches(['$', '€', '£', '¥']);
+    let (unsigned, scale) = match unsigned.strip_suffix('%') {
+        Some(rest) => (rest, 0.01),
+        None => (unsigned, 1.0),
+    };
+    let bare: String = unsigned.chars().filter(|&c| c != ',').collect();
+    if bare.is_empty() {
+        return None;
+    }
+    bare.parse::<f64>().ok().map(|n| sign * n * scale).filter(|n| n.is_finite())
+}
+
+/// Cell text as a number for a file format that stores numbers as such. Leading zeros
+/// (`02134`) would be lost, so text that has them doesn't count.
+pub fn exported_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let digits = text.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return None;
+    }
+    text.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Add `delta` to the number in cell text, as vim's `ctrl-a` does. A plain number keeps its
+/// decimal places and a date moves by days (written back as ISO); otherwise the first run of
+/// digits changes, keeping its leading zeros. `None` when there's no number.
+pub fn increment(text: &str, delta: i64) -> Option<String> {
+    let trimmed = text.trim();
+    if let Ok(n) = trimmed.parse::<i64>() {
+        return Some(n.checked_add(delta)?.to_string());
+    }
+    // Only plain numbers; `$1,200` keeps its symbol and separator by way of its digits below
+    if let Some(n) = trimmed.parse::<f64>().ok().filter(|n| n.is_finite()) {
+        let decimals = trimmed.split_once('.').map_or(0, |(_, fraction)| fraction.len());
+        return Some(format!("{:.*}", decimals, n + delta as f64));
+    }
+    if let Some(date) = DateTime::parse(trimmed).filter(|date| date.has_date()) {
+        return Some(date.add_days(delta).to_string());
+    }
+
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let end = text[start..].find(|c: char| !c.is_ascii_digit()).map_or(text.len(), |len| start + len);
+    // A minus sign counts unless it joins words, as in `item-3`
+    let before = &text[..start];
+    let signed = before.ends_with('-') && !before[..before.len() - 1].ends_with(char::is_alphanumeric);
+    let number_start = if signed { start - 1 } else { start };
+    let value: i64 = text[number_start..end].parse().ok()?;
+    let value = value.checked_add(delta)?;
+    let digits = &text[start..end];
+    let width = if digits.len() > 1 && digits.starts_with('0') { digits.len() } else { 0 };
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{}{}{:0width$}{}", &text[..number_start], sign, value.unsigned_abs(), &text[end..], width = width))
+}
+
+/// Compare two cells for sorting. Numbers sort first and compare numerically, then dates
+/// chronologically; text compares case-insensitively with digit runs in numeric order ("item2" < "item10"),
+/// and empty cells always sort last regardless of direction.
+pub fn compare_for_sort(a: &str, b: &str, descending: bool) -> Ordering {
+    let (a_empty, b_empty) = (a.trim().is_empty(), b.trim().is_empty());
+    if a_empty || b_empty {
+        return a_empty.cmp(&b_empty);
+    }
+
+    let ordering = match (parse_number(a), parse_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => match (DateTime::parse(a), DateTime::parse(b)) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => natural_cmp(a, b),
+        },
+    };
+    if descending { ordering.reverse() } else { ordering }
+}
+
+/// Compare two cells for sorting as their column's type says: a text column compares
+/// everything as text and a date column puts dates first; otherwise as `compare_for_sort`
+pub fn compare_as(column_type: Option<ColumnType>, a: &str, b: &str, descending: bool) -> Ordering {
+    let (a_empty, b_empty) = (a.trim().is_empty(), b.trim().is_empty());
+    if a_empty || b_empty {
+        return a_empty.cmp(&b_empty);
+    }
+
+    let ordering = match column_type {
+        Some(ColumnType::Text) => natural_cmp(a, b),
+        Some(ColumnType::Date) => match (DateTime::parse(a), DateTime::parse(b)) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return compare_for_sort(a, b, descending),
+        },
+        _ => return compare_for_sort(a, b, descending),
+    };
+    if descending { ordering.reverse() } else { ordering }
+}
+
+/// Case-insensitive comparison treating runs of ASCII digits as numbers
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_run = take_digits(&mut a_chars);
+                let y_run = take_digits(&mut b_chars);
+                let x_trimmed = x_run.trim_start_matches('0');
+                let y_trimmed = y_run.trim_start_matches('0');
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+        run.push(c);
+        chars.next();
+    }
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatted_numbers_parse_as_they_classify() {
+        for text in ["$1,200", "-€20.50", "12%", "£3", "1,000,000"] {
+            assert_eq!(classify(text), ValueKind::Number, "{}", text);
+            assert!(parse_number(text).is_some(), "{}", text);
+        }
+        assert_eq!(parse_number("$1,200"), Some(1200.0));
+        assert_eq!(parse_number("-€20.50"), Some(-20.5));
+        assert_eq!(parse_number("12%"), Some(0.12));
+        for text in ["$", "%", "-", "12 apples", "1.2.3"] {
+            assert_eq!(parse_number(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn currency_sorts_by_amount() {
+        let mut values = vec!["$1,200", "$95", "", "$10,000.50", "-$5", "n/a", "$300"];
+        values.sort_by(|a, b| compare_for_sort(a, b, false));
+        assert_eq!(values, ["-$5", "$95", "$300", "$1,200", "$10,000.50", "n/a", ""]);
+        values.sort_by(|a, b| compare_for_sort(a, b, true));
+        assert_eq!(values, ["n/a", "$10,000.50", "$1,200", "$300", "$95", "-$5", ""]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use gpui::{ScrollStrategy, UniformListScrollHandle};
+use memmap2::Mmap;
+
+use crate::file_io::EMBEDDED_METADATA_PREFIX;
+
+/// Files at least this big open in the viewer rather than being loaded into the grid
+pub const VIEWER_MIN_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Bytes scanned for row starts per step of the background indexing
+const INDEX_CHUNK: usize = 16 * 1024 * 1024;
+
+/// A CSV file mapped into memory and read a row at a time, for files too big to load
+pub struct MappedFile {
+    map: Mmap,
+    delimiter: u8,
+    index: Mutex<RowIndex>,
+}
+
+/// Where each row starts, as far as indexing has got
+#[derive(Default)]
+struct RowIndex {
+    starts: Vec<usize>,
+    scanned: usize,
+    in_quotes: bool,
+}
+
+impl MappedFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read. If another program truncates the file while it's
+        // mapped, reads past the new end fault; the viewer accepts that, as a pager would.
+        let map = unsafe { Mmap::map(&file)? };
+        let delimiter = match path.extension().map(|ext| ext.to_string_lossy().to_lowercase()) {
+            Some(ext) if ext == "tsv" || ext == "tab" => b'\t',
+            _ => b',',
+        };
+        // An embedded metadata line isn't a row
+        let first = match map.starts_with(EMBEDDED_METADATA_PREFIX.as_bytes()) {
+            true => map.iter().position(|&byte| byte == b'\n').map_or(map.len(), |end| end + 1),
+            false => 0,
+        };
+        let index = RowIndex { starts: Vec::new(), scanned: first, in_quotes: false };
+        Ok(Self { map, delimiter, index: Mutex::new(index) })
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Rows indexed so far
+    pub fn row_count(&self) -> usize {
+        self.index.lock().unwrap().starts.len()
+    }
+
+    /// Bytes indexed so far
+    pub fn indexed_len(&self) -> usize {
+        self.index.lock().unwrap().scanned
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indexed_len() >= self.map.len()
+    }
+
+    /// Find the rows starting in the next chunk of the file, skipping line breaks inside
+    /// quoted fields. Blocks, so run it off the main thread; returns whether the file is done.
+    pub fn index_chunk(&self) -> bool {
+        let (from, mut in_quotes) = {
+            let index = self.index.lock().unwrap();
+            (index.scanned, index.in_quotes)
+        };
+        let to = (from + INDEX_CHUNK).min(self.map.len());
+        let mut starts = Vec::new();
+        let mut line_start = from == 0 || self.map[from - 1] == b'\n';
+        for (offset, &byte) in self.map[from..to].iter().enumerate() {
+            if line_start && !in_quotes && byte != b'\n' && byte != b'\r' {
+                starts.push(from + offset);
+            }
+            line_start = false;
+            match byte {
+                b'"' => in_quotes = !in_quotes,
+                b'\n' if !in_quotes => line_start = true,
+                _ => {}
+            }
+        }
+        let mut index = self.index.lock().unwrap();
+        index.starts.extend(starts);
+        index.scanned = to;
+        index.in_quotes = in_quotes;
+        to >= self.map.len()
+    }
+
+    /// The fields of a row, parsed from the map when asked for
+    pub fn row(&self, row: usize) -> Vec<String> {
+        let (start, end) = {
+            let index = self.index.lock().unwrap();
+            let Some(&start) = index.starts.get(row) else {
+                return Vec::new();
+            };
+            (start, index.starts.get(row + 1).copied().unwrap_or(index.scanned))
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(self.delimiter)
+            .from_reader(&self.map[start..end]);
+        let mut record = csv::ByteRecord::new();
+        match reader.read_byte_record(&mut record) {
+            Ok(true) => record.iter().map(|field| String::from_utf8_lossy(field).into_owned()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A file open in the viewer, and the row the cursor is on
+pub struct Viewer {
+    pub file: Arc<MappedFile>,
+    pub row: usize,
+    pub scroll: UniformListScrollHandle,
+}
+
+impl Viewer {
+    pub fn new(file: MappedFile) -> Self {
+        Self { file: Arc::new(file), row: 0, scroll: UniformListScrollHandle::new() }
+    }
+
+    /// Move the cursor by `delta` rows, scrolling it into view
+    pub fn move_by(&mut self, delta: isize) {
+        self.move_to(self.row.saturating_add_signed(delta));
+    }
+
+    pub fn move_to(&mut self, row: usize) {
+        self.row = row.min(self.file.row_count().saturating_sub(1));
+        self.scroll.scroll_to_item(self.row, ScrollStrategy::Top);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use gpui::{App, Bounds, Pixels, WindowBounds, point, px, size};
+use serde::{Deserialize, Serialize};
+
+use crate::config::app_support_dir;
+
+/// A window's position and size, in screen coordinates
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SavedBounds {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default)]
+    pub maximized: bool,
+}
+
+impl SavedBounds {
+    pub fn from_window_bounds(bounds: WindowBounds) -> Self {
+        let b = bounds.get_bounds();
+        Self {
+            x: f32::from(b.origin.x),
+            y: f32::from(b.origin.y),
+            width: f32::from(b.size.width),
+            height: f32::from(b.size.height),
+            maximized: !matches!(bounds, WindowBounds::Windowed(_)),
+        }
+    }
+
+    pub fn to_window_bounds(self) -> WindowBounds {
+        let bounds = Bounds::new(point(px(self.x), px(self.y)), size(px(self.width), px(self.height)));
+        if self.maximized { WindowBounds::Maximized(bounds) } else { WindowBounds::Windowed(bounds) }
+    }
+}
+
+/// Window placement remembered across launches, kept in `window.json` in the app support directory
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WindowState {
+    /// Reopen the window where it was left, toggled with `:set remember_geometry`
+    pub remember_geometry: bool,
+    /// Last bounds for each arrangement of displays
+    pub geometry: BTreeMap<String, SavedBounds>,
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        Self { remember_geometry: true, geometry: BTreeMap::new() }
+    }
+}
+
+impl WindowState {
+    pub fn load() -> Self {
+        let Some(path) = app_support_dir().map(|dir| dir.join("window.json")) else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let dir = app_support_dir().ok_or_else(|| std::io::Error::other("No home directory"))?;
+        std::fs::create_dir_all(&dir)?;
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(dir.join("window.json"), json)
+    }
+
+    /// Where to open the window on the current displays, if it's remembered
+    pub fn bounds_for(&self, cx: &App) -> Option<WindowBounds> {
+        if !self.remember_geometry {
+            return None;
+        }
+        self.geometry.get(&display_key(cx)).map(|saved| saved.to_window_bounds())
+    }
+}
+
+/// Identifies the arrangement of connected displays, so each setup keeps its own geometry
+pub fn display_key(cx: &App) -> String {
+    let mut displays: Vec<String> = cx
+        .displays()
+        .iter()
+        .map(|display| describe(display.bounds()))
+        .collect();
+    displays.sort();
+    displays.join(";")
+}
+
+fn describe(bounds: Bounds<Pixels>) -> String {
+    format!(
+        "{}x{}@{},{}",
+        f32::from(bounds.size.width),
+        f32::from(bounds.size.height),
+        f32::from(bounds.origin.x),
+        f32::from(bounds.origin.y)
+    )
+}
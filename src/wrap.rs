@@ -0,0 +1,72 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Approximate advance of one narrow character at the cell font size, matching auto-fit's estimate
+pub const CHAR_WIDTH: f32 = 8.0;
+/// Height of one rendered line of cell text
+pub const LINE_HEIGHT: f32 = 18.0;
+
+/// How many narrow characters fit on a line in a cell of the given width (after padding)
+pub fn chars_per_line(cell_width: f32) -> usize {
+    (((cell_width - 8.0) / CHAR_WIDTH).floor() as usize).max(1)
+}
+
+/// Width of text in narrow characters: full-width CJK and emoji count as two, and combining
+/// marks and joined emoji sequences take no more room than the character they build on
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().clamp(1, 2)
+}
+
+/// Byte index where `text` stops fitting in `max_width`, keeping at least one character
+fn split_at_width(text: &str, max_width: usize) -> usize {
+    let mut width = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        width += grapheme_width(grapheme);
+        if width > max_width && index > 0 {
+            return index;
+        }
+    }
+    text.len()
+}
+
+/// Word-wrap text to lines at most `max_width` narrow characters wide. Explicit newlines are
+/// kept, and words longer than a line are broken between characters.
+pub fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for mut word in paragraph.split_whitespace() {
+            let mut word_width = text_width(word);
+            if line_width > 0 && line_width + 1 + word_width > max_width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            while word_width > max_width {
+                let split = split_at_width(word, max_width);
+                if split == word.len() {
+                    break;
+                }
+                if line_width > 0 {
+                    lines.push(std::mem::take(&mut line));
+                }
+                lines.push(word[..split].to_string());
+                line_width = 0;
+                word = &word[split..];
+                word_width = text_width(word);
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line_width += word_width;
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
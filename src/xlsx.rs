@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use zip::write::SimpleFileOptions;
+use zip::ZipArchive;
+
+use crate::date::DateTime;
+use crate::state::CellPosition;
+use crate::value::{self, ColumnType};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Sheet1" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#;
+
+/// Write rows as a one-sheet workbook. `=` cells are stored as formulas for the spreadsheet
+/// to calculate. Other cells follow their column's type: text columns stay text, number and
+/// boolean columns are stored as such where the value fits, and in untyped columns numbers
+/// without leading zeros are stored as numbers.
+pub fn write(path: &Path, rows: &[Vec<String>], column_types: &BTreeMap<usize, ColumnType>) -> io::Result<()> {
+    let sheet = sheet_xml(rows, column_types);
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, contents) in [
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", ROOT_RELS),
+        ("xl/workbook.xml", WORKBOOK),
+        ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+        ("xl/worksheets/sheet1.xml", sheet.as_str()),
+    ] {
+        zip.start_file(name, options).map_err(io::Error::other)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+fn sheet_xml(rows: &[Vec<String>], column_types: &BTreeMap<usize, ColumnType>) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    );
+    for (row, cells) in rows.iter().enumerate() {
+        if cells.iter().all(String::is_empty) {
+            continue;
+        }
+        xml.push_str(&format!(r#"<row r="{}">"#, row + 1));
+        for (col, value) in cells.iter().enumerate() {
+            if value.is_empty() {
+                continue;
+            }
+            let reference = CellPosition::new(row, col).to_reference();
+            let column_type = column_types.get(&col).copied();
+            if let Some(formula) = value.strip_prefix('=') {
+                xml.push_str(&format!(r#"<c r="{}"><f>{}</f></c>"#, reference, escape(formula)));
+            } else if let Some(flag) = boolean(value).filter(|_| column_type == Some(ColumnType::Boolean)) {
+                xml.push_str(&format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, reference, flag as u8));
+            } else if let Some(number) = number(value, column_type) {
+                xml.push_str(&format!(r#"<c r="{}"><v>{}</v></c>"#, reference, number));
+            } else {
+                xml.push_str(&format!(
+                    r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+                    reference,
+                    escape(value)
+                ));
+            }
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str("</sheetData></worksheet>");
+    xml
+}
+
+/// The number a cell is stored as: any number in a number column, none in a text, date, or
+/// boolean column, and one without leading zeros in an untyped column
+fn number(value: &str, column_type: Option<ColumnType>) -> Option<f64> {
+    match column_type {
+        Some(ColumnType::Integer | ColumnType::Float) => value.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        Some(_) => None,
+        None => value::exported_number(value),
+    }
+}
+
+fn boolean(value: &str) -> Option<bool> {
+    match value.trim() {
+        text if text.eq_ignore_ascii_case("true") => Some(true),
+        text if text.eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// Read the first sheet of a workbook. Formulas come back as `=` cells, booleans as `TRUE` and
+/// `FALSE`, and numbers shown as dates or times as ISO dates and times.
+pub fn read(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let strings = match part(&mut archive, "xl/sharedStrings.xml")? {
+        Some(xml) => shared_strings(&parse(&xml)?),
+        None => Vec::new(),
+    };
+    let date_styles = match part(&mut archive, "xl/styles.xml")? {
+        Some(xml) => date_styles(&parse(&xml)?),
+        None => Vec::new(),
+    };
+    let sheet_path = first_sheet_path(&mut archive)?;
+    let sheet = part(&mut archive, &sheet_path)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("No {} in the workbook", sheet_path)))?;
+    Ok(sheet_rows(&parse(&sheet)?, &strings, &date_styles))
+}
+
+/// A part of the workbook's package as text, if it has one by that name
+fn part(archive: &mut ZipArchive<File>, name: &str) -> io::Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(io::Error::other(e)),
+    };
+    let mut xml = String::new();
+    file.read_to_string(&mut xml)?;
+    Ok(Some(xml))
+}
+
+fn parse(xml: &str) -> io::Result<roxmltree::Document<'_>> {
+    roxmltree::Document::parse(xml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Elements are matched by local name, so both the transitional and strict namespaces read
+fn is(node: &roxmltree::Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn children<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |child| is(child, name))
+}
+
+/// The first sheet's part, through the workbook's relationships; `sheet1.xml` if they're missing
+fn first_sheet_path(archive: &mut ZipArchive<File>) -> io::Result<String> {
+    let (Some(workbook), Some(rels)) = (part(archive, "xl/workbook.xml")?, part(archive, "xl/_rels/workbook.xml.rels")?) else {
+        return Ok("xl/worksheets/sheet1.xml".to_string());
+    };
+    let (workbook, rels) = (parse(&workbook)?, parse(&rels)?);
+    let id = workbook
+        .descendants()
+        .find(|node| is(node, "sheet"))
+        .and_then(|sheet| sheet.attributes().find(|attribute| attribute.name() == "id").map(|attribute| attribute.value()));
+    let target = rels
+        .descendants()
+        .find(|node| is(node, "Relationship") && node.attribute("Id") == id)
+        .and_then(|relationship| relationship.attribute("Target"));
+    Ok(match target {
+        // Absolute within the package, else relative to the workbook in `xl/`
+        Some(target) => match target.strip_prefix('/') {
+            Some(absolute) => absolute.to_string(),
+            None => format!("xl/{}", target),
+        },
+        None => "xl/worksheets/sheet1.xml".to_string(),
+    })
+}
+
+/// The workbook's shared strings, in order. Rich text runs are joined; phonetic guides aren't text.
+fn shared_strings(document: &roxmltree::Document) -> Vec<String> {
+    children(document.root_element(), "si").map(text_of).collect()
+}
+
+/// The text of a string item: its `t` elements joined
+fn text_of(item: roxmltree::Node) -> String {
+    item.descendants()
+        .filter(|node| is(node, "t") && !node.ancestors().any(|ancestor| is(&ancestor, "rPh")))
+        .filter_map(|node| node.text())
+        .collect()
+}
+
+/// For each cell style, whether its number format shows a date or time
+fn date_styles(document: &roxmltree::Document) -> Vec<bool> {
+    let custom: BTreeMap<&str, &str> = document
+        .descendants()
+        .filter(|node| is(node, "numFmt"))
+        .filter_map(|format| Some((format.attribute("numFmtId")?, format.attribute("formatCode")?)))
+        .collect();
+    let Some(cell_formats) = document.descendants().find(|node| is(node, "cellXfs")) else {
+        return Vec::new();
+    };
+    children(cell_formats, "xf")
+        .map(|style| {
+            let id = style.attribute("numFmtId").unwrap_or("0");
+            match custom.get(id) {
+                Some(code) => is_date_format(code),
+                // The built-in date and time formats
+                None => id.parse().is_ok_and(|id: u32| matches!(id, 14..=22 | 27..=36 | 45..=47 | 50..=58)),
+            }
+        })
+        .collect()
+}
+
+/// Whether a format code has day, month, year, hour, or second parts outside quoted text,
+/// escaped characters, and `[...]` sections such as colors
+fn is_date_format(code: &str) -> bool {
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => while chars.next().is_some_and(|c| c != '"') {},
+            '[' => while chars.next().is_some_and(|c| c != ']') {},
+            '\\' | '_' | '*' => {
+                chars.next();
+            }
+            'd' | 'D' | 'm' | 'M' | 'y' | 'Y' | 'h' | 'H' | 's' | 'S' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Rows of cell text, placed by each row's and cell's reference
+fn sheet_rows(document: &roxmltree::Document, strings: &[String], date_styles: &[bool]) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let Some(data) = document.descendants().find(|node| is(node, "sheetData")) else {
+        return rows;
+    };
+    for row_node in children(data, "row") {
+        // Rows and cells without a reference follow the one before
+        let row = row_node.attribute("r").and_then(|r| r.parse::<usize>().ok()).map_or(rows.len(), |r| r.saturating_sub(1));
+        let mut cells: Vec<String> = Vec::new();
+        for cell in children(row_node, "c") {
+            let col = cell.attribute("r").and_then(column_of).unwrap_or(cells.len());
+            let value = cell_text(cell, strings, date_styles);
+            if value.is_empty() {
+                continue;
+            }
+            if cells.len() <= col {
+                cells.resize(col + 1, String::new());
+            }
+            cells[col] = value;
+        }
+        if rows.len() <= row {
+            rows.resize(row + 1, Vec::new());
+        }
+        rows[row] = cells;
+    }
+    rows
+}
+
+/// The column of a cell reference like `AB12`
+fn column_of(reference: &str) -> Option<usize> {
+    let letters = reference.find(|c: char| c.is_ascii_digit()).map_or(reference, |end| &reference[..end]);
+    CellPosition::letter_to_col(letters)
+}
+
+fn cell_text(cell: roxmltree::Node, strings: &[String], date_styles: &[bool]) -> String {
+    let child_text = |name: &'static str| children(cell, name).next().and_then(|node| node.text()).unwrap_or_default();
+    let formula = child_text("f");
+    if !formula.is_empty() {
+        return format!("={}", formula);
+    }
+    let value = child_text("v");
+    match cell.attribute("t").unwrap_or("n") {
+        "s" => value.trim().parse::<usize>().ok().and_then(|index| strings.get(index)).cloned().unwrap_or_default(),
+        "inlineStr" => children(cell, "is").next().map(text_of).unwrap_or_default(),
+        "b" => if value.trim() == "1" { "TRUE" } else { "FALSE" }.to_string(),
+        _ => {
+            let style = cell.attribute("s").and_then(|s| s.parse::<usize>().ok()).unwrap_or(0);
+            match value.trim().parse::<f64>() {
+                Ok(serial) if date_styles.get(style).copied().unwrap_or(false) => date_text(serial),
+                _ => value.to_string(),
+            }
+        }
+    }
+}
+
+/// A date serial as ISO text; under a day is just the time
+fn date_text(serial: f64) -> String {
+    let text = DateTime::from_serial(serial).to_string();
+    match text.split_once(' ') {
+        Some((_, time)) if (0.0..1.0).contains(&serial) => time.to_string(),
+        _ => text,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_follow_their_column_type() {
+        let rows = vec![
+            vec!["zip".to_string(), "count".to_string(), "code".to_string(), "done".to_string()],
+            vec!["02134".to_string(), "7".to_string(), "42".to_string(), "TRUE".to_string()],
+        ];
+        let types = BTreeMap::from([(2, ColumnType::Text), (3, ColumnType::Boolean)]);
+        let xml = sheet_xml(&rows, &types);
+        assert!(xml.contains(r#"<c r="A2" t="inlineStr"><is><t xml:space="preserve">02134</t></is></c>"#));
+        assert!(xml.contains(r#"<c r="B2"><v>7</v></c>"#));
+        assert!(xml.contains(r#"<c r="C2" t="inlineStr"><is><t xml:space="preserve">42</t></is></c>"#));
+        assert!(xml.contains(r#"<c r="D2" t="b"><v>1</v></c>"#));
+
+        let types = BTreeMap::from([(0, ColumnType::Integer)]);
+        assert!(sheet_xml(&rows, &types).contains(r#"<c r="A2"><v>2134</v></c>"#));
+    }
+
+    #[test]
+    fn written_workbooks_read_back() {
+        let rows: Vec<Vec<String>> = [
+            vec!["name", "zip", "total", "done"],
+            vec!["Fish & <Chips>", "02134", "12.5", "true"],
+            vec![],
+            vec!["", "", "=SUM(C2:C3)"],
+        ]
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+        .collect();
+        let path = std::env::temp_dir().join(format!("zsheets-read-{}.xlsx", std::process::id()));
+        write(&path, &rows, &BTreeMap::from([(3, ColumnType::Boolean)])).unwrap();
+        let read_back = read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = rows.clone();
+        expected[1][3] = "TRUE".to_string();
+        assert_eq!(read_back.unwrap(), expected);
+    }
+
+    #[test]
+    fn date_formats_read_as_dates() {
+        let styles = r#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <numFmts><numFmt numFmtId="164" formatCode="yyyy\-mm\-dd"/><numFmt numFmtId="165" formatCode="&quot;day&quot; 0.0"/></numFmts>
+            <cellXfs><xf numFmtId="0"/><xf numFmtId="14"/><xf numFmtId="164"/><xf numFmtId="165"/></cellXfs></styleSheet>"#;
+        let styles = date_styles(&parse(styles).unwrap());
+        assert_eq!(styles, [false, true, true, false]);
+
+        let sheet = r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+            <row r="2"><c r="B2" s="1"><v>45322</v></c><c r="D2" s="2"><v>45322.5</v></c><c r="E2" s="3"><v>1.5</v></c><c r="F2" s="1"><v>0.25</v></c></row>
+            </sheetData></worksheet>"#;
+        let rows = sheet_rows(&parse(sheet).unwrap(), &[], &styles);
+        assert_eq!(rows[0], Vec::<String>::new());
+        assert_eq!(rows[1], ["", "2024-01-31", "", "2024-01-31 12:00:00", "1.5", "06:00:00"]);
+    }
+}